@@ -0,0 +1,155 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use lsp_client_rs::client::LspClient;
+use lsp_client_rs::protocol::{
+    NotificationMessage, Position, RequestMessage, ResponseMessage, WorkspaceFolder,
+};
+use lsp_client_rs::streaming::ResultItems;
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::runtime::Runtime;
+
+fn location(i: usize) -> serde_json::Value {
+    json!({
+        "uri": format!("file:///workspace/pkg{}/file{}.go", i % 50, i),
+        "range": {
+            "start": { "line": i, "character": 4 },
+            "end": { "line": i, "character": 12 }
+        }
+    })
+}
+
+fn response_body(result: serde_json::Value) -> Vec<u8> {
+    serde_json::to_vec(&json!({ "jsonrpc": "2.0", "id": 1, "result": result })).unwrap()
+}
+
+fn serialize(c: &mut Criterion) {
+    c.bench_function("serialize initialize", |b| {
+        b.iter(|| {
+            let request = RequestMessage::new_initialize(
+                1,
+                42,
+                "file:///workspace".to_string(),
+                "bench".to_string(),
+                "0.1.0".to_string(),
+                vec![WorkspaceFolder {
+                    uri: "file:///workspace".to_string(),
+                    name: "workspace".to_string(),
+                }],
+            );
+            serde_json::to_vec(black_box(&request)).unwrap()
+        })
+    });
+
+    c.bench_function("serialize definition", |b| {
+        b.iter(|| {
+            let request = RequestMessage::new_get_definition(
+                1,
+                "file:///workspace/main.go".to_string(),
+                Position::new(10, 4),
+            );
+            serde_json::to_vec(black_box(&request)).unwrap()
+        })
+    });
+}
+
+fn deserialize(c: &mut Criterion) {
+    let definitions = response_body(json!((0..1_000).map(location).collect::<Vec<_>>()));
+    c.bench_function("deserialize 1k locations", |b| {
+        b.iter(|| {
+            let response: ResponseMessage =
+                serde_json::from_slice(black_box(&definitions)).unwrap();
+            response.handle_definition().unwrap()
+        })
+    });
+
+    let diagnostics = serde_json::to_vec(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {
+            "uri": "file:///workspace/main.go",
+            "version": 3,
+            "diagnostics": (0..200).map(|i| json!({
+                "range": location(i)["range"],
+                "severity": 2,
+                "source": "vet",
+                "message": format!("unused variable v{}", i)
+            })).collect::<Vec<_>>()
+        }
+    }))
+    .unwrap();
+    c.bench_function("deserialize publishDiagnostics", |b| {
+        b.iter(|| {
+            let notification: NotificationMessage =
+                serde_json::from_slice(black_box(&diagnostics)).unwrap();
+            notification
+        })
+    });
+
+    let symbols = response_body(json!((0..10_000)
+        .map(|i| json!({ "name": format!("Symbol{}", i), "kind": 12, "location": location(i) }))
+        .collect::<Vec<_>>()));
+    c.bench_function("stream 10k symbols", |b| {
+        b.iter(|| {
+            ResultItems::<serde_json::Value>::new(black_box(&symbols))
+                .unwrap()
+                .count()
+        })
+    });
+}
+
+/// Answers every request with an empty result, like a trivial server would.
+async fn echo_server(stream: tokio::io::DuplexStream) {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        let mut content_length = 0;
+        loop {
+            line.clear();
+            if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            if let Some(length) = line.strip_prefix("Content-Length:") {
+                content_length = length.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; content_length];
+        stream.read_exact(&mut body).await.unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": null }).to_string();
+        let frame = format!("Content-Length: {}\r\n\r\n{}", response.len(), response);
+        stream.get_mut().write_all(frame.as_bytes()).await.unwrap();
+    }
+}
+
+fn round_trip(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    c.bench_function("round trip definition", |b| {
+        b.to_async(&rt).iter_batched(
+            || {
+                let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+                tokio::spawn(echo_server(server_end));
+                LspClient::from_stream(client_end)
+            },
+            |client| async move {
+                for id in 0..10 {
+                    client
+                        .send_request(RequestMessage::new_get_definition(
+                            id,
+                            "file:///workspace/main.go".to_string(),
+                            Position::new(10, 4),
+                        ))
+                        .await
+                        .unwrap();
+                    client.handle_response().await.unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, serialize, deserialize, round_trip);
+criterion_main!(benches);
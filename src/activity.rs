@@ -0,0 +1,183 @@
+//! Telling when a server has finished analyzing: no work-done progress under
+//! way, no request waiting for its response, and nothing received for a
+//! while. Batch and test tooling wait on this instead of sleeping.
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use std::time::Duration;
+//! let settle = Duration::from_millis(300);
+//! tokio::time::timeout(Duration::from_secs(30), client.wait_for_idle(settle)).await??;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::LspClient;
+use crate::methods;
+use crate::protocol::ProgressToken;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use tokio::sync::watch;
+
+#[derive(Debug, Default)]
+struct State {
+    /// Tokens of the progress created or begun, and not ended yet.
+    progress: HashSet<ProgressToken>,
+    in_flight: usize,
+}
+
+/// What a connection is busy with. Every change, and every message
+/// received, wakes the tasks waiting for the connection to go idle.
+#[derive(Debug)]
+pub(crate) struct Activity {
+    state: Mutex<State>,
+    changes: watch::Sender<()>,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Activity {
+            state: Mutex::default(),
+            changes: watch::Sender::new(()),
+        }
+    }
+}
+
+impl Activity {
+    /// Notes a message received from the server, following the progress it
+    /// reports.
+    pub(crate) fn received(&self, method: Option<&str>, params: Option<&Value>) {
+        let token = params
+            .and_then(|params| params.get("token"))
+            .and_then(|token| ProgressToken::deserialize(token).ok());
+        match (method, token) {
+            (Some(methods::WINDOW_WORK_DONE_PROGRESS_CREATE), Some(token)) => {
+                self.state().progress.insert(token);
+            }
+            (Some(methods::PROGRESS), Some(token)) => {
+                let kind = params.and_then(|params| params["value"]["kind"].as_str());
+                let mut state = self.state();
+                match kind {
+                    Some("begin") => state.progress.insert(token),
+                    Some("end") => state.progress.remove(&token),
+                    _ => false,
+                };
+            }
+            _ => {}
+        }
+        self.changes.send_replace(());
+    }
+
+    /// Counts a request as in flight until the returned guard is dropped.
+    pub(crate) fn request_started(&self) -> InFlight<'_> {
+        self.state().in_flight += 1;
+        InFlight { activity: self }
+    }
+
+    fn is_busy(&self) -> bool {
+        let state = self.state();
+        !state.progress.is_empty() || state.in_flight > 0
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A request waiting for its response.
+pub(crate) struct InFlight<'a> {
+    activity: &'a Activity,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.activity.state().in_flight -= 1;
+        self.activity.changes.send_replace(());
+    }
+}
+
+impl LspClient {
+    /// Waits until the server looks done: no work-done progress under way,
+    /// no response outstanding, and nothing received for `settle`. A server
+    /// that never reports progress is judged on the quiet period alone.
+    /// Wrap the call in a timeout, since a busy server may never settle.
+    pub async fn wait_for_idle(&self, settle: Duration) -> Result<()> {
+        let activity = self.activity();
+        let mut changes = activity.changes.subscribe();
+        loop {
+            changes.borrow_and_update();
+            let changed = if activity.is_busy() {
+                changes.changed().await
+            } else {
+                match tokio::time::timeout(settle, changes.changed()).await {
+                    Ok(changed) => changed,
+                    Err(_) => return Ok(()),
+                }
+            };
+            changed.map_err(|_| anyhow!("Connection to the server was closed"))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::RequestMessage;
+    use crate::transport::FrameReader;
+    use serde_json::json;
+    use std::time::Instant;
+    use tokio::io::AsyncWriteExt;
+
+    fn frame(message: Value) -> Vec<u8> {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_idle() {
+        let (client_side, server_side) = tokio::io::duplex(1 << 16);
+        let client = LspClient::from_stream(client_side);
+        let (reader, mut writer) = tokio::io::split(server_side);
+        let progress = |kind: &str| {
+            frame(json!({
+                "jsonrpc": "2.0",
+                "method": "$/progress",
+                "params": { "token": 1, "value": { "kind": kind } }
+            }))
+        };
+
+        writer.write_all(&progress("begin")).await.unwrap();
+        let request = client.request(
+            RequestMessage::builder()
+                .id(9)
+                .method("workspace/executeCommand")
+                .build()
+                .unwrap(),
+        );
+        let server = async {
+            let mut reader = FrameReader::new(reader);
+            reader.read_frame().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            writer.write_all(&progress("end")).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let response = json!({ "jsonrpc": "2.0", "id": 9, "result": null });
+            writer.write_all(&frame(response)).await.unwrap();
+            Instant::now()
+        };
+
+        let started = Instant::now();
+        let settle = Duration::from_millis(20);
+        let (response, answered, idle) = tokio::join!(request, server, async {
+            // Let the request go out and the progress begin first.
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            client.wait_for_idle(settle).await.unwrap();
+            Instant::now()
+        });
+        response.unwrap();
+        assert!(idle >= answered + settle);
+        assert!(idle - started < Duration::from_secs(2));
+    }
+}
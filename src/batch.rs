@@ -0,0 +1,200 @@
+//! Headless analysis, as in running a linter in CI through a language
+//! server: start the server on a workspace, open the files, wait until it
+//! stops publishing diagnostics and collect them.
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use lsp_client_rs::batch::{self, BatchOptions};
+//! use lsp_client_rs::registry::ServerConfig;
+//! let report = batch::run(
+//!     &ServerConfig::new("gopls"),
+//!     "/src/app".as_ref(),
+//!     ["/src/app/main.go"],
+//!     &BatchOptions::default(),
+//! )
+//! .await?;
+//! for (uri, diagnostics) in &report.diagnostics {
+//!     println!("{}: {} problems", uri, diagnostics.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::LspClient;
+use crate::diagnostics::DiagnosticsStore;
+use crate::notifications::PublishDiagnostics;
+use crate::protocol::Diagnostic;
+use crate::registry::ServerConfig;
+use crate::workspace::path_to_uri;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// When the server counts as done. See `LspClient::wait_for_idle`.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// How long the server must stay quiet, with no progress under way,
+    /// before its diagnostics are taken as final.
+    pub settle: Duration,
+    /// How long to wait for the server to settle at most.
+    pub timeout: Duration,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            settle: Duration::from_millis(500),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// The diagnostics of every document the server reported on.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchReport {
+    /// Diagnostics by document URI. Every file that was opened is listed,
+    /// with no diagnostics if the server found nothing wrong.
+    pub diagnostics: BTreeMap<String, Vec<Diagnostic>>,
+    /// Whether the server was still busy when the timeout elapsed, in which
+    /// case the diagnostics may be incomplete.
+    pub timed_out: bool,
+}
+
+/// Starts the server of `config` on the workspace at `root`, analyzes
+/// `files` and shuts the server down.
+pub async fn run<I>(
+    config: &ServerConfig,
+    root: &Path,
+    files: I,
+    options: &BatchOptions,
+) -> Result<BatchReport>
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    let supervisor = config.supervisor(&path_to_uri(root)).start().await?;
+    let report = collect_diagnostics(&supervisor.client(), files, options).await;
+    supervisor.shutdown().await?;
+    report
+}
+
+/// Opens `files` on an initialized server and collects the diagnostics it
+/// publishes until it settles.
+pub async fn collect_diagnostics<I>(
+    client: &LspClient,
+    files: I,
+    options: &BatchOptions,
+) -> Result<BatchReport>
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    let mut published = client.subscribe::<PublishDiagnostics>();
+    let uris = client.open_documents(files).await?;
+
+    let mut store = DiagnosticsStore::new();
+    let idle = tokio::time::timeout(options.timeout, client.wait_for_idle(options.settle));
+    tokio::pin!(idle);
+    let timed_out = loop {
+        tokio::select! {
+            idle = &mut idle => match idle {
+                Ok(idle) => {
+                    idle?;
+                    break false;
+                }
+                Err(_) => break true,
+            },
+            params = published.recv() => {
+                store.publish(params?);
+            }
+        }
+    };
+
+    let mut diagnostics: BTreeMap<_, _> = store
+        .iter()
+        .map(|(uri, document)| (uri.clone(), document.diagnostics.clone()))
+        .collect();
+    for uri in uris {
+        diagnostics.entry(uri).or_default();
+    }
+    Ok(BatchReport {
+        diagnostics,
+        timed_out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::FrameReader;
+    use serde_json::json;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_collect_diagnostics_waits_for_progress() {
+        let root = std::env::temp_dir().join(format!("lsp-client-rs-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let (main, util) = (root.join("main.go"), root.join("util.go"));
+        std::fs::write(&main, "package main").unwrap();
+        std::fs::write(&util, "package main").unwrap();
+        let main_uri = path_to_uri(&main);
+
+        let (client_side, server_side) = tokio::io::duplex(1 << 16);
+        let client = LspClient::from_stream(client_side);
+        let (reader, mut writer) = tokio::io::split(server_side);
+        let uri = main_uri.clone();
+        let server = tokio::spawn(async move {
+            let mut reader = FrameReader::new(reader);
+            reader.read_frame().await.unwrap();
+            reader.read_frame().await.unwrap();
+            let progress = |kind: &str| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "$/progress",
+                    "params": { "token": "index", "value": { "kind": kind } }
+                })
+            };
+            let diagnostics = json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": {
+                    "uri": uri,
+                    "diagnostics": [{
+                        "range": {
+                            "start": { "line": 0, "character": 0 },
+                            "end": { "line": 0, "character": 7 }
+                        },
+                        "message": "unused package"
+                    }]
+                }
+            });
+            writer.write_all(&frame(progress("begin"))).await.unwrap();
+            // Quiet for longer than the settle period, but still indexing.
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            writer.write_all(&frame(diagnostics)).await.unwrap();
+            writer.write_all(&frame(progress("end"))).await.unwrap();
+            writer
+        });
+
+        let options = BatchOptions {
+            settle: Duration::from_millis(50),
+            timeout: Duration::from_secs(5),
+        };
+        let report = collect_diagnostics(&client, [&main, &util], &options)
+            .await
+            .unwrap();
+        assert!(!report.timed_out);
+        assert_eq!(report.diagnostics.len(), 2);
+        assert_eq!(report.diagnostics[&main_uri][0].message, "unused package");
+        assert!(report.diagnostics[&path_to_uri(&util)].is_empty());
+
+        drop(server.await.unwrap());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn frame(message: serde_json::Value) -> Vec<u8> {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+    }
+}
@@ -0,0 +1,592 @@
+//! Several consumers sharing one server connection, so that editor windows
+//! or tools working on the same workspace don't each start a heavyweight
+//! server of their own.
+//!
+//! Each consumer gets a `BrokerSession`. The ids of its requests are
+//! prefixed with the session's id before they reach the server, so sessions
+//! can't collide, and the responses get their original ids back. A document
+//! belongs to the session that opened it: only that session may change or
+//! close it. Notifications from the server go to every session.
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::broker::Broker;
+//! let broker = Broker::new(client);
+//! // Another editor window, connected over a local socket.
+//! let listener = tokio::net::UnixListener::bind("/tmp/gopls.sock")?;
+//! loop {
+//!     let (stream, _) = listener.accept().await?;
+//!     let broker = broker.clone();
+//!     tokio::spawn(async move { broker.serve(stream).await });
+//! }
+//! # }
+//! ```
+
+use crate::client::LspClient;
+use crate::event_bus::{EventFilter, EventSubscription};
+use crate::methods;
+use crate::protocol::{
+    intern_method, BaseMessage, NotificationMessage, RequestMessage, ResponseMessage,
+    TextDocumentContentChangeEvent, INVALID_REQUEST, REQUEST_CANCELLED,
+};
+use crate::transport::{FrameReader, FrameWriter};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::oneshot;
+
+/// Shares one `LspClient` between sessions.
+#[derive(Clone)]
+pub struct Broker {
+    client: LspClient,
+    state: Arc<Mutex<BrokerState>>,
+}
+
+#[derive(Default)]
+struct BrokerState {
+    next_session: u32,
+    /// The session that opened each open document.
+    owners: HashMap<String, u32>,
+}
+
+impl Broker {
+    /// A broker for `client`, which should already be initialized. The
+    /// broker never shuts the server down; its owner does.
+    pub fn new(client: LspClient) -> Self {
+        Broker {
+            client,
+            state: Arc::default(),
+        }
+    }
+
+    pub fn client(&self) -> &LspClient {
+        &self.client
+    }
+
+    /// A new session, with an id of its own.
+    pub fn connect(&self) -> BrokerSession {
+        let mut state = self.state();
+        state.next_session += 1;
+        BrokerSession {
+            id: state.next_session,
+            broker: self.clone(),
+        }
+    }
+
+    /// The id of the session that opened `uri`.
+    pub fn owner(&self, uri: &str) -> Option<u32> {
+        self.state().owners.get(uri).copied()
+    }
+
+    /// Serves a consumer speaking LSP over `stream`, e.g. an editor window
+    /// connected to a local socket, as a session of its own. Its
+    /// `initialize` is answered with the server's capabilities, and its
+    /// `shutdown` and `exit` end the session but not the server. Returns
+    /// once the consumer exits or disconnects, after closing its documents.
+    pub async fn serve<S>(&self, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let session = Arc::new(self.connect());
+        let (read_half, write_half) = tokio::io::split(stream);
+        let mut reader = FrameReader::new(read_half);
+        let writer = Arc::new(tokio::sync::Mutex::new(FrameWriter::new(write_half)));
+        let mut notifications = session.notifications();
+        // Requests in flight, by the consumer's id, to cancel them.
+        let cancels: Arc<Mutex<HashMap<String, oneshot::Sender<()>>>> = Arc::default();
+
+        // Documents are closed however serving ends, e.g. on a failed write
+        // to a consumer that went away.
+        let served: Result<()> = async {
+            loop {
+                let message: Value = tokio::select! {
+                    frame = reader.read_frame() => match frame {
+                        Ok(frame) => match serde_json::from_slice(&frame) {
+                            Ok(message) => message,
+                            Err(_) => continue,
+                        },
+                        Err(_) => break,
+                    },
+                    notification = notifications.recv() => {
+                        let Ok(notification) = notification else {
+                            break;
+                        };
+                        let body = notification.as_value().to_string();
+                        writer.lock().await.write_frame(body.as_bytes()).await?;
+                        continue;
+                    }
+                };
+                let Some(method) = message.get("method").and_then(Value::as_str) else {
+                    // The client answers the server's requests itself.
+                    continue;
+                };
+                let params = message.get("params").cloned().unwrap_or(Value::Null);
+                let Some(id) = message.get("id").filter(|id| !id.is_null()).cloned() else {
+                    match method {
+                        methods::EXIT => break,
+                        methods::INITIALIZED => {}
+                        methods::CANCEL_REQUEST => {
+                            let key = params.get("id").map(Value::to_string).unwrap_or_default();
+                            if let Some(cancel) = lock(&cancels).remove(&key) {
+                                let _ = cancel.send(());
+                            }
+                        }
+                        _ => {
+                            if let Err(e) = session.notify(method, params).await {
+                                // Tell the consumer, since notifications have no response.
+                                let message = NotificationMessage {
+                                    base_message: BaseMessage::new(),
+                                    method: intern_method(methods::WINDOW_LOG_MESSAGE),
+                                    params: json!({ "type": 1, "message": e.to_string() }),
+                                };
+                                let body = serde_json::to_vec(&message)?;
+                                writer.lock().await.write_frame(&body).await?;
+                            }
+                        }
+                    }
+                    continue;
+                };
+
+                let response = match method {
+                    methods::INITIALIZE => Some(ResponseMessage::new_result(
+                        id.clone(),
+                        json!({
+                            "capabilities": self.client.server_capabilities(),
+                            "serverInfo": self.client.server_info(),
+                        }),
+                    )),
+                    methods::SHUTDOWN => Some(ResponseMessage::new_result(id.clone(), Value::Null)),
+                    _ => None,
+                };
+                if let Some(response) = response {
+                    let body = serde_json::to_vec(&response)?;
+                    writer.lock().await.write_frame(&body).await?;
+                    continue;
+                }
+
+                let (cancel, cancelled) = oneshot::channel();
+                lock(&cancels).insert(id.to_string(), cancel);
+                let request = RequestMessage {
+                    base_message: BaseMessage::new(),
+                    id: id.clone(),
+                    method: intern_method(method),
+                    notification: 0,
+                    params,
+                };
+                let (session, writer, cancels) = (session.clone(), writer.clone(), cancels.clone());
+                tokio::spawn(async move {
+                    let response = tokio::select! {
+                        response = session.request(request) => response,
+                        // Dropping the request cancels it on the server.
+                        _ = cancelled => Ok(ResponseMessage::new_error(
+                            id.clone(),
+                            REQUEST_CANCELLED,
+                            "Request cancelled".to_string(),
+                        )),
+                    };
+                    lock(&cancels).remove(&id.to_string());
+                    let response = response.unwrap_or_else(|e| {
+                        ResponseMessage::new_error(id, INVALID_REQUEST, e.to_string())
+                    });
+                    if let Ok(body) = serde_json::to_vec(&response) {
+                        let _ = writer.lock().await.write_frame(&body).await;
+                    }
+                });
+            }
+            Ok(())
+        }
+        .await;
+        let closed = session.close().await;
+        served.and(closed)
+    }
+
+    fn state(&self) -> MutexGuard<'_, BrokerState> {
+        lock(&self.state)
+    }
+}
+
+/// One consumer of a `Broker`.
+pub struct BrokerSession {
+    id: u32,
+    broker: Broker,
+}
+
+impl BrokerSession {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Sends `request` to the server under an id unique to this session,
+    /// and returns the response with the request's own id.
+    pub async fn request(&self, mut request: RequestMessage) -> Result<ResponseMessage> {
+        if matches!(
+            request.method.as_ref(),
+            methods::INITIALIZE | methods::SHUTDOWN | methods::EXIT
+        ) {
+            bail!("{} is up to the owner of the broker", request.method);
+        }
+        let id = std::mem::replace(&mut request.id, Value::Null);
+        // String ids without their JSON quotes.
+        request.id = Value::String(match &id {
+            Value::String(id) => format!("{}:{}", self.id, id),
+            id => format!("{}:{}", self.id, id),
+        });
+        let mut response = self.broker.client.request(request).await?;
+        response.id = Some(id);
+        // A `null` result parsed as `None`, which wouldn't be written back.
+        if response.error.is_none() {
+            response.result.get_or_insert(Value::Null);
+        }
+        Ok(response)
+    }
+
+    /// Opens `uri` as a document of this session. Fails if another session
+    /// opened it.
+    pub async fn did_open(&self, uri: String, language_id: String, text: String) -> Result<()> {
+        {
+            let mut state = self.broker.state();
+            if let Some(owner) = state.owners.get(&uri) {
+                bail!("Document {} is already open in session {}", uri, owner);
+            }
+            state.owners.insert(uri.clone(), self.id);
+        }
+        let opened = self
+            .broker
+            .client
+            .did_open(uri.clone(), language_id, text)
+            .await;
+        if opened.is_err() {
+            self.broker.state().owners.remove(&uri);
+        }
+        opened
+    }
+
+    /// Changes a document this session opened.
+    pub async fn did_change(
+        &self,
+        uri: &str,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Result<i32> {
+        self.check_owner(uri)?;
+        self.broker.client.did_change(uri, changes).await
+    }
+
+    /// Closes a document this session opened.
+    pub async fn did_close(&self, uri: &str) -> Result<()> {
+        self.check_owner(uri)?;
+        self.broker.client.did_close(uri).await?;
+        self.broker.state().owners.remove(uri);
+        Ok(())
+    }
+
+    /// The documents this session opened.
+    pub fn documents(&self) -> Vec<String> {
+        let state = self.broker.state();
+        let mut uris: Vec<String> = state
+            .owners
+            .iter()
+            .filter(|(_, owner)| **owner == self.id)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        uris.sort();
+        uris
+    }
+
+    /// The notifications the server sends from now on. Every session gets
+    /// all of them.
+    pub fn notifications(&self) -> EventSubscription {
+        self.broker
+            .client
+            .subscribe_incoming(EventFilter::new().matching(|message| message.is_notification()))
+    }
+
+    /// Ends the session, closing the documents it opened.
+    pub async fn close(&self) -> Result<()> {
+        for uri in self.documents() {
+            self.did_close(&uri).await?;
+        }
+        Ok(())
+    }
+
+    /// Forwards a notification from a consumer, keeping track of the
+    /// documents it opens and closes.
+    async fn notify(&self, method: &str, params: Value) -> Result<()> {
+        match method {
+            methods::TEXT_DOCUMENT_DID_OPEN => {
+                let document = &params["textDocument"];
+                self.did_open(
+                    param(&document["uri"])?,
+                    param(&document["languageId"])?,
+                    param(&document["text"])?,
+                )
+                .await
+            }
+            methods::TEXT_DOCUMENT_DID_CHANGE => {
+                let uri: String = param(&params["textDocument"]["uri"])?;
+                let changes = param(&params["contentChanges"])?;
+                self.did_change(&uri, changes).await.map(|_| ())
+            }
+            methods::TEXT_DOCUMENT_DID_CLOSE => {
+                self.did_close(&param::<String>(&params["textDocument"]["uri"])?)
+                    .await
+            }
+            _ => {
+                let notification = NotificationMessage {
+                    base_message: BaseMessage::new(),
+                    method: intern_method(method),
+                    params,
+                };
+                self.broker.client.send_request(notification).await
+            }
+        }
+    }
+
+    fn check_owner(&self, uri: &str) -> Result<()> {
+        match self.broker.owner(uri) {
+            Some(owner) if owner == self.id => Ok(()),
+            Some(owner) => Err(anyhow!("Document {} belongs to session {}", uri, owner)),
+            None => Err(anyhow!("Document {} is not open", uri)),
+        }
+    }
+}
+
+/// Releases the documents of a session dropped without `close`, e.g. when
+/// the task serving it was aborted, closing them in the background.
+impl Drop for BrokerSession {
+    fn drop(&mut self) {
+        let uris = self.documents();
+        if uris.is_empty() {
+            return;
+        }
+        let (broker, id) = (self.broker.clone(), self.id);
+        let release = move |uri: &str| {
+            let mut state = broker.state();
+            if state.owners.get(uri) == Some(&id) {
+                state.owners.remove(uri);
+            }
+        };
+        // Without a runtime there is nobody to send `didClose`.
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            uris.iter().for_each(|uri| release(uri));
+            return;
+        };
+        let client = self.broker.client.clone();
+        runtime.spawn(async move {
+            for uri in uris {
+                let _ = client.did_close(&uri).await;
+                release(&uri);
+            }
+        });
+    }
+}
+
+fn param<T: DeserializeOwned>(value: &Value) -> Result<T> {
+    serde_json::from_value(value.clone()).context("Invalid params")
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn read(reader: &mut FrameReader<impl AsyncRead + Unpin>) -> Value {
+        serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap()
+    }
+
+    async fn write(writer: &mut FrameWriter<impl AsyncWrite + Unpin>, message: Value) {
+        writer
+            .write_frame(message.to_string().as_bytes())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_broker_sessions() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let broker = Broker::new(LspClient::from_stream(client_end));
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut server_reader = FrameReader::new(read_half);
+        let mut server_writer = FrameWriter::new(write_half);
+        let (first, second) = (broker.connect(), broker.connect());
+        let mut second_notifications = second.notifications();
+
+        // Both sessions use id 1; the server sees two different ids.
+        let hover = |uri: &str| {
+            let mut request =
+                RequestMessage::new_hover(1, uri.to_string(), crate::protocol::Position::new(0, 0));
+            request.id = json!(1);
+            request
+        };
+        let (first_response, second_response, _) = tokio::join!(
+            first.request(hover("file:///a.go")),
+            second.request(hover("file:///b.go")),
+            async {
+                let mut ids = Vec::new();
+                for _ in 0..2 {
+                    let request = read(&mut server_reader).await;
+                    ids.push(request["id"].clone());
+                    let result = json!({ "contents": request["params"]["textDocument"]["uri"] });
+                    let response =
+                        json!({ "jsonrpc": "2.0", "id": request["id"], "result": result });
+                    write(&mut server_writer, response).await;
+                }
+                ids.sort_by_key(Value::to_string);
+                assert_eq!(ids, [json!("1:1"), json!("2:1")]);
+            }
+        );
+        let (first_response, second_response) = (first_response.unwrap(), second_response.unwrap());
+        assert_eq!(first_response.id, Some(json!(1)));
+        assert_eq!(first_response.result.unwrap()["contents"], "file:///a.go");
+        assert_eq!(second_response.result.unwrap()["contents"], "file:///b.go");
+
+        // Documents belong to the session that opened them.
+        let uri = "file:///a.go".to_string();
+        first
+            .did_open(uri.clone(), "go".to_string(), "package a".to_string())
+            .await
+            .unwrap();
+        read(&mut server_reader).await;
+        assert!(second
+            .did_open(uri.clone(), "go".to_string(), String::new())
+            .await
+            .is_err());
+        assert!(second.did_close(&uri).await.is_err());
+        assert_eq!(broker.owner(&uri), Some(first.id()));
+
+        // Notifications reach every session.
+        let diagnostics = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": [] }
+        });
+        write(&mut server_writer, diagnostics).await;
+        let notification = second_notifications.recv().await.unwrap();
+        assert_eq!(notification.uri(), Some(uri.as_str()));
+
+        first.close().await.unwrap();
+        assert_eq!(
+            read(&mut server_reader).await["method"],
+            "textDocument/didClose"
+        );
+        assert_eq!(broker.owner(&uri), None);
+    }
+
+    #[tokio::test]
+    async fn test_broker_serve() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let broker = Broker::new(LspClient::from_stream(client_end));
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut server_reader = FrameReader::new(read_half);
+        let mut server_writer = FrameWriter::new(write_half);
+
+        let (consumer_end, broker_end) = tokio::io::duplex(4096);
+        let serving = tokio::spawn({
+            let broker = broker.clone();
+            async move { broker.serve(broker_end).await }
+        });
+        let (read_half, write_half) = tokio::io::split(consumer_end);
+        let mut consumer_reader = FrameReader::new(read_half);
+        let mut consumer_writer = FrameWriter::new(write_half);
+
+        let initialize = json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {} });
+        write(&mut consumer_writer, initialize).await;
+        assert_eq!(read(&mut consumer_reader).await["id"], 0);
+
+        let open = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": "file:///a.go",
+                    "languageId": "go",
+                    "version": 0,
+                    "text": "package a"
+                }
+            }
+        });
+        write(&mut consumer_writer, open).await;
+        assert_eq!(
+            read(&mut server_reader).await["method"],
+            "textDocument/didOpen"
+        );
+
+        let symbols = json!({
+            "jsonrpc": "2.0",
+            "id": "symbols",
+            "method": "textDocument/documentSymbol",
+            "params": { "textDocument": { "uri": "file:///a.go" } }
+        });
+        write(&mut consumer_writer, symbols).await;
+        let request = read(&mut server_reader).await;
+        assert_eq!(request["id"], "1:symbols");
+        let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": [] });
+        write(&mut server_writer, response).await;
+        let response = read(&mut consumer_reader).await;
+        assert_eq!(response["id"], "symbols");
+        assert_eq!(response["result"], json!([]));
+
+        // Exiting closes the consumer's documents, not the server.
+        write(
+            &mut consumer_writer,
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        )
+        .await;
+        assert_eq!(
+            read(&mut server_reader).await["method"],
+            "textDocument/didClose"
+        );
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_broker_consumer_disconnects() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let broker = Broker::new(LspClient::from_stream(client_end));
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut server_reader = FrameReader::new(read_half);
+        let mut server_writer = FrameWriter::new(write_half);
+
+        let (consumer_end, broker_end) = tokio::io::duplex(4096);
+        let serving = tokio::spawn({
+            let broker = broker.clone();
+            async move { broker.serve(broker_end).await }
+        });
+        let mut consumer_writer = FrameWriter::new(consumer_end);
+        let open = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": "file:///a.go",
+                    "languageId": "go",
+                    "version": 0,
+                    "text": "package a"
+                }
+            }
+        });
+        write(&mut consumer_writer, open).await;
+        read(&mut server_reader).await;
+
+        // The consumer goes away while the server has something for it.
+        drop(consumer_writer);
+        let progress = json!({
+            "jsonrpc": "2.0",
+            "method": "$/progress",
+            "params": { "token": 1, "value": { "kind": "end" } }
+        });
+        write(&mut server_writer, progress).await;
+        assert_eq!(
+            read(&mut server_reader).await["method"],
+            "textDocument/didClose"
+        );
+        let _ = serving.await.unwrap();
+        assert_eq!(broker.owner("file:///a.go"), None);
+    }
+}
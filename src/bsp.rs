@@ -0,0 +1,286 @@
+//! Core messages of the Build Server Protocol: initializing a build server,
+//! listing its build targets and compiling them. BSP is JSON-RPC framed like
+//! LSP, so `LspClient` carries these messages unchanged. Enabled by the `bsp`
+//! feature.
+
+use crate::protocol::{BaseMessage, NotificationMessage, RequestMessage};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+pub const BUILD_INITIALIZE: &str = "build/initialize";
+pub const BUILD_INITIALIZED: &str = "build/initialized";
+pub const BUILD_SHUTDOWN: &str = "build/shutdown";
+pub const BUILD_EXIT: &str = "build/exit";
+pub const WORKSPACE_BUILD_TARGETS: &str = "workspace/buildTargets";
+pub const BUILD_TARGET_COMPILE: &str = "buildTarget/compile";
+
+/// The BSP version these types follow.
+pub const BSP_VERSION: &str = "2.1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeBuildParams {
+    pub display_name: String,
+    pub version: String,
+    pub bsp_version: String,
+    pub root_uri: String,
+    pub capabilities: BuildClientCapabilities,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildClientCapabilities {
+    /// The languages the client supports, e.g. `scala` or `rust`.
+    pub language_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeBuildResult {
+    pub display_name: String,
+    pub version: String,
+    pub bsp_version: String,
+    pub capabilities: BuildServerCapabilities,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildServerCapabilities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compile_provider: Option<LanguageProvider>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_provider: Option<LanguageProvider>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_provider: Option<LanguageProvider>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_provider: Option<LanguageProvider>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LanguageProvider {
+    pub language_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BuildTargetIdentifier {
+    pub uri: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildTarget {
+    pub id: BuildTargetIdentifier,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_directory: Option<String>,
+    /// E.g. `library`, `application` or `test`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub language_ids: Vec<String>,
+    #[serde(default)]
+    pub dependencies: Vec<BuildTargetIdentifier>,
+    pub capabilities: BuildTargetCapabilities,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildTargetCapabilities {
+    #[serde(default)]
+    pub can_compile: bool,
+    #[serde(default)]
+    pub can_test: bool,
+    #[serde(default)]
+    pub can_run: bool,
+    #[serde(default)]
+    pub can_debug: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceBuildTargetsResult {
+    pub targets: Vec<BuildTarget>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompileParams {
+    pub targets: Vec<BuildTargetIdentifier>,
+    /// Attached to the notifications the server sends while compiling.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompileResult {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin_id: Option<String>,
+    pub status_code: StatusCode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// How a compilation ended.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum StatusCode {
+    Ok,
+    Error,
+    Cancelled,
+}
+
+impl TryFrom<u8> for StatusCode {
+    type Error = String;
+
+    fn try_from(code: u8) -> Result<Self, String> {
+        match code {
+            1 => Ok(StatusCode::Ok),
+            2 => Ok(StatusCode::Error),
+            3 => Ok(StatusCode::Cancelled),
+            _ => Err(format!("Unknown status code {}", code)),
+        }
+    }
+}
+
+impl From<StatusCode> for u8 {
+    fn from(code: StatusCode) -> u8 {
+        match code {
+            StatusCode::Ok => 1,
+            StatusCode::Error => 2,
+            StatusCode::Cancelled => 3,
+        }
+    }
+}
+
+/// A `build/initialize` request.
+pub fn initialize_request(id: u32, params: &InitializeBuildParams) -> Result<RequestMessage> {
+    RequestMessage::builder()
+        .id(id)
+        .method(BUILD_INITIALIZE)
+        .params(params)
+        .build()
+}
+
+/// The `build/initialized` notification, sent once the client processed the
+/// `build/initialize` response.
+pub fn initialized_notification() -> NotificationMessage {
+    notification(BUILD_INITIALIZED)
+}
+
+/// A `workspace/buildTargets` request.
+pub fn build_targets_request(id: u32) -> Result<RequestMessage> {
+    RequestMessage::builder()
+        .id(id)
+        .method(WORKSPACE_BUILD_TARGETS)
+        .build()
+}
+
+/// A `buildTarget/compile` request.
+pub fn compile_request(id: u32, params: &CompileParams) -> Result<RequestMessage> {
+    RequestMessage::builder()
+        .id(id)
+        .method(BUILD_TARGET_COMPILE)
+        .params(params)
+        .build()
+}
+
+/// A `build/shutdown` request. Send `build/exit` once it is answered.
+pub fn shutdown_request(id: u32) -> Result<RequestMessage> {
+    RequestMessage::builder()
+        .id(id)
+        .method(BUILD_SHUTDOWN)
+        .build()
+}
+
+/// The `build/exit` notification, which asks the server process to exit.
+pub fn exit_notification() -> NotificationMessage {
+    notification(BUILD_EXIT)
+}
+
+fn notification(method: &'static str) -> NotificationMessage {
+    NotificationMessage {
+        base_message: BaseMessage::new(),
+        method: Cow::Borrowed(method),
+        params: serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bsp_messages() {
+        let params = InitializeBuildParams {
+            display_name: "lsp-client-rs".to_string(),
+            version: "0.1.0".to_string(),
+            bsp_version: BSP_VERSION.to_string(),
+            root_uri: "file:///code/app".to_string(),
+            capabilities: BuildClientCapabilities {
+                language_ids: vec!["scala".to_string()],
+            },
+            data: None,
+        };
+        let request = initialize_request(1, &params).unwrap();
+        assert_eq!(request.method, BUILD_INITIALIZE);
+        assert_eq!(
+            request.params,
+            json!({
+                "displayName": "lsp-client-rs",
+                "version": "0.1.0",
+                "bspVersion": "2.1.0",
+                "rootUri": "file:///code/app",
+                "capabilities": { "languageIds": ["scala"] }
+            })
+        );
+
+        let targets: WorkspaceBuildTargetsResult = serde_json::from_value(json!({
+            "targets": [{
+                "id": { "uri": "file:///code/app/?id=app" },
+                "displayName": "app",
+                "tags": ["application"],
+                "languageIds": ["scala"],
+                "dependencies": [{ "uri": "file:///code/app/?id=core" }],
+                "capabilities": { "canCompile": true, "canTest": false, "canRun": true, "canDebug": true }
+            }]
+        }))
+        .unwrap();
+        let target = &targets.targets[0];
+        assert_eq!(target.display_name.as_deref(), Some("app"));
+        assert!(target.capabilities.can_compile && !target.capabilities.can_test);
+
+        let compile = compile_request(
+            2,
+            &CompileParams {
+                targets: vec![target.id.clone()],
+                origin_id: Some("build-1".to_string()),
+                arguments: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            compile.params,
+            json!({ "targets": [{ "uri": "file:///code/app/?id=app" }], "originId": "build-1" })
+        );
+        let result: CompileResult =
+            serde_json::from_value(json!({ "originId": "build-1", "statusCode": 2 })).unwrap();
+        assert_eq!(result.status_code, StatusCode::Error);
+        assert!(serde_json::from_value::<CompileResult>(json!({ "statusCode": 7 })).is_err());
+    }
+}
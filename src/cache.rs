@@ -0,0 +1,312 @@
+//! A cache on disk of the results of requests that only depend on the text
+//! of a document, such as `textDocument/documentSymbol`, so that analysing
+//! the same files again, or reopening them in a new session, doesn't wait
+//! for the server:
+//!
+//! ```no_run
+//! # fn example(client: lsp_client_rs::client::LspClient) {
+//! use lsp_client_rs::cache::ResultCache;
+//! client.set_result_cache(Some(ResultCache::new("/home/me/.cache/my-editor/lsp")));
+//! # }
+//! ```
+//!
+//! Results are kept per document and per server name and version, along
+//! with a hash of the text they were computed for, and are only used for the
+//! same text. Within a document they are keyed by method and params, so that
+//! e.g. hovers at two positions don't share a result. Changing a document
+//! drops its results.
+
+use crate::methods;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResultCache {
+    dir: PathBuf,
+    methods: BTreeSet<String>,
+}
+
+/// The cached results of one document for one server.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    uri: String,
+    content_hash: String,
+    results: Map<String, Value>,
+}
+
+impl ResultCache {
+    /// A cache in `dir` of `textDocument/documentSymbol` and, with
+    /// `lsp-3-16`, `textDocument/semanticTokens/full` results.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let cache = ResultCache {
+            dir: dir.into(),
+            methods: BTreeSet::new(),
+        }
+        .method(methods::TEXT_DOCUMENT_DOCUMENT_SYMBOL);
+        #[cfg(feature = "lsp-3-16")]
+        let cache = cache.method(methods::TEXT_DOCUMENT_SEMANTIC_TOKENS_FULL);
+        cache
+    }
+
+    /// Caches the results of `method` too. Its result must only depend on
+    /// the text of the document it is sent for and on its params.
+    pub fn method(mut self, method: impl Into<String>) -> Self {
+        self.methods.insert(method.into());
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether the results of `method` are cached.
+    pub fn caches(&self, method: &str) -> bool {
+        self.methods.contains(method)
+    }
+
+    /// The result of `method` with `params` for `uri` from `server`, if it
+    /// was cached for the same `text`.
+    pub async fn get(
+        &self,
+        server: &str,
+        method: &str,
+        params: &Value,
+        uri: &str,
+        text: &str,
+    ) -> Option<Value> {
+        let entry = self.read(server, uri).await.ok()??;
+        if entry.content_hash != hash(text.as_bytes()) {
+            return None;
+        }
+        entry.results.get(&result_key(method, params)).cloned()
+    }
+
+    /// Caches `result` as the result of `method` with `params` for `uri`
+    /// with `text`.
+    pub async fn put(
+        &self,
+        server: &str,
+        method: &str,
+        params: &Value,
+        uri: &str,
+        text: &str,
+        result: &Value,
+    ) -> Result<()> {
+        let content_hash = hash(text.as_bytes());
+        let mut entry = match self.read(server, uri).await? {
+            Some(entry) if entry.content_hash == content_hash => entry,
+            _ => Entry {
+                uri: uri.to_string(),
+                content_hash,
+                results: Map::new(),
+            },
+        };
+        entry
+            .results
+            .insert(result_key(method, params), result.clone());
+
+        let path = self.entry_path(server, uri);
+        let dir = path.parent().expect("entries are in a directory");
+        tokio::fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        // Written next to the entry and renamed over it, so that a reader
+        // never sees half of it.
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        tokio::fs::write(&temporary, serde_json::to_vec(&entry)?)
+            .await
+            .with_context(|| format!("Failed to write {}", temporary.display()))?;
+        tokio::fs::rename(&temporary, &path)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Drops the results cached for `uri`, from every server.
+    pub async fn invalidate(&self, uri: &str) -> Result<()> {
+        let dir = self.dir.join(hash(uri.as_bytes()));
+        match tokio::fs::remove_dir_all(&dir).await {
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                Err(error).with_context(|| format!("Failed to remove {}", dir.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Drops every cached result.
+    pub async fn clear(&self) -> Result<()> {
+        match tokio::fs::remove_dir_all(&self.dir).await {
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                Err(error).with_context(|| format!("Failed to remove {}", self.dir.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn read(&self, server: &str, uri: &str) -> Result<Option<Entry>> {
+        let path = self.entry_path(server, uri);
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(error).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+        // A hash collision between two URIs reads as a miss.
+        Ok(serde_json::from_slice(&bytes)
+            .ok()
+            .filter(|entry: &Entry| entry.uri == uri))
+    }
+
+    fn entry_path(&self, server: &str, uri: &str) -> PathBuf {
+        self.dir
+            .join(hash(uri.as_bytes()))
+            .join(format!("{}.json", hash(server.as_bytes())))
+    }
+}
+
+/// The key of a result in its entry: the method and the params, without
+/// the progress tokens, which differ between requests for the same result.
+fn result_key(method: &str, params: &Value) -> String {
+    let mut params = params.clone();
+    if let Some(params) = params.as_object_mut() {
+        params.remove("workDoneToken");
+        params.remove("partialResultToken");
+    }
+    format!("{} {}", method, params)
+}
+
+/// The 64-bit FNV-1a hash of `bytes`, in hex. Unlike the standard library's
+/// hashers, it is the same across builds, which entries on disk rely on.
+fn hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::LspClient;
+    use crate::protocol::TextDocumentContentChangeEvent;
+    use crate::transport::{FrameReader, FrameWriter};
+    use serde_json::json;
+
+    /// Answers a `textDocument/documentSymbol` request.
+    async fn answer<R, W>(reader: &mut FrameReader<R>, writer: &mut FrameWriter<W>)
+    where
+        R: tokio::io::AsyncRead + Unpin,
+        W: tokio::io::AsyncWrite + Unpin,
+    {
+        let request: Value = serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+        assert_eq!(request["method"], "textDocument/documentSymbol");
+        let symbols = json!([{
+            "name": "main",
+            "kind": 4,
+            "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 12 } },
+            "selectionRange": { "start": { "line": 0, "character": 8 }, "end": { "line": 0, "character": 12 } }
+        }]);
+        let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": symbols });
+        writer
+            .write_frame(response.to_string().as_bytes())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_result_cache() {
+        let dir = std::env::temp_dir().join(format!("lsp-client-rs-cache-{}", std::process::id()));
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        client.set_result_cache(Some(ResultCache::new(&dir)));
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut reader = FrameReader::new(read_half);
+        let mut writer = FrameWriter::new(write_half);
+
+        let uri = "file:///app/main.go".to_string();
+        client
+            .did_open(uri.clone(), "go".to_string(), "package main".to_string())
+            .await
+            .unwrap();
+        reader.read_frame().await.unwrap();
+
+        let (first, _) = tokio::join!(
+            client.document_symbol(uri.clone()),
+            answer(&mut reader, &mut writer)
+        );
+        // Answered from the cache, without a request to the server.
+        let second = client.document_symbol(uri.clone()).await.unwrap();
+        assert_eq!(
+            serde_json::to_value(first.unwrap()).unwrap(),
+            serde_json::to_value(second).unwrap()
+        );
+
+        client
+            .did_change(
+                &uri,
+                vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    text: "package app".to_string(),
+                }],
+            )
+            .await
+            .unwrap();
+        reader.read_frame().await.unwrap();
+        assert!(!dir.join(hash(uri.as_bytes())).exists());
+        let (third, _) = tokio::join!(
+            client.document_symbol(uri.clone()),
+            answer(&mut reader, &mut writer)
+        );
+        third.unwrap();
+
+        ResultCache::new(&dir).clear().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_results_are_keyed_by_params() {
+        let dir =
+            std::env::temp_dir().join(format!("lsp-client-rs-cache-params-{}", std::process::id()));
+        let cache = ResultCache::new(&dir).method(methods::TEXT_DOCUMENT_HOVER);
+        let uri = "file:///app/main.go";
+        let hover = |line: u32| {
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": 0 }
+            })
+        };
+        let hover_result = json!({ "contents": "package main" });
+        cache
+            .put(
+                "gopls",
+                "textDocument/hover",
+                &hover(0),
+                uri,
+                "package main",
+                &hover_result,
+            )
+            .await
+            .unwrap();
+
+        let get = |params: Value| {
+            let cache = cache.clone();
+            async move {
+                cache
+                    .get("gopls", "textDocument/hover", &params, uri, "package main")
+                    .await
+            }
+        };
+        assert_eq!(get(hover(0)).await, Some(hover_result));
+        assert_eq!(get(hover(1)).await, None);
+        // Progress tokens don't change the result.
+        let mut with_token = hover(0);
+        with_token["workDoneToken"] = json!("hover-3");
+        assert!(get(with_token).await.is_some());
+
+        cache.clear().await.unwrap();
+    }
+}
@@ -0,0 +1,491 @@
+//! The capabilities a server reports in its `initialize` result.
+//!
+//! Providers the client acts on are typed, using `BoolOr` for the many
+//! `boolean | Options` fields. Everything else is kept as sent in
+//! `ServerCapabilities::other`.
+//!
+//! ```no_run
+//! # fn example(client: lsp_client_rs::client::LspClient) {
+//! use lsp_client_rs::capabilities::TextDocumentSyncKind;
+//! if let Some(capabilities) = client.server_capabilities() {
+//!     let incremental = capabilities.text_document_sync_kind() == TextDocumentSyncKind::Incremental;
+//!     let can_rename = capabilities.rename_provider.is_some_and(|rename| rename.is_enabled());
+//! }
+//! # }
+//! ```
+
+use crate::protocol::{BoolOr, OneOf};
+use serde::{Deserialize, Serialize};
+
+/// Options shared by every provider: whether the server reports progress.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkDoneProgressOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_done_progress: Option<bool>,
+}
+
+/// How the client sends document changes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum TextDocumentSyncKind {
+    /// Documents are not synced at all.
+    #[default]
+    None = 0,
+    /// The whole text is sent on every change.
+    Full = 1,
+    /// Only the changed ranges are sent.
+    Incremental = 2,
+}
+
+impl TryFrom<u8> for TextDocumentSyncKind {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            0 => Ok(TextDocumentSyncKind::None),
+            1 => Ok(TextDocumentSyncKind::Full),
+            2 => Ok(TextDocumentSyncKind::Incremental),
+            _ => Err(format!("Invalid text document sync kind: {}", value)),
+        }
+    }
+}
+
+impl From<TextDocumentSyncKind> for u8 {
+    fn from(kind: TextDocumentSyncKind) -> Self {
+        kind as u8
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentSyncOptions {
+    /// Whether `didOpen` and `didClose` are sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_close: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<TextDocumentSyncKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub will_save: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub will_save_wait_until: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub save: Option<BoolOr<SaveOptions>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveOptions {
+    /// Whether `didSave` should carry the text of the document.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_text: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeActionOptions {
+    /// The kinds of code actions the server may return.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_action_kinds: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolve_provider: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_done_progress: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameOptions {
+    /// Whether the server answers `textDocument/prepareRename`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prepare_provider: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_done_progress: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionOptions {
+    /// Characters that start a completion request when typed, besides
+    /// identifier characters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_characters: Option<Vec<String>>,
+    /// Characters that accept the selected completion when typed, and are
+    /// then inserted after it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all_commit_characters: Option<Vec<String>>,
+    /// Whether the server answers `completionItem/resolve`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolve_provider: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_item: Option<CompletionItemOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_done_progress: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionItemOptions {
+    /// Whether items may come with `labelDetails`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_details_support: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureHelpOptions {
+    /// Characters that start a signature help request when typed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_characters: Option<Vec<String>>,
+    /// Characters that request signature help again while it is showing,
+    /// in addition to the trigger characters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retrigger_characters: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_done_progress: Option<bool>,
+}
+
+/// Options of the providers whose results can be completed later with a
+/// `resolve` request: code lenses, document links and inlay hints.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolve_provider: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_done_progress: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecuteCommandOptions {
+    /// The commands `workspace/executeCommand` runs.
+    pub commands: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_done_progress: Option<bool>,
+}
+
+/// The token types and modifiers semantic tokens index into.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensLegend {
+    pub token_types: Vec<String>,
+    pub token_modifiers: Vec<String>,
+}
+
+impl SemanticTokensLegend {
+    /// The name of token type `index`.
+    pub fn token_type(&self, index: u32) -> Option<&str> {
+        self.token_types.get(index as usize).map(String::as_str)
+    }
+
+    /// The names of the modifiers set in the bit set `modifiers`.
+    pub fn token_modifiers(&self, modifiers: u32) -> impl Iterator<Item = &str> {
+        self.token_modifiers
+            .iter()
+            .enumerate()
+            .filter(move |(bit, _)| *bit < 32 && modifiers & (1 << bit) != 0)
+            .map(|(_, modifier)| modifier.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensFullOptions {
+    /// Whether the server answers `textDocument/semanticTokens/full/delta`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensOptions {
+    pub legend: SemanticTokensLegend,
+    /// Whether the server answers `textDocument/semanticTokens/range`. The
+    /// protocol defines no options for it yet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<BoolOr<serde_json::Map<String, serde_json::Value>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub full: Option<BoolOr<SemanticTokensFullOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_done_progress: Option<bool>,
+}
+
+impl SemanticTokensOptions {
+    pub fn supports_range(&self) -> bool {
+        self.range.as_ref().is_some_and(BoolOr::is_enabled)
+    }
+
+    pub fn supports_full(&self) -> bool {
+        self.full.as_ref().is_some_and(BoolOr::is_enabled)
+    }
+
+    pub fn supports_delta(&self) -> bool {
+        self.full
+            .as_ref()
+            .and_then(BoolOr::options)
+            .and_then(|full| full.delta)
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticOptions {
+    /// Tells the server's diagnostics apart from those of other sources.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    /// Whether editing one document can change the diagnostics of others,
+    /// so that they should be pulled again too.
+    pub inter_file_dependencies: bool,
+    /// Whether the server answers `workspace/diagnostic`.
+    pub workspace_diagnostics: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_done_progress: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentOnTypeFormattingOptions {
+    pub first_trigger_character: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub more_trigger_character: Option<Vec<String>>,
+}
+
+impl DocumentOnTypeFormattingOptions {
+    /// Whether typing `character` should send `textDocument/onTypeFormatting`.
+    pub fn should_trigger(&self, character: char) -> bool {
+        contains_char(
+            std::slice::from_ref(&self.first_trigger_character),
+            character,
+        ) || contains_char(
+            self.more_trigger_character.as_deref().unwrap_or_default(),
+            character,
+        )
+    }
+}
+
+/// The capabilities of a server. Fields left out by the server are `None`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerCapabilities {
+    /// The position encoding the server picked, `utf-16` if left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position_encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_document_sync: Option<OneOf<TextDocumentSyncOptions, TextDocumentSyncKind>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hover_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_provider: Option<CompletionOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature_help_provider: Option<SignatureHelpOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub declaration_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub definition_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_definition_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub implementation_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub references_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_highlight_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_symbol_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace_symbol_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_action_provider: Option<BoolOr<CodeActionOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_lens_provider: Option<ResolveOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_link_provider: Option<ResolveOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_formatting_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_range_formatting_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rename_provider: Option<BoolOr<RenameOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_on_type_formatting_provider: Option<DocumentOnTypeFormattingOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folding_range_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection_range_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_editing_range_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub call_hierarchy_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_hierarchy_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inlay_hint_provider: Option<BoolOr<ResolveOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inline_value_provider: Option<BoolOr<WorkDoneProgressOptions>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub semantic_tokens_provider: Option<SemanticTokensOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostic_provider: Option<DiagnosticOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execute_command_provider: Option<ExecuteCommandOptions>,
+    /// Whether the server converts line endings to `\n` itself. The client
+    /// then normalizes its copy of the documents too.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalizes_line_endings: Option<bool>,
+    /// The capabilities without a typed field, such as `workspace` or
+    /// `experimental`, as sent.
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ServerCapabilities {
+    /// How the server wants document changes, `None` if it didn't say.
+    pub fn text_document_sync_kind(&self) -> TextDocumentSyncKind {
+        match &self.text_document_sync {
+            Some(OneOf::Left(options)) => options.change.unwrap_or_default(),
+            Some(OneOf::Right(kind)) => *kind,
+            None => TextDocumentSyncKind::None,
+        }
+    }
+
+    /// The characters that start a completion request, none if the server
+    /// doesn't complete.
+    pub fn completion_trigger_characters(&self) -> &[String] {
+        self.completion_provider
+            .as_ref()
+            .and_then(|options| options.trigger_characters.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The characters that accept the selected completion. Items can add
+    /// their own with `commitCharacters`.
+    pub fn completion_commit_characters(&self) -> &[String] {
+        self.completion_provider
+            .as_ref()
+            .and_then(|options| options.all_commit_characters.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether typing `character` should start a completion request.
+    pub fn is_completion_trigger(&self, character: char) -> bool {
+        contains_char(self.completion_trigger_characters(), character)
+    }
+
+    /// Whether typing `character` should accept the selected completion.
+    pub fn is_completion_commit(&self, character: char) -> bool {
+        contains_char(self.completion_commit_characters(), character)
+    }
+
+    /// Whether typing `character` should format the document as you type,
+    /// false if the server doesn't.
+    pub fn is_on_type_formatting_trigger(&self, character: char) -> bool {
+        self.document_on_type_formatting_provider
+            .as_ref()
+            .is_some_and(|options| options.should_trigger(character))
+    }
+}
+
+fn contains_char(characters: &[String], character: char) -> bool {
+    let mut buffer = [0; 4];
+    let character = &*character.encode_utf8(&mut buffer);
+    characters.iter().any(|c| c == character)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_server_capabilities() {
+        let capabilities: ServerCapabilities = serde_json::from_value(json!({
+            "textDocumentSync": { "openClose": true, "change": 2, "save": { "includeText": false } },
+            "hoverProvider": true,
+            "definitionProvider": { "workDoneProgress": true },
+            "referencesProvider": false,
+            "renameProvider": { "prepareProvider": true },
+            "completionProvider": { "triggerCharacters": [".", ":"], "allCommitCharacters": [";"] },
+            "documentOnTypeFormattingProvider": {
+                "firstTriggerCharacter": "}",
+                "moreTriggerCharacter": [";", "\n"]
+            },
+            "experimental": { "gc_details": true }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            capabilities.text_document_sync_kind(),
+            TextDocumentSyncKind::Incremental
+        );
+        assert!(capabilities.is_completion_trigger('.'));
+        assert!(!capabilities.is_completion_trigger('('));
+        assert!(capabilities.is_completion_commit(';'));
+        assert_eq!(capabilities.completion_trigger_characters(), [".", ":"]);
+        assert!(capabilities.is_on_type_formatting_trigger('}'));
+        assert!(capabilities.is_on_type_formatting_trigger('\n'));
+        assert!(!capabilities.is_on_type_formatting_trigger('{'));
+        assert!(capabilities.hover_provider.unwrap().is_enabled());
+        assert!(capabilities.definition_provider.unwrap().is_enabled());
+        assert!(!capabilities.references_provider.unwrap().is_enabled());
+        assert_eq!(capabilities.code_action_provider, None);
+        let rename = capabilities.rename_provider.unwrap();
+        assert_eq!(rename.options().unwrap().prepare_provider, Some(true));
+        assert_eq!(
+            capabilities.other["experimental"],
+            json!({ "gc_details": true })
+        );
+
+        let full: ServerCapabilities =
+            serde_json::from_value(json!({ "textDocumentSync": 1 })).unwrap();
+        assert_eq!(full.text_document_sync_kind(), TextDocumentSyncKind::Full);
+    }
+
+    #[test]
+    fn test_parse_provider_options() {
+        let capabilities: ServerCapabilities = serde_json::from_value(json!({
+            "completionProvider": { "resolveProvider": true, "completionItem": { "labelDetailsSupport": true } },
+            "signatureHelpProvider": { "triggerCharacters": ["("], "retriggerCharacters": [","] },
+            "inlayHintProvider": { "resolveProvider": true },
+            "semanticTokensProvider": {
+                "legend": { "tokenTypes": ["namespace", "type", "function"], "tokenModifiers": ["declaration", "readonly"] },
+                "range": true,
+                "full": { "delta": true }
+            },
+            "diagnosticProvider": { "identifier": "gopls", "interFileDependencies": true, "workspaceDiagnostics": false },
+            "executeCommandProvider": { "commands": ["gopls.tidy"] }
+        }))
+        .unwrap();
+
+        let completion = capabilities.completion_provider.unwrap();
+        assert_eq!(completion.resolve_provider, Some(true));
+        assert_eq!(
+            completion.completion_item.unwrap().label_details_support,
+            Some(true)
+        );
+        let signature_help = capabilities.signature_help_provider.unwrap();
+        assert_eq!(signature_help.retrigger_characters.unwrap(), [","]);
+        let inlay_hints = capabilities.inlay_hint_provider.unwrap();
+        assert_eq!(inlay_hints.options().unwrap().resolve_provider, Some(true));
+
+        let tokens = capabilities.semantic_tokens_provider.unwrap();
+        assert!(tokens.supports_range() && tokens.supports_full() && tokens.supports_delta());
+        assert_eq!(tokens.legend.token_type(2), Some("function"));
+        assert_eq!(
+            tokens.legend.token_modifiers(0b11).collect::<Vec<_>>(),
+            ["declaration", "readonly"]
+        );
+
+        let diagnostics = capabilities.diagnostic_provider.unwrap();
+        assert_eq!(diagnostics.identifier.as_deref(), Some("gopls"));
+        assert!(diagnostics.inter_file_dependencies && !diagnostics.workspace_diagnostics);
+        assert_eq!(
+            capabilities.execute_command_provider.unwrap().commands,
+            ["gopls.tidy"]
+        );
+        assert!(capabilities.other.is_empty());
+    }
+}
@@ -1,18 +1,242 @@
-use crate::protocol::ResponseMessage;
-use anyhow::{anyhow, Result};
-use serde::Serialize;
-use std::fmt::Debug;
+use crate::activity::Activity;
+use crate::cache::ResultCache;
+use crate::capabilities::ServerCapabilities;
+use crate::columns::PositionEncoding;
+use crate::commands::CommandRegistry;
+use crate::documents::{DocumentStore, TextDocument, VersionGuard};
+use crate::edits::{ChangeAnnotation, DocumentEdits, WorkspaceEdit};
+use crate::event_bus::IncomingMessage;
+use crate::launch::StderrTail;
+use crate::limits::ProcessTree;
+use crate::logging::{Level, LogSink, Logger};
+use crate::methods;
+use crate::position_encoding::{negotiated_encoding, params_uri, Conversion};
+use crate::protocol::{
+    deserialize_method, intern_method, BaseMessage, InitializeResult, NotificationMessage,
+    ProgressToken, RefreshKind, Registration, RequestMessage, ResponseMessage, ServerInfo,
+    TextDocumentContentChangeEvent, WorkspaceFolder, INVALID_PARAMS, INVALID_REQUEST,
+    METHOD_NOT_FOUND, REQUEST_CANCELLED,
+};
+use crate::quirks::ServerQuirks;
+use crate::rate_limit::RateLimits;
+use crate::retry::RetryPolicy;
+#[cfg(feature = "schema-validation")]
+use crate::schema::{Direction, MessageValidator, Schemas};
+use crate::session_log::{self, SessionLog};
+use crate::settings::SettingsStore;
+use crate::streaming::RawResponse;
+use crate::transport::{BackgroundTask, FrameHeaders, FrameReader, FrameWriter, PendingRequests};
+use crate::uri_map::UriMap;
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
 use std::pin::Pin;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "tracing")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
 use tokio::net::{TcpStream, UnixStream};
+use tokio::process::Child;
+use tokio::sync::{broadcast, mpsc, watch};
+#[cfg(feature = "tracing")]
+use tracing::Instrument;
 
 pub trait AsyncReadWrite: AsyncRead + AsyncWrite + Unpin {}
 impl<T: AsyncRead + AsyncWrite + Unpin + ?Sized> AsyncReadWrite for T {}
 
 type Stream = Pin<Box<dyn AsyncReadWrite + Send>>;
 
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// How long `close` waits for the server to answer `shutdown` and for the
+/// spawned process to exit before killing it.
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Id of the `shutdown` request sent by `close`. A string can't collide with
+/// the numeric ids of the request builders.
+const SHUTDOWN_REQUEST_ID: &str = "lsp-client-rs/shutdown";
+
+/// First id of the requests the typed methods send. Ids from the upper half
+/// of the range don't collide with the small ids callers usually pick.
+const FIRST_TYPED_REQUEST_ID: u32 = 1 << 31;
+
+/// How long a server that went away gets to finish writing to stderr, before
+/// its last words are quoted in errors.
+const STDERR_SETTLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Events raised by the client for things the server asked of the application.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// The server asked the client to re-query the given kind of data for all
+    /// open documents. The request itself has already been acknowledged.
+    Refresh(RefreshKind),
+    /// The server process the client owns terminated.
+    ServerExit(ServerExit),
+}
+
+/// Where a connection is in its lifecycle. See `LspClient::watch_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    /// Connected to a server that is starting up: `initialize` wasn't sent
+    /// yet, or it failed.
+    Spawning,
+    /// Waiting for the response to `initialize`, or for the client to send
+    /// `initialized` after it.
+    Initializing,
+    /// The server answered `initialize` and the client sent `initialized`.
+    Ready,
+    /// `close` is shutting the server down.
+    ShuttingDown,
+    /// The connection is closed, by the client or the server.
+    Closed,
+}
+
+/// What happens to requests sent before the server answered `initialize`,
+/// which the specification forbids. See `LspClient::set_before_initialize`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BeforeInitialize {
+    /// Send them anyway, for lenient servers and mocks.
+    #[default]
+    Send,
+    /// Hold them back until the server is ready.
+    Queue,
+    /// Fail them with `NotInitialized`.
+    Reject,
+}
+
+/// The error of requests rejected because the server wasn't initialized yet.
+/// Returned inside `anyhow::Error`; use `downcast_ref` to tell it apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotInitialized {
+    pub method: String,
+}
+
+impl fmt::Display for NotInitialized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Can't send {} before the server is initialized",
+            self.method
+        )
+    }
+}
+
+impl std::error::Error for NotInitialized {}
+
+/// How a server process terminated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerExit {
+    /// The exit code, unless the process was killed by a signal.
+    pub code: Option<i32>,
+    /// The signal that killed the process, on Unix.
+    pub signal: Option<i32>,
+    /// The end of what the server wrote to stderr.
+    pub stderr_tail: String,
+    /// Whether the client shut the server down with `close`, as opposed to
+    /// the server exiting on its own.
+    pub was_requested: bool,
+}
+
+impl ServerExit {
+    /// Whether the server exited with code 0.
+    pub fn is_clean(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// A handle to a connection with a language server.
+///
+/// The handle is cheap to clone and can be shared across tasks: every clone
+/// talks over the same connection. Messages from the server are read by a
+/// background task, so responses to `request` calls are routed back to the
+/// caller that sent them, whichever order they arrive in.
+#[derive(Clone)]
 pub struct LspClient {
-    stream: Stream,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    writer: tokio::sync::Mutex<Writer>,
+    documents: Mutex<DocumentStore>,
+    events: broadcast::Sender<ClientEvent>,
+    /// Subscribed to for every stream of incoming messages. The reader task
+    /// holds the only sender, so the streams end when it stops.
+    incoming: Mutex<broadcast::Receiver<Arc<IncomingMessage>>>,
+    /// Requests sent with `request`, keyed by the JSON representation of their id.
+    pending: PendingRequests<RawResponse>,
+    /// What is known of the requests in `pending`, by the same keys.
+    sent_requests: Mutex<HashMap<String, SentRequest>>,
+    /// Responses nobody is waiting on, consumed by `handle_response`.
+    responses: tokio::sync::Mutex<mpsc::UnboundedReceiver<RawResponse>>,
+    /// The server process, when the client spawned or was handed it.
+    child: tokio::sync::Mutex<Option<Child>>,
+    /// The end of the server's stderr, when the client owns the process.
+    stderr: Mutex<Option<StderrTail>>,
+    /// The server and the processes it started, killed once the server is.
+    process_tree: Mutex<Option<ProcessTree>>,
+    grace_period: Mutex<Duration>,
+    retry_policy: Mutex<Option<RetryPolicy>>,
+    rate_limits: Mutex<Option<RateLimits>>,
+    /// Answers `workspace/configuration`, and pushes its changes.
+    settings: Mutex<Option<AttachedSettings>>,
+    /// Id of the next request sent by a typed method, such as `hover`.
+    next_id: AtomicU32,
+    /// The params of the last `initialize` request.
+    initialize_params: Mutex<Option<serde_json::Value>>,
+    initialize_result: Mutex<Option<InitializeResult>>,
+    /// The encoding the host works in, if positions are converted.
+    native_position_encoding: Mutex<Option<PositionEncoding>>,
+    quirks: Mutex<ServerQuirks>,
+    /// Capabilities registered with `client/registerCapability`.
+    registrations: Mutex<Vec<Registration>>,
+    /// Commands the client runs itself instead of asking the server.
+    commands: Mutex<CommandRegistry>,
+    /// Whether typed queries share the response of an identical one.
+    coalesce_queries: AtomicBool,
+    /// Queries being sent that identical ones wait for.
+    in_flight: Mutex<Vec<InFlightQuery>>,
+    /// Answers the requests it caches, for documents it has a result for.
+    result_cache: Mutex<Option<ResultCache>>,
+    /// Sent with `initialize`, then kept up to date by
+    /// `add_workspace_folder` and `remove_workspace_folder`.
+    workspace_folders: Mutex<Vec<WorkspaceFolder>>,
+    activity: Activity,
+    /// Watched by the reader task, which reads nothing while it is `true`.
+    reading_paused: watch::Sender<bool>,
+    state: watch::Sender<ConnectionState>,
+    /// How long to wait for the response to `initialize`, if at all.
+    initialize_timeout: Mutex<Option<Duration>>,
+    before_initialize: Mutex<BeforeInitialize>,
+    /// The number of `didChange` notifications written so far.
+    changes_sent: watch::Receiver<u64>,
+    /// Wakes the flush task whenever a change is held back by the debounce.
+    changes_queued: watch::Sender<()>,
+    /// Set once the server acknowledged `shutdown`. From then on only
+    /// `exit` may follow, so its requests are refused and its notifications
+    /// dropped.
+    shut_down: AtomicBool,
+    closing: AtomicBool,
+    closed: AtomicBool,
+    /// Also held by the reader task, which logs without upgrading to `Shared`.
+    log: Arc<Logger>,
+    #[cfg(feature = "otel")]
+    telemetry: crate::telemetry::Instruments,
+    /// Parent of the spans of the requests sent over this connection.
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    /// Shared with the writer, which checks outgoing messages.
+    #[cfg(feature = "schema-validation")]
+    validator: Arc<Mutex<Option<MessageValidator>>>,
+    /// Shared with the writer and the reader task, which record the messages.
+    session_log: Arc<Mutex<Option<SessionLog>>>,
+    /// Shared with the writer, which rewrites outgoing messages.
+    uri_map: Arc<Mutex<Option<UriMap>>>,
+    _reader: BackgroundTask,
+    _flush: BackgroundTask,
 }
 
 impl LspClient {
@@ -24,18 +248,16 @@ impl LspClient {
         let stream: Stream = match scheme {
             "tcp" => {
                 // Skip the scheme part and rejoin the rest (address and port)
-                let addr = addr
-                    .splitn(2, ':')
-                    .nth(1)
+                let (_, addr) = addr
+                    .split_once(':')
                     .ok_or(anyhow!("Invalid TCP address format."))?;
                 let tcp_stream = TcpStream::connect(addr).await?;
                 Box::pin(tcp_stream) as Stream
             }
             "unix" => {
                 // Skip the scheme part for UNIX domain socket path
-                let path = addr
-                    .splitn(2, ':')
-                    .nth(1)
+                let (_, path) = addr
+                    .split_once(':')
                     .ok_or(anyhow!("Invalid UNIX socket path format."))?;
                 let unix_stream = UnixStream::connect(path).await?;
                 Box::pin(unix_stream) as Stream
@@ -48,120 +270,3362 @@ impl LspClient {
             }
         };
 
-        Ok(Self { stream })
+        Ok(Self::from_boxed_stream(stream, None))
+    }
+
+    /// Creates a client talking to a spawned server process over its stdio.
+    /// The process must have been spawned with piped stdin and stdout. The
+    /// client owns the process from then on: `close` shuts it down, and it is
+    /// killed if it outlives the last handle by more than the grace period.
+    /// A piped stderr is captured, see `server_stderr`.
+    /// Must be called from within a tokio runtime.
+    pub fn from_child(mut child: Child) -> Result<Self> {
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("The server's stdin must be piped"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("The server's stdout must be piped"))?;
+        let stderr = child.stderr.take().map(|pipe| StderrTail::capture(pipe).0);
+        let stream = Box::pin(tokio::io::join(stdout, stdin)) as Stream;
+        let client = Self::from_boxed_stream(stream, Some(child));
+        *lock(&client.shared.stderr) = stderr;
+        Ok(client)
+    }
+
+    /// Creates a client talking over an already connected stream, e.g. the
+    /// stdio pipes of a spawned server or an in-memory mock.
+    /// Must be called from within a tokio runtime.
+    pub fn from_stream<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(stream: S) -> Self {
+        Self::from_boxed_stream(Box::pin(stream), None)
+    }
+
+    fn from_boxed_stream(stream: Stream, child: Option<Child>) -> Self {
+        let (read_half, write_half) = tokio::io::split(stream);
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let (responses_tx, responses_rx) = mpsc::unbounded_channel();
+        let (incoming_tx, incoming_rx) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let (reading_paused, paused_rx) = watch::channel(false);
+        let (changes_sent_tx, changes_sent) = watch::channel(0);
+        let (changes_queued, queued_rx) = watch::channel(());
+        let log = Arc::new(Logger::new());
+        #[cfg(feature = "schema-validation")]
+        let validator = Arc::new(Mutex::new(None));
+        let session_log = Arc::new(Mutex::new(None));
+        let uri_map = Arc::new(Mutex::new(None));
+
+        #[cfg(feature = "tracing")]
+        let span = {
+            static CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+            let connection = CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+            tracing::info_span!("lsp_connection", connection)
+        };
+
+        let shared = Arc::new_cyclic(|weak: &Weak<Shared>| {
+            let reader = FrameReader::new(read_half);
+            let read_loop = read_loop(
+                reader,
+                weak.clone(),
+                log.clone(),
+                session_log.clone(),
+                responses_tx,
+                incoming_tx,
+                paused_rx,
+            );
+            #[cfg(feature = "tracing")]
+            let read_loop = read_loop.instrument(span.clone());
+            let task = tokio::spawn(read_loop);
+            let flush = tokio::spawn(flush_loop(weak.clone(), queued_rx));
+            Shared {
+                writer: tokio::sync::Mutex::new(Writer {
+                    frames: FrameWriter::new(write_half),
+                    body_buf: Vec::new(),
+                    changes_sent: changes_sent_tx,
+                    #[cfg(feature = "schema-validation")]
+                    validator: validator.clone(),
+                    session_log: session_log.clone(),
+                    uri_map: uri_map.clone(),
+                    log: log.clone(),
+                }),
+                documents: Mutex::new(DocumentStore::new()),
+                events,
+                incoming: Mutex::new(incoming_rx),
+                pending: PendingRequests::new(),
+                sent_requests: Mutex::new(HashMap::new()),
+                responses: tokio::sync::Mutex::new(responses_rx),
+                child: tokio::sync::Mutex::new(child),
+                stderr: Mutex::new(None),
+                process_tree: Mutex::new(None),
+                grace_period: Mutex::new(DEFAULT_GRACE_PERIOD),
+                retry_policy: Mutex::new(None),
+                rate_limits: Mutex::new(None),
+                settings: Mutex::new(None),
+                next_id: AtomicU32::new(FIRST_TYPED_REQUEST_ID),
+                initialize_params: Mutex::new(None),
+                initialize_result: Mutex::new(None),
+                native_position_encoding: Mutex::new(None),
+                quirks: Mutex::new(ServerQuirks::new()),
+                registrations: Mutex::new(Vec::new()),
+                commands: Mutex::new(CommandRegistry::new()),
+                coalesce_queries: AtomicBool::new(false),
+                in_flight: Mutex::new(Vec::new()),
+                result_cache: Mutex::new(None),
+                workspace_folders: Mutex::new(Vec::new()),
+                activity: Activity::default(),
+                reading_paused,
+                state: watch::Sender::new(ConnectionState::Spawning),
+                initialize_timeout: Mutex::new(None),
+                before_initialize: Mutex::new(BeforeInitialize::default()),
+                changes_sent,
+                changes_queued,
+                shut_down: AtomicBool::new(false),
+                closing: AtomicBool::new(false),
+                closed: AtomicBool::new(false),
+                log,
+                #[cfg(feature = "otel")]
+                telemetry: crate::telemetry::Instruments::new(),
+                #[cfg(feature = "tracing")]
+                span,
+                #[cfg(feature = "schema-validation")]
+                validator,
+                session_log,
+                uri_map,
+                _reader: BackgroundTask(task),
+                _flush: BackgroundTask(flush),
+            }
+        });
+
+        Self { shared }
+    }
+
+    /// A receiver of every message received from now on.
+    pub(crate) fn incoming_receiver(&self) -> broadcast::Receiver<Arc<IncomingMessage>> {
+        lock(&self.shared.incoming).resubscribe()
+    }
+
+    pub(crate) fn activity(&self) -> &Activity {
+        &self.shared.activity
+    }
+
+    /// Stops reading from the server until `resume_reading`, e.g. while a
+    /// slow consumer catches up with a flood of notifications. Messages then
+    /// queue up in the server's output, not in the client's memory, and the
+    /// server blocks once the pipe is full. The message being read when
+    /// reading is paused is still delivered. Responses aren't read either,
+    /// so requests sent meanwhile wait until reading resumes.
+    pub fn pause_reading(&self) {
+        self.shared.reading_paused.send_replace(true);
+    }
+
+    pub fn resume_reading(&self) {
+        self.shared.reading_paused.send_replace(false);
+    }
+
+    pub fn is_reading_paused(&self) -> bool {
+        *self.shared.reading_paused.borrow()
+    }
+
+    /// Returns a receiver for the events raised while reading server messages.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ClientEvent> {
+        self.shared.events.subscribe()
+    }
+
+    /// Checks every message sent and received from now on against `schemas`
+    /// and prints each mismatch with its path in the message.
+    #[cfg(feature = "schema-validation")]
+    pub fn enable_schema_validation(&self, schemas: Arc<Schemas>) {
+        *lock(&self.shared.validator) = Some(MessageValidator::new(schemas));
+    }
+
+    /// Sets where the client reports its own diagnostics, such as framing
+    /// errors or dropped messages. See `logging::default_sink` for the default.
+    pub fn set_log_sink(&self, sink: Arc<dyn LogSink>) {
+        self.shared.log.set_sink(sink);
+    }
+
+    /// Records every message sent and received from now on in `log`, or
+    /// stops recording with `None`.
+    pub fn set_session_log(&self, log: Option<SessionLog>) {
+        *lock(&self.shared.session_log) = log;
+    }
+
+    /// Rewrites the paths in every message from now on, for a server that
+    /// sees the files at other paths, or stops rewriting with `None`.
+    pub fn set_uri_map(&self, uri_map: Option<UriMap>) {
+        *lock(&self.shared.uri_map) = uri_map;
+    }
+
+    /// Answers the requests `cache` caches from it when it can, and caches
+    /// their results otherwise, or stops caching with `None`.
+    pub fn set_result_cache(&self, cache: Option<ResultCache>) {
+        *lock(&self.shared.result_cache) = cache;
+    }
+
+    /// Sets the headers written with every following message, e.g. a
+    /// `Content-Type` or fields a proxy requires. Fails if one of them can't
+    /// be written as a header.
+    pub async fn set_frame_headers(&self, headers: FrameHeaders) -> Result<()> {
+        self.shared.writer.lock().await.frames.set_headers(headers)
     }
 
-    pub async fn send_request<T: Serialize + Debug>(&mut self, request: T) -> Result<()> {
-        println!("Sending request: {:?}", request);
-        let request_str = serde_json::to_string(&request)?;
-        let content_length = request_str.len();
-        let header = format!("Content-Length: {}\r\n\r\n{}", content_length, request_str);
-        self.stream.write_all(header.as_bytes()).await?;
-        self.stream.flush().await?;
+    /// Sends a request without waiting for its response; use `handle_response`
+    /// to read it. Pending document changes are flushed first so the server
+    /// never answers position-dependent requests against stale text.
+    ///
+    /// Sending the `initialized` notification this way makes the connection
+    /// `Ready`, releasing the requests queued until then.
+    pub async fn send_request<T: Serialize + Debug>(&self, request: T) -> Result<()> {
+        let mut writer = self.shared.lock_writer().await?;
+        // The pending changes go out in the same write as the request.
+        for notification in self.documents().take_pending() {
+            writer.queue_notification(&notification)?;
+        }
+        self.shared
+            .log
+            .log(Level::Trace, format_args!("Sending request: {:?}", request));
+        // Only inspected during the handshake, which is rare.
+        let initialized = self.state() == ConnectionState::Initializing
+            && lock(&self.shared.initialize_result).is_some()
+            && serde_json::to_value(&request)
+                .is_ok_and(|message| message["method"] == methods::INITIALIZED);
+        writer.write_message(&request).await?;
+        if initialized {
+            // Set while holding the writer, so that queued requests follow.
+            self.shared.set_state(ConnectionState::Ready);
+        }
         Ok(())
     }
 
-    pub async fn handle_response(&mut self) -> Result<ResponseMessage> {
-        loop {
-            let mut headers = Vec::new();
-            let mut content_length: Option<usize> = None;
-
-            // Read headers
-            loop {
-                let mut byte = [0];
-                self.stream.read_exact(&mut byte).await?;
-                headers.push(byte[0]);
-
-                if headers.ends_with(b"\r\n\r\n") {
-                    let headers_str = String::from_utf8_lossy(&headers);
-                    for line in headers_str.lines() {
-                        if line.starts_with("Content-Length:") {
-                            let parts: Vec<&str> = line.splitn(2, ':').collect();
-                            if parts.len() > 1 {
-                                let length_str = parts[1].trim();
-                                content_length = Some(length_str.parse()?);
-                                break;
-                            }
-                        }
-                    }
-                    break; // Exit headers reading loop
+    /// Sends a request and waits for the response with the same id.
+    ///
+    /// Dropping the returned future before the response arrived, e.g. in a
+    /// `select!` or on a timeout, cancels the request: the server is sent
+    /// `$/cancelRequest` and a late response is treated as unclaimed.
+    pub async fn request(&self, request: RequestMessage) -> Result<ResponseMessage> {
+        if request.method == methods::INITIALIZE {
+            return self.initialize(request).await;
+        }
+        self.request_raw(request).await?.to_response()
+    }
+
+    /// Sends a `method` request with a fresh id and waits for its response,
+    /// for methods without a typed helper such as server extensions.
+    pub async fn call(&self, method: &str, params: serde_json::Value) -> Result<ResponseMessage> {
+        let request = RequestMessage {
+            base_message: BaseMessage::new(),
+            id: self.next_request_id().into(),
+            method: intern_method(method),
+            notification: 0,
+            params,
+        };
+        self.request(request).await
+    }
+
+    async fn initialize(&self, request: RequestMessage) -> Result<ResponseMessage> {
+        *lock(&self.shared.initialize_params) = Some(request.params.clone());
+        let folders = request.params.get("workspaceFolders").cloned();
+        *lock(&self.shared.workspace_folders) = folders
+            .and_then(|folders| serde_json::from_value(folders).ok())
+            .unwrap_or_default();
+
+        self.shared.set_state(ConnectionState::Initializing);
+        let timeout = *lock(&self.shared.initialize_timeout);
+        let response = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.request_raw(request))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(anyhow!(
+                        "Server didn't answer initialize within {:?}",
+                        timeout
+                    ))
+                }),
+            None => self.request_raw(request).await,
+        };
+        let response = response.and_then(|response| {
+            let response = response.to_response()?;
+            if response.error.is_none() {
+                self.record_initialize(&response)?;
+            }
+            Ok(response)
+        });
+        // Ready once the caller sent `initialized`, see `send_request`.
+        let initialized = matches!(&response, Ok(response) if response.error.is_none());
+        if !initialized {
+            self.shared.set_state(ConnectionState::Spawning);
+        }
+        response
+    }
+
+    /// Fails `initialize` requests the server doesn't answer within
+    /// `timeout`. `None`, the default, waits as long as it takes.
+    pub fn set_initialize_timeout(&self, timeout: Option<Duration>) {
+        *lock(&self.shared.initialize_timeout) = timeout;
+    }
+
+    /// Sets what happens to requests sent before the server answered
+    /// `initialize`. `shutdown` is always sent.
+    pub fn set_before_initialize(&self, policy: BeforeInitialize) {
+        *lock(&self.shared.before_initialize) = policy;
+    }
+
+    /// Applies the `BeforeInitialize` policy to a `method` request.
+    async fn wait_until_initialized(&self, method: &str) -> Result<()> {
+        if matches!(method, methods::INITIALIZE | methods::SHUTDOWN) {
+            return Ok(());
+        }
+        let policy = *lock(&self.shared.before_initialize);
+        let mut states = self.shared.state.subscribe();
+        let initializing = matches!(
+            *states.borrow_and_update(),
+            ConnectionState::Spawning | ConnectionState::Initializing
+        );
+        match policy {
+            _ if !initializing => Ok(()),
+            BeforeInitialize::Send => Ok(()),
+            BeforeInitialize::Reject => Err(NotInitialized {
+                method: method.to_string(),
+            }
+            .into()),
+            BeforeInitialize::Queue => {
+                let state = *states
+                    .wait_for(|state| {
+                        !matches!(
+                            state,
+                            ConnectionState::Spawning | ConnectionState::Initializing
+                        )
+                    })
+                    .await?;
+                ensure!(
+                    state == ConnectionState::Ready,
+                    "Connection closed before the server was initialized"
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Where the connection is in its lifecycle.
+    pub fn state(&self) -> ConnectionState {
+        *self.shared.state.borrow()
+    }
+
+    /// A receiver notified of every change of the connection's state, e.g.
+    /// for a status bar.
+    pub fn watch_state(&self) -> watch::Receiver<ConnectionState> {
+        self.shared.state.subscribe()
+    }
+
+    /// Keeps the result of `initialize`, and fails if the server is older
+    /// than the minimum version set for it.
+    fn record_initialize(&self, response: &ResponseMessage) -> Result<()> {
+        let result = response.handle_initialize()?;
+        if let Some(server) = &result.server_info {
+            lock(&self.shared.quirks).check(server)?;
+        }
+        if result.capabilities.normalizes_line_endings == Some(true) {
+            self.documents().set_normalize_line_endings(true);
+        }
+        *lock(&self.shared.initialize_result) = Some(result);
+        Ok(())
+    }
+
+    /// The name and version the server reported, once it answered `initialize`.
+    pub fn server_info(&self) -> Option<ServerInfo> {
+        lock(&self.shared.initialize_result)
+            .as_ref()?
+            .server_info
+            .clone()
+    }
+
+    /// The capabilities the server reported, once it answered `initialize`.
+    pub fn server_capabilities(&self) -> Option<ServerCapabilities> {
+        Some(
+            lock(&self.shared.initialize_result)
+                .as_ref()?
+                .capabilities
+                .clone(),
+        )
+    }
+
+    /// Sets the minimum versions and quirks of the servers this client may
+    /// talk to. Set them before sending `initialize` for the minimum version
+    /// to be checked.
+    pub fn set_server_quirks(&self, quirks: ServerQuirks) {
+        *lock(&self.shared.quirks) = quirks;
+    }
+
+    /// The capabilities the server registered dynamically and didn't
+    /// unregister since.
+    pub fn registrations(&self) -> Vec<Registration> {
+        lock(&self.shared.registrations).clone()
+    }
+
+    /// Whether the server registered `method` dynamically for the document
+    /// `uri` of language `language_id`.
+    pub fn is_registered(&self, method: &str, uri: &str, language_id: &str) -> bool {
+        lock(&self.shared.registrations).iter().any(|registration| {
+            registration.method == method
+                && registration
+                    .document_selector()
+                    .is_none_or(|selector| selector.matches(uri, language_id))
+        })
+    }
+
+    /// Replaces the capabilities known to be registered, such as with the
+    /// ones registered before the client restarted.
+    pub(crate) fn set_registrations(&self, registrations: Vec<Registration>) {
+        *lock(&self.shared.registrations) = registrations;
+    }
+
+    /// The params of the last `initialize` request sent.
+    pub(crate) fn initialize_params(&self) -> Option<serde_json::Value> {
+        lock(&self.shared.initialize_params).clone()
+    }
+
+    /// The folders of the workspace, as sent with `initialize` and changed
+    /// since.
+    pub fn workspace_folders(&self) -> Vec<WorkspaceFolder> {
+        lock(&self.shared.workspace_folders).clone()
+    }
+
+    /// Adds a folder to the workspace and notifies the server with
+    /// `workspace/didChangeWorkspaceFolders`. Returns `false`, sending
+    /// nothing, if a folder with its URI is already part of the workspace.
+    pub async fn add_workspace_folder(&self, folder: WorkspaceFolder) -> Result<bool> {
+        let mut writer = self.shared.lock_writer().await?;
+        {
+            let mut folders = lock(&self.shared.workspace_folders);
+            if folders.iter().any(|existing| existing.uri == folder.uri) {
+                return Ok(false);
+            }
+            folders.push(folder.clone());
+        }
+        let notification =
+            NotificationMessage::new_did_change_workspace_folders(vec![folder], Vec::new());
+        writer.write_notification(&notification).await?;
+        Ok(true)
+    }
+
+    /// Removes the folder with `uri` from the workspace and notifies the
+    /// server. Returns `false`, sending nothing, if it isn't part of it.
+    pub async fn remove_workspace_folder(&self, uri: &str) -> Result<bool> {
+        let mut writer = self.shared.lock_writer().await?;
+        let removed = {
+            let mut folders = lock(&self.shared.workspace_folders);
+            let Some(index) = folders.iter().position(|folder| folder.uri == uri) else {
+                return Ok(false);
+            };
+            folders.remove(index)
+        };
+        let notification =
+            NotificationMessage::new_did_change_workspace_folders(Vec::new(), vec![removed]);
+        writer.write_notification(&notification).await?;
+        Ok(true)
+    }
+
+    /// Whether the server, once initialized, is flagged with `quirk`.
+    pub fn has_quirk(&self, quirk: &str) -> bool {
+        let Some(server) = self.server_info() else {
+            return false;
+        };
+        lock(&self.shared.quirks).has_quirk(&server, quirk)
+    }
+
+    /// Like `request`, but returns the response body unparsed.
+    pub async fn request_raw(&self, request: RequestMessage) -> Result<RawResponse> {
+        self.wait_until_initialized(&request.method).await?;
+        self.request_converted(request).await
+    }
+
+    /// Makes a typed query sent while an identical one for the same version
+    /// of the document is waiting for its response share that response
+    /// instead of being sent again. The shared request is only cancelled
+    /// once every caller waiting for it stopped. Off by default.
+    pub fn set_coalesce_queries(&self, coalesce: bool) {
+        self.shared
+            .coalesce_queries
+            .store(coalesce, Ordering::Relaxed);
+    }
+
+    /// Like `request_raw`, but shares the response of an identical query if
+    /// coalescing is on. `request` must have an id from `next_request_id`,
+    /// which no other caller waits for a response to.
+    pub(crate) async fn request_coalesced(&self, request: RequestMessage) -> Result<RawResponse> {
+        self.wait_until_initialized(&request.method).await?;
+        let Some(key) = self.query_key(&request) else {
+            return self.request_converted(request).await;
+        };
+        let id = request.id.clone();
+        let mut response = {
+            let mut in_flight = lock(&self.shared.in_flight);
+            let index = match in_flight.iter().position(|query| query.key == key) {
+                Some(index) => index,
+                None => {
+                    let (sender, receiver) = watch::channel(None);
+                    let client = self.clone();
+                    let task = tokio::spawn(async move {
+                        let response = client.request_converted(request).await;
+                        sender.send_replace(Some(response.map_err(Arc::new)));
+                    });
+                    in_flight.push(InFlightQuery {
+                        key,
+                        response: receiver,
+                        waiters: 0,
+                        task: task.abort_handle(),
+                    });
+                    in_flight.len() - 1
                 }
+            };
+            in_flight[index].waiters += 1;
+            in_flight[index].response.clone()
+        };
+        let _waiter = QueryWaiter {
+            in_flight: &self.shared.in_flight,
+            response: response.clone(),
+        };
+        let response = response
+            .wait_for(Option::is_some)
+            .await
+            .map_err(|_| anyhow!("The request was dropped before its response arrived"))?;
+        match response.clone().expect("waited for a response") {
+            Ok(response) if response.id.as_ref() == Some(&id) => Ok(response),
+            Ok(response) => response.with_id(id),
+            Err(error) => Err(anyhow!("{:#}", error)),
+        }
+    }
+
+    /// What identical queries have in common, or `None` if `request` isn't
+    /// a query or coalescing is off.
+    fn query_key(&self, request: &RequestMessage) -> Option<QueryKey> {
+        if !self.shared.coalesce_queries.load(Ordering::Relaxed)
+            || !crate::retry::is_idempotent(&request.method)
+        {
+            return None;
+        }
+        let version = params_uri(&request.params).and_then(|uri| self.documents().version(uri));
+        Some(QueryKey {
+            method: request.method.clone(),
+            version,
+            params: request.params.clone(),
+        })
+    }
+
+    async fn request_converted(&self, mut request: RequestMessage) -> Result<RawResponse> {
+        let now = tokio::time::Instant::now();
+        let send_at = match lock(&self.shared.rate_limits).as_mut() {
+            Some(limits) => limits.reserve(&request.method, params_uri(&request.params), now)?,
+            None => now,
+        };
+        if send_at > now {
+            tokio::time::sleep_until(send_at).await;
+        }
+        let Some(conversion) = self.shared.position_conversion() else {
+            return self.request_cached(request).await;
+        };
+        let uri = params_uri(&request.params).map(str::to_string);
+        conversion.convert_outgoing(&mut request.params, &self.documents());
+        let response = self.request_cached(request).await?;
+        conversion.convert_response(response, uri.as_deref(), &self.documents())
+    }
+
+    /// Sends `request`, unless the result cache has its result for the
+    /// current text of the document.
+    async fn request_cached(&self, request: RequestMessage) -> Result<RawResponse> {
+        let cache = lock(&self.shared.result_cache)
+            .clone()
+            .filter(|cache| cache.caches(&request.method));
+        let uri = params_uri(&request.params).map(str::to_string);
+        let (Some(cache), Some(uri)) = (cache, uri) else {
+            return self.request_with_retries(request).await;
+        };
+        let Some(text) = self.document_text(&uri).await else {
+            return self.request_with_retries(request).await;
+        };
+        let server = self
+            .server_info()
+            .map(|info| format!("{} {}", info.name, info.version.unwrap_or_default()))
+            .unwrap_or_default();
+
+        if let Some(result) = cache
+            .get(&server, &request.method, &request.params, &uri, &text)
+            .await
+        {
+            let body = serde_json::json!({ "jsonrpc": "2.0", "id": request.id, "result": result });
+            return Ok(RawResponse::new(
+                Some(request.id),
+                None,
+                serde_json::to_vec(&body)?,
+            ));
+        }
+        let method = request.method.clone();
+        let params = request.params.clone();
+        let response = self.request_with_retries(request).await?;
+        // Not cached if the document changed while the server worked on it.
+        let unchanged = self.document_text(&uri).await.as_deref() == Some(text.as_str());
+        if let (
+            true,
+            Ok(ResponseMessage {
+                result: Some(result),
+                error: None,
+                ..
+            }),
+        ) = (unchanged, response.to_response())
+        {
+            if let Err(error) = cache
+                .put(&server, &method, &params, &uri, &text, &result)
+                .await
+            {
+                self.shared.log.log(
+                    Level::Warn,
+                    format_args!("Failed to cache the {} result: {:#}", method, error),
+                );
             }
+        }
+        Ok(response)
+    }
 
-            let content_length =
-                content_length.ok_or_else(|| anyhow!("Failed to find Content-Length header"))?;
-            let mut body = vec![0u8; content_length];
-            self.stream.read_exact(&mut body).await?;
-            println!("Response body: {:?}", String::from_utf8_lossy(&body));
-            let response: ResponseMessage = serde_json::from_slice(&body)
-                .map_err(|e| anyhow!("Failed to parse response body: {}", e))?;
+    /// The text of `uri`: the open document's, or else the file's on disk.
+    async fn document_text(&self, uri: &str) -> Option<String> {
+        if let Some(document) = self.documents().get(uri) {
+            return Some(document.text.clone());
+        }
+        let path = crate::workspace::uri_to_path(uri)?;
+        tokio::fs::read_to_string(path).await.ok()
+    }
 
-            // If response has a valid id, return it
-            if response.id.is_some() {
-                return Ok(response);
+    /// Drops the cached results of the documents in `uris`.
+    async fn invalidate_cached<'a>(&self, uris: impl IntoIterator<Item = &'a str>) {
+        let Some(cache) = lock(&self.shared.result_cache).clone() else {
+            return;
+        };
+        for uri in uris {
+            if let Err(error) = cache.invalidate(uri).await {
+                self.shared.log.log(
+                    Level::Warn,
+                    format_args!("Failed to drop the cached results of {}: {:#}", uri, error),
+                );
             }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::protocol::RequestMessage;
-    use serde_json::json;
-    use tokio_test::io::Builder;
+    async fn request_with_retries(&self, request: RequestMessage) -> Result<RawResponse> {
+        let policy = self.shared.retry_policy().clone();
+        let Some(policy) = policy else {
+            return self.request_once(request).await;
+        };
+        let attempts = policy.attempts_for(&request.method);
+        let mut attempt = 1;
+        loop {
+            let mut changes_sent = self.shared.changes_sent.clone();
+            // Ids must stay unique while a request is pending, and a late
+            // response to an earlier attempt may still arrive.
+            let mut sent = request.clone();
+            if attempt > 1 {
+                sent.id = self.next_request_id().into();
+            }
+            let attempt_timeout = policy.timeout_per_attempt();
+            let response = match attempt_timeout {
+                // A timed out attempt is dropped, which cancels it.
+                Some(timeout) => tokio::time::timeout(timeout, self.request_once(sent))
+                    .await
+                    .unwrap_or_else(|elapsed| {
+                        Err(anyhow::Error::new(elapsed).context(format!(
+                            "Server didn't answer {} within {:?}",
+                            request.method, timeout
+                        )))
+                    }),
+                None => self.request_once(sent).await,
+            };
+            let response = match response {
+                Ok(response) if response.id.as_ref() != Some(&request.id) => {
+                    response.with_id(request.id.clone())
+                }
+                response => response,
+            };
+            let closing = self.shared.closing.load(Ordering::SeqCst);
+            if attempt >= attempts || closing || !RetryPolicy::is_transient(&response) {
+                return response;
+            }
+            let backoff = policy.backoff_after(attempt);
+            let content_modified =
+                matches!(&response, Ok(response) if response.is_content_modified());
+            if content_modified && policy.resyncs_on_content_modified() {
+                self.shared.log.log(
+                    Level::Debug,
+                    format_args!(
+                        "Resending {} request {} once the document is resynced",
+                        request.method, request.id
+                    ),
+                );
+                // Changes held back by the debounce go out now. Without any,
+                // the request waits for the next change or the backoff.
+                self.flush().await?;
+                let _ = tokio::time::timeout(backoff, changes_sent.changed()).await;
+                attempt += 1;
+                continue;
+            }
+            self.shared.log.log(
+                Level::Debug,
+                format_args!(
+                    "Retrying {} request {} in {:?} after attempt {} failed",
+                    request.method, request.id, backoff, attempt
+                ),
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
 
-    #[tokio::test]
-    async fn test_send_request_and_response() {
-        // Assume this is the exact request JSON your client will send
-        let request = RequestMessage::new_initialize(
-            1,
-            std::process::id(),
-            "file:///tmp".into(),
-            "unit_test_client".into(),
-            "0.1.0".into(),
-            vec![],
-        );
+    /// A fresh id for a request sent by a typed method.
+    pub(crate) fn next_request_id(&self) -> u32 {
+        self.shared.next_id.fetch_add(1, Ordering::Relaxed)
+    }
 
-        let request_json = serde_json::to_string(&request).unwrap();
-        let request_content_length = request_json.len();
+    /// Limits how often requests of some methods are sent. `None`, the
+    /// default, sends them as soon as they are made.
+    pub fn set_rate_limits(&self, limits: Option<RateLimits>) {
+        *lock(&self.shared.rate_limits) = limits;
+    }
 
-        // The actual response from the server
-        let response_payload = json!({
-            "jsonrpc": "2.0",
-            "id": 1, // Match the ID of the request
-            "result": {}
+    /// Retries idempotent requests that time out or fail with a
+    /// `ContentModified` or `ServerCancelled` error, each time with a fresh
+    /// id. `None`, the default, disables retries.
+    pub fn set_retry_policy(&self, policy: Option<RetryPolicy>) {
+        *self.shared.retry_policy() = policy;
+    }
+
+    async fn request_once(&self, request: RequestMessage) -> Result<RawResponse> {
+        #[cfg(feature = "otel")]
+        let telemetry = self.shared.telemetry.start(&request.method, &request.id);
+        #[cfg(feature = "tracing")]
+        let response = {
+            let span = tracing::info_span!(
+                parent: &self.shared.span,
+                "lsp_request",
+                method = %request.method,
+                id = %request.id,
+                duration_ms = tracing::field::Empty,
+                outcome = tracing::field::Empty,
+            );
+            let started = Instant::now();
+            let response = self.send_and_wait(request).instrument(span.clone()).await;
+            span.record("duration_ms", started.elapsed().as_secs_f64() * 1000.0);
+            span.record(
+                "outcome",
+                match &response {
+                    Ok(response) if response.error.is_some() => "error",
+                    Ok(_) => "ok",
+                    Err(_) => "failed",
+                },
+            );
+            response
+        };
+        #[cfg(not(feature = "tracing"))]
+        let response = self.send_and_wait(request).await;
+        #[cfg(feature = "otel")]
+        telemetry.finish(&response);
+        response
+    }
+
+    async fn send_and_wait(&self, request: RequestMessage) -> Result<RawResponse> {
+        let _in_flight = self.shared.activity.request_started();
+        let key = id_key(&request.id);
+        let rx = self.shared.pending.register(key.clone());
+        lock(&self.shared.sent_requests).insert(key.clone(), SentRequest::new(&request));
+        let cancel = CancelOnDrop {
+            shared: &self.shared,
+            id: Some(request.id.clone()),
+            key: key.clone(),
+        };
+
+        if let Err(e) = self.send_request(request).await {
+            cancel.disarm();
+            self.shared.pending.forget(&key);
+            return Err(e);
+        }
+        let response = rx.await;
+        cancel.disarm();
+        response.map_err(|_| {
+            let message = format!("Connection closed before the response to {} arrived", key);
+            anyhow!(self.shared.with_stderr(message))
         })
-        .to_string();
-        let response_content_length = response_payload.len();
-        let server_response = format!(
-            "Content-Length: {}\r\n\r\n{}",
-            response_content_length, response_payload
+    }
+
+    /// Asks the server to cancel the progress reported under `token` with
+    /// `window/workDoneProgress/cancel`. If `token` is the `workDoneToken`
+    /// of a pending request, the request is cancelled too: the server is
+    /// sent `$/cancelRequest` and the request resolves right away with a
+    /// `RequestCancelled` error.
+    pub async fn cancel_work_done_progress(&self, token: impl Into<ProgressToken>) -> Result<()> {
+        let token = token.into();
+        let ids = self.sent_request_ids(|request| request.work_done_token.as_ref() == Some(&token));
+        let cancel = NotificationMessage::new_work_done_progress_cancel(token);
+        self.cancel_requests(vec![cancel], ids, "its work done progress was cancelled")
+            .await
+    }
+
+    /// The requests waiting for their response, oldest first.
+    pub fn pending_requests(&self) -> Vec<PendingRequest> {
+        let mut pending: Vec<_> = lock(&self.shared.sent_requests)
+            .values()
+            .map(|request| PendingRequest {
+                id: request.id.clone(),
+                method: request.method.to_string(),
+                uri: request.uri.clone(),
+                elapsed: request.sent_at.elapsed(),
+            })
+            .collect();
+        pending.sort_by_key(|request| std::cmp::Reverse(request.elapsed));
+        pending
+    }
+
+    /// Cancels every pending request about the document `uri`, e.g. once it
+    /// is closed: the server is sent `$/cancelRequest` for each, and they
+    /// resolve right away with a `RequestCancelled` error. Returns how many
+    /// were cancelled.
+    pub async fn cancel_requests_for(&self, uri: &str) -> Result<usize> {
+        let ids = self.sent_request_ids(|request| request.uri.as_deref() == Some(uri));
+        let count = ids.len();
+        self.cancel_requests(Vec::new(), ids, "its document was discarded")
+            .await?;
+        Ok(count)
+    }
+
+    fn sent_request_ids(&self, matches: impl Fn(&SentRequest) -> bool) -> Vec<serde_json::Value> {
+        lock(&self.shared.sent_requests)
+            .values()
+            .filter(|request| matches(request))
+            .map(|request| request.id.clone())
+            .collect()
+    }
+
+    /// Sends `notifications` followed by `$/cancelRequest` for each of `ids`,
+    /// and resolves those requests with a `RequestCancelled` error.
+    async fn cancel_requests(
+        &self,
+        mut notifications: Vec<NotificationMessage>,
+        ids: Vec<serde_json::Value>,
+        reason: &str,
+    ) -> Result<()> {
+        notifications.extend(
+            ids.iter()
+                .cloned()
+                .map(NotificationMessage::new_cancel_request),
         );
+        self.shared
+            .lock_writer()
+            .await?
+            .write_notifications(&notifications)
+            .await?;
+        for id in &ids {
+            self.resolve_cancelled(id, reason)?;
+        }
+        Ok(())
+    }
 
-        // Set up the mock server
-        let mock_server = Builder::new()
-            .write(
-                format!(
-                    "Content-Length: {}\r\n\r\n{}",
-                    request_content_length, request_json
-                )
-                .as_bytes(),
+    fn resolve_cancelled(&self, id: &serde_json::Value, reason: &str) -> Result<()> {
+        let message = format!("Request was cancelled because {}", reason);
+        let response = ResponseMessage::new_error(id.clone(), REQUEST_CANCELLED, message);
+        let body = serde_json::to_vec(&response)?;
+        let response = RawResponse::new(Some(id.clone()), response.error, body);
+        // A response that arrived in the meantime is simply handed back.
+        let _ = self.shared.pending.complete(&id_key(id), response);
+        Ok(())
+    }
+
+    /// Merges rapid `did_change` calls into fewer `didChange` notifications.
+    /// Changes are held back for at most `debounce`, or until the next request
+    /// or `flush`. `None` sends every change immediately.
+    pub fn set_change_debounce(&self, debounce: Option<Duration>) {
+        self.documents().set_debounce(debounce);
+        // The deadlines of the changes already held back moved.
+        self.shared.changes_queued.send_replace(());
+    }
+
+    /// Converts the line endings of the text of documents opened and changed
+    /// from now on to `\n` before syncing them. Turned on by `initialize` when
+    /// the server reports `normalizesLineEndings`.
+    pub fn set_normalize_line_endings(&self, normalize: bool) {
+        self.documents().set_normalize_line_endings(normalize);
+    }
+
+    /// Makes the client convert positions between `encoding` and the one the
+    /// server picked. See `position_encoding`. `None`, the default, passes
+    /// positions through unchanged.
+    pub fn set_native_position_encoding(&self, encoding: Option<PositionEncoding>) {
+        *lock(&self.shared.native_position_encoding) = encoding;
+    }
+
+    /// Sends all pending document changes.
+    pub async fn flush(&self) -> Result<()> {
+        let mut writer = self.shared.lock_writer().await?;
+        let notifications = self.documents().take_pending();
+        writer.write_notifications(&notifications).await
+    }
+
+    /// When pending document changes are flushed at the latest. A background
+    /// task sends them then, unless something else flushed them first.
+    pub fn next_flush_deadline(&self) -> Option<Instant> {
+        self.documents().next_flush_deadline()
+    }
+
+    /// Opens a document on the server and starts tracking its version.
+    pub async fn did_open(&self, uri: String, language_id: String, text: String) -> Result<()> {
+        // Holding the writer while the store is updated keeps the order of
+        // notifications on the wire consistent with the document versions.
+        let mut writer = self.shared.lock_writer().await?;
+        let notification = {
+            let mut documents = self.documents();
+            let document = documents.open(uri, language_id, text);
+            NotificationMessage::new_did_open(
+                document.uri.clone(),
+                document.language_id.clone(),
+                document.version,
+                document.text.clone(),
             )
-            .read(server_response.as_bytes())
-            .build();
+        };
+        writer.write_notification(&notification).await
+    }
 
-        let mut lsp_client = LspClient {
-            stream: Box::pin(mock_server),
+    /// Opens `document` on the server at its version. Does nothing if a
+    /// document with its URI is open already.
+    pub(crate) async fn reopen(&self, document: TextDocument) -> Result<()> {
+        let mut writer = self.shared.lock_writer().await?;
+        let notification = {
+            let mut documents = self.documents();
+            if documents.get(&document.uri).is_some() {
+                return Ok(());
+            }
+            let document = documents.reopen(document);
+            NotificationMessage::new_did_open(
+                document.uri.clone(),
+                document.language_id.clone(),
+                document.version,
+                document.text.clone(),
+            )
         };
+        writer.write_notification(&notification).await
+    }
 
-        // Test sending the request
-        let send_result = lsp_client.send_request(request).await;
-        assert!(send_result.is_ok());
+    /// Applies `changes` to an open document, notifies the server and returns
+    /// the new version of the document.
+    pub async fn did_change(
+        &self,
+        uri: &str,
+        mut changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Result<i32> {
+        let mut writer = self.shared.lock_writer().await?;
+        let conversion = self.shared.position_conversion();
+        let (version, notifications) = {
+            let mut documents = self.documents();
+            documents.normalize_changes(&mut changes);
+            let stored = convert_changes(conversion, &documents, uri, &mut changes);
+            let version = documents.change(uri, stored.as_deref().unwrap_or(&changes))?;
+            if documents.debounce().is_some() {
+                documents.queue_change(uri, changes);
+                self.shared.changes_queued.send_replace(());
+                (version, documents.take_due(Instant::now()))
+            } else {
+                let notification =
+                    NotificationMessage::new_did_change(uri.to_string(), version, changes);
+                (version, vec![notification])
+            }
+        };
+        writer.write_notifications(&notifications).await?;
+        drop(writer);
+        self.invalidate_cached([uri]).await;
+        Ok(version)
+    }
 
-        // Test handling the response
-        let response = lsp_client.handle_response().await;
-        assert!(response.is_ok());
-        assert_eq!(response.unwrap().result.unwrap(), json!({}));
+    /// Applies a workspace edit to the open documents and notifies the server.
+    /// `confirm` is asked about each change annotation the edit uses, e.g. to
+    /// show a confirmation dialog; edits whose annotation it rejects are skipped.
+    /// Edits computed for another version of a document than the one open are
+    /// refused. Returns the new version of every changed document.
+    pub async fn apply_workspace_edit<F>(
+        &self,
+        edit: &WorkspaceEdit,
+        confirm: F,
+    ) -> Result<Vec<(String, i32)>>
+    where
+        F: FnMut(&str, &ChangeAnnotation) -> bool,
+    {
+        self.apply_document_edits(edit.content_changes(confirm)?)
+            .await
+    }
+
+    /// Applies the content changes of a workspace edit to the open documents
+    /// and notifies the server, refusing them all if one can't be applied.
+    pub(crate) async fn apply_document_edits(
+        &self,
+        document_edits: Vec<DocumentEdits>,
+    ) -> Result<Vec<(String, i32)>> {
+        let conversion = self.shared.position_conversion();
+        let mut writer = self.shared.lock_writer().await?;
+        let (versions, notifications) = {
+            let mut documents = self.documents();
+            // Check every document first, so that a failing edit changes nothing.
+            for edits in &document_edits {
+                match edits.version {
+                    Some(version) => documents.check_version(&edits.uri, version)?,
+                    None if documents.get(&edits.uri).is_none() => {
+                        return Err(anyhow!("Document {} is not open", edits.uri))
+                    }
+                    None => {}
+                }
+            }
+
+            let debounced = documents.debounce().is_some();
+            let mut versions = Vec::with_capacity(document_edits.len());
+            let mut notifications = Vec::new();
+            for DocumentEdits {
+                uri, mut changes, ..
+            } in document_edits
+            {
+                documents.normalize_changes(&mut changes);
+                let stored = convert_changes(conversion, &documents, &uri, &mut changes);
+                let version = documents.change(&uri, stored.as_deref().unwrap_or(&changes))?;
+                if debounced {
+                    documents.queue_change(&uri, changes);
+                } else {
+                    notifications.push(NotificationMessage::new_did_change(
+                        uri.clone(),
+                        version,
+                        changes,
+                    ));
+                }
+                versions.push((uri, version));
+            }
+            if debounced {
+                self.shared.changes_queued.send_replace(());
+                notifications.extend(documents.take_due(Instant::now()));
+            }
+            (versions, notifications)
+        };
+        writer.write_notifications(&notifications).await?;
+        drop(writer);
+        self.invalidate_cached(versions.iter().map(|(uri, _)| uri.as_str()))
+            .await;
+        Ok(versions)
+    }
+
+    /// Closes a document on the server and stops tracking it.
+    pub async fn did_close(&self, uri: &str) -> Result<()> {
+        let mut writer = self.shared.lock_writer().await?;
+        let pending = {
+            let mut documents = self.documents();
+            let pending = documents.take_pending_for(uri);
+            documents
+                .close(uri)
+                .ok_or_else(|| anyhow!("Document {} is not open", uri))?;
+            pending
+        };
+        let close = NotificationMessage::new_did_close(uri.to_string());
+        match pending {
+            Some(pending) => writer.write_notifications(&[pending, close]).await,
+            None => writer.write_notification(&close).await,
+        }
+    }
+
+    /// Locks the document store. Don't hold the guard across an `.await`.
+    pub fn documents(&self) -> MutexGuard<'_, DocumentStore> {
+        self.shared.documents()
+    }
+
+    /// Locks the client-side command handlers consulted by `run_command`.
+    /// Don't hold the guard across an `.await`.
+    pub fn commands(&self) -> MutexGuard<'_, CommandRegistry> {
+        lock(&self.shared.commands)
+    }
+
+    /// Captures the current version of `uri`. Take a guard when sending a
+    /// request about a document and check it with `is_current` when the
+    /// response arrives to drop results computed against older text.
+    pub fn version_guard(&self, uri: &str) -> Option<VersionGuard> {
+        self.documents().guard(uri)
+    }
+
+    /// Whether the document guarded by `guard` hasn't changed since.
+    pub fn is_current(&self, guard: &VersionGuard) -> bool {
+        guard.is_current(&self.documents())
+    }
+
+    /// Hands the client the process tree of its server, to be killed along
+    /// with the server.
+    pub(crate) fn adopt_process_tree(&self, tree: ProcessTree) {
+        *self
+            .shared
+            .process_tree
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(tree);
+    }
+
+    /// Hands the client the stderr of its server, captured by the launcher.
+    pub(crate) fn set_stderr(&self, stderr: StderrTail) {
+        *lock(&self.shared.stderr) = Some(stderr);
+    }
+
+    /// The end of what the server wrote to stderr, or `None` if the client
+    /// doesn't own the server process or its stderr wasn't piped. Errors
+    /// about the server going away quote it too.
+    pub fn server_stderr(&self) -> Option<String> {
+        lock(&self.shared.stderr).as_ref().map(StderrTail::contents)
+    }
+
+    /// Answers the server's `workspace/configuration` requests from `store`,
+    /// and sends `workspace/didChangeConfiguration` with all settings
+    /// whenever they change. Replaces an earlier store.
+    pub fn set_settings_store(&self, store: SettingsStore) {
+        let mut changes = store.subscribe();
+        changes.mark_unchanged();
+        let shared = Arc::downgrade(&self.shared);
+        let settings = store.clone();
+        let task = tokio::spawn(async move {
+            while changes.changed().await.is_ok() {
+                let Some(shared) = shared.upgrade() else {
+                    return;
+                };
+                let notification =
+                    NotificationMessage::new_did_change_configuration(settings.get(None, None));
+                let sent = match shared.lock_writer().await {
+                    Ok(mut writer) => writer.write_notification(&notification).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = sent {
+                    shared.log.log(
+                        Level::Warn,
+                        format_args!("Failed to push changed settings: {}", e),
+                    );
+                }
+            }
+        });
+        *self
+            .shared
+            .settings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(AttachedSettings {
+            store,
+            _sync: BackgroundTask(task),
+        });
+    }
+
+    /// The store attached with `set_settings_store`.
+    pub(crate) fn settings_store(&self) -> Option<SettingsStore> {
+        lock(&self.shared.settings)
+            .as_ref()
+            .map(|settings| settings.store.clone())
+    }
+
+    /// Sets how long `close` waits for each step of the shutdown sequence.
+    pub fn set_shutdown_grace_period(&self, grace_period: Duration) {
+        *self.shared.grace_period() = grace_period;
+    }
+
+    /// Shuts the server down gracefully: flushes pending changes, sends
+    /// `shutdown` and waits for its answer, sends `exit`, closes the connection
+    /// and kills the server process if it hasn't exited within the grace period.
+    /// Afterwards every handle of this client fails to send. Calling `close`
+    /// more than once is a no-op.
+    pub async fn close(&self) -> Result<()> {
+        if self.shared.closing.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.shared.set_state(ConnectionState::ShuttingDown);
+        let grace_period = *self.shared.grace_period();
+
+        let shutdown = RequestMessage::builder()
+            .id(SHUTDOWN_REQUEST_ID)
+            .method(methods::SHUTDOWN)
+            .build()?;
+        let shutdown_result = tokio::time::timeout(grace_period, self.request(shutdown)).await;
+        if !matches!(shutdown_result, Ok(Ok(_))) {
+            self.shared.log.log(
+                Level::Warn,
+                format_args!("Server didn't acknowledge shutdown, exiting anyway"),
+            );
+        }
+
+        let exit_result = {
+            let mut writer = self.shared.writer.lock().await;
+            self.shared.closed.store(true, Ordering::SeqCst);
+            let exit_result = writer
+                .write_notification(&NotificationMessage::new_exit())
+                .await;
+            // Closing our end lets servers waiting for EOF exit too.
+            let _ = writer.frames.shutdown().await;
+            exit_result
+        };
+
+        if let Some(mut child) = self.shared.child.lock().await.take() {
+            let status = match tokio::time::timeout(grace_period, child.wait()).await {
+                Ok(status) => status?,
+                Err(_) => {
+                    self.shared.log.log(
+                        Level::Warn,
+                        format_args!("Server didn't exit within {:?}, killing it", grace_period),
+                    );
+                    child.kill().await?;
+                    child.wait().await?
+                }
+            };
+            self.shared.report_exit(status, true);
+        }
+        // Dropping the tree kills what the server left behind.
+        self.shared
+            .process_tree
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        self.shared.set_state(ConnectionState::Closed);
+        exit_result
+    }
+
+    /// Waits for the next response that wasn't claimed by a `request` call.
+    /// Requests sent by the server in the meantime are answered, and
+    /// notifications are skipped.
+    pub async fn handle_response(&self) -> Result<ResponseMessage> {
+        self.handle_response_raw().await?.to_response()
+    }
+
+    /// Like `handle_response`, but keeps the body as raw bytes without parsing
+    /// the result, so large array results can be consumed item by item with
+    /// `RawResponse::items`.
+    pub async fn handle_response_raw(&self) -> Result<RawResponse> {
+        self.shared
+            .responses
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(|| anyhow!("Connection to the server was closed"))
+    }
+}
+
+impl Shared {
+    /// How positions are converted between the host and the server, if
+    /// their encodings differ.
+    fn position_conversion(&self) -> Option<Conversion> {
+        let native = (*lock(&self.native_position_encoding))?;
+        let initialize_result = lock(&self.initialize_result);
+        let server = negotiated_encoding(
+            initialize_result
+                .as_ref()
+                .map(|result| &result.capabilities),
+        );
+        Conversion::between(native, server)
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        // Once closed, a connection stays closed.
+        self.state.send_if_modified(|current| {
+            let changed = *current != state && *current != ConnectionState::Closed;
+            if changed {
+                *current = state;
+            }
+            changed
+        });
+    }
+
+    /// Reports the exit of a server that closed the connection on its own,
+    /// once it exited. A server that lingers past the grace period is left
+    /// to `close` or to the last handle being dropped.
+    async fn wait_for_crash(&self) {
+        let grace_period = *self.grace_period();
+        let status = {
+            let mut child = self.child.lock().await;
+            let Some(child) = child.as_mut() else {
+                return;
+            };
+            tokio::time::timeout(grace_period, child.wait()).await
+        };
+        if let Ok(Ok(status)) = status {
+            self.report_exit(status, false);
+        }
+    }
+
+    fn report_exit(&self, status: std::process::ExitStatus, was_requested: bool) {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+        let exit = ServerExit {
+            code: status.code(),
+            signal,
+            stderr_tail: lock(&self.stderr)
+                .as_ref()
+                .map(StderrTail::contents)
+                .unwrap_or_default(),
+            was_requested,
+        };
+        let level = if was_requested || exit.is_clean() {
+            Level::Debug
+        } else {
+            Level::Warn
+        };
+        self.log
+            .log(level, format_args!("Server exited with {}", status));
+        let _ = self.events.send(ClientEvent::ServerExit(exit));
+    }
+
+    /// Gives the stderr drain a moment to pick up the last words of a
+    /// server that just went away.
+    async fn stderr_settled(&self) {
+        let stderr = lock(&self.stderr).clone();
+        if let Some(stderr) = stderr {
+            let _ = tokio::time::timeout(STDERR_SETTLE_TIMEOUT, stderr.closed()).await;
+        }
+    }
+
+    /// Appends the end of the server's stderr to `message`, since that is
+    /// where servers say why they crashed.
+    fn with_stderr(&self, mut message: String) -> String {
+        let stderr = lock(&self.stderr).as_ref().map(StderrTail::contents);
+        if let Some(stderr) = stderr.filter(|stderr| !stderr.trim().is_empty()) {
+            message.push_str(&format!("\nstderr:\n{}", stderr.trim_end()));
+        }
+        message
+    }
+
+    /// Locks the writer, failing once the client was closed.
+    async fn lock_writer(&self) -> Result<tokio::sync::MutexGuard<'_, Writer>> {
+        let writer = self.writer.lock().await;
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(anyhow!("The client was closed"));
+        }
+        Ok(writer)
+    }
+
+    fn grace_period(&self) -> MutexGuard<'_, Duration> {
+        self.grace_period
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn retry_policy(&self) -> MutexGuard<'_, Option<RetryPolicy>> {
+        self.retry_policy
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn documents(&self) -> MutexGuard<'_, DocumentStore> {
+        self.documents
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Handles a request from the server and returns the response to send.
+    /// Called by the reader task, so it must not wait on the writer.
+    fn answer_server_request(
+        &self,
+        id: serde_json::Value,
+        method: &str,
+        params: &serde_json::Value,
+    ) -> Result<ResponseMessage> {
+        if self.shut_down.load(Ordering::SeqCst) {
+            return Ok(ResponseMessage::new_error(
+                id,
+                INVALID_REQUEST,
+                format!("Can't handle {} after shutdown", method),
+            ));
+        }
+        if let Some(result) = self.update_registrations(method, params) {
+            return Ok(match result {
+                Ok(()) => ResponseMessage::new_result(id, serde_json::Value::Null),
+                Err(e) => ResponseMessage::new_error(id, INVALID_PARAMS, e.to_string()),
+            });
+        }
+        let settings = match method {
+            methods::WORKSPACE_CONFIGURATION => self
+                .settings
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_ref()
+                .map(|settings| settings.store.answer(params)),
+            _ => None,
+        };
+        let response = match (RefreshKind::from_method(method), settings) {
+            // `Activity` already noted the token when the request arrived.
+            _ if method == methods::WINDOW_WORK_DONE_PROGRESS_CREATE => {
+                ResponseMessage::new_result(id, serde_json::Value::Null)
+            }
+            _ if method == methods::WORKSPACE_WORKSPACE_FOLDERS => {
+                // `null` tells the server that no workspace is open.
+                let folders = lock(&self.workspace_folders).clone();
+                let folders = match folders.is_empty() {
+                    true => serde_json::Value::Null,
+                    false => serde_json::to_value(folders)?,
+                };
+                ResponseMessage::new_result(id, folders)
+            }
+            (Some(kind), _) => {
+                // Nobody listening is not an error; the refresh is simply dropped.
+                let _ = self.events.send(ClientEvent::Refresh(kind));
+                ResponseMessage::new_result(id, serde_json::Value::Null)
+            }
+            (None, Some(settings)) => ResponseMessage::new_result(id, settings),
+            (None, None) => ResponseMessage::new_error(
+                id,
+                METHOD_NOT_FOUND,
+                format!("Unhandled method {}", method),
+            ),
+        };
+        Ok(response)
+    }
+
+    /// Applies a `client/registerCapability` or `client/unregisterCapability`
+    /// request. `None` for other methods.
+    fn update_registrations(&self, method: &str, params: &serde_json::Value) -> Option<Result<()>> {
+        #[derive(Deserialize)]
+        struct RegistrationParams {
+            registrations: Vec<Registration>,
+        }
+        #[derive(Deserialize)]
+        struct Unregistration {
+            id: String,
+        }
+        #[derive(Deserialize)]
+        struct UnregistrationParams {
+            // Misspelled in the specification.
+            unregisterations: Vec<Unregistration>,
+        }
+
+        let mut registrations = lock(&self.registrations);
+        let result = match method {
+            methods::CLIENT_REGISTER_CAPABILITY => {
+                RegistrationParams::deserialize(params).map(|params| {
+                    // A registration restored from a snapshot is registered
+                    // again by the restarted server.
+                    registrations.retain(|registration| {
+                        params
+                            .registrations
+                            .iter()
+                            .all(|new| new.id != registration.id)
+                    });
+                    registrations.extend(params.registrations)
+                })
+            }
+            methods::CLIENT_UNREGISTER_CAPABILITY => {
+                UnregistrationParams::deserialize(params).map(|params| {
+                    for unregistration in params.unregisterations {
+                        registrations.retain(|registration| registration.id != unregistration.id);
+                    }
+                })
+            }
+            _ => return None,
+        };
+        Some(result.map_err(|e| anyhow!("Invalid {} params: {}", method, e)))
+    }
+
+    /// Hands a response to the `request` call waiting for it, or queues it for
+    /// `handle_response` if nobody is.
+    fn dispatch_response(
+        &self,
+        response: RawResponse,
+        unclaimed: &mpsc::UnboundedSender<RawResponse>,
+    ) {
+        let unclaimed_response = match &response.id {
+            Some(id) => {
+                let key = id_key(id);
+                let shut_down = response.error.is_none()
+                    && lock(&self.sent_requests)
+                        .get(&key)
+                        .is_some_and(|sent| sent.method == methods::SHUTDOWN);
+                if shut_down {
+                    self.shut_down.store(true, Ordering::SeqCst);
+                }
+                self.pending.complete(&key, response)
+            }
+            None => Some(response),
+        };
+        if let Some(response) = unclaimed_response {
+            let _ = unclaimed.send(response);
+        }
+    }
+}
+
+/// A request waiting for its response. See `LspClient::pending_requests`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingRequest {
+    pub id: serde_json::Value,
+    pub method: String,
+    /// The document the request is about, if any.
+    pub uri: Option<String>,
+    /// How long ago the request was sent.
+    pub elapsed: Duration,
+}
+
+/// What a pending request is about.
+struct SentRequest {
+    id: serde_json::Value,
+    method: Cow<'static, str>,
+    uri: Option<String>,
+    work_done_token: Option<ProgressToken>,
+    sent_at: Instant,
+}
+
+impl SentRequest {
+    fn new(request: &RequestMessage) -> Self {
+        let params = &request.params;
+        let uri = params
+            .get("textDocument")
+            .and_then(|document| document.get("uri"))
+            .or_else(|| params.get("uri"))
+            .and_then(serde_json::Value::as_str);
+        SentRequest {
+            id: request.id.clone(),
+            method: request.method.clone(),
+            uri: uri.map(str::to_string),
+            work_done_token: params
+                .get("workDoneToken")
+                .and_then(|token| ProgressToken::deserialize(token).ok()),
+            sent_at: Instant::now(),
+        }
+    }
+}
+
+/// Cancels a request whose caller stopped waiting for the response: the
+/// request is forgotten and `$/cancelRequest` is sent in the background.
+struct CancelOnDrop<'a> {
+    shared: &'a Arc<Shared>,
+    /// `None` once the request needs no cancelling.
+    id: Option<serde_json::Value>,
+    key: String,
+}
+
+impl CancelOnDrop<'_> {
+    fn disarm(mut self) {
+        self.id = None;
+    }
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        // Done, whether it was cancelled or not.
+        lock(&self.shared.sent_requests).remove(&self.key);
+        let Some(id) = self.id.take() else {
+            return;
+        };
+        self.shared.pending.forget(&self.key);
+        // Without a runtime there is nobody to send the notification.
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let shared = Arc::downgrade(self.shared);
+        runtime.spawn(async move {
+            let Some(shared) = shared.upgrade() else {
+                return;
+            };
+            let cancel = NotificationMessage::new_cancel_request(id);
+            let Ok(mut writer) = shared.lock_writer().await else {
+                return;
+            };
+            let _ = writer.write_notification(&cancel).await;
+        });
+    }
+}
+
+/// The response to a request that identical ones wait for.
+type SharedResponse = std::result::Result<RawResponse, Arc<anyhow::Error>>;
+
+/// A query being sent, which identical ones wait for too.
+struct InFlightQuery {
+    key: QueryKey,
+    response: watch::Receiver<Option<SharedResponse>>,
+    /// The callers waiting for the response.
+    waiters: usize,
+    /// The task sending the request, whose cancellation cancels it.
+    task: tokio::task::AbortHandle,
+}
+
+/// What identical queries have in common. Compared rather than hashed, so
+/// the params aren't serialized again for every query.
+#[derive(PartialEq)]
+struct QueryKey {
+    method: Cow<'static, str>,
+    /// The version of the document the query is about.
+    version: Option<i32>,
+    params: serde_json::Value,
+}
+
+/// One caller waiting for an in-flight query. The last one to stop waiting
+/// forgets the query, and cancels it if it wasn't answered yet.
+struct QueryWaiter<'a> {
+    in_flight: &'a Mutex<Vec<InFlightQuery>>,
+    response: watch::Receiver<Option<SharedResponse>>,
+}
+
+impl Drop for QueryWaiter<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = lock(self.in_flight);
+        let Some(index) = in_flight
+            .iter()
+            .position(|query| query.response.same_channel(&self.response))
+        else {
+            return;
+        };
+        in_flight[index].waiters -= 1;
+        if in_flight[index].waiters == 0 {
+            // Dropping the request's future sends `$/cancelRequest`, unless
+            // it is done already.
+            in_flight.swap_remove(index).task.abort();
+        }
+    }
+}
+
+/// A settings store attached with `set_settings_store`, and the task pushing
+/// its changes.
+struct AttachedSettings {
+    store: SettingsStore,
+    _sync: BackgroundTask,
+}
+
+/// Best-effort fallback for clients dropped without `close`: the `exit`
+/// notification can't be written from a synchronous `drop`, but dropping the
+/// writer closes the server's stdin, which makes most servers exit. A server
+/// still running after the grace period is killed.
+impl Drop for Shared {
+    fn drop(&mut self) {
+        let tree = self
+            .process_tree
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let Some(mut child) = self.child.get_mut().take() else {
+            return;
+        };
+        let grace_period = *self
+            .grace_period
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    if tokio::time::timeout(grace_period, child.wait())
+                        .await
+                        .is_err()
+                    {
+                        let _ = child.kill().await;
+                    }
+                    drop(tree);
+                });
+            }
+            Err(_) => {
+                let _ = child.start_kill();
+            }
+        }
+    }
+}
+
+/// The `pending` map key of a request id. Ids can be numbers or strings, so
+/// their JSON form keeps `1` and `"1"` apart.
+fn id_key(id: &serde_json::Value) -> String {
+    id.to_string()
+}
+
+struct Writer {
+    frames: FrameWriter<WriteHalf<Stream>>,
+    // Reused across messages so that each one doesn't allocate a new body.
+    body_buf: Vec<u8>,
+    /// Counts the `didChange` notifications written, for requests waiting
+    /// to be resent after a `ContentModified` error.
+    changes_sent: watch::Sender<u64>,
+    #[cfg(feature = "schema-validation")]
+    validator: Arc<Mutex<Option<MessageValidator>>>,
+    session_log: Arc<Mutex<Option<SessionLog>>>,
+    uri_map: Arc<Mutex<Option<UriMap>>>,
+    log: Arc<Logger>,
+}
+
+impl Writer {
+    async fn write_notification(&mut self, notification: &NotificationMessage) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(method = %notification.method, "sending notification");
+        self.write_message(notification).await?;
+        self.count_change(notification);
+        Ok(())
+    }
+
+    /// Writes `notifications` together, in as few writes as the stream allows.
+    async fn write_notifications(&mut self, notifications: &[NotificationMessage]) -> Result<()> {
+        let Some((last, queued)) = notifications.split_last() else {
+            return Ok(());
+        };
+        for notification in queued {
+            self.queue_notification(notification)?;
+        }
+        self.write_notification(last).await
+    }
+
+    /// Queues `notification` to go out with the next message written.
+    fn queue_notification(&mut self, notification: &NotificationMessage) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(method = %notification.method, "sending notification");
+        self.encode(notification)?;
+        self.frames.queue_frame(&self.body_buf)?;
+        self.record();
+        self.count_change(notification);
+        Ok(())
+    }
+
+    fn count_change(&self, notification: &NotificationMessage) {
+        if notification.method == methods::TEXT_DOCUMENT_DID_CHANGE {
+            self.changes_sent.send_modify(|sent| *sent += 1);
+        }
+    }
+
+    async fn write_message<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        self.encode(message)?;
+        self.frames.write_frame(&self.body_buf).await?;
+        self.record();
+        Ok(())
+    }
+
+    /// Serializes `message` into `body_buf`.
+    fn encode<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        self.body_buf.clear();
+        match lock(&self.uri_map).as_ref() {
+            Some(uri_map) => {
+                let mut message = serde_json::to_value(message)?;
+                uri_map.rewrite_outgoing(&mut message);
+                serde_json::to_writer(&mut self.body_buf, &message)?;
+            }
+            None => serde_json::to_writer(&mut self.body_buf, message)?,
+        }
+        #[cfg(feature = "schema-validation")]
+        validate_message(
+            &self.validator,
+            &self.log,
+            Direction::Outgoing,
+            &self.body_buf,
+        );
+        Ok(())
+    }
+
+    /// Records the message in `body_buf` in the session log.
+    fn record(&self) {
+        record_message(
+            &self.session_log,
+            &self.log,
+            session_log::Direction::Outgoing,
+            &self.body_buf,
+        );
+    }
+}
+
+/// Reads every message from the server until the connection closes or the
+/// last client handle is dropped.
+async fn read_loop(
+    mut reader: FrameReader<ReadHalf<Stream>>,
+    shared: Weak<Shared>,
+    log: Arc<Logger>,
+    session_log: Arc<Mutex<Option<SessionLog>>>,
+    unclaimed: mpsc::UnboundedSender<RawResponse>,
+    incoming: broadcast::Sender<Arc<IncomingMessage>>,
+    mut paused: watch::Receiver<bool>,
+) {
+    loop {
+        // Fails once `Shared` is gone, along with the sender.
+        if paused.wait_for(|paused| !paused).await.is_err() {
+            return;
+        }
+        // Borrowed from the reader's buffer; only responses, which outlive
+        // this iteration, are copied out of it.
+        let body = match reader.next_frame().await {
+            Ok(body) => body,
+            Err(e) => {
+                let message = format!("Stopped reading from the server: {}", e);
+                let Some(shared) = shared.upgrade() else {
+                    log.log(Level::Debug, format_args!("{}", message));
+                    return;
+                };
+                // Expected once the client closed the connection itself.
+                if shared.closing.load(Ordering::SeqCst) {
+                    log.log(Level::Debug, format_args!("{}", message));
+                } else {
+                    shared.stderr_settled().await;
+                    log.log(Level::Warn, format_args!("{}", shared.with_stderr(message)));
+                }
+                // Nothing will answer the requests still waiting.
+                shared.pending.clear();
+                shared.set_state(ConnectionState::Closed);
+                if !shared.closing.load(Ordering::SeqCst) {
+                    shared.wait_for_crash().await;
+                }
+                return;
+            }
+        };
+        log.log(
+            Level::Trace,
+            format_args!("Received message: {}", String::from_utf8_lossy(body)),
+        );
+        record_message(&session_log, &log, session_log::Direction::Incoming, body);
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        let rewritten = rewrite_incoming(&shared.uri_map, body);
+        let body = rewritten.as_deref().unwrap_or(body);
+        let envelope: IncomingEnvelope = match serde_json::from_slice(body) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                log.log(
+                    Level::Warn,
+                    format_args!("Dropping message that isn't valid JSON-RPC: {}", e),
+                );
+                continue;
+            }
+        };
+        if let (Some(MethodName(method)), None) = (&envelope.method, &envelope.id) {
+            if shared.shut_down.load(Ordering::SeqCst) {
+                log.log(
+                    Level::Debug,
+                    format_args!("Dropping {} notification sent after shutdown", method),
+                );
+                continue;
+            }
+        }
+        shared.activity.received(
+            envelope
+                .method
+                .as_ref()
+                .map(|MethodName(method)| method.as_ref()),
+            envelope.params.as_ref(),
+        );
+        // `Shared` holds one receiver to subscribe new streams from.
+        let subscribed = incoming.receiver_count() > 1;
+        if subscribed {
+            if let Ok(mut message) = serde_json::from_slice::<serde_json::Value>(body) {
+                if let Some(conversion) = shared.position_conversion() {
+                    if let Some(params) = message.get_mut("params") {
+                        conversion.convert_incoming(params, &shared.documents());
+                    }
+                }
+                let _ = incoming.send(Arc::new(IncomingMessage::new(message)));
+            }
+        }
+        #[cfg(feature = "schema-validation")]
+        validate_message(&shared.validator, &log, Direction::Incoming, body);
+
+        #[cfg(feature = "tracing")]
+        if let Some(MethodName(method)) = &envelope.method {
+            match &envelope.id {
+                Some(id) => tracing::debug!(%method, %id, "received request"),
+                None => tracing::debug!(%method, "received notification"),
+            }
+        }
+
+        match (envelope.method, envelope.id) {
+            (Some(MethodName(method)), Some(id)) => {
+                let params = envelope.params.unwrap_or_default();
+                match shared.answer_server_request(id, &method, &params) {
+                    Ok(response) => send_response(&shared, method, response),
+                    Err(e) => log.log(
+                        Level::Warn,
+                        format_args!("Failed to answer {} request: {}", method, e),
+                    ),
+                }
+            }
+            (Some(MethodName(_)), None) if subscribed => {}
+            (Some(MethodName(method)), None) => log.log(
+                Level::Debug,
+                format_args!("Dropping {} notification, nobody subscribed to it", method),
+            ),
+            (None, Some(id)) => {
+                let response = RawResponse::new(Some(id), envelope.error, body.to_vec());
+                shared.dispatch_response(response, &unclaimed);
+            }
+            (None, None) => log.log(
+                Level::Warn,
+                format_args!("Dropping response without an id: {:?}", envelope.error),
+            ),
+        }
+    }
+}
+
+/// Writes the response to a server request in the background. Waiting for
+/// the writer in the reader task would deadlock once the writer is stuck on
+/// a server that is itself stuck writing to us.
+fn send_response(shared: &Arc<Shared>, method: Cow<'static, str>, response: ResponseMessage) {
+    let shared = Arc::downgrade(shared);
+    tokio::spawn(async move {
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        let sent = match shared.lock_writer().await {
+            Ok(mut writer) => writer.write_message(&response).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = sent {
+            shared.log.log(
+                Level::Warn,
+                format_args!("Failed to answer {} request: {}", method, e),
+            );
+        }
+    });
+}
+
+/// Sends the changes held back by the debounce once their deadline passed,
+/// until the last client handle is dropped.
+async fn flush_loop(shared: Weak<Shared>, mut queued: watch::Receiver<()>) {
+    loop {
+        // Marked before the deadline is read, so no change slips through.
+        queued.mark_unchanged();
+        let Some(deadline) = shared
+            .upgrade()
+            .map(|shared| shared.documents().next_flush_deadline())
+        else {
+            return;
+        };
+        let Some(deadline) = deadline else {
+            // Fails once `Shared` is gone, along with the sender.
+            if queued.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline.into()) => {}
+            changed = queued.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                continue;
+            }
+        }
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        let sent = match shared.lock_writer().await {
+            // `deadline` passed on the tokio clock, which runs ahead of
+            // `Instant` when it is paused.
+            Ok(mut writer) => {
+                let notifications = shared.documents().take_due(deadline.max(Instant::now()));
+                writer.write_notifications(&notifications).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = sent {
+            shared.log.log(
+                Level::Warn,
+                format_args!("Failed to flush pending changes: {}", e),
+            );
+            if shared.closed.load(Ordering::SeqCst) {
+                return;
+            }
+        }
+    }
+}
+
+/// Converts `changes` to `uri` to the server's encoding, if there is a
+/// conversion, and returns them as the document store counts.
+fn convert_changes(
+    conversion: Option<Conversion>,
+    documents: &DocumentStore,
+    uri: &str,
+    changes: &mut Vec<TextDocumentContentChangeEvent>,
+) -> Option<Vec<TextDocumentContentChangeEvent>> {
+    let (Some(conversion), Some(document)) = (conversion, documents.get(uri)) else {
+        return None;
+    };
+    let (stored, sent) = conversion.changes(&document.text, std::mem::take(changes));
+    *changes = sent;
+    Some(stored)
+}
+
+/// `body` with its paths rewritten, if a URI map is set and `body` is JSON.
+fn rewrite_incoming(uri_map: &Mutex<Option<UriMap>>, body: &[u8]) -> Option<Vec<u8>> {
+    let uri_map = lock(uri_map);
+    let uri_map = uri_map.as_ref()?;
+    let mut message: serde_json::Value = serde_json::from_slice(body).ok()?;
+    uri_map.rewrite_incoming(&mut message);
+    serde_json::to_vec(&message).ok()
+}
+
+/// Records a message in the session log, if one is attached.
+fn record_message(
+    session_log: &Mutex<Option<SessionLog>>,
+    log: &Logger,
+    direction: session_log::Direction,
+    body: &[u8],
+) {
+    let mut session_log = lock(session_log);
+    let Some(session_log) = session_log.as_mut() else {
+        return;
+    };
+    if let Err(e) = session_log.record(direction, body) {
+        log.log(
+            Level::Warn,
+            format_args!("Failed to write the session log: {}", e),
+        );
+    }
+}
+
+/// Logs the schema mismatches of a message, if validation is enabled.
+#[cfg(feature = "schema-validation")]
+fn validate_message(
+    validator: &Mutex<Option<MessageValidator>>,
+    log: &Logger,
+    direction: Direction,
+    body: &[u8],
+) {
+    let mut validator = lock(validator);
+    let Some(validator) = validator.as_mut() else {
+        return;
+    };
+    let Ok(message) = serde_json::from_slice(body) else {
+        return;
+    };
+    let (method, mismatches) = validator.check(direction, &message);
+    let method = method.as_deref().unwrap_or("unknown method");
+    for mismatch in mismatches {
+        log.log(
+            Level::Warn,
+            format_args!(
+                "Schema mismatch in {:?} {}: {}",
+                direction, method, mismatch
+            ),
+        );
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The parts of a message needed to route it. The result, which can be huge,
+/// is skipped without being built.
+#[derive(Deserialize)]
+struct IncomingEnvelope {
+    id: Option<serde_json::Value>,
+    method: Option<MethodName>,
+    params: Option<serde_json::Value>,
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct MethodName(#[serde(deserialize_with = "deserialize_method")] Cow<'static, str>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio_test::io::Builder;
+
+    /// Reads one framed message on the server side of a test connection.
+    async fn read_frame<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> serde_json::Value {
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            if let Some(length) = line.strip_prefix("Content-Length:") {
+                content_length = length.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_send_request_and_response() {
+        // Assume this is the exact request JSON your client will send
+        let request = RequestMessage::new_initialize(
+            1,
+            std::process::id(),
+            "file:///tmp".into(),
+            "unit_test_client".into(),
+            "0.1.0".into(),
+            vec![],
+        );
+
+        let request_json = serde_json::to_string(&request).unwrap();
+        let request_content_length = request_json.len();
+
+        // The actual response from the server
+        let response_payload = json!({
+            "jsonrpc": "2.0",
+            "id": 1, // Match the ID of the request
+            "result": {}
+        })
+        .to_string();
+        let response_content_length = response_payload.len();
+        let server_response = format!(
+            "Content-Length: {}\r\n\r\n{}",
+            response_content_length, response_payload
+        );
+
+        // Set up the mock server
+        let mock_server = Builder::new()
+            .write(
+                format!(
+                    "Content-Length: {}\r\n\r\n{}",
+                    request_content_length, request_json
+                )
+                .as_bytes(),
+            )
+            .read(server_response.as_bytes())
+            .build();
+
+        let lsp_client = LspClient::from_stream(mock_server);
+
+        // Test sending the request
+        let send_result = lsp_client.send_request(request).await;
+        assert!(send_result.is_ok());
+
+        // Test handling the response
+        let response = lsp_client.handle_response().await;
+        assert!(response.is_ok());
+        assert_eq!(response.unwrap().result.unwrap(), json!({}));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "lsp-3-16")]
+    async fn test_refresh_request_is_acknowledged() {
+        let frame = |payload: &str| format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+
+        let mock_server = Builder::new()
+            .read(
+                frame(r#"{"jsonrpc":"2.0","id":7,"method":"workspace/codeLens/refresh"}"#)
+                    .as_bytes(),
+            )
+            .write(frame(r#"{"jsonrpc":"2.0","id":7,"result":null}"#).as_bytes())
+            .read(frame(r#"{"jsonrpc":"2.0","id":1,"result":[]}"#).as_bytes())
+            .build();
+
+        let lsp_client = LspClient::from_stream(mock_server);
+        let mut events = lsp_client.subscribe_events();
+
+        let response = lsp_client.handle_response().await.unwrap();
+        assert_eq!(response.id, Some(json!(1)));
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ClientEvent::Refresh(RefreshKind::CodeLens)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_raw_response_items() {
+        let payload = r#"{"jsonrpc":"2.0","id":2,"result":[{"name":"a"},{"name":"b"}]}"#;
+        let mock_server = Builder::new()
+            .read(format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload).as_bytes())
+            .build();
+
+        let lsp_client = LspClient::from_stream(mock_server);
+        let response = lsp_client.handle_response_raw().await.unwrap();
+        assert_eq!(response.id, Some(json!(2)));
+
+        let names: Vec<serde_json::Value> = response
+            .items::<serde_json::Value>()
+            .unwrap()
+            .map(|item| item.unwrap()["name"].clone())
+            .collect();
+        assert_eq!(names, vec![json!("a"), json!("b")]);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_from_cloned_handles() {
+        fn assert_handle<T: Clone + Send + Sync + 'static>() {}
+        assert_handle::<LspClient>();
+
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+
+        // A server that answers both requests in reverse order.
+        let server = tokio::spawn(async move {
+            let (read_half, mut write_half) = tokio::io::split(server_end);
+            let mut reader = BufReader::new(read_half);
+            let first = read_frame(&mut reader).await;
+            let second = read_frame(&mut reader).await;
+            for request in [second, first] {
+                let payload = json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": request["id"]
+                })
+                .to_string();
+                let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+                write_half.write_all(frame.as_bytes()).await.unwrap();
+            }
+        });
+
+        // At different positions, so that they aren't coalesced.
+        let definition = |id| {
+            RequestMessage::new_get_definition(
+                id,
+                "file:///main.go".to_string(),
+                crate::protocol::Position::new(0, id),
+            )
+        };
+        let other = client.clone();
+        let (first, second) =
+            tokio::join!(client.request(definition(1)), other.request(definition(2)));
+
+        assert_eq!(first.unwrap().result, Some(json!(1)));
+        assert_eq!(second.unwrap().result, Some(json!(2)));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_call_assigns_ids() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+
+        // A server that sends a notification first, then answers in reverse
+        // order with the method of each request.
+        let server = tokio::spawn(async move {
+            let (read_half, mut write_half) = tokio::io::split(server_end);
+            let mut reader = BufReader::new(read_half);
+            let first = read_frame(&mut reader).await;
+            let second = read_frame(&mut reader).await;
+            let log = json!({
+                "jsonrpc": "2.0",
+                "method": "window/logMessage",
+                "params": { "type": 3, "message": "indexing" }
+            });
+            let responses = [second, first].map(|request| {
+                json!({ "jsonrpc": "2.0", "id": request["id"], "result": request["method"] })
+            });
+            for message in std::iter::once(log).chain(responses) {
+                let payload = message.to_string();
+                let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+                write_half.write_all(frame.as_bytes()).await.unwrap();
+            }
+        });
+
+        let (first, second) = tokio::join!(
+            client.call("gopls/workspace/packages", json!({})),
+            client.call("rust-analyzer/analyzerStatus", json!({}))
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+
+        assert_ne!(first.id, second.id);
+        assert_eq!(first.result, Some(json!("gopls/workspace/packages")));
+        assert_eq!(second.result, Some(json!("rust-analyzer/analyzerStatus")));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_is_cancelled() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let (read_half, mut write_half) = tokio::io::split(server_end);
+        let mut reader = BufReader::new(read_half);
+
+        let request = RequestMessage::new_hover(
+            4,
+            "file:///main.go".to_string(),
+            crate::protocol::Position::new(0, 0),
+        );
+        let timed_out =
+            tokio::time::timeout(Duration::from_millis(50), client.request(request)).await;
+        assert!(timed_out.is_err());
+        assert_eq!(
+            read_frame(&mut reader).await["method"],
+            "textDocument/hover"
+        );
+        assert_eq!(
+            read_frame(&mut reader).await,
+            json!({ "jsonrpc": "2.0", "method": "$/cancelRequest", "params": { "id": 4 } })
+        );
+
+        // The late response goes to `handle_response` like any unclaimed one.
+        let payload = r#"{"jsonrpc":"2.0","id":4,"result":null}"#;
+        let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+        write_half.write_all(frame.as_bytes()).await.unwrap();
+        assert_eq!(client.handle_response().await.unwrap().id, Some(json!(4)));
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_work_done_progress_cancels_the_request() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let (read_half, _write_half) = tokio::io::split(server_end);
+        let mut reader = BufReader::new(read_half);
+
+        let request = RequestMessage::builder()
+            .id(5)
+            .method("workspace/symbol")
+            .params(json!({ "query": "App", "workDoneToken": "symbols" }))
+            .build()
+            .unwrap();
+        let (response, _) = tokio::join!(client.request(request), async {
+            assert_eq!(read_frame(&mut reader).await["id"], 5);
+            client.cancel_work_done_progress("symbols").await.unwrap();
+        });
+        let error = response.unwrap().error.unwrap();
+        assert_eq!(error["code"], REQUEST_CANCELLED);
+
+        assert_eq!(
+            read_frame(&mut reader).await,
+            json!({
+                "jsonrpc": "2.0",
+                "method": "window/workDoneProgress/cancel",
+                "params": { "token": "symbols" }
+            })
+        );
+        assert_eq!(read_frame(&mut reader).await["params"], json!({ "id": 5 }));
+        assert!(client.pending_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pending_requests_for_a_document_are_cancelled() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let (read_half, _write_half) = tokio::io::split(server_end);
+        let mut reader = BufReader::new(read_half);
+
+        let position = crate::protocol::Position::new(0, 0);
+        let hover = RequestMessage::new_hover(1, "file:///main.go".to_string(), position);
+        let symbols = RequestMessage::builder()
+            .id(2)
+            .method("workspace/symbol")
+            .params(json!({ "query": "App" }))
+            .build()
+            .unwrap();
+        let (hover, _) = tokio::join!(client.request(hover), async {
+            read_frame(&mut reader).await;
+            let other = client.clone();
+            tokio::spawn(async move { other.request(symbols).await });
+            read_frame(&mut reader).await;
+
+            let pending = client.pending_requests();
+            assert_eq!(pending.len(), 2);
+            assert_eq!(pending[0].method, "textDocument/hover");
+            assert_eq!(pending[0].uri.as_deref(), Some("file:///main.go"));
+            assert_eq!(pending[1].uri, None);
+
+            assert_eq!(
+                client.cancel_requests_for("file:///main.go").await.unwrap(),
+                1
+            );
+            assert_eq!(read_frame(&mut reader).await["params"], json!({ "id": 1 }));
+        });
+        assert_eq!(hover.unwrap().error.unwrap()["code"], REQUEST_CANCELLED);
+        assert_eq!(client.pending_requests()[0].method, "workspace/symbol");
+    }
+
+    #[tokio::test]
+    async fn test_connection_states() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let (read_half, mut write_half) = tokio::io::split(server_end);
+        let mut reader = BufReader::new(read_half);
+        let mut states = client.watch_state();
+        assert_eq!(client.state(), ConnectionState::Spawning);
+
+        let initialize = |id: u32| {
+            RequestMessage::builder()
+                .id(id)
+                .method(methods::INITIALIZE)
+                .params(json!({ "capabilities": {} }))
+                .build()
+                .unwrap()
+        };
+        // A wedged server.
+        client.set_initialize_timeout(Some(Duration::from_millis(20)));
+        let error = client.request(initialize(1)).await.unwrap_err();
+        assert!(error.to_string().contains("didn't answer initialize"));
+        assert_eq!(client.state(), ConnectionState::Spawning);
+        read_frame(&mut reader).await;
+        read_frame(&mut reader).await;
+
+        client.set_initialize_timeout(None);
+        let (response, _) = tokio::join!(client.request(initialize(2)), async {
+            read_frame(&mut reader).await;
+            assert_eq!(*states.borrow_and_update(), ConnectionState::Initializing);
+            let payload = r#"{"jsonrpc":"2.0","id":2,"result":{"capabilities":{}}}"#;
+            let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+            write_half.write_all(frame.as_bytes()).await.unwrap();
+        });
+        response.unwrap();
+        assert_eq!(client.state(), ConnectionState::Initializing);
+        client
+            .send_request(NotificationMessage::new_initialized())
+            .await
+            .unwrap();
+        assert_eq!(client.state(), ConnectionState::Ready);
+
+        drop((reader, write_half));
+        states
+            .wait_for(|state| *state == ConnectionState::Closed)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_requests_before_initialize() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let (read_half, mut write_half) = tokio::io::split(server_end);
+        let mut reader = BufReader::new(read_half);
+        let position = crate::protocol::Position::new(0, 0);
+        let hover = |id| RequestMessage::new_hover(id, "file:///main.go".to_string(), position);
+
+        client.set_before_initialize(BeforeInitialize::Reject);
+        let error = client.request(hover(1)).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<NotInitialized>(),
+            Some(&NotInitialized {
+                method: "textDocument/hover".to_string()
+            })
+        );
+
+        client.set_before_initialize(BeforeInitialize::Queue);
+        let initialize = RequestMessage::builder()
+            .id(2)
+            .method(methods::INITIALIZE)
+            .params(json!({ "capabilities": {} }))
+            .build()
+            .unwrap();
+        let respond = |id: u32, result: &str| {
+            let payload = format!(r#"{{"jsonrpc":"2.0","id":{},"result":{}}}"#, id, result);
+            format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload)
+        };
+        let (hover, initialized, _) = tokio::join!(
+            client.request(hover(3)),
+            async {
+                // Let the hover be queued first.
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                let response = client.request(initialize).await?;
+                // Queued requests wait for `initialized` too.
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                client
+                    .send_request(NotificationMessage::new_initialized())
+                    .await?;
+                anyhow::Ok(response)
+            },
+            async {
+                // The hover only goes out once `initialized` was sent.
+                assert_eq!(read_frame(&mut reader).await["method"], "initialize");
+                let frame = respond(2, r#"{"capabilities":{}}"#);
+                write_half.write_all(frame.as_bytes()).await.unwrap();
+                assert_eq!(read_frame(&mut reader).await["method"], "initialized");
+                assert_eq!(read_frame(&mut reader).await["id"], 3);
+                write_half
+                    .write_all(respond(3, "null").as_bytes())
+                    .await
+                    .unwrap();
+            }
+        );
+        initialized.unwrap();
+        assert_eq!(hover.unwrap().id, Some(json!(3)));
+    }
+
+    #[tokio::test]
+    async fn test_server_traffic_after_shutdown() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let (read_half, mut write_half) = tokio::io::split(server_end);
+        let mut reader = BufReader::new(read_half);
+        let mut incoming = client.incoming_receiver();
+        let frame = |payload: &str| format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+
+        let shutdown = RequestMessage::builder()
+            .id(1)
+            .method(methods::SHUTDOWN)
+            .build()
+            .unwrap();
+        let (response, _) = tokio::join!(client.request(shutdown), async {
+            read_frame(&mut reader).await;
+            let ack = frame(r#"{"jsonrpc":"2.0","id":1,"result":null}"#);
+            write_half.write_all(ack.as_bytes()).await.unwrap();
+        });
+        response.unwrap();
+
+        let notification =
+            r#"{"jsonrpc":"2.0","method":"window/logMessage","params":{"type":3,"message":"bye"}}"#;
+        let request =
+            r#"{"jsonrpc":"2.0","id":7,"method":"workspace/configuration","params":{"items":[]}}"#;
+        write_half
+            .write_all((frame(notification) + &frame(request)).as_bytes())
+            .await
+            .unwrap();
+        let answer = read_frame(&mut reader).await;
+        assert_eq!(answer["id"], 7);
+        assert_eq!(answer["error"]["code"], INVALID_REQUEST);
+        // The notification never made it past the reader.
+        while let Ok(message) = incoming.try_recv() {
+            assert_ne!(message.method(), Some("window/logMessage"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_capability_registrations() {
+        let frame = |payload: &str| format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+        let register = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "client/registerCapability",
+            "params": { "registrations": [
+                {
+                    "id": "format-ts",
+                    "method": "textDocument/formatting",
+                    "registerOptions": { "documentSelector": [{ "pattern": "**/*.ts" }] }
+                },
+                { "id": "watch", "method": "workspace/didChangeWatchedFiles" }
+            ] }
+        });
+        let unregister = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "client/unregisterCapability",
+            "params": { "unregisterations": [{ "id": "watch", "method": "workspace/didChangeWatchedFiles" }] }
+        });
+
+        let mock_server = Builder::new()
+            .read(frame(&register.to_string()).as_bytes())
+            .write(frame(r#"{"jsonrpc":"2.0","id":1,"result":null}"#).as_bytes())
+            .read(frame(&unregister.to_string()).as_bytes())
+            .write(frame(r#"{"jsonrpc":"2.0","id":2,"result":null}"#).as_bytes())
+            .read(frame(r#"{"jsonrpc":"2.0","id":3,"result":null}"#).as_bytes())
+            .build();
+        let client = LspClient::from_stream(mock_server);
+        client.handle_response().await.unwrap();
+
+        let formatting = "textDocument/formatting";
+        assert!(client.is_registered(formatting, "file:///web/app.ts", "typescript"));
+        assert!(!client.is_registered(formatting, "file:///web/app.js", "javascript"));
+        assert_eq!(
+            client
+                .registrations()
+                .iter()
+                .map(|registration| registration.id.as_str())
+                .collect::<Vec<_>>(),
+            ["format-ts"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_initialize_records_server_info() {
+        let initialize = || {
+            RequestMessage::builder()
+                .id(1)
+                .method(methods::INITIALIZE)
+                .build()
+                .unwrap()
+        };
+        let respond = |version: &'static str| {
+            let payload = json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": {
+                    "capabilities": { "hoverProvider": true },
+                    "serverInfo": { "name": "gopls", "version": version }
+                }
+            })
+            .to_string();
+            format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload)
+        };
+
+        for (version, supported) in [("v0.13.2", true), ("v0.10.0", false)] {
+            let (client_end, server_end) = tokio::io::duplex(4096);
+            let client = LspClient::from_stream(client_end);
+            client.set_server_quirks(
+                ServerQuirks::new()
+                    .minimum_version("gopls", "0.11.0")
+                    .quirk_below("gopls", "0.14.0", "definition-returns-location"),
+            );
+            let (read_half, mut write_half) = tokio::io::split(server_end);
+            let server = tokio::spawn(async move {
+                read_frame(&mut BufReader::new(read_half)).await;
+                write_half
+                    .write_all(respond(version).as_bytes())
+                    .await
+                    .unwrap();
+            });
+
+            let response = client.request(initialize()).await;
+            server.await.unwrap();
+            assert_eq!(response.is_ok(), supported);
+            if supported {
+                assert_eq!(client.server_info().unwrap().name, "gopls");
+                let capabilities = client.server_capabilities().unwrap();
+                assert!(capabilities.hover_provider.unwrap().is_enabled());
+                assert!(client.has_quirk("definition-returns-location"));
+            } else {
+                assert_eq!(client.server_info(), None);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_initialize_turns_on_line_ending_normalization() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let (read_half, mut write_half) = tokio::io::split(server_end);
+        let mut reader = BufReader::new(read_half);
+        let server = tokio::spawn(async move {
+            read_frame(&mut reader).await;
+            let payload = r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"normalizesLineEndings":true}}}"#;
+            let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+            write_half.write_all(frame.as_bytes()).await.unwrap();
+            read_frame(&mut reader).await
+        });
+
+        let initialize = RequestMessage::builder()
+            .id(1)
+            .method(methods::INITIALIZE)
+            .build()
+            .unwrap();
+        client.request(initialize).await.unwrap();
+        assert!(client.documents().normalizes_line_endings());
+
+        client
+            .did_open(
+                "file:///main.go".to_string(),
+                "go".to_string(),
+                "a\r\nb".to_string(),
+            )
+            .await
+            .unwrap();
+        let did_open = server.await.unwrap();
+        assert_eq!(did_open["params"]["textDocument"]["text"], "a\nb");
+    }
+
+    #[tokio::test]
+    async fn test_close_sends_shutdown_and_exit() {
+        let frame = |payload: &str| format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+
+        let mock_server = Builder::new()
+            .write(
+                frame(r#"{"jsonrpc":"2.0","id":"lsp-client-rs/shutdown","notification":0,"method":"shutdown"}"#)
+                    .as_bytes(),
+            )
+            .read(frame(r#"{"jsonrpc":"2.0","id":"lsp-client-rs/shutdown","result":null}"#).as_bytes())
+            .write(frame(r#"{"jsonrpc":"2.0","method":"exit"}"#).as_bytes())
+            .build();
+
+        let lsp_client = LspClient::from_stream(mock_server);
+        lsp_client.close().await.unwrap();
+        lsp_client.close().await.unwrap();
+        assert!(lsp_client
+            .send_request(RequestMessage::new_shutdown(1))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_workspace_edit_for_other_version_is_refused() {
+        let (client_end, _server_end) = tokio::io::duplex(4096);
+        let lsp_client = LspClient::from_stream(client_end);
+        lsp_client
+            .did_open(
+                "file:///main.go".to_string(),
+                "go".to_string(),
+                "var foo".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let edit = |version: i32| -> WorkspaceEdit {
+            serde_json::from_value(json!({
+                "documentChanges": [{
+                    "textDocument": { "uri": "file:///main.go", "version": version },
+                    "edits": [{
+                        "range": {
+                            "start": { "line": 0, "character": 4 },
+                            "end": { "line": 0, "character": 7 }
+                        },
+                        "newText": "bar"
+                    }]
+                }]
+            }))
+            .unwrap()
+        };
+
+        assert!(lsp_client
+            .apply_workspace_edit(&edit(3), |_, _| true)
+            .await
+            .is_err());
+        assert_eq!(
+            lsp_client.documents().get("file:///main.go").unwrap().text,
+            "var foo"
+        );
+
+        let versions = lsp_client
+            .apply_workspace_edit(&edit(0), |_, _| true)
+            .await
+            .unwrap();
+        assert_eq!(versions, vec![("file:///main.go".to_string(), 1)]);
+        assert_eq!(
+            lsp_client.documents().get("file:///main.go").unwrap().text,
+            "var bar"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_content_modified_is_retried() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        let (server_read, mut server_write) = tokio::io::split(server_side);
+        let lsp_client = LspClient::from_stream(client_side);
+        lsp_client.set_retry_policy(Some(
+            RetryPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(1)),
+        ));
+
+        let server = tokio::spawn(async move {
+            let mut reader = BufReader::new(server_read);
+            let mut requests = Vec::new();
+            for mut response in [
+                json!({ "jsonrpc": "2.0", "error": { "code": -32801, "message": "modified" } }),
+                json!({ "jsonrpc": "2.0", "result": { "contents": "hello" } }),
+            ] {
+                let request = read_frame(&mut reader).await;
+                response["id"] = request["id"].clone();
+                requests.push((request["method"].clone(), request["id"].clone()));
+                let payload = response.to_string();
+                let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+                server_write.write_all(frame.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let position = crate::protocol::Position::new(0, 0);
+        let hover = RequestMessage::new_hover(1, "file:///main.go".to_string(), position);
+        let response = lsp_client.request(hover).await.unwrap();
+        assert_eq!(response.id, Some(json!(1)));
+        assert_eq!(response.result, Some(json!({ "contents": "hello" })));
+        let requests = server.await.unwrap();
+        assert_eq!(
+            requests
+                .iter()
+                .map(|(method, _)| method)
+                .collect::<Vec<_>>(),
+            [&json!("textDocument/hover"), &json!("textDocument/hover")]
+        );
+        // The retry isn't sent with the id of the first attempt.
+        assert_eq!(requests[0].1, json!(1));
+        assert_ne!(requests[1].1, json!(1));
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_attempt_is_retried() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        let lsp_client = LspClient::from_stream(client_side);
+        lsp_client.set_retry_policy(Some(
+            RetryPolicy::new()
+                .backoff(Duration::from_millis(1), Duration::from_millis(1))
+                .attempt_timeout(Duration::from_millis(50)),
+        ));
+
+        // A server that only answers the second attempt.
+        let server = tokio::spawn(async move {
+            let (read_half, mut write_half) = tokio::io::split(server_side);
+            let mut reader = BufReader::new(read_half);
+            let first = read_frame(&mut reader).await;
+            let cancel = read_frame(&mut reader).await;
+            assert_eq!(cancel["method"], "$/cancelRequest");
+            assert_eq!(cancel["params"]["id"], first["id"]);
+            let second = read_frame(&mut reader).await;
+            let payload = json!({ "jsonrpc": "2.0", "id": second["id"], "result": null });
+            let payload = payload.to_string();
+            let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+            write_half.write_all(frame.as_bytes()).await.unwrap();
+            (first["id"].clone(), second["id"].clone())
+        });
+
+        let position = crate::protocol::Position::new(0, 0);
+        let hover = RequestMessage::new_hover(1, "file:///main.go".to_string(), position);
+        let response = lsp_client.request(hover).await.unwrap();
+        assert_eq!(response.id, Some(json!(1)));
+        let (first, second) = server.await.unwrap();
+        assert_eq!(first, json!(1));
+        assert_ne!(second, first);
+    }
+
+    #[tokio::test]
+    async fn test_closed_connection_is_not_retried() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        let lsp_client = LspClient::from_stream(client_side);
+        lsp_client.set_retry_policy(Some(
+            RetryPolicy::new().backoff(Duration::from_secs(30), Duration::from_secs(30)),
+        ));
+
+        // A server that goes away without answering.
+        let server = tokio::spawn(async move {
+            let mut reader = BufReader::new(server_side);
+            read_frame(&mut reader).await;
+        });
+
+        let position = crate::protocol::Position::new(0, 0);
+        let hover = RequestMessage::new_hover(1, "file:///main.go".to_string(), position);
+        let response = tokio::time::timeout(Duration::from_secs(5), lsp_client.request(hover))
+            .await
+            .unwrap();
+        assert!(response.is_err());
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_content_modified_is_resent_after_resync() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        let (server_read, mut server_write) = tokio::io::split(server_side);
+        let lsp_client = LspClient::from_stream(client_side);
+        // Only the resync can make the retry come in time.
+        lsp_client.set_retry_policy(Some(
+            RetryPolicy::new()
+                .backoff(Duration::from_secs(30), Duration::from_secs(30))
+                .resync_on_content_modified(true),
+        ));
+        lsp_client.set_change_debounce(Some(Duration::from_secs(30)));
+        let uri = "file:///main.go";
+        lsp_client
+            .did_open(
+                uri.to_string(),
+                "go".to_string(),
+                "package main".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let (requested_tx, requested_rx) = tokio::sync::oneshot::channel();
+        let (changed_tx, changed_rx) = tokio::sync::oneshot::channel();
+        let server = tokio::spawn(async move {
+            let mut reader = BufReader::new(server_read);
+            let mut methods = Vec::new();
+            let mut handshake = Some((requested_tx, changed_rx));
+            for mut response in [
+                json!({ "jsonrpc": "2.0", "error": { "code": -32801, "message": "modified" } }),
+                json!({ "jsonrpc": "2.0", "result": null }),
+            ] {
+                loop {
+                    let message = read_frame(&mut reader).await;
+                    methods.push(message["method"].clone());
+                    if message["method"] == "textDocument/hover" {
+                        response["id"] = message["id"].clone();
+                        break;
+                    }
+                }
+                // The change comes in while the first hover is worked on.
+                if let Some((requested, changed)) = handshake.take() {
+                    requested.send(()).unwrap();
+                    changed.await.unwrap();
+                }
+                let payload = response.to_string();
+                let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+                server_write.write_all(frame.as_bytes()).await.unwrap();
+            }
+            methods
+        });
+
+        let hover =
+            RequestMessage::new_hover(1, uri.to_string(), crate::protocol::Position::new(0, 0));
+        let (response, _) = tokio::join!(
+            tokio::time::timeout(Duration::from_secs(5), lsp_client.request(hover)),
+            async {
+                requested_rx.await.unwrap();
+                let change = TextDocumentContentChangeEvent {
+                    range: None,
+                    text: "package app".to_string(),
+                };
+                lsp_client.did_change(uri, vec![change]).await.unwrap();
+                changed_tx.send(()).unwrap();
+            }
+        );
+        assert!(!response.unwrap().unwrap().is_content_modified());
+        assert_eq!(
+            server.await.unwrap(),
+            vec![
+                json!("textDocument/didOpen"),
+                json!("textDocument/hover"),
+                json!("textDocument/didChange"),
+                json!("textDocument/hover")
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_debounced_change_is_flushed_at_its_deadline() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        let lsp_client = LspClient::from_stream(client_side);
+        let mut reader = BufReader::new(server_side);
+        lsp_client.set_change_debounce(Some(Duration::from_millis(100)));
+        let uri = "file:///main.go";
+        lsp_client
+            .did_open(
+                uri.to_string(),
+                "go".to_string(),
+                "package main".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            read_frame(&mut reader).await["method"],
+            "textDocument/didOpen"
+        );
+
+        let change = TextDocumentContentChangeEvent {
+            range: None,
+            text: "package app".to_string(),
+        };
+        lsp_client.did_change(uri, vec![change]).await.unwrap();
+        let started = tokio::time::Instant::now();
+        // Nothing else is sent: only the deadline passing flushes the change.
+        let message = read_frame(&mut reader).await;
+        assert_eq!(message["method"], "textDocument/didChange");
+        assert_eq!(
+            message["params"]["contentChanges"][0]["text"],
+            "package app"
+        );
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(lsp_client.next_flush_deadline(), None);
+    }
+
+    #[tokio::test]
+    async fn test_settings_are_pulled_and_pushed() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        let (server_read, mut server_write) = tokio::io::split(server_side);
+        let mut reader = BufReader::new(server_read);
+        let lsp_client = LspClient::from_stream(client_side);
+        let store = SettingsStore::new();
+        store.set("gopls.staticcheck", true);
+        lsp_client.set_settings_store(store.clone());
+
+        let payload = json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "workspace/configuration",
+            "params": { "items": [{ "section": "gopls.staticcheck" }] }
+        })
+        .to_string();
+        let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+        server_write.write_all(frame.as_bytes()).await.unwrap();
+        let response = read_frame(&mut reader).await;
+        assert_eq!(response["id"], json!(7));
+        assert_eq!(response["result"], json!([true]));
+
+        store.set("gopls.staticcheck", false);
+        let notification = read_frame(&mut reader).await;
+        assert_eq!(
+            notification["method"],
+            json!("workspace/didChangeConfiguration")
+        );
+        assert_eq!(
+            notification["params"],
+            json!({ "settings": { "gopls": { "staticcheck": false } } })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_workspace_folders() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        let (server_read, mut server_write) = tokio::io::split(server_side);
+        let mut reader = BufReader::new(server_read);
+        let client = LspClient::from_stream(client_side);
+        let folder = |name: &str| WorkspaceFolder {
+            uri: format!("file:///src/{}", name),
+            name: name.to_string(),
+        };
+        *lock(&client.shared.workspace_folders) = vec![folder("app")];
+
+        assert!(client.add_workspace_folder(folder("lib")).await.unwrap());
+        assert!(!client.add_workspace_folder(folder("lib")).await.unwrap());
+        let added = read_frame(&mut reader).await;
+        assert_eq!(added["method"], "workspace/didChangeWorkspaceFolders");
+        assert_eq!(
+            added["params"]["event"],
+            json!({ "added": [folder("lib")], "removed": [] })
+        );
+
+        assert!(client
+            .remove_workspace_folder("file:///src/app")
+            .await
+            .unwrap());
+        assert!(!client
+            .remove_workspace_folder("file:///src/app")
+            .await
+            .unwrap());
+        let removed = read_frame(&mut reader).await;
+        assert_eq!(
+            removed["params"]["event"]["removed"],
+            json!([folder("app")])
+        );
+
+        let pull = r#"{"jsonrpc":"2.0","id":4,"method":"workspace/workspaceFolders"}"#;
+        let frame = format!("Content-Length: {}\r\n\r\n{}", pull.len(), pull);
+        server_write.write_all(frame.as_bytes()).await.unwrap();
+        let response = read_frame(&mut reader).await;
+        assert_eq!(response["result"], json!([folder("lib")]));
+        assert_eq!(client.workspace_folders(), [folder("lib")]);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_messages_are_logged() {
+        #[derive(Default)]
+        struct Collect(Mutex<Vec<(Level, String)>>);
+
+        impl LogSink for Collect {
+            fn log(&self, level: Level, message: std::fmt::Arguments<'_>) {
+                self.0.lock().unwrap().push((level, message.to_string()));
+            }
+        }
+
+        let frame = |payload: &str| format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+        let (client_side, mut server_side) = tokio::io::duplex(4096);
+        let lsp_client = LspClient::from_stream(client_side);
+        let sink = Arc::new(Collect::default());
+        lsp_client.set_log_sink(sink.clone());
+
+        let messages = [
+            "not json",
+            r#"{"jsonrpc":"2.0","method":"window/logMessage","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":1,"result":null}"#,
+        ];
+        for message in messages {
+            server_side
+                .write_all(frame(message).as_bytes())
+                .await
+                .unwrap();
+        }
+        lsp_client.handle_response().await.unwrap();
+
+        let logged: Vec<(Level, String)> = sink
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(level, _)| *level < Level::Trace)
+            .cloned()
+            .collect();
+        assert_eq!(logged.len(), 2);
+        assert_eq!(logged[0].0, Level::Warn);
+        assert!(logged[0]
+            .1
+            .starts_with("Dropping message that isn't valid JSON-RPC"));
+        assert_eq!(
+            logged[1],
+            (
+                Level::Debug,
+                "Dropping window/logMessage notification, nobody subscribed to it".to_string()
+            )
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_close_kills_unresponsive_server() {
+        let child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let pid = child.id().unwrap();
+
+        let lsp_client = LspClient::from_child(child).unwrap();
+        let mut events = lsp_client.subscribe_events();
+        lsp_client.set_shutdown_grace_period(Duration::from_millis(50));
+        lsp_client.close().await.unwrap();
+        let exit = events.recv().await.unwrap();
+        assert!(
+            matches!(
+                &exit,
+                ClientEvent::ServerExit(exit)
+                    if exit.was_requested && exit.code.is_none() && exit.signal == Some(9)
+            ),
+            "{:?}",
+            exit
+        );
+
+        assert!(!std::path::Path::new(&format!("/proc/{}", pid)).exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_crash_errors_quote_stderr() {
+        let child = tokio::process::Command::new("sh")
+            .args([
+                "-c",
+                "read header; echo 'panic: index out of range' >&2; exit 2",
+            ])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let lsp_client = LspClient::from_child(child).unwrap();
+        let mut events = lsp_client.subscribe_events();
+
+        let position = crate::protocol::Position::new(0, 0);
+        let hover = RequestMessage::new_hover(1, "file:///main.go".to_string(), position);
+        let error = tokio::time::timeout(Duration::from_secs(5), lsp_client.request(hover))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("stderr:\npanic: index out of range"));
+        assert_eq!(
+            lsp_client.server_stderr().as_deref(),
+            Some("panic: index out of range\n")
+        );
+        let exit = tokio::time::timeout(Duration::from_secs(5), events.recv()).await;
+        assert_eq!(
+            exit.unwrap().unwrap(),
+            ClientEvent::ServerExit(ServerExit {
+                code: Some(2),
+                signal: None,
+                stderr_tail: "panic: index out of range\n".to_string(),
+                was_requested: false,
+            })
+        );
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_requests_are_traced() {
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+
+        /// The name, parent and fields of every span created.
+        type Spans = Vec<(&'static str, Option<u64>, Vec<(String, String)>)>;
+
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Spans>>);
+
+        struct Fields<'a>(&'a mut Vec<(String, String)>);
+
+        impl Visit for Fields<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.0
+                    .push((field.name().to_string(), format!("{:?}", value)));
+            }
+        }
+
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+                let mut spans = self.0.lock().unwrap();
+                let mut fields = Vec::new();
+                attributes.record(&mut Fields(&mut fields));
+                let parent = attributes.parent().map(Id::into_u64);
+                spans.push((attributes.metadata().name(), parent, fields));
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, span: &Id, values: &Record<'_>) {
+                let mut spans = self.0.lock().unwrap();
+                let fields = &mut spans[span.into_u64() as usize - 1].2;
+                values.record(&mut Fields(fields));
+            }
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &tracing::Event<'_>) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder = Recorder::default();
+        let _default = tracing::subscriber::set_default(recorder.clone());
+
+        let request = serde_json::to_string(&RequestMessage::new_shutdown(1)).unwrap();
+        let response = r#"{"jsonrpc":"2.0","id":1,"result":null}"#;
+        let frame = |payload: &str| format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+        let mock_server = Builder::new()
+            .write(frame(&request).as_bytes())
+            .read(frame(response).as_bytes())
+            .build();
+
+        let lsp_client = LspClient::from_stream(mock_server);
+        lsp_client
+            .request(RequestMessage::new_shutdown(1))
+            .await
+            .unwrap();
+
+        let spans = recorder.0.lock().unwrap();
+        assert_eq!(spans[0].0, "lsp_connection");
+        let (name, parent, fields) = &spans[1];
+        assert_eq!((*name, *parent), ("lsp_request", Some(1)));
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(field("method"), Some("shutdown"));
+        assert_eq!(field("id"), Some("1"));
+        assert_eq!(field("outcome"), Some("\"ok\""));
+        assert!(field("duration_ms").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_server_requests_are_answered_while_the_writer_is_busy() {
+        let (client_side, mut server_side) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_side);
+        let mut messages = client.subscribe_incoming(crate::event_bus::EventFilter::new());
+
+        // As if another task were stuck writing to a server that doesn't read.
+        let writer = client.shared.writer.lock().await;
+        for message in [
+            r#"{"jsonrpc":"2.0","id":1,"method":"workspace/workspaceFolders"}"#,
+            r#"{"jsonrpc":"2.0","method":"$/progress","params":{"token":1,"value":{}}}"#,
+        ] {
+            let frame = format!("Content-Length: {}\r\n\r\n{}", message.len(), message);
+            server_side.write_all(frame.as_bytes()).await.unwrap();
+        }
+        // The reader goes on to the notification without waiting to answer.
+        for method in ["workspace/workspaceFolders", "$/progress"] {
+            let message = tokio::time::timeout(Duration::from_secs(5), messages.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(message.method(), Some(method));
+        }
+
+        drop(writer);
+        let mut reader = BufReader::new(server_side);
+        assert_eq!(
+            read_frame(&mut reader).await,
+            json!({ "jsonrpc": "2.0", "id": 1, "result": null })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pause_reading() {
+        let (client_side, mut server_side) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_side);
+        let mut messages = client.subscribe_incoming(crate::event_bus::EventFilter::new());
+        client.pause_reading();
+        assert!(client.is_reading_paused());
+
+        let message = r#"{"jsonrpc":"2.0","method":"$/progress","params":{"token":1,"value":{}}}"#;
+        let frame = format!("Content-Length: {}\r\n\r\n{}", message.len(), message);
+        server_side.write_all(frame.as_bytes()).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_millis(50), messages.recv()).await;
+        assert!(received.is_err());
+
+        client.resume_reading();
+        let message = messages.recv().await.unwrap();
+        assert_eq!(message.method(), Some("$/progress"));
+    }
+
+    #[tokio::test]
+    async fn test_identical_queries_are_coalesced() {
+        use crate::transport::{FrameReader, FrameWriter};
+
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut reader = FrameReader::new(read_half);
+        let mut writer = FrameWriter::new(write_half);
+
+        client.set_coalesce_queries(true);
+        let uri = "file:///app/main.go".to_string();
+        let (first, second, _) = tokio::join!(
+            client.document_symbol(uri.clone()),
+            client.document_symbol(uri.clone()),
+            async {
+                let request: serde_json::Value =
+                    serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+                // Both callers are waiting before the response goes out.
+                tokio::task::yield_now().await;
+                let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": [] });
+                writer
+                    .write_frame(response.to_string().as_bytes())
+                    .await
+                    .unwrap();
+            }
+        );
+        first.unwrap();
+        second.unwrap();
+
+        // Answered, so the next one is sent.
+        let (third, _) = tokio::join!(client.document_symbol(uri), async {
+            let request: serde_json::Value =
+                serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+            assert_eq!(request["method"], "textDocument/documentSymbol");
+            let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": null });
+            writer
+                .write_frame(response.to_string().as_bytes())
+                .await
+                .unwrap();
+        });
+        third.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_query_is_cancelled_by_its_last_waiter() {
+        use crate::transport::{FrameReader, FrameWriter};
+
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        client.set_coalesce_queries(true);
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut reader = FrameReader::new(read_half);
+        let mut writer = FrameWriter::new(write_half);
+        async fn read<R: AsyncRead + Unpin>(reader: &mut FrameReader<R>) -> serde_json::Value {
+            serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap()
+        }
+
+        let uri = "file:///app/main.go".to_string();
+        let mut first = Box::pin(client.document_symbol(uri.clone()));
+        let mut second = Box::pin(client.document_symbol(uri.clone()));
+        // Both are waiting before the first gives up.
+        let request = tokio::select! {
+            _ = &mut first => unreachable!(),
+            _ = &mut second => unreachable!(),
+            request = read(&mut reader) => request,
+        };
+        drop(first);
+
+        // The other one still gets the response.
+        let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": [] });
+        let (second, _) = tokio::join!(second, async {
+            writer
+                .write_frame(response.to_string().as_bytes())
+                .await
+                .unwrap();
+        });
+        second.unwrap();
+
+        // Once nobody waits any more, the request is cancelled.
+        let third = tokio::time::timeout(Duration::from_millis(50), client.document_symbol(uri));
+        assert!(third.await.is_err());
+        let request = read(&mut reader).await;
+        assert_eq!(request["method"], "textDocument/documentSymbol");
+        assert_eq!(
+            read(&mut reader).await,
+            json!({ "jsonrpc": "2.0", "method": "$/cancelRequest", "params": { "id": request["id"] } })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_raw_requests_are_not_coalesced() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        client.set_coalesce_queries(true);
+
+        // A server that answers each request with its id.
+        let server = tokio::spawn(async move {
+            let (read_half, mut write_half) = tokio::io::split(server_end);
+            let mut reader = BufReader::new(read_half);
+            let first = read_frame(&mut reader).await;
+            let second = read_frame(&mut reader).await;
+            for request in [first, second] {
+                let payload = json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": request["id"]
+                })
+                .to_string();
+                let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+                write_half.write_all(frame.as_bytes()).await.unwrap();
+            }
+        });
+
+        let symbols = |id| RequestMessage::new_document_symbol(id, "file:///main.go".to_string());
+        let (first, second) = tokio::join!(
+            client.request_raw(symbols(1)),
+            client.request_raw(symbols(2))
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_eq!(first.id, Some(json!(1)));
+        assert_eq!(second.id, Some(json!(2)));
+        assert_eq!(second.to_response().unwrap().result, Some(json!(2)));
+        server.await.unwrap();
     }
 }
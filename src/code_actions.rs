@@ -0,0 +1,377 @@
+//! Code actions, and applying one in a single call: resolving it if the
+//! server fills in edits lazily, applying its edit and running its command.
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::protocol::{OneOf, Position, Range};
+//! let uri = "file:///src/main.rs".to_string();
+//! let range = Range::new(Position::new(3, 0), Position::new(3, 12));
+//! for action in client.code_action(uri, range, Vec::new()).await? {
+//!     if let OneOf::Right(action) = action {
+//!         if action.is_preferred == Some(true) {
+//!             client.apply_code_action(action, false, |_, _| true).await?;
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::LspClient;
+use crate::columns::PositionEncoding;
+use crate::documents::apply_change;
+use crate::edits::{ChangeAnnotation, DocumentEdits, WorkspaceEdit};
+use crate::position_encoding::convert_range;
+use crate::protocol::Diagnostic;
+use crate::rename::RenamePreview;
+use crate::workspace::uri_to_path;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// A command the server runs with `workspace/executeCommand`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Command {
+    pub title: String,
+    /// The identifier of the command, one of the server's
+    /// `executeCommandProvider.commands`.
+    pub command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Vec<Value>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeAction {
+    pub title: String,
+    /// `quickfix`, `refactor.extract`, `source.organizeImports`, ...
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// The diagnostics the action fixes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<Vec<Diagnostic>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_preferred: Option<bool>,
+    /// Set if the action can't be applied right now.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled: Option<Disabled>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edit: Option<WorkspaceEdit>,
+    /// Run after `edit` is applied.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Command>,
+    /// Kept by the server between `textDocument/codeAction` and
+    /// `codeAction/resolve`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Disabled {
+    /// Why the action is disabled, to show to the user.
+    pub reason: String,
+}
+
+/// What `LspClient::apply_code_action` did, or would do on a dry run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedCodeAction {
+    /// The action, resolved if it had to be.
+    pub action: CodeAction,
+    /// The changes the edit makes. Only computed on dry runs.
+    pub preview: Option<RenamePreview>,
+    /// The new version of every open document changed.
+    pub versions: Vec<(String, i32)>,
+    /// The URIs of the files changed on disk, which weren't open.
+    pub written: Vec<String>,
+    /// The result of the command, if it was run and returned one.
+    pub command_result: Option<Value>,
+}
+
+impl LspClient {
+    /// Applies a code action as an editor would: resolves it if it comes
+    /// without an edit and the server resolves code actions, applies its edit
+    /// and then runs its command with `run_command`.
+    ///
+    /// The edit is applied to the open documents, which the server is
+    /// notified about, and written to disk for the other files. `confirm` is
+    /// asked about change annotations, as with `apply_workspace_edit`. The
+    /// files are written to temporary files first and renamed into place
+    /// once the open documents are changed, so nothing is applied if any of
+    /// the edit can't be, short of a failed rename.
+    ///
+    /// A dry run only resolves the action and previews its edit.
+    pub async fn apply_code_action<F>(
+        &self,
+        action: CodeAction,
+        dry_run: bool,
+        confirm: F,
+    ) -> Result<AppliedCodeAction>
+    where
+        F: FnMut(&str, &ChangeAnnotation) -> bool,
+    {
+        if let Some(disabled) = &action.disabled {
+            bail!(
+                "Code action {:?} is disabled: {}",
+                action.title,
+                disabled.reason
+            );
+        }
+        let action = self.resolve_code_action(action).await?;
+        let mut applied = AppliedCodeAction {
+            action,
+            preview: None,
+            versions: Vec::new(),
+            written: Vec::new(),
+            command_result: None,
+        };
+
+        if dry_run {
+            if let Some(edit) = &applied.action.edit {
+                applied.preview = Some(self.preview_workspace_edit(edit).await?);
+            }
+            return Ok(applied);
+        }
+
+        if let Some(edit) = &applied.action.edit {
+            let (open, unopened): (Vec<DocumentEdits>, Vec<DocumentEdits>) = {
+                let documents = self.documents();
+                edit.content_changes(confirm)?
+                    .into_iter()
+                    .partition(|edits| documents.get(&edits.uri).is_some())
+            };
+            // Read and stage every file before changing anything.
+            let mut files = Vec::with_capacity(unopened.len());
+            for edits in unopened {
+                files.push(self.edited_file(edits).await?);
+            }
+            let mut staged = Vec::with_capacity(files.len());
+            for (uri, path, text) in files {
+                let temporary = temporary_path(&path);
+                if let Err(e) = tokio::fs::write(&temporary, text).await {
+                    remove_staged(&staged).await;
+                    return Err(e).with_context(|| format!("Failed to write {}", path.display()));
+                }
+                staged.push((uri, path, temporary));
+            }
+            applied.versions = match self.apply_document_edits(open).await {
+                Ok(versions) => versions,
+                Err(e) => {
+                    remove_staged(&staged).await;
+                    return Err(e);
+                }
+            };
+            for (index, (uri, path, temporary)) in staged.iter().enumerate() {
+                if let Err(e) = tokio::fs::rename(temporary, path).await {
+                    remove_staged(&staged[index..]).await;
+                    return Err(e).with_context(|| {
+                        format!(
+                            "Failed to write {}; the open documents and {:?} were changed",
+                            path.display(),
+                            applied.written
+                        )
+                    });
+                }
+                applied.written.push(uri.clone());
+            }
+        }
+
+        if let Some(command) = &applied.action.command {
+            applied.command_result = self.run_command(command).await?;
+        }
+        Ok(applied)
+    }
+
+    #[cfg(feature = "lsp-3-16")]
+    async fn resolve_code_action(&self, action: CodeAction) -> Result<CodeAction> {
+        let resolves = self
+            .server_capabilities()
+            .and_then(|capabilities| capabilities.code_action_provider)
+            .and_then(|provider| provider.options()?.resolve_provider)
+            .unwrap_or(false);
+        if action.edit.is_some() || !resolves {
+            return Ok(action);
+        }
+        self.code_action_resolve(&action).await
+    }
+
+    #[cfg(not(feature = "lsp-3-16"))]
+    async fn resolve_code_action(&self, action: CodeAction) -> Result<CodeAction> {
+        Ok(action)
+    }
+
+    /// The text of a file that isn't open once `edits` are applied, with its
+    /// URI and path.
+    async fn edited_file(&self, edits: DocumentEdits) -> Result<(String, PathBuf, String)> {
+        let path = uri_to_path(&edits.uri)
+            .ok_or_else(|| anyhow!("Can't edit {}: not a file URI", edits.uri))?;
+        let mut text = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        // Positions in files that aren't open are left in the server's encoding.
+        let encoding = self.position_encoding();
+        for mut change in edits.changes {
+            change.range = change
+                .range
+                .map(|range| convert_range(&text, range, encoding, PositionEncoding::Utf16));
+            apply_change(&mut text, &change);
+        }
+        Ok((edits.uri, path, text))
+    }
+}
+
+/// Where the new text of `path` is staged, next to it so that renaming it
+/// into place doesn't cross file systems.
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+async fn remove_staged(staged: &[(String, PathBuf, PathBuf)]) {
+    for (_, _, temporary) in staged {
+        let _ = tokio::fs::remove_file(temporary).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{FrameReader, FrameWriter};
+    use crate::workspace::path_to_uri;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_apply_code_action() {
+        let root = std::env::temp_dir().join(format!("lsp-code-action-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let util = root.join("util.go");
+        std::fs::write(&util, "package main\n\nfunc helper() {}\n").unwrap();
+        let util_uri = path_to_uri(&util);
+
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut reader = FrameReader::new(read_half);
+        let mut writer = FrameWriter::new(write_half);
+        let uri = "file:///main.go".to_string();
+        client
+            .did_open(uri.clone(), "go".to_string(), "helper()".to_string())
+            .await
+            .unwrap();
+        reader.read_frame().await.unwrap();
+
+        let action: CodeAction = serde_json::from_value(json!({
+            "title": "Export helper",
+            "kind": "refactor.rewrite",
+            "edit": {
+                "changes": {
+                    uri.clone(): [{
+                        "range": {
+                            "start": { "line": 0, "character": 0 },
+                            "end": { "line": 0, "character": 6 }
+                        },
+                        "newText": "Helper"
+                    }],
+                    util_uri.clone(): [{
+                        "range": {
+                            "start": { "line": 2, "character": 5 },
+                            "end": { "line": 2, "character": 11 }
+                        },
+                        "newText": "Helper"
+                    }]
+                }
+            },
+            "command": { "title": "Organize imports", "command": "go.organizeImports" }
+        }))
+        .unwrap();
+
+        // A dry run changes nothing and sends nothing.
+        let dry_run = client
+            .apply_code_action(action.clone(), true, |_, _| true)
+            .await
+            .unwrap();
+        let preview = dry_run.preview.unwrap();
+        assert_eq!(preview.files.len(), 2);
+        assert_eq!(client.documents().get(&uri).unwrap().text, "helper()");
+        assert!(std::fs::read_to_string(&util)
+            .unwrap()
+            .contains("func helper"));
+
+        let (applied, _) = tokio::join!(
+            client.apply_code_action(action, false, |_, _| true),
+            async {
+                let change: Value =
+                    serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+                assert_eq!(change["method"], "textDocument/didChange");
+                let request: Value =
+                    serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+                assert_eq!(request["method"], "workspace/executeCommand");
+                assert_eq!(request["params"]["command"], "go.organizeImports");
+                let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": null });
+                writer
+                    .write_frame(response.to_string().as_bytes())
+                    .await
+                    .unwrap();
+            }
+        );
+        let applied = applied.unwrap();
+        assert_eq!(applied.versions, [(uri.clone(), 1)]);
+        assert_eq!(applied.written, [util_uri]);
+        assert_eq!(applied.command_result, None);
+        assert_eq!(client.documents().get(&uri).unwrap().text, "Helper()");
+        assert_eq!(
+            std::fs::read_to_string(&util).unwrap(),
+            "package main\n\nfunc Helper() {}\n"
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_write_applies_nothing() {
+        let root =
+            std::env::temp_dir().join(format!("lsp-code-action-failed-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let util = root.join("util.go");
+        std::fs::write(&util, "func helper() {}\n").unwrap();
+        // A directory where the new text would be staged.
+        std::fs::create_dir_all(temporary_path(&util)).unwrap();
+
+        let (client_end, _server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let uri = "file:///main.go".to_string();
+        client
+            .did_open(uri.clone(), "go".to_string(), "helper()".to_string())
+            .await
+            .unwrap();
+        let comment = json!([{
+            "range": {
+                "start": { "line": 0, "character": 0 },
+                "end": { "line": 0, "character": 0 }
+            },
+            "newText": "// "
+        }]);
+        let action: CodeAction = serde_json::from_value(json!({
+            "title": "Comment out",
+            "edit": {
+                "changes": {
+                    uri.clone(): comment.clone(),
+                    path_to_uri(&util): comment
+                }
+            }
+        }))
+        .unwrap();
+
+        assert!(client
+            .apply_code_action(action, false, |_, _| true)
+            .await
+            .is_err());
+        assert_eq!(client.documents().get(&uri).unwrap().text, "helper()");
+        assert_eq!(client.documents().version(&uri), Some(0));
+        assert_eq!(
+            std::fs::read_to_string(&util).unwrap(),
+            "func helper() {}\n"
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
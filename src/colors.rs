@@ -0,0 +1,187 @@
+//! Colors found by `textDocument/documentColor`, and their conversion to and
+//! from the formats GUI clients draw with.
+//!
+//! LSP colors have normalized components in `[0, 1]`. `Color::to_hex` and
+//! `Color::from_hex` convert to CSS hex notation, `to_rgba8` and
+//! `from_rgba8` to 8-bit channels, and `Color::labels` and
+//! `Color::from_label` handle the usual presentations of a color, so that a
+//! label picked from `textDocument/colorPresentation` can be turned back into
+//! a color.
+
+use crate::edits::TextEdit;
+use crate::protocol::Range;
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// A color, with each component in `[0, 1]`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub alpha: f32,
+}
+
+impl Color {
+    pub fn from_rgba8([red, green, blue, alpha]: [u8; 4]) -> Self {
+        let normalize = |channel: u8| channel as f32 / 255.0;
+        Color {
+            red: normalize(red),
+            green: normalize(green),
+            blue: normalize(blue),
+            alpha: normalize(alpha),
+        }
+    }
+
+    /// The color in 8-bit channels. Components outside of `[0, 1]` are clamped.
+    pub fn to_rgba8(&self) -> [u8; 4] {
+        let channel = |component: f32| (component.clamp(0.0, 1.0) * 255.0).round() as u8;
+        [
+            channel(self.red),
+            channel(self.green),
+            channel(self.blue),
+            channel(self.alpha),
+        ]
+    }
+
+    /// Parses `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`. The `#` is optional.
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        ensure!(
+            digits.chars().all(|digit| digit.is_ascii_hexdigit()),
+            "Invalid hex color {:?}: not a hex number",
+            hex
+        );
+        let expanded: String = match digits.len() {
+            3 | 4 => digits.chars().flat_map(|digit| [digit, digit]).collect(),
+            6 | 8 => digits.to_string(),
+            _ => bail!("Invalid hex color {:?}: expected 3, 4, 6 or 8 digits", hex),
+        };
+        let mut rgba = [255; 4];
+        for (channel, pair) in rgba.iter_mut().zip(expanded.as_bytes().chunks(2)) {
+            *channel = u8::from_str_radix(std::str::from_utf8(pair)?, 16)?;
+        }
+        Ok(Color::from_rgba8(rgba))
+    }
+
+    /// `#rrggbb`, or `#rrggbbaa` if the color isn't opaque.
+    pub fn to_hex(&self) -> String {
+        let [red, green, blue, alpha] = self.to_rgba8();
+        match alpha {
+            255 => format!("#{:02x}{:02x}{:02x}", red, green, blue),
+            _ => format!("#{:02x}{:02x}{:02x}{:02x}", red, green, blue, alpha),
+        }
+    }
+
+    /// The usual presentations of the color: hex and CSS `rgb()`/`rgba()`,
+    /// for clients presenting colors themselves when the server doesn't
+    /// answer `textDocument/colorPresentation`.
+    pub fn labels(&self) -> Vec<String> {
+        let [red, green, blue, alpha] = self.to_rgba8();
+        let functional = match alpha {
+            255 => format!("rgb({}, {}, {})", red, green, blue),
+            _ => format!(
+                "rgba({}, {}, {}, {})",
+                red,
+                green,
+                blue,
+                // Three decimals are enough to read the same 8 bits back.
+                (alpha as f32 / 255.0 * 1000.0).round() / 1000.0
+            ),
+        };
+        vec![self.to_hex(), functional]
+    }
+
+    /// Parses a presentation label: a hex color, or CSS `rgb()` or `rgba()`
+    /// with 8-bit channels and an alpha in `[0, 1]`.
+    pub fn from_label(label: &str) -> Result<Self> {
+        let label = label.trim();
+        if label.starts_with('#') {
+            return Color::from_hex(label);
+        }
+        let Some(arguments) = label
+            .strip_prefix("rgba(")
+            .or_else(|| label.strip_prefix("rgb("))
+            .and_then(|rest| rest.strip_suffix(')'))
+        else {
+            bail!("Unknown color presentation {:?}", label);
+        };
+        let arguments: Vec<&str> = arguments.split(',').map(str::trim).collect();
+        let [red, green, blue, alpha @ ..] = arguments.as_slice() else {
+            bail!("Invalid color {:?}: expected 3 or 4 components", label);
+        };
+        let channel = |channel: &str| {
+            channel
+                .parse::<u8>()
+                .with_context(|| format!("Invalid color {:?}: bad channel {:?}", label, channel))
+        };
+        let mut color = Color::from_rgba8([channel(red)?, channel(green)?, channel(blue)?, 255]);
+        match alpha {
+            [] => {}
+            [alpha] => {
+                color.alpha = alpha
+                    .parse::<f32>()
+                    .with_context(|| format!("Invalid color {:?}: bad alpha {:?}", label, alpha))?;
+                ensure!(
+                    (0.0..=1.0).contains(&color.alpha),
+                    "Invalid color {:?}: alpha out of range",
+                    label
+                );
+            }
+            _ => bail!("Invalid color {:?}: expected 3 or 4 components", label),
+        }
+        Ok(color)
+    }
+}
+
+/// A color and where it appears in the document.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ColorInformation {
+    pub range: Range,
+    pub color: Color,
+}
+
+/// One way of writing a color, as offered by `textDocument/colorPresentation`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ColorPresentation {
+    /// Shown in the color picker, and inserted unless `text_edit` is set.
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_edit: Option<TextEdit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub additional_text_edits: Option<Vec<TextEdit>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_conversions() {
+        let coral = Color::from_hex("#ff7f50").unwrap();
+        assert_eq!(coral.to_rgba8(), [255, 127, 80, 255]);
+        assert_eq!(coral.to_hex(), "#ff7f50");
+        assert_eq!(Color::from_hex("f80").unwrap().to_hex(), "#ff8800");
+        assert_eq!(
+            Color::from_hex("#0000ff80").unwrap().to_rgba8(),
+            [0, 0, 255, 128]
+        );
+        assert!(Color::from_hex("#ff7f5").is_err());
+        assert!(Color::from_hex("#gg0000").is_err());
+
+        let translucent = Color::from_rgba8([0, 128, 255, 128]);
+        let labels = translucent.labels();
+        assert_eq!(labels, ["#0080ff80", "rgba(0, 128, 255, 0.502)"]);
+        // Every label reads back as the same color, to 8 bits.
+        for label in &labels {
+            assert_eq!(
+                Color::from_label(label).unwrap().to_rgba8(),
+                translucent.to_rgba8()
+            );
+        }
+        assert_eq!(coral.labels()[1], "rgb(255, 127, 80)");
+        assert!(Color::from_label("hsl(16, 100%, 66%)").is_err());
+        assert!(Color::from_label("rgba(0, 0, 0, 2)").is_err());
+    }
+}
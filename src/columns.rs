@@ -0,0 +1,182 @@
+//! Conversions between the column units of a single line: LSP columns in
+//! UTF-16 code units, `char` indices, byte offsets, and the grapheme clusters
+//! an editor shows as one visual column.
+//!
+//! An emoji such as 👍🏽 is one grapheme, two `char`s and four UTF-16 units,
+//! so none of these can be converted with plain arithmetic. Columns past the
+//! end of the line are clamped to its end, and columns that fall inside a
+//! character or a grapheme are rounded down to its start.
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// What the `character` of a position counts, as negotiated through the
+/// `positionEncoding` capability.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PositionEncoding {
+    /// Bytes.
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// UTF-16 code units, the encoding every server supports.
+    #[default]
+    #[serde(rename = "utf-16")]
+    Utf16,
+    /// `char`s.
+    #[serde(rename = "utf-32")]
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Parses the name of an encoding, e.g. `utf-8`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "utf-8" => Some(PositionEncoding::Utf8),
+            "utf-16" => Some(PositionEncoding::Utf16),
+            "utf-32" => Some(PositionEncoding::Utf32),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PositionEncoding::Utf8 => "utf-8",
+            PositionEncoding::Utf16 => "utf-16",
+            PositionEncoding::Utf32 => "utf-32",
+        }
+    }
+}
+
+/// Converts a column of `line` from one encoding to another.
+pub fn convert_column(
+    line: &str,
+    column: u32,
+    from: PositionEncoding,
+    to: PositionEncoding,
+) -> u32 {
+    if from == to {
+        return column;
+    }
+    let offset = match from {
+        PositionEncoding::Utf8 => {
+            let mut offset = (column as usize).min(line.len());
+            while !line.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            offset
+        }
+        PositionEncoding::Utf16 => utf16_to_byte(line, column),
+        PositionEncoding::Utf32 => line
+            .char_indices()
+            .nth(column as usize)
+            .map_or(line.len(), |(offset, _)| offset),
+    };
+    match to {
+        PositionEncoding::Utf8 => offset as u32,
+        PositionEncoding::Utf16 => byte_to_utf16(line, offset),
+        PositionEncoding::Utf32 => line[..offset].chars().count() as u32,
+    }
+}
+
+/// Converts a UTF-16 column to a byte offset in `line`.
+pub fn utf16_to_byte(line: &str, column: u32) -> usize {
+    let mut units = 0;
+    for (offset, ch) in line.char_indices() {
+        units += ch.len_utf16() as u32;
+        if units > column {
+            return offset;
+        }
+    }
+    line.len()
+}
+
+/// Converts a byte offset in `line` to a UTF-16 column.
+pub fn byte_to_utf16(line: &str, offset: usize) -> u32 {
+    line.char_indices()
+        .take_while(|&(start, ch)| start + ch.len_utf8() <= offset)
+        .map(|(_, ch)| ch.len_utf16() as u32)
+        .sum()
+}
+
+/// Converts a UTF-16 column to a `char` index in `line`.
+pub fn utf16_to_char(line: &str, column: u32) -> usize {
+    line[..utf16_to_byte(line, column)].chars().count()
+}
+
+/// Converts a `char` index in `line` to a UTF-16 column.
+pub fn char_to_utf16(line: &str, index: usize) -> u32 {
+    line.chars()
+        .take(index)
+        .map(|ch| ch.len_utf16() as u32)
+        .sum()
+}
+
+/// Converts a UTF-16 column to a visual column, counted in graphemes.
+pub fn utf16_to_grapheme(line: &str, column: u32) -> usize {
+    let offset = utf16_to_byte(line, column);
+    line.grapheme_indices(true)
+        .take_while(|&(start, grapheme)| start + grapheme.len() <= offset)
+        .count()
+}
+
+/// Converts a visual column, counted in graphemes, to a UTF-16 column.
+pub fn grapheme_to_utf16(line: &str, column: usize) -> u32 {
+    line.graphemes(true)
+        .take(column)
+        .flat_map(str::chars)
+        .map(|ch| ch.len_utf16() as u32)
+        .sum()
+}
+
+/// Converts a visual column, counted in graphemes, to a `char` index.
+pub fn grapheme_to_char(line: &str, column: usize) -> usize {
+    line.graphemes(true)
+        .take(column)
+        .map(|grapheme| grapheme.chars().count())
+        .sum()
+}
+
+/// Converts a `char` index in `line` to a visual column, counted in graphemes.
+pub fn char_to_grapheme(line: &str, index: usize) -> usize {
+    utf16_to_grapheme(line, char_to_utf16(line, index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_conversions() {
+        // "e" + combining acute, a thumbs up with a skin tone, then "x".
+        let line = "e\u{301}👍🏽x";
+
+        assert_eq!(grapheme_to_utf16(line, 0), 0);
+        assert_eq!(grapheme_to_utf16(line, 1), 2);
+        assert_eq!(grapheme_to_utf16(line, 2), 6);
+        assert_eq!(grapheme_to_utf16(line, 3), 7);
+        assert_eq!(grapheme_to_utf16(line, 10), 7);
+
+        assert_eq!(utf16_to_grapheme(line, 6), 2);
+        // Inside the emoji, rounded down to its start.
+        assert_eq!(utf16_to_grapheme(line, 3), 1);
+        assert_eq!(utf16_to_grapheme(line, 99), 3);
+
+        assert_eq!(utf16_to_char(line, 6), 4);
+        assert_eq!(char_to_utf16(line, 4), 6);
+        assert_eq!(grapheme_to_char(line, 2), 4);
+        assert_eq!(char_to_grapheme(line, 4), 2);
+        assert_eq!(char_to_grapheme(line, 1), 0);
+
+        assert_eq!(utf16_to_byte(line, 2), 3);
+        assert_eq!(byte_to_utf16(line, 3), 2);
+        assert_eq!(byte_to_utf16(line, line.len()), 7);
+
+        use PositionEncoding::*;
+        // Before "x": 6 UTF-16 units, 11 bytes, 4 chars.
+        assert_eq!(convert_column(line, 6, Utf16, Utf8), 11);
+        assert_eq!(convert_column(line, 11, Utf8, Utf32), 4);
+        assert_eq!(convert_column(line, 4, Utf32, Utf16), 6);
+        // Inside the thumbs up, rounded down to its start.
+        assert_eq!(convert_column(line, 5, Utf8, Utf16), 2);
+        assert_eq!(PositionEncoding::from_name(Utf8.as_str()), Some(Utf8));
+    }
+}
@@ -0,0 +1,135 @@
+//! Commands the client implements itself. Servers attach commands to code
+//! actions, code lenses and completions, and some of them are meant for the
+//! editor rather than the server, such as `editor.action.triggerSuggest`.
+//! Register a handler for those, and `LspClient::run_command` calls it instead
+//! of sending `workspace/executeCommand`:
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! client.commands().register("editor.action.triggerSuggest", |_| {
+//!     println!("Show completions");
+//!     Ok(None)
+//! });
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::LspClient;
+use crate::code_actions::Command;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+type Handler = Arc<dyn Fn(&Command) -> Result<Option<Value>> + Send + Sync>;
+
+/// Client-side command handlers, by command name.
+#[derive(Clone, Default)]
+pub struct CommandRegistry {
+    handlers: HashMap<String, Handler>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles `name` with `handler`, replacing any handler it had. The
+    /// handler's result is returned by `run_command`.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        handler: impl Fn(&Command) -> Result<Option<Value>> + Send + Sync + 'static,
+    ) {
+        self.handlers.insert(name.into(), Arc::new(handler));
+    }
+
+    /// Stops handling `name`. Returns whether it was handled.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.handlers.remove(name).is_some()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.handlers.contains_key(name)
+    }
+
+    fn handler(&self, name: &str) -> Option<Handler> {
+        self.handlers.get(name).cloned()
+    }
+}
+
+impl fmt::Debug for CommandRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+impl LspClient {
+    /// Runs `command` with its client-side handler if one is registered,
+    /// and on the server with `workspace/executeCommand` otherwise.
+    pub async fn run_command(&self, command: &Command) -> Result<Option<Value>> {
+        // Not called with the registry locked, so handlers can use it.
+        let handler = self.commands().handler(&command.command);
+        match handler {
+            Some(handler) => handler(command),
+            None => self.execute_command(command).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{FrameReader, FrameWriter};
+    use serde_json::json;
+
+    fn command(name: &str) -> Command {
+        Command {
+            title: name.to_string(),
+            command: name.to_string(),
+            arguments: Some(vec![json!(1)]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_command() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut reader = FrameReader::new(read_half);
+        let mut writer = FrameWriter::new(write_half);
+        client
+            .commands()
+            .register("editor.action.triggerSuggest", |command| {
+                Ok(command
+                    .arguments
+                    .as_ref()
+                    .map(|arguments| json!(arguments.len())))
+            });
+
+        let result = client
+            .run_command(&command("editor.action.triggerSuggest"))
+            .await
+            .unwrap();
+        assert_eq!(result, Some(json!(1)));
+
+        // Unknown to the client, so it goes to the server.
+        let tidy = command("gopls.tidy");
+        let (result, _) = tokio::join!(client.run_command(&tidy), async {
+            let request: Value =
+                serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+            assert_eq!(request["method"], "workspace/executeCommand");
+            assert_eq!(request["params"]["command"], "gopls.tidy");
+            let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": "done" });
+            writer
+                .write_frame(response.to_string().as_bytes())
+                .await
+                .unwrap();
+        });
+        assert_eq!(result.unwrap(), Some(json!("done")));
+
+        assert!(client.commands().unregister("editor.action.triggerSuggest"));
+        assert!(!client.commands().contains("editor.action.triggerSuggest"));
+    }
+}
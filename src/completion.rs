@@ -0,0 +1,244 @@
+//! Completion items returned by `textDocument/completion`, and the context
+//! it is requested with. Editors check typed characters against the
+//! server's trigger characters to decide when to ask:
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::completion::CompletionContext;
+//! use lsp_client_rs::protocol::Position;
+//! let typed = '.';
+//! let triggers = client
+//!     .server_capabilities()
+//!     .is_some_and(|capabilities| capabilities.is_completion_trigger(typed));
+//! if triggers {
+//!     let context = CompletionContext::trigger_character(typed);
+//!     let uri = "file:///app/main.go".to_string();
+//!     client.completion(uri, Position::new(3, 8), Some(context)).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum CompletionItemKind {
+    Text = 1,
+    Method = 2,
+    Function = 3,
+    Constructor = 4,
+    Field = 5,
+    Variable = 6,
+    Class = 7,
+    Interface = 8,
+    Module = 9,
+    Property = 10,
+    Unit = 11,
+    Value = 12,
+    Enum = 13,
+    Keyword = 14,
+    Snippet = 15,
+    Color = 16,
+    File = 17,
+    Reference = 18,
+    Folder = 19,
+    EnumMember = 20,
+    Constant = 21,
+    Struct = 22,
+    Event = 23,
+    Operator = 24,
+    TypeParameter = 25,
+}
+
+impl TryFrom<u8> for CompletionItemKind {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            1 => Ok(CompletionItemKind::Text),
+            2 => Ok(CompletionItemKind::Method),
+            3 => Ok(CompletionItemKind::Function),
+            4 => Ok(CompletionItemKind::Constructor),
+            5 => Ok(CompletionItemKind::Field),
+            6 => Ok(CompletionItemKind::Variable),
+            7 => Ok(CompletionItemKind::Class),
+            8 => Ok(CompletionItemKind::Interface),
+            9 => Ok(CompletionItemKind::Module),
+            10 => Ok(CompletionItemKind::Property),
+            11 => Ok(CompletionItemKind::Unit),
+            12 => Ok(CompletionItemKind::Value),
+            13 => Ok(CompletionItemKind::Enum),
+            14 => Ok(CompletionItemKind::Keyword),
+            15 => Ok(CompletionItemKind::Snippet),
+            16 => Ok(CompletionItemKind::Color),
+            17 => Ok(CompletionItemKind::File),
+            18 => Ok(CompletionItemKind::Reference),
+            19 => Ok(CompletionItemKind::Folder),
+            20 => Ok(CompletionItemKind::EnumMember),
+            21 => Ok(CompletionItemKind::Constant),
+            22 => Ok(CompletionItemKind::Struct),
+            23 => Ok(CompletionItemKind::Event),
+            24 => Ok(CompletionItemKind::Operator),
+            25 => Ok(CompletionItemKind::TypeParameter),
+            _ => Err(format!("Invalid completion item kind: {}", value)),
+        }
+    }
+}
+
+impl From<CompletionItemKind> for u8 {
+    fn from(kind: CompletionItemKind) -> Self {
+        kind as u8
+    }
+}
+
+/// A completion proposal. Fields this crate doesn't interpret are kept as raw
+/// JSON so they can be sent back untouched in `completionItem/resolve`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CompletionItem {
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<CompletionItemKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preselect: Option<bool>,
+    #[serde(rename = "sortText", skip_serializing_if = "Option::is_none")]
+    pub sort_text: Option<String>,
+    #[serde(rename = "filterText", skip_serializing_if = "Option::is_none")]
+    pub filter_text: Option<String>,
+    #[serde(rename = "insertText", skip_serializing_if = "Option::is_none")]
+    pub insert_text: Option<String>,
+    #[serde(rename = "textEdit", skip_serializing_if = "Option::is_none")]
+    pub text_edit: Option<serde_json::Value>,
+    #[serde(
+        rename = "additionalTextEdits",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub additional_text_edits: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl CompletionItem {
+    /// The text typed characters are matched against: `filterText`, or the label.
+    pub fn filter_key(&self) -> &str {
+        self.filter_text.as_deref().unwrap_or(&self.label)
+    }
+
+    /// The text items are ordered by: `sortText`, or the label.
+    pub fn sort_key(&self) -> &str {
+        self.sort_text.as_deref().unwrap_or(&self.label)
+    }
+}
+
+/// Result of a `textDocument/completion` request. If `is_incomplete` is set,
+/// typing more characters must trigger a new request instead of filtering
+/// this list locally.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CompletionList {
+    #[serde(rename = "isIncomplete")]
+    pub is_incomplete: bool,
+    pub items: Vec<CompletionItem>,
+}
+
+/// How a completion request was triggered.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum CompletionTriggerKind {
+    /// Typing an identifier, or explicitly requested by the user.
+    Invoked = 1,
+    /// Typing one of the server's trigger characters.
+    TriggerCharacter = 2,
+    /// Typing more while the last list was incomplete.
+    TriggerForIncompleteCompletions = 3,
+}
+
+impl TryFrom<u8> for CompletionTriggerKind {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            1 => Ok(CompletionTriggerKind::Invoked),
+            2 => Ok(CompletionTriggerKind::TriggerCharacter),
+            3 => Ok(CompletionTriggerKind::TriggerForIncompleteCompletions),
+            _ => Err(format!("Invalid completion trigger kind: {}", value)),
+        }
+    }
+}
+
+impl From<CompletionTriggerKind> for u8 {
+    fn from(kind: CompletionTriggerKind) -> Self {
+        kind as u8
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionContext {
+    pub trigger_kind: CompletionTriggerKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_character: Option<String>,
+}
+
+impl CompletionContext {
+    pub fn invoked() -> Self {
+        CompletionContext {
+            trigger_kind: CompletionTriggerKind::Invoked,
+            trigger_character: None,
+        }
+    }
+
+    pub fn trigger_character(character: impl Into<String>) -> Self {
+        CompletionContext {
+            trigger_kind: CompletionTriggerKind::TriggerCharacter,
+            trigger_character: Some(character.into()),
+        }
+    }
+
+    /// For a request made because `CompletionList::is_incomplete` was set.
+    pub fn incomplete() -> Self {
+        CompletionContext {
+            trigger_kind: CompletionTriggerKind::TriggerForIncompleteCompletions,
+            trigger_character: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_completion_item_keys() {
+        let item: CompletionItem = serde_json::from_value(json!({
+            "label": "Println",
+            "kind": 3,
+            "sortText": "00001",
+            "data": { "pkg": "fmt" }
+        }))
+        .unwrap();
+
+        assert_eq!(item.kind, Some(CompletionItemKind::Function));
+        assert_eq!(item.filter_key(), "Println");
+        assert_eq!(item.sort_key(), "00001");
+        assert_eq!(
+            serde_json::to_value(&item).unwrap()["data"],
+            json!({ "pkg": "fmt" })
+        );
+
+        assert_eq!(
+            serde_json::to_value(CompletionContext::trigger_character(".")).unwrap(),
+            json!({ "triggerKind": 2, "triggerCharacter": "." })
+        );
+        assert_eq!(
+            serde_json::to_value(CompletionContext::incomplete()).unwrap(),
+            json!({ "triggerKind": 3 })
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Base Debug Adapter Protocol messages and a client sending them, over the
+//! same framing and request correlation as `LspClient`. Enabled by the `dap`
+//! feature.
+//!
+//! Only the base protocol is modelled; the `arguments` and `body` of specific
+//! requests and events are left as JSON.
+
+use crate::transport::{BackgroundTask, FrameReader, FrameWriter, PendingRequests};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Weak};
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::process::Child;
+use tokio::sync::broadcast;
+
+type Stream = std::pin::Pin<Box<dyn crate::client::AsyncReadWrite + Send>>;
+
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// A message of the Debug Adapter Protocol, told apart by its `type`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DapMessage {
+    Request(DapRequest),
+    Response(DapResponse),
+    Event(DapEvent),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DapRequest {
+    pub seq: i64,
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DapResponse {
+    pub seq: i64,
+    pub request_seq: i64,
+    pub success: bool,
+    pub command: String,
+    /// Why the request failed, or `cancelled` / `notStopped`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DapEvent {
+    pub seq: i64,
+    pub event: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+}
+
+/// A handle to a connection with a debug adapter. Cheap to clone, like
+/// `LspClient`.
+#[derive(Clone)]
+pub struct DapClient {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    writer: tokio::sync::Mutex<FrameWriter<WriteHalf<Stream>>>,
+    pending: PendingRequests<DapResponse>,
+    next_seq: AtomicI64,
+    events: broadcast::Sender<DapEvent>,
+    /// The adapter process, when the client was handed it.
+    _child: Option<Child>,
+    _reader: BackgroundTask,
+}
+
+impl DapClient {
+    /// Creates a client talking over an already connected stream.
+    /// Must be called from within a tokio runtime.
+    pub fn from_stream<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(stream: S) -> Self {
+        Self::from_boxed_stream(Box::pin(stream), None)
+    }
+
+    /// Creates a client talking to an adapter process over its stdio, which
+    /// must be piped. Spawn it with `kill_on_drop` to have it killed along
+    /// with the last handle. Must be called from within a tokio runtime.
+    pub fn from_child(mut child: Child) -> Result<Self> {
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("The adapter's stdin must be piped"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("The adapter's stdout must be piped"))?;
+        let stream = Box::pin(tokio::io::join(stdout, stdin)) as Stream;
+        Ok(Self::from_boxed_stream(stream, Some(child)))
+    }
+
+    fn from_boxed_stream(stream: Stream, child: Option<Child>) -> Self {
+        let (read_half, write_half) = tokio::io::split(stream);
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let shared = Arc::new_cyclic(|weak: &Weak<Shared>| {
+            let task = tokio::spawn(read_loop(FrameReader::new(read_half), weak.clone()));
+            Shared {
+                writer: tokio::sync::Mutex::new(FrameWriter::new(write_half)),
+                pending: PendingRequests::new(),
+                next_seq: AtomicI64::new(1),
+                events,
+                _child: child,
+                _reader: BackgroundTask(task),
+            }
+        });
+        DapClient { shared }
+    }
+
+    /// Sends a `command` request and waits for its response. A response with
+    /// `success: false` is returned, not turned into an error.
+    pub async fn request(
+        &self,
+        command: impl Into<String>,
+        arguments: Option<serde_json::Value>,
+    ) -> Result<DapResponse> {
+        let request = DapRequest {
+            seq: self.shared.next_seq(),
+            command: command.into(),
+            arguments,
+        };
+        let key = request.seq.to_string();
+        let rx = self.shared.pending.register(key.clone());
+        if let Err(e) = self.shared.send(&DapMessage::Request(request)).await {
+            self.shared.pending.forget(&key);
+            return Err(e);
+        }
+        rx.await.map_err(|_| {
+            anyhow!(
+                "Connection closed before the response to request {} arrived",
+                key
+            )
+        })
+    }
+
+    /// Receives the events sent by the adapter from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<DapEvent> {
+        self.shared.events.subscribe()
+    }
+}
+
+impl Shared {
+    fn next_seq(&self) -> i64 {
+        self.next_seq.fetch_add(1, Ordering::Relaxed)
+    }
+
+    async fn send(&self, message: &DapMessage) -> Result<()> {
+        let body = serde_json::to_vec(message)?;
+        self.writer.lock().await.write_frame(&body).await
+    }
+
+    /// Declines a reverse request, e.g. `runInTerminal`; none are supported.
+    async fn decline(&self, request: DapRequest) -> Result<()> {
+        let response = DapResponse {
+            seq: self.next_seq(),
+            request_seq: request.seq,
+            success: false,
+            message: Some(format!("Unsupported request {}", request.command)),
+            command: request.command,
+            body: None,
+        };
+        self.send(&DapMessage::Response(response)).await
+    }
+}
+
+async fn read_loop(mut reader: FrameReader<ReadHalf<Stream>>, shared: Weak<Shared>) {
+    while let Ok(body) = reader.next_frame().await {
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        match serde_json::from_slice(body) {
+            Ok(DapMessage::Response(response)) => {
+                shared
+                    .pending
+                    .complete(&response.request_seq.to_string(), response);
+            }
+            Ok(DapMessage::Event(event)) => {
+                // Nobody listening is not an error; the event is simply dropped.
+                let _ = shared.events.send(event);
+            }
+            Ok(DapMessage::Request(request)) => {
+                let _ = shared.decline(request).await;
+            }
+            // Messages that aren't DAP are skipped.
+            Err(_) => {}
+        }
+    }
+}
+
+/// Fails if `response` reports failure, with the adapter's message.
+pub fn check_success(response: &DapResponse) -> Result<()> {
+    if !response.success {
+        bail!(
+            "{} failed: {}",
+            response.command,
+            response.message.as_deref().unwrap_or("no reason given")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_requests_and_events() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        let (server_read, server_write) = tokio::io::split(server_side);
+        let client = DapClient::from_stream(client_side);
+        let mut events = client.subscribe();
+
+        let adapter = tokio::spawn(async move {
+            let mut reader = FrameReader::new(server_read);
+            let mut writer = FrameWriter::new(server_write);
+            let request: serde_json::Value =
+                serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+            for message in [
+                json!({ "seq": 1, "type": "event", "event": "initialized" }),
+                json!({
+                    "seq": 2, "type": "response", "request_seq": request["seq"],
+                    "success": true, "command": "initialize",
+                    "body": { "supportsConfigurationDoneRequest": true }
+                }),
+            ] {
+                writer
+                    .write_frame(message.to_string().as_bytes())
+                    .await
+                    .unwrap();
+            }
+            request
+        });
+
+        let response = client
+            .request("initialize", Some(json!({ "adapterID": "lldb" })))
+            .await
+            .unwrap();
+        check_success(&response).unwrap();
+        assert_eq!(
+            response.body,
+            Some(json!({ "supportsConfigurationDoneRequest": true }))
+        );
+        assert_eq!(events.recv().await.unwrap().event, "initialized");
+        assert_eq!(
+            adapter.await.unwrap(),
+            json!({
+                "seq": 1, "type": "request", "command": "initialize",
+                "arguments": { "adapterID": "lldb" }
+            })
+        );
+    }
+}
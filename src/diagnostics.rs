@@ -0,0 +1,332 @@
+use crate::methods;
+#[cfg(feature = "lsp-3-17")]
+use crate::protocol::DocumentDiagnosticReport;
+use crate::protocol::{Diagnostic, NotificationMessage, PublishDiagnosticsParams};
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+
+/// Capacity of the change channel. Subscribers that fall further behind than
+/// this will observe a `Lagged` error and should re-read the store.
+const CHANGE_CHANNEL_CAPACITY: usize = 256;
+
+/// The latest diagnostics known for a single document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentDiagnostics {
+    /// The document version the diagnostics were computed for, if known.
+    pub version: Option<i32>,
+    /// The `resultId` of the last pull report, used for `previousResultId`.
+    pub result_id: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Sent to subscribers whenever the diagnostics of a document change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticsChange {
+    pub uri: String,
+    pub version: Option<i32>,
+}
+
+/// Keeps the latest set of diagnostics per document URI, fed from
+/// `textDocument/publishDiagnostics` notifications and `textDocument/diagnostic`
+/// pull reports. Updates computed against an older document version than the
+/// newest one seen are discarded.
+pub struct DiagnosticsStore {
+    documents: HashMap<String, DocumentDiagnostics>,
+    /// The highest version seen per document. Unversioned updates don't
+    /// reset it, so older versioned ones stay stale after them.
+    highest_versions: HashMap<String, i32>,
+    changes: broadcast::Sender<DiagnosticsChange>,
+}
+
+impl Default for DiagnosticsStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DiagnosticsStore {
+    pub fn new() -> Self {
+        let (changes, _) = broadcast::channel(CHANGE_CHANNEL_CAPACITY);
+        Self {
+            documents: HashMap::new(),
+            highest_versions: HashMap::new(),
+            changes,
+        }
+    }
+
+    /// Returns a receiver that is notified every time a document's diagnostics change.
+    pub fn subscribe(&self) -> broadcast::Receiver<DiagnosticsChange> {
+        self.changes.subscribe()
+    }
+
+    /// Ingests a `textDocument/publishDiagnostics` notification.
+    /// Returns whether the store was updated.
+    pub fn ingest_notification(&mut self, notification: &NotificationMessage) -> Result<bool> {
+        if notification.method != methods::TEXT_DOCUMENT_PUBLISH_DIAGNOSTICS {
+            bail!(
+                "Expected a {} notification, got {}",
+                methods::TEXT_DOCUMENT_PUBLISH_DIAGNOSTICS,
+                notification.method
+            );
+        }
+        let params = PublishDiagnosticsParams::deserialize(&notification.params)
+            .map_err(|e| anyhow!("Failed to parse publishDiagnostics params: {}", e))?;
+        Ok(self.publish(params))
+    }
+
+    /// Replaces the diagnostics of `params.uri` unless they are stale.
+    /// Returns whether the store was updated.
+    pub fn publish(&mut self, params: PublishDiagnosticsParams) -> bool {
+        if self.is_stale(&params.uri, params.version) {
+            return false;
+        }
+        self.record_version(&params.uri, params.version);
+        let entry = self.documents.entry(params.uri.clone()).or_default();
+        entry.version = params.version;
+        entry.diagnostics = params.diagnostics;
+        let version = entry.version;
+        self.notify(params.uri, version);
+        true
+    }
+
+    /// Ingests the report of a `textDocument/diagnostic` request issued for
+    /// `version` of `uri`. Returns whether the store was updated.
+    #[cfg(feature = "lsp-3-17")]
+    pub fn ingest_report(
+        &mut self,
+        uri: String,
+        version: Option<i32>,
+        report: DocumentDiagnosticReport,
+    ) -> bool {
+        if self.is_stale(&uri, version) {
+            return false;
+        }
+        self.record_version(&uri, version);
+        let entry = self.documents.entry(uri.clone()).or_default();
+        entry.version = version;
+        match report {
+            DocumentDiagnosticReport::Full { result_id, items } => {
+                entry.result_id = result_id;
+                entry.diagnostics = items;
+            }
+            DocumentDiagnosticReport::Unchanged { result_id } => {
+                entry.result_id = Some(result_id);
+            }
+        }
+        let version = entry.version;
+        self.notify(uri, version);
+        true
+    }
+
+    /// Returns the stored state of a document, if any diagnostics were received for it.
+    pub fn get(&self, uri: &str) -> Option<&DocumentDiagnostics> {
+        self.documents.get(uri)
+    }
+
+    /// Returns the latest diagnostics of a document, or an empty slice.
+    pub fn diagnostics(&self, uri: &str) -> &[Diagnostic] {
+        self.documents
+            .get(uri)
+            .map(|doc| doc.diagnostics.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns the diagnostics of a document if they were computed for
+    /// `version` of it, so that squiggles computed against an older buffer
+    /// are never painted over a newer one. `None` if they belong to another
+    /// version, or the server didn't say which.
+    pub fn diagnostics_for_version(&self, uri: &str, version: i32) -> Option<&[Diagnostic]> {
+        self.documents
+            .get(uri)
+            .filter(|doc| doc.version == Some(version))
+            .map(|doc| doc.diagnostics.as_slice())
+    }
+
+    /// Iterates over every document with stored diagnostics.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &DocumentDiagnostics)> {
+        self.documents.iter()
+    }
+
+    /// Forgets a document, e.g. after it was closed.
+    pub fn remove(&mut self, uri: &str) -> Option<DocumentDiagnostics> {
+        self.highest_versions.remove(uri);
+        let removed = self.documents.remove(uri);
+        if removed.is_some() {
+            self.notify(uri.to_string(), None);
+        }
+        removed
+    }
+
+    fn is_stale(&self, uri: &str, version: Option<i32>) -> bool {
+        match (self.highest_versions.get(uri), version) {
+            (Some(highest), Some(incoming)) => incoming < *highest,
+            _ => false,
+        }
+    }
+
+    fn record_version(&mut self, uri: &str, version: Option<i32>) {
+        if let Some(version) = version {
+            let highest = self
+                .highest_versions
+                .entry(uri.to_string())
+                .or_insert(version);
+            *highest = (*highest).max(version);
+        }
+    }
+
+    fn notify(&self, uri: String, version: Option<i32>) {
+        // Sending only fails when nobody is subscribed, which is fine.
+        let _ = self.changes.send(DiagnosticsChange { uri, version });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::DiagnosticTag;
+    use serde_json::json;
+
+    fn publish_notification(version: i32, message: &str) -> NotificationMessage {
+        serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": "file:///main.go",
+                "version": version,
+                "diagnostics": [{
+                    "range": {
+                        "start": { "line": 0, "character": 0 },
+                        "end": { "line": 0, "character": 4 }
+                    },
+                    "severity": 1,
+                    "message": message
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_stale_versions_are_discarded() {
+        let mut store = DiagnosticsStore::new();
+        let mut changes = store.subscribe();
+
+        assert!(store
+            .ingest_notification(&publish_notification(2, "new"))
+            .unwrap());
+        assert!(!store
+            .ingest_notification(&publish_notification(1, "old"))
+            .unwrap());
+
+        let diagnostics = store.diagnostics("file:///main.go");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "new");
+        assert_eq!(
+            changes.try_recv().unwrap(),
+            DiagnosticsChange {
+                uri: "file:///main.go".to_string(),
+                version: Some(2),
+            }
+        );
+        assert!(changes.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_diagnostics_for_version() {
+        let mut store = DiagnosticsStore::new();
+        store
+            .ingest_notification(&publish_notification(3, "unused variable"))
+            .unwrap();
+
+        let current = store.diagnostics_for_version("file:///main.go", 3).unwrap();
+        assert_eq!(current[0].message, "unused variable");
+        assert!(store
+            .diagnostics_for_version("file:///main.go", 4)
+            .is_none());
+        assert!(store
+            .diagnostics_for_version("file:///other.go", 3)
+            .is_none());
+
+        // Diagnostics without a version can't be trusted for any of them.
+        store.publish(PublishDiagnosticsParams {
+            uri: "file:///main.go".to_string(),
+            version: None,
+            diagnostics: Vec::new(),
+        });
+        assert!(store
+            .diagnostics_for_version("file:///main.go", 3)
+            .is_none());
+    }
+
+    #[test]
+    fn test_unversioned_publish_keeps_highest_version() {
+        let mut store = DiagnosticsStore::new();
+        store
+            .ingest_notification(&publish_notification(2, "new"))
+            .unwrap();
+        assert!(store.publish(PublishDiagnosticsParams {
+            uri: "file:///main.go".to_string(),
+            version: None,
+            diagnostics: Vec::new(),
+        }));
+
+        // Version 1 is still older than the newest one seen.
+        assert!(!store
+            .ingest_notification(&publish_notification(1, "old"))
+            .unwrap());
+        assert!(store.diagnostics("file:///main.go").is_empty());
+        assert!(store
+            .ingest_notification(&publish_notification(2, "again"))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_unknown_severity_and_tags_are_tolerated() {
+        let notification: NotificationMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": "file:///main.go",
+                "diagnostics": [{
+                    "range": {
+                        "start": { "line": 0, "character": 0 },
+                        "end": { "line": 0, "character": 4 }
+                    },
+                    "severity": 9,
+                    "tags": [1, 7, 2],
+                    "message": "from the future"
+                }]
+            }
+        }))
+        .unwrap();
+
+        let mut store = DiagnosticsStore::new();
+        assert!(store.ingest_notification(&notification).unwrap());
+        let diagnostic = &store.diagnostics("file:///main.go")[0];
+        assert_eq!(diagnostic.severity, None);
+        assert_eq!(
+            diagnostic.tags,
+            Some(vec![DiagnosticTag::Unnecessary, DiagnosticTag::Deprecated])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "lsp-3-17")]
+    fn test_unchanged_report_keeps_diagnostics() {
+        let mut store = DiagnosticsStore::new();
+        store
+            .ingest_notification(&publish_notification(1, "unused variable"))
+            .unwrap();
+
+        let report: DocumentDiagnosticReport =
+            serde_json::from_value(json!({ "kind": "unchanged", "resultId": "r1" })).unwrap();
+        assert!(store.ingest_report("file:///main.go".to_string(), Some(2), report));
+
+        let doc = store.get("file:///main.go").unwrap();
+        assert_eq!(doc.version, Some(2));
+        assert_eq!(doc.result_id.as_deref(), Some("r1"));
+        assert_eq!(doc.diagnostics[0].message, "unused variable");
+    }
+}
@@ -0,0 +1,599 @@
+use crate::client::LspClient;
+use crate::protocol::{NotificationMessage, Position, TextDocumentContentChangeEvent};
+use crate::workspace::path_to_uri;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How many files `open_documents` reads at the same time.
+const OPEN_DOCUMENTS_CONCURRENCY: usize = 16;
+
+/// A document the client has opened on the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocument {
+    pub uri: String,
+    pub language_id: String,
+    pub version: i32,
+    pub text: String,
+}
+
+impl TextDocument {
+    /// Applies a single content change to the document text.
+    /// This does not bump the version.
+    pub fn apply_change(&mut self, change: &TextDocumentContentChangeEvent) {
+        apply_change(&mut self.text, change);
+    }
+}
+
+pub(crate) fn apply_change(text: &mut String, change: &TextDocumentContentChangeEvent) {
+    match &change.range {
+        Some(range) => {
+            let start = offset_at(text, &range.start);
+            let end = offset_at(text, &range.end).max(start);
+            text.replace_range(start..end, &change.text);
+        }
+        None => *text = change.text.clone(),
+    }
+}
+
+/// Changes applied locally but not yet sent to the server.
+#[derive(Debug)]
+struct PendingChanges {
+    first_change: Instant,
+    changes: Vec<TextDocumentContentChangeEvent>,
+}
+
+/// Tracks the documents open on the server along with their current version.
+///
+/// With a debounce configured, changes are queued with `queue_change` and
+/// merged into a single `didChange` notification per document once the
+/// debounce window of its oldest pending change has elapsed (`take_due`) or
+/// when the caller flushes explicitly (`take_pending`).
+///
+/// With line ending normalization enabled, the text of opened documents and
+/// of changes has its `\r\n` and `\r` line endings replaced with `\n`.
+/// Positions are unaffected, since every kind of line ending ends one line.
+#[derive(Debug, Default)]
+pub struct DocumentStore {
+    documents: HashMap<String, TextDocument>,
+    debounce: Option<Duration>,
+    normalize_line_endings: bool,
+    pending: HashMap<String, PendingChanges>,
+}
+
+impl DocumentStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts tracking a document at version 0 and returns it.
+    pub fn open(&mut self, uri: String, language_id: String, text: String) -> &TextDocument {
+        self.documents.insert(
+            uri.clone(),
+            TextDocument {
+                uri: uri.clone(),
+                language_id,
+                version: 0,
+                text: if self.normalize_line_endings {
+                    normalize_line_endings(&text).into_owned()
+                } else {
+                    text
+                },
+            },
+        );
+        &self.documents[&uri]
+    }
+
+    /// Starts tracking `document` at its version, such as a document open
+    /// before the client restarted.
+    pub fn reopen(&mut self, document: TextDocument) -> &TextDocument {
+        let uri = document.uri.clone();
+        self.documents.insert(uri.clone(), document);
+        &self.documents[&uri]
+    }
+
+    /// Applies `changes` to an open document and returns its new version.
+    pub fn change(&mut self, uri: &str, changes: &[TextDocumentContentChangeEvent]) -> Result<i32> {
+        let document = self
+            .documents
+            .get_mut(uri)
+            .ok_or_else(|| anyhow!("Document {} is not open", uri))?;
+        for change in changes {
+            document.apply_change(change);
+        }
+        document.version += 1;
+        Ok(document.version)
+    }
+
+    /// Stops tracking a document. Pending changes of the document are dropped.
+    pub fn close(&mut self, uri: &str) -> Option<TextDocument> {
+        self.pending.remove(uri);
+        self.documents.remove(uri)
+    }
+
+    pub fn normalizes_line_endings(&self) -> bool {
+        self.normalize_line_endings
+    }
+
+    /// Sets whether line endings are normalized from now on. Documents already
+    /// open keep their text.
+    pub fn set_normalize_line_endings(&mut self, normalize: bool) {
+        self.normalize_line_endings = normalize;
+    }
+
+    /// Normalizes the line endings of `changes`, if normalization is enabled.
+    /// Call before `change`, and send the normalized changes.
+    pub fn normalize_changes(&self, changes: &mut [TextDocumentContentChangeEvent]) {
+        if !self.normalize_line_endings {
+            return;
+        }
+        for change in changes {
+            if let Cow::Owned(text) = normalize_line_endings(&change.text) {
+                change.text = text;
+            }
+        }
+    }
+
+    pub fn debounce(&self) -> Option<Duration> {
+        self.debounce
+    }
+
+    /// Sets how long changes may be held back before they have to be sent.
+    /// `None` disables batching.
+    pub fn set_debounce(&mut self, debounce: Option<Duration>) {
+        self.debounce = debounce;
+    }
+
+    /// Queues changes already applied with `change` to be sent later.
+    pub fn queue_change(&mut self, uri: &str, changes: Vec<TextDocumentContentChangeEvent>) {
+        let pending = self
+            .pending
+            .entry(uri.to_string())
+            .or_insert_with(|| PendingChanges {
+                first_change: Instant::now(),
+                changes: Vec::new(),
+            });
+        for change in changes {
+            // A full-text change supersedes everything queued before it.
+            if change.range.is_none() {
+                pending.changes.clear();
+            }
+            pending.changes.push(change);
+        }
+    }
+
+    /// When the oldest pending change has to be sent, if any change is pending.
+    pub fn next_flush_deadline(&self) -> Option<Instant> {
+        let debounce = self.debounce.unwrap_or_default();
+        self.pending
+            .values()
+            .map(|pending| pending.first_change + debounce)
+            .min()
+    }
+
+    /// Takes the `didChange` notifications of documents whose debounce window
+    /// has elapsed at `now`.
+    pub fn take_due(&mut self, now: Instant) -> Vec<NotificationMessage> {
+        let debounce = self.debounce.unwrap_or_default();
+        let due: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.first_change + debounce <= now)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        due.iter()
+            .filter_map(|uri| self.take_pending_for(uri))
+            .collect()
+    }
+
+    /// Takes the `didChange` notifications of every document with pending changes.
+    pub fn take_pending(&mut self) -> Vec<NotificationMessage> {
+        let uris: Vec<String> = self.pending.keys().cloned().collect();
+        uris.iter()
+            .filter_map(|uri| self.take_pending_for(uri))
+            .collect()
+    }
+
+    /// Takes the merged `didChange` notification of a single document.
+    pub fn take_pending_for(&mut self, uri: &str) -> Option<NotificationMessage> {
+        let pending = self.pending.remove(uri)?;
+        let version = self.version(uri)?;
+        Some(NotificationMessage::new_did_change(
+            uri.to_string(),
+            version,
+            pending.changes,
+        ))
+    }
+
+    pub fn get(&self, uri: &str) -> Option<&TextDocument> {
+        self.documents.get(uri)
+    }
+
+    /// Iterates over the open documents, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &TextDocument> {
+        self.documents.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Returns the current version of an open document.
+    pub fn version(&self, uri: &str) -> Option<i32> {
+        self.documents.get(uri).map(|doc| doc.version)
+    }
+
+    /// Fails unless `uri` is open at `version`. Used to refuse edits and
+    /// results computed for another version of the document.
+    pub fn check_version(&self, uri: &str, version: i32) -> Result<()> {
+        match self.version(uri) {
+            Some(current) if current == version => Ok(()),
+            Some(current) => bail!(
+                "Stale response for {}: computed for version {}, document is at version {}",
+                uri,
+                version,
+                current
+            ),
+            None => bail!("Stale response for {}: document was closed", uri),
+        }
+    }
+
+    /// Captures the current version of `uri`, to be checked once the response
+    /// of a request about that document arrives.
+    pub fn guard(&self, uri: &str) -> Option<VersionGuard> {
+        self.version(uri).map(|version| VersionGuard {
+            uri: uri.to_string(),
+            version,
+        })
+    }
+}
+
+/// Remembers which version of a document a request was computed against.
+/// If the document changed since, results like completions or text edits
+/// refer to outdated text and should be dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionGuard {
+    uri: String,
+    version: i32,
+}
+
+impl VersionGuard {
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Whether the document is still open at the guarded version.
+    pub fn is_current(&self, documents: &DocumentStore) -> bool {
+        documents.version(&self.uri) == Some(self.version)
+    }
+
+    /// Like `is_current`, but returns an error describing the staleness.
+    pub fn check(&self, documents: &DocumentStore) -> Result<()> {
+        documents.check_version(&self.uri, self.version)
+    }
+}
+
+/// Converts an LSP position (in UTF-16 code units) to a byte offset in `text`.
+/// Positions past the end of a line or of the text are clamped, as the spec asks.
+pub(crate) fn offset_at(text: &str, position: &Position) -> usize {
+    let Some(line) = line_bounds(text, position.line) else {
+        return text.len();
+    };
+    let mut units = 0;
+    for (offset, ch) in text[line.clone()].char_indices() {
+        if units >= position.character {
+            return line.start + offset;
+        }
+        units += ch.len_utf16() as u32;
+    }
+    line.end
+}
+
+/// The byte range of line `line` of `text`, without its line ending. Lines
+/// end with `\n`, `\r\n` or `\r`, as in LSP.
+pub(crate) fn line_bounds(text: &str, line: u32) -> Option<std::ops::Range<usize>> {
+    let mut start = 0;
+    for _ in 0..line {
+        let (end, ending) = line_end(&text[start..])?;
+        start += end + ending;
+    }
+    let end = line_end(&text[start..]).map_or(text.len(), |(end, _)| start + end);
+    Some(start..end)
+}
+
+/// The offset of the first line ending in `text`, and its length in bytes.
+fn line_end(text: &str) -> Option<(usize, usize)> {
+    let end = text.find(['\r', '\n'])?;
+    let ending = if text[end..].starts_with("\r\n") {
+        2
+    } else {
+        1
+    };
+    Some((end, ending))
+}
+
+/// Replaces `\r\n` and `\r` line endings with `\n`.
+pub fn normalize_line_endings(text: &str) -> Cow<'_, str> {
+    if !text.contains('\r') {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+}
+
+/// The `languageId` of the files with the extension of `path`, for the
+/// languages whose id can be told from it.
+pub fn language_id_for_path(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?;
+    Some(match extension {
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "css" => "css",
+        "go" => "go",
+        "html" | "htm" => "html",
+        "java" => "java",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "javascriptreact",
+        "json" => "json",
+        "kt" | "kts" => "kotlin",
+        "lua" => "lua",
+        "md" => "markdown",
+        "php" => "php",
+        "py" => "python",
+        "rb" => "ruby",
+        "rs" => "rust",
+        "scala" => "scala",
+        "sh" | "bash" => "shellscript",
+        "swift" => "swift",
+        "toml" => "toml",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "typescriptreact",
+        "yaml" | "yml" => "yaml",
+        "zig" => "zig",
+        _ => return None,
+    })
+}
+
+impl LspClient {
+    /// Reads the files at `paths` and opens them on the server, e.g. to warm
+    /// up its index before batch queries. Files are read a few at a time,
+    /// but opened in the order given; their `languageId` comes from
+    /// `language_id_for_path`, or is `plaintext`. Files that are already
+    /// open are skipped. Returns the URIs of the files, in order.
+    pub async fn open_documents<I>(&self, paths: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        let mut paths = paths.into_iter().map(|path| path.as_ref().to_path_buf());
+        let mut reads = VecDeque::new();
+        let mut uris = Vec::new();
+        loop {
+            while reads.len() < OPEN_DOCUMENTS_CONCURRENCY {
+                let Some(path) = paths.next() else { break };
+                reads.push_back(tokio::spawn(read_document(path)));
+            }
+            let Some(read) = reads.pop_front() else {
+                return Ok(uris);
+            };
+            let (path, text) = read.await??;
+            let uri = path_to_uri(&path);
+            if self.documents().get(&uri).is_none() {
+                let language_id = language_id_for_path(&path).unwrap_or("plaintext");
+                self.did_open(uri.clone(), language_id.to_string(), text)
+                    .await?;
+            }
+            uris.push(uri);
+        }
+    }
+}
+
+async fn read_document(path: PathBuf) -> Result<(PathBuf, String)> {
+    let text = tokio::fs::read_to_string(&path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok((path, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Range;
+
+    fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range {
+                start: Position::new(start.0, start.1),
+                end: Position::new(end.0, end.1),
+            }),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_incremental_changes() {
+        let mut store = DocumentStore::new();
+        store.open(
+            "file:///main.go".to_string(),
+            "go".to_string(),
+            "package main\n\nfunc 😀() {}\n".to_string(),
+        );
+
+        let version = store
+            .change(
+                "file:///main.go",
+                &[edit((2, 5), (2, 7), "main"), edit((0, 8), (0, 12), "app")],
+            )
+            .unwrap();
+
+        assert_eq!(version, 1);
+        assert_eq!(
+            store.get("file:///main.go").unwrap().text,
+            "package app\n\nfunc main() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_crlf_line_endings() {
+        let mut store = DocumentStore::new();
+        store.open(
+            "file:///crlf.txt".to_string(),
+            "plaintext".to_string(),
+            "one\r\ntwo\rthree\n".to_string(),
+        );
+        // Columns past the end of a line stop before its line ending.
+        store
+            .change(
+                "file:///crlf.txt",
+                &[edit((0, 3), (0, 99), "!"), edit((2, 0), (2, 5), "3")],
+            )
+            .unwrap();
+        assert_eq!(
+            store.get("file:///crlf.txt").unwrap().text,
+            "one!\r\ntwo\r3\n"
+        );
+
+        store.set_normalize_line_endings(true);
+        store.open(
+            "file:///normalized.txt".to_string(),
+            "plaintext".to_string(),
+            "a\r\nb\rc".to_string(),
+        );
+        let mut changes = vec![edit((1, 1), (1, 1), "\r\nd")];
+        store.normalize_changes(&mut changes);
+        assert_eq!(changes[0].text, "\nd");
+        store.change("file:///normalized.txt", &changes).unwrap();
+        assert_eq!(
+            store.get("file:///normalized.txt").unwrap().text,
+            "a\nb\nd\nc"
+        );
+    }
+
+    #[test]
+    fn test_queued_changes_are_merged() {
+        let mut store = DocumentStore::new();
+        store.set_debounce(Some(Duration::from_millis(50)));
+        store.open(
+            "file:///main.go".to_string(),
+            "go".to_string(),
+            String::new(),
+        );
+
+        for (i, text) in ["p", "a", "c"].iter().enumerate() {
+            let change = vec![edit((0, i as u32), (0, i as u32), text)];
+            store.change("file:///main.go", &change).unwrap();
+            store.queue_change("file:///main.go", change);
+        }
+
+        let deadline = store.next_flush_deadline().unwrap();
+        assert!(store
+            .take_due(deadline - Duration::from_millis(1))
+            .is_empty());
+
+        let notifications = store.take_due(deadline);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].params["textDocument"]["version"], 3);
+        assert_eq!(
+            notifications[0].params["contentChanges"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+        assert!(store.take_pending().is_empty());
+    }
+
+    #[test]
+    fn test_full_text_change_supersedes_queued_changes() {
+        let mut store = DocumentStore::new();
+        store.open(
+            "file:///main.go".to_string(),
+            "go".to_string(),
+            String::new(),
+        );
+        store.queue_change("file:///main.go", vec![edit((0, 0), (0, 0), "x")]);
+        store.queue_change(
+            "file:///main.go",
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                text: "package main".to_string(),
+            }],
+        );
+
+        let notification = store.take_pending_for("file:///main.go").unwrap();
+        assert_eq!(
+            notification.params["contentChanges"],
+            serde_json::json!([{ "text": "package main" }])
+        );
+    }
+
+    #[test]
+    fn test_version_guard_detects_stale_responses() {
+        let mut store = DocumentStore::new();
+        store.open(
+            "file:///main.go".to_string(),
+            "go".to_string(),
+            String::new(),
+        );
+
+        let guard = store.guard("file:///main.go").unwrap();
+        assert!(guard.check(&store).is_ok());
+
+        store
+            .change("file:///main.go", &[edit((0, 0), (0, 0), "package main")])
+            .unwrap();
+        assert!(!guard.is_current(&store));
+        assert!(guard.check(&store).is_err());
+
+        store.close("file:///main.go");
+        assert!(guard.check(&store).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_documents_in_order() {
+        let root = std::env::temp_dir().join(format!("lsp-client-rs-open-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let paths: Vec<PathBuf> = (0..40)
+            .map(|i| {
+                let path = root.join(format!("file{}.go", i));
+                std::fs::write(&path, format!("package file{}", i)).unwrap();
+                path
+            })
+            .collect();
+
+        let (client_side, server_side) = tokio::io::duplex(1 << 20);
+        let client = LspClient::from_stream(client_side);
+        let uris = client.open_documents(&paths).await.unwrap();
+        // Opening again sends nothing.
+        client.open_documents(&paths[..3]).await.unwrap();
+
+        let mut reader = crate::transport::FrameReader::new(server_side);
+        for (i, uri) in uris.iter().enumerate() {
+            let frame: serde_json::Value =
+                serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+            let document = &frame["params"]["textDocument"];
+            assert_eq!(document["uri"], uri.as_str());
+            assert_eq!(document["languageId"], "go");
+            assert_eq!(document["text"], format!("package file{}", i));
+        }
+        let next = tokio::time::timeout(Duration::from_millis(50), reader.read_frame());
+        assert!(next.await.is_err());
+        assert!(client
+            .open_documents([root.join("missing.go")])
+            .await
+            .is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
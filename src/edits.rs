@@ -0,0 +1,338 @@
+//! Workspace edits sent by the server, e.g. as the result of a rename or in a
+//! `workspace/applyEdit` request.
+
+use crate::protocol::{Range, TextDocumentContentChangeEvent};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// A replacement of `range` with `new_text`. With an `annotation_id` this is
+/// an `AnnotatedTextEdit`, whose annotation is found in the `change_annotations`
+/// of the enclosing `WorkspaceEdit`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range,
+    #[serde(rename = "newText")]
+    pub new_text: String,
+    #[serde(rename = "annotationId", skip_serializing_if = "Option::is_none")]
+    pub annotation_id: Option<String>,
+}
+
+/// Describes a group of changes, so the client can show them to the user and
+/// ask for confirmation if `needs_confirmation` is set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChangeAnnotation {
+    pub label: String,
+    #[serde(rename = "needsConfirmation", skip_serializing_if = "Option::is_none")]
+    pub needs_confirmation: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// A document and, if the server knows it, the version the edits were computed for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OptionalVersionedTextDocumentIdentifier {
+    pub uri: String,
+    /// `None` if the server doesn't know the version, e.g. for files that aren't open.
+    #[serde(default)]
+    pub version: Option<i32>,
+}
+
+/// The edits of a single document.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TextDocumentEdit {
+    #[serde(rename = "textDocument")]
+    pub text_document: OptionalVersionedTextDocumentIdentifier,
+    pub edits: Vec<TextEdit>,
+}
+
+/// A create, rename or delete operation on a file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ResourceOperation {
+    Create {
+        uri: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        options: Option<serde_json::Value>,
+        #[serde(rename = "annotationId", skip_serializing_if = "Option::is_none")]
+        annotation_id: Option<String>,
+    },
+    Rename {
+        #[serde(rename = "oldUri")]
+        old_uri: String,
+        #[serde(rename = "newUri")]
+        new_uri: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        options: Option<serde_json::Value>,
+        #[serde(rename = "annotationId", skip_serializing_if = "Option::is_none")]
+        annotation_id: Option<String>,
+    },
+    Delete {
+        uri: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        options: Option<serde_json::Value>,
+        #[serde(rename = "annotationId", skip_serializing_if = "Option::is_none")]
+        annotation_id: Option<String>,
+    },
+}
+
+/// An entry of `WorkspaceEdit::document_changes`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum DocumentChange {
+    Edit(TextDocumentEdit),
+    Operation(ResourceOperation),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct WorkspaceEdit {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changes: Option<HashMap<String, Vec<TextEdit>>>,
+    /// Preferred over `changes` when present.
+    #[serde(rename = "documentChanges", skip_serializing_if = "Option::is_none")]
+    pub document_changes: Option<Vec<DocumentChange>>,
+    #[serde(rename = "changeAnnotations", skip_serializing_if = "Option::is_none")]
+    pub change_annotations: Option<HashMap<String, ChangeAnnotation>>,
+}
+
+impl WorkspaceEdit {
+    pub fn annotation(&self, id: &str) -> Option<&ChangeAnnotation> {
+        self.change_annotations.as_ref()?.get(id)
+    }
+
+    /// Whether any annotation asks the user to confirm its changes.
+    pub fn needs_confirmation(&self) -> bool {
+        self.change_annotations
+            .iter()
+            .flatten()
+            .any(|(_, annotation)| annotation.needs_confirmation.unwrap_or(false))
+    }
+
+    /// The text edits of every document, in the order the server sent them.
+    pub fn text_edits(&self) -> Result<Vec<&TextDocumentEdit>> {
+        self.document_changes
+            .iter()
+            .flatten()
+            .map(|change| match change {
+                DocumentChange::Edit(edit) => Ok(edit),
+                DocumentChange::Operation(operation) => {
+                    bail!("Resource operations are not supported: {:?}", operation)
+                }
+            })
+            .collect()
+    }
+
+    /// Groups the accepted edits by document and turns them into content
+    /// changes that can be applied one after the other.
+    ///
+    /// `confirm` is called once per annotation used by the edits. Edits whose
+    /// annotation it rejects are left out.
+    pub fn content_changes<F>(&self, mut confirm: F) -> Result<Vec<DocumentEdits>>
+    where
+        F: FnMut(&str, &ChangeAnnotation) -> bool,
+    {
+        let mut confirmed: HashMap<String, bool> = HashMap::new();
+        let mut accept = |edit: &TextEdit| -> Result<bool> {
+            let Some(id) = edit.annotation_id.as_deref() else {
+                return Ok(true);
+            };
+            if let Some(accepted) = confirmed.get(id) {
+                return Ok(*accepted);
+            }
+            let annotation = self
+                .annotation(id)
+                .ok_or_else(|| anyhow!("Unknown change annotation {}", id))?;
+            let accepted = confirm(id, annotation);
+            confirmed.insert(id.to_string(), accepted);
+            Ok(accepted)
+        };
+
+        let mut documents: Vec<(&str, Option<i32>, Vec<&TextEdit>)> = Vec::new();
+        if self.document_changes.is_some() {
+            for edit in self.text_edits()? {
+                let document = &edit.text_document;
+                documents.push((&document.uri, document.version, edit.edits.iter().collect()));
+            }
+        } else if let Some(changes) = &self.changes {
+            for (uri, edits) in changes {
+                documents.push((uri, None, edits.iter().collect()));
+            }
+        }
+
+        let mut result = Vec::with_capacity(documents.len());
+        for (uri, version, edits) in documents {
+            let mut accepted = Vec::with_capacity(edits.len());
+            for edit in edits {
+                if accept(edit)? {
+                    accepted.push(edit);
+                }
+            }
+            if !accepted.is_empty() {
+                result.push(DocumentEdits {
+                    uri: uri.to_string(),
+                    version,
+                    changes: to_content_changes(accepted),
+                });
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// The content changes a workspace edit makes to one document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentEdits {
+    pub uri: String,
+    /// The version of the document the edits were computed for, if the server sent one.
+    /// The edits must not be applied to any other version.
+    pub version: Option<i32>,
+    pub changes: Vec<TextDocumentContentChangeEvent>,
+}
+
+/// All text edits of a document refer to the original text, while content
+/// changes apply one after the other. Applying the edits from the end of the
+/// document backwards makes both the same. Inserts at the same position keep
+/// the order they were sent in. Inverted ranges are normalized.
+pub(crate) fn to_content_changes(mut edits: Vec<&TextEdit>) -> Vec<TextDocumentContentChangeEvent> {
+    edits.reverse();
+    edits.sort_by_key(|edit| {
+        let range = edit.range.normalized();
+        Reverse((range.start(), range.end()))
+    });
+    edits
+        .into_iter()
+        .map(|edit| TextDocumentContentChangeEvent {
+            range: Some(edit.range.normalized()),
+            text: edit.new_text.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::documents::DocumentStore;
+    use serde_json::json;
+
+    fn rename_edit() -> WorkspaceEdit {
+        serde_json::from_value(json!({
+            "documentChanges": [{
+                "textDocument": { "uri": "file:///main.go", "version": 0 },
+                "edits": [
+                    {
+                        "range": {
+                            "start": { "line": 0, "character": 4 },
+                            "end": { "line": 0, "character": 7 }
+                        },
+                        "newText": "bar"
+                    },
+                    {
+                        "range": {
+                            "start": { "line": 1, "character": 0 },
+                            "end": { "line": 1, "character": 3 }
+                        },
+                        "newText": "bar",
+                        "annotationId": "in-comment"
+                    }
+                ]
+            }],
+            "changeAnnotations": {
+                "in-comment": {
+                    "label": "Rename occurrences in comments",
+                    "needsConfirmation": true
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_annotated_edits_need_confirmation() {
+        let edit = rename_edit();
+        assert!(edit.needs_confirmation());
+
+        let mut store = DocumentStore::new();
+        store.open(
+            "file:///main.go".to_string(),
+            "go".to_string(),
+            "var foo\nfoo is used".to_string(),
+        );
+
+        let mut asked = Vec::new();
+        let rejected = edit
+            .content_changes(|id, annotation| {
+                asked.push((id.to_string(), annotation.label.clone()));
+                false
+            })
+            .unwrap();
+        assert_eq!(
+            asked,
+            vec![(
+                "in-comment".to_string(),
+                "Rename occurrences in comments".to_string()
+            )]
+        );
+        store
+            .change("file:///main.go", &rejected[0].changes)
+            .unwrap();
+        assert_eq!(
+            store.get("file:///main.go").unwrap().text,
+            "var bar\nfoo is used"
+        );
+    }
+
+    #[test]
+    fn test_edits_apply_against_original_text() {
+        let edit: WorkspaceEdit = serde_json::from_value(json!({
+            "changes": {
+                "file:///main.go": [
+                    {
+                        "range": {
+                            "start": { "line": 0, "character": 0 },
+                            "end": { "line": 0, "character": 3 }
+                        },
+                        "newText": "const"
+                    },
+                    {
+                        "range": {
+                            "start": { "line": 0, "character": 4 },
+                            "end": { "line": 0, "character": 7 }
+                        },
+                        "newText": "bar"
+                    },
+                    {
+                        "range": {
+                            "start": { "line": 0, "character": 10 },
+                            "end": { "line": 0, "character": 10 }
+                        },
+                        "newText": "2"
+                    },
+                    {
+                        "range": {
+                            "start": { "line": 0, "character": 10 },
+                            "end": { "line": 0, "character": 10 }
+                        },
+                        "newText": "3"
+                    }
+                ]
+            }
+        }))
+        .unwrap();
+        let mut store = DocumentStore::new();
+        store.open(
+            "file:///main.go".to_string(),
+            "go".to_string(),
+            "var foo = 1".to_string(),
+        );
+
+        let accepted = edit.content_changes(|_, _| true).unwrap();
+        store
+            .change("file:///main.go", &accepted[0].changes)
+            .unwrap();
+        assert_eq!(
+            store.get("file:///main.go").unwrap().text,
+            "const bar = 231"
+        );
+    }
+}
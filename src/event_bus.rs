@@ -0,0 +1,274 @@
+//! A bus of every message the server sends: requests, notifications and
+//! responses. Each subscriber gets its own stream, narrowed by an
+//! `EventFilter`, so UI components can follow the traffic they care about
+//! without sharing the client's read loop.
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::event_bus::EventFilter;
+//! let filter = EventFilter::new()
+//!     .method_prefix("textDocument/")
+//!     .uri("file:///app/main.go");
+//! let mut events = client.subscribe_incoming(filter);
+//! while let Ok(message) = events.recv().await {
+//!     println!("{:?}", message.method());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::LspClient;
+use crate::methods;
+use anyhow::{bail, Result};
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// A message received from the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncomingMessage {
+    message: Value,
+}
+
+impl IncomingMessage {
+    pub fn new(message: Value) -> Self {
+        IncomingMessage { message }
+    }
+
+    pub fn method(&self) -> Option<&str> {
+        self.message.get("method")?.as_str()
+    }
+
+    pub fn id(&self) -> Option<&Value> {
+        self.message.get("id").filter(|id| !id.is_null())
+    }
+
+    /// The params, or `Value::Null` for messages without any.
+    pub fn params(&self) -> &Value {
+        self.message.get("params").unwrap_or(&Value::Null)
+    }
+
+    pub fn is_request(&self) -> bool {
+        self.method().is_some() && self.id().is_some()
+    }
+
+    pub fn is_notification(&self) -> bool {
+        self.method().is_some() && self.id().is_none()
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.method().is_none()
+    }
+
+    /// The document the message is about: `params.textDocument.uri`, or
+    /// `params.uri` as in `textDocument/publishDiagnostics`.
+    pub fn uri(&self) -> Option<&str> {
+        let params = self.params();
+        params
+            .get("textDocument")
+            .and_then(|document| document.get("uri"))
+            .or_else(|| params.get("uri"))?
+            .as_str()
+    }
+
+    /// The token of `$/progress` and `window/workDoneProgress/create`.
+    pub fn progress_token(&self) -> Option<&Value> {
+        match self.method()? {
+            methods::PROGRESS | methods::WINDOW_WORK_DONE_PROGRESS_CREATE => {
+                self.params().get("token")
+            }
+            _ => None,
+        }
+    }
+
+    /// The whole message, as received.
+    pub fn as_value(&self) -> &Value {
+        &self.message
+    }
+}
+
+type Predicate = Arc<dyn Fn(&IncomingMessage) -> bool + Send + Sync>;
+
+/// Which messages a subscriber receives. A message must pass every condition
+/// set; the default filter lets everything through.
+#[derive(Clone, Default)]
+pub struct EventFilter {
+    method_prefix: Option<String>,
+    uri: Option<String>,
+    progress_token: Option<Value>,
+    predicates: Vec<Predicate>,
+}
+
+impl EventFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only messages whose method starts with `prefix`, e.g. `"$/"`.
+    /// Responses have no method, so they never match.
+    pub fn method_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.method_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Only messages about the document `uri`.
+    pub fn uri(mut self, uri: impl Into<String>) -> Self {
+        self.uri = Some(uri.into());
+        self
+    }
+
+    /// Only the progress messages of `token`, a number or a string.
+    pub fn progress_token(mut self, token: impl Into<Value>) -> Self {
+        self.progress_token = Some(token.into());
+        self
+    }
+
+    /// Only messages `predicate` returns `true` for.
+    pub fn matching(
+        mut self,
+        predicate: impl Fn(&IncomingMessage) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.predicates.push(Arc::new(predicate));
+        self
+    }
+
+    pub fn matches(&self, message: &IncomingMessage) -> bool {
+        if let Some(prefix) = &self.method_prefix {
+            if !message
+                .method()
+                .is_some_and(|m| m.starts_with(prefix.as_str()))
+            {
+                return false;
+            }
+        }
+        if let Some(uri) = &self.uri {
+            if message.uri() != Some(uri.as_str()) {
+                return false;
+            }
+        }
+        if let Some(token) = &self.progress_token {
+            if message.progress_token() != Some(token) {
+                return false;
+            }
+        }
+        self.predicates.iter().all(|predicate| predicate(message))
+    }
+}
+
+impl fmt::Debug for EventFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventFilter")
+            .field("method_prefix", &self.method_prefix)
+            .field("uri", &self.uri)
+            .field("progress_token", &self.progress_token)
+            .field("predicates", &self.predicates.len())
+            .finish()
+    }
+}
+
+/// The messages received since the subscription was made that pass its
+/// filter.
+pub struct EventSubscription {
+    receiver: broadcast::Receiver<Arc<IncomingMessage>>,
+    filter: EventFilter,
+}
+
+impl EventSubscription {
+    pub(crate) fn new(
+        receiver: broadcast::Receiver<Arc<IncomingMessage>>,
+        filter: EventFilter,
+    ) -> Self {
+        EventSubscription { receiver, filter }
+    }
+
+    /// Waits for the next matching message. Fails once the connection
+    /// closed. A subscriber that falls more than the channel's capacity
+    /// behind skips the messages it missed.
+    pub async fn recv(&mut self) -> Result<Arc<IncomingMessage>> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(message) if self.filter.matches(&message) => return Ok(message),
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => bail!("Connection to the server was closed"),
+            }
+        }
+    }
+}
+
+impl LspClient {
+    /// Receives every message from now on that passes `filter`.
+    pub fn subscribe_incoming(&self, filter: EventFilter) -> EventSubscription {
+        EventSubscription::new(self.incoming_receiver(), filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::AsyncWriteExt;
+
+    #[test]
+    fn test_filter() {
+        let progress = IncomingMessage::new(json!({
+            "jsonrpc": "2.0",
+            "method": "$/progress",
+            "params": { "token": 7, "value": { "kind": "report" } }
+        }));
+        let diagnostics = IncomingMessage::new(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": "file:///main.go", "diagnostics": [] }
+        }));
+        let response = IncomingMessage::new(json!({ "jsonrpc": "2.0", "id": 1, "result": null }));
+
+        let everything = EventFilter::new();
+        assert!([&progress, &diagnostics, &response]
+            .iter()
+            .all(|message| everything.matches(message)));
+
+        let prefix = EventFilter::new().method_prefix("$/");
+        assert!(prefix.matches(&progress));
+        assert!(!prefix.matches(&diagnostics));
+        assert!(!prefix.matches(&response));
+
+        let uri = EventFilter::new().uri("file:///main.go");
+        assert!(uri.matches(&diagnostics));
+        assert!(!uri.matches(&progress));
+
+        assert!(EventFilter::new().progress_token(7).matches(&progress));
+        assert!(!EventFilter::new().progress_token("7").matches(&progress));
+
+        let responses = EventFilter::new().matching(IncomingMessage::is_response);
+        assert!(responses.matches(&response));
+        assert!(!responses.matches(&diagnostics));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_incoming() {
+        let frame = |payload: &str| format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+        let (client_side, mut server_side) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_side);
+        let mut progress = client.subscribe_incoming(EventFilter::new().progress_token("index"));
+        let mut everything = client.subscribe_incoming(EventFilter::new());
+
+        for message in [
+            r#"{"jsonrpc":"2.0","method":"$/progress","params":{"token":"build","value":{}}}"#,
+            r#"{"jsonrpc":"2.0","method":"$/progress","params":{"token":"index","value":{}}}"#,
+        ] {
+            server_side
+                .write_all(frame(message).as_bytes())
+                .await
+                .unwrap();
+        }
+
+        let message = progress.recv().await.unwrap();
+        assert_eq!(message.progress_token(), Some(&json!("index")));
+        let message = everything.recv().await.unwrap();
+        assert_eq!(message.progress_token(), Some(&json!("build")));
+
+        drop(server_side);
+        assert!(progress.recv().await.is_err());
+    }
+}
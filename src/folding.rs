@@ -0,0 +1,150 @@
+//! Folding ranges, and their conversion to the tree of folds an editor shows.
+//!
+//! Servers may send ranges in any order, overlapping each other or past the
+//! end of a document that changed since. `fold_tree` cleans them up:
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::folding::fold_tree;
+//! let ranges = client.folding_range("file:///src/main.rs".to_string()).await?;
+//! for fold in fold_tree(ranges, 120) {
+//!     println!("{}..{}: {} nested", fold.range.start_line, fold.range.end_line, fold.children.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// A range of lines that can be folded. Characters are only hints; editors
+/// usually fold whole lines.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FoldingRange {
+    pub start_line: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_character: Option<u32>,
+    pub end_line: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_character: Option<u32>,
+    /// `comment`, `imports`, `region` or a kind of the server's own.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// The text to show in place of the folded lines, if the server has a
+    /// preference.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collapsed_text: Option<String>,
+}
+
+/// A folding range and the folds nested in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fold {
+    pub range: FoldingRange,
+    pub children: Vec<Fold>,
+}
+
+/// Arranges `ranges` into a tree of properly nested folds, in document order.
+///
+/// Ranges are clipped to the last of `line_count` lines, and dropped if that
+/// leaves nothing to fold. Of several ranges starting on the same line, the
+/// longest is kept. A range that starts inside another but ends after it is
+/// dropped, so the earlier range wins. A range starting on the line where
+/// another ends, as in `} else {`, follows it as a sibling.
+pub fn fold_tree(mut ranges: Vec<FoldingRange>, line_count: u32) -> Vec<Fold> {
+    let last_line = line_count.saturating_sub(1);
+    for range in &mut ranges {
+        if range.end_line > last_line {
+            range.end_line = last_line;
+            range.end_character = None;
+        }
+    }
+    ranges.retain(|range| range.start_line < range.end_line);
+    ranges.sort_by(|a, b| {
+        a.start_line
+            .cmp(&b.start_line)
+            .then(b.end_line.cmp(&a.end_line))
+    });
+    ranges.dedup_by_key(|range| range.start_line);
+
+    let mut roots = Vec::new();
+    // The folds the current range may still be nested in, outermost first.
+    let mut open: Vec<Fold> = Vec::new();
+    let close = |open: &mut Vec<Fold>, roots: &mut Vec<Fold>| {
+        if let Some(fold) = open.pop() {
+            match open.last_mut() {
+                Some(parent) => parent.children.push(fold),
+                None => roots.push(fold),
+            }
+        }
+    };
+    for range in ranges {
+        while open
+            .last()
+            .is_some_and(|fold| fold.range.end_line <= range.start_line)
+        {
+            close(&mut open, &mut roots);
+        }
+        if open
+            .last()
+            .is_some_and(|fold| fold.range.end_line < range.end_line)
+        {
+            continue;
+        }
+        open.push(Fold {
+            range,
+            children: Vec::new(),
+        });
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut roots);
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start_line: u32, end_line: u32) -> FoldingRange {
+        FoldingRange {
+            start_line,
+            start_character: None,
+            end_line,
+            end_character: None,
+            kind: None,
+            collapsed_text: None,
+        }
+    }
+
+    fn lines(folds: &[Fold]) -> Vec<(u32, u32)> {
+        folds
+            .iter()
+            .map(|fold| (fold.range.start_line, fold.range.end_line))
+            .collect()
+    }
+
+    #[test]
+    fn test_fold_tree() {
+        let folds = fold_tree(
+            vec![
+                // Out of order, and an `} else {` sibling.
+                range(4, 6),
+                range(2, 4),
+                range(0, 10),
+                // Crosses the end of 2..4 and is dropped.
+                range(3, 5),
+                // Shorter than 0..10 on the same line.
+                range(0, 1),
+                // Nothing to fold.
+                range(7, 7),
+                // Past the end of the document, clipped like 0..10.
+                range(8, 40),
+                // Starts past the end.
+                range(30, 35),
+            ],
+            10,
+        );
+        assert_eq!(lines(&folds), [(0, 9)]);
+        assert_eq!(lines(&folds[0].children), [(2, 4), (4, 6), (8, 9)]);
+    }
+}
@@ -0,0 +1,192 @@
+//! Client-side filtering and ranking of completion items, for clients that
+//! don't get this from their editor. Enabled by the `fuzzy` feature.
+
+use crate::completion::{CompletionItem, CompletionList};
+
+const MATCH: i32 = 1;
+const SAME_CASE: i32 = 1;
+const CONSECUTIVE: i32 = 5;
+const WORD_START: i32 = 6;
+const CANDIDATE_START: i32 = 8;
+const MAX_GAP_PENALTY: i32 = 3;
+
+/// Scores how well `pattern` fuzzy-matches `candidate`, or `None` if the
+/// characters of `pattern` don't all appear in order in `candidate`. Matching
+/// ignores case. Matches at the start of words (`snake_case`, `camelCase`,
+/// `path/like`) and runs of consecutive matches score higher.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i32> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let candidate: Vec<char> = candidate.chars().collect();
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    if pattern.len() > candidate.len() {
+        return None;
+    }
+
+    // best[j]: the best score of the pattern so far with its last character
+    // matched at candidate[j].
+    let mut best: Vec<Option<i32>> = vec![None; candidate.len()];
+    for (i, &p) in pattern.iter().enumerate() {
+        let mut next = vec![None; candidate.len()];
+        // The best score of a previous match at an index below j, and that index.
+        let mut previous: Option<(i32, usize)> = None;
+        for j in 0..candidate.len() {
+            if i > 0 && j > 0 {
+                if let Some(score) = best[j - 1] {
+                    if previous.is_none_or(|(best_score, _)| score >= best_score) {
+                        previous = Some((score, j - 1));
+                    }
+                }
+            }
+            let c = candidate[j];
+            if !c.to_lowercase().eq(p.to_lowercase()) {
+                continue;
+            }
+            let mut score = MATCH + char_bonus(&candidate, j);
+            if c == p {
+                score += SAME_CASE;
+            }
+            if i == 0 {
+                next[j] = Some(score - (j as i32).min(MAX_GAP_PENALTY));
+                continue;
+            }
+            let consecutive = if j > 0 {
+                best[j - 1].map(|s| s + CONSECUTIVE)
+            } else {
+                None
+            };
+            let gapped = previous.map(|(s, at)| s - ((j - at - 1) as i32).min(MAX_GAP_PENALTY));
+            next[j] = match (consecutive, gapped) {
+                (Some(a), Some(b)) => Some(score + a.max(b)),
+                (Some(a), None) | (None, Some(a)) => Some(score + a),
+                (None, None) => None,
+            };
+        }
+        best = next;
+    }
+    best.into_iter().flatten().max()
+}
+
+fn char_bonus(candidate: &[char], j: usize) -> i32 {
+    if j == 0 {
+        return CANDIDATE_START;
+    }
+    let (prev, c) = (candidate[j - 1], candidate[j]);
+    let after_separator = !prev.is_alphanumeric();
+    let camel_hump = prev.is_lowercase() && c.is_uppercase();
+    if after_separator || camel_hump {
+        WORD_START
+    } else {
+        0
+    }
+}
+
+/// The items matching `prefix`, best match first. Items are matched on their
+/// `filterText` and ties are broken by `sortText`, as the spec asks.
+pub fn filter_and_rank<'a>(items: &'a [CompletionItem], prefix: &str) -> Vec<&'a CompletionItem> {
+    let mut matches: Vec<(i32, &CompletionItem)> = items
+        .iter()
+        .filter_map(|item| Some((fuzzy_score(prefix, item.filter_key())?, item)))
+        .collect();
+    matches.sort_by(|(a_score, a), (b_score, b)| {
+        b_score
+            .cmp(a_score)
+            .then_with(|| a.sort_key().cmp(b.sort_key()))
+            .then_with(|| a.label.cmp(&b.label))
+    });
+    matches.into_iter().map(|(_, item)| item).collect()
+}
+
+/// A completion list together with the prefix it was requested for, so it
+/// can be re-filtered locally as the user keeps typing.
+#[derive(Debug, Clone)]
+pub struct CompletionSession {
+    list: CompletionList,
+    prefix: String,
+}
+
+impl CompletionSession {
+    pub fn new(list: CompletionList, prefix: impl Into<String>) -> Self {
+        CompletionSession {
+            list,
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn list(&self) -> &CompletionList {
+        &self.list
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Whether typing up to `prefix` requires a new request. This is the case
+    /// for incomplete lists as soon as the prefix changed.
+    pub fn needs_request(&self, prefix: &str) -> bool {
+        self.list.is_incomplete && prefix != self.prefix
+    }
+
+    /// Filters the list for `prefix`. For an incomplete list this is only a
+    /// stand-in until the response of the new request arrives.
+    pub fn refilter(&self, prefix: &str) -> Vec<&CompletionItem> {
+        filter_and_rank(&self.list.items, prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(label: &str, sort_text: Option<&str>) -> CompletionItem {
+        serde_json::from_value(serde_json::json!({
+            "label": label,
+            "sortText": sort_text,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("fb", "foo_bar").is_some());
+        assert!(fuzzy_score("bf", "foo_bar").is_none());
+        assert!(fuzzy_score("fb", "fooBar") > fuzzy_score("fb", "fabric"));
+        assert!(fuzzy_score("pri", "Println") > fuzzy_score("pri", "Sprint"));
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn test_session_refilters_and_ranks() {
+        let list = CompletionList {
+            is_incomplete: false,
+            items: vec![
+                item("Sprintf", Some("2")),
+                item("Println", Some("1")),
+                item("Errorf", Some("0")),
+                item("Printf", Some("3")),
+            ],
+        };
+        let session = CompletionSession::new(list, "");
+        let labels = |items: Vec<&CompletionItem>| -> Vec<String> {
+            items.iter().map(|item| item.label.clone()).collect()
+        };
+
+        assert_eq!(
+            labels(session.refilter("")),
+            vec!["Errorf", "Println", "Sprintf", "Printf"]
+        );
+        assert_eq!(labels(session.refilter("pf")), vec!["Printf", "Sprintf"]);
+        assert!(!session.needs_request("prin"));
+
+        let incomplete = CompletionSession::new(
+            CompletionList {
+                is_incomplete: true,
+                items: Vec::new(),
+            },
+            "pr",
+        );
+        assert!(!incomplete.needs_request("pr"));
+        assert!(incomplete.needs_request("pri"));
+    }
+}
@@ -0,0 +1,184 @@
+//! Document highlights, the occurrences of the symbol under the cursor, and
+//! their merging with the selection into non-overlapping spans, for editors
+//! that can only paint one highlight group per character, such as terminal
+//! UIs.
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::highlights::merge_highlights;
+//! use lsp_client_rs::protocol::{Position, Range};
+//! let cursor = Position::new(4, 8);
+//! let highlights = client
+//!     .document_highlight("file:///src/main.rs".to_string(), cursor)
+//!     .await?;
+//! let selection = Range::new(Position::new(4, 6), Position::new(4, 10));
+//! for span in merge_highlights(&highlights, &[selection]) {
+//!     println!("{:?} {:?}", span.layer, span.range);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::protocol::{Position, Range};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum DocumentHighlightKind {
+    /// A textual occurrence.
+    #[default]
+    Text = 1,
+    /// A read of a symbol, such as reading a variable.
+    Read = 2,
+    /// A write to a symbol, such as assigning a variable.
+    Write = 3,
+}
+
+impl TryFrom<u8> for DocumentHighlightKind {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            1 => Ok(DocumentHighlightKind::Text),
+            2 => Ok(DocumentHighlightKind::Read),
+            3 => Ok(DocumentHighlightKind::Write),
+            _ => Err(format!("Invalid document highlight kind: {}", value)),
+        }
+    }
+}
+
+impl From<DocumentHighlightKind> for u8 {
+    fn from(kind: DocumentHighlightKind) -> Self {
+        kind as u8
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DocumentHighlight {
+    pub range: Range,
+    /// `Text` if left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<DocumentHighlightKind>,
+}
+
+/// What a span is painted as. Where highlights overlap, the later layer
+/// wins, so the selection always shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HighlightLayer {
+    Text,
+    Read,
+    Write,
+    Selection,
+}
+
+impl From<DocumentHighlightKind> for HighlightLayer {
+    fn from(kind: DocumentHighlightKind) -> Self {
+        match kind {
+            DocumentHighlightKind::Text => HighlightLayer::Text,
+            DocumentHighlightKind::Read => HighlightLayer::Read,
+            DocumentHighlightKind::Write => HighlightLayer::Write,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HighlightSpan {
+    pub range: Range,
+    pub layer: HighlightLayer,
+}
+
+/// Merges `highlights` and `selections` into sorted spans that don't overlap.
+/// Duplicate and overlapping ranges are combined, adjacent spans of the same
+/// layer joined, and empty ranges, such as a bare cursor, dropped.
+pub fn merge_highlights(
+    highlights: &[DocumentHighlight],
+    selections: &[Range],
+) -> Vec<HighlightSpan> {
+    let layered: Vec<(Range, HighlightLayer)> = highlights
+        .iter()
+        .map(|highlight| (highlight.range, highlight.kind.unwrap_or_default().into()))
+        .chain(
+            selections
+                .iter()
+                .map(|range| (*range, HighlightLayer::Selection)),
+        )
+        .filter(|(range, _)| !range.is_empty())
+        .collect();
+
+    let mut boundaries: Vec<Position> = layered
+        .iter()
+        .flat_map(|(range, _)| [range.start, range.end])
+        .collect();
+    boundaries.sort();
+    boundaries.dedup();
+
+    let mut spans: Vec<HighlightSpan> = Vec::new();
+    for segment in boundaries.windows(2) {
+        let segment = Range::new(segment[0], segment[1]);
+        let layer = layered
+            .iter()
+            .filter(|(range, _)| range.start <= segment.start && segment.end <= range.end)
+            .map(|(_, layer)| *layer)
+            .max();
+        let Some(layer) = layer else {
+            continue;
+        };
+        match spans.last_mut() {
+            Some(last) if last.layer == layer && last.range.end == segment.start => {
+                last.range.end = segment.end;
+            }
+            _ => spans.push(HighlightSpan {
+                range: segment,
+                layer,
+            }),
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u32, end: u32) -> Range {
+        Range::new(Position::new(0, start), Position::new(0, end))
+    }
+
+    fn highlight(start: u32, end: u32, kind: Option<DocumentHighlightKind>) -> DocumentHighlight {
+        DocumentHighlight {
+            range: range(start, end),
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_merge_highlights() {
+        let highlights = [
+            highlight(20, 25, Some(DocumentHighlightKind::Read)),
+            highlight(0, 5, Some(DocumentHighlightKind::Write)),
+            // Sent twice, and overlapping the write.
+            highlight(3, 10, None),
+            highlight(3, 10, None),
+            highlight(20, 25, Some(DocumentHighlightKind::Read)),
+        ];
+        let spans: Vec<_> = merge_highlights(&highlights, &[range(22, 30), range(40, 40)])
+            .into_iter()
+            .map(|span| {
+                (
+                    span.range.start.character,
+                    span.range.end.character,
+                    span.layer,
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (0, 5, HighlightLayer::Write),
+                (5, 10, HighlightLayer::Text),
+                (20, 22, HighlightLayer::Read),
+                (22, 30, HighlightLayer::Selection),
+            ]
+        );
+    }
+}
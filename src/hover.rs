@@ -0,0 +1,382 @@
+//! Hover results, and their conversion to plain text for clients that can't
+//! render markdown, such as terminal UIs.
+
+use crate::protocol::Range;
+use serde::{Deserialize, Serialize};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Result of a `textDocument/hover` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Hover {
+    pub contents: HoverContents,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum HoverContents {
+    Markup(MarkupContent),
+    Scalar(MarkedString),
+    Array(Vec<MarkedString>),
+}
+
+/// Deprecated in favor of `MarkupContent`, but still sent by some servers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MarkedString {
+    /// Markdown.
+    String(String),
+    /// A code block in the given language.
+    LanguageString { language: String, value: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MarkupContent {
+    /// `plaintext` or `markdown`.
+    pub kind: String,
+    pub value: String,
+}
+
+impl Hover {
+    /// The contents as plain text. Markdown markup is removed; the content of
+    /// code blocks is kept verbatim. Separate parts are separated by a blank line.
+    pub fn to_plaintext(&self) -> String {
+        self.render(|line| line.text.clone())
+    }
+
+    /// Like `to_plaintext`, wrapped to lines at most `width` columns wide.
+    /// Lines of code are kept as they are.
+    pub fn to_wrapped_plaintext(&self, width: usize) -> String {
+        self.render(|line| match line.code {
+            true => line.text.clone(),
+            false => wrap(&line.text, width),
+        })
+    }
+
+    /// Renders each line of each part, separating the parts by a blank line.
+    fn render(&self, render_line: impl Fn(&PlainLine) -> String) -> String {
+        let parts: Vec<Vec<PlainLine>> = match &self.contents {
+            HoverContents::Markup(markup) if markup.kind == "plaintext" => {
+                vec![plain_lines(markup.value.trim(), false)]
+            }
+            HoverContents::Markup(markup) => vec![markdown_lines(&markup.value)],
+            HoverContents::Scalar(marked) => vec![marked_lines(marked)],
+            HoverContents::Array(marked) => marked.iter().map(marked_lines).collect(),
+        };
+        parts
+            .iter()
+            .filter(|lines| !lines.is_empty())
+            .map(|lines| {
+                lines
+                    .iter()
+                    .map(&render_line)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// A line of plain text, and whether it is code.
+struct PlainLine {
+    text: String,
+    code: bool,
+}
+
+fn plain_lines(text: &str, code: bool) -> Vec<PlainLine> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    text.lines()
+        .map(|line| PlainLine {
+            text: line.to_string(),
+            code,
+        })
+        .collect()
+}
+
+fn marked_lines(marked: &MarkedString) -> Vec<PlainLine> {
+    match marked {
+        MarkedString::String(markdown) => markdown_lines(markdown),
+        MarkedString::LanguageString { value, .. } => plain_lines(value.trim(), true),
+    }
+}
+
+/// Strips the markdown commonly found in hovers: code fences, headings,
+/// emphasis, inline code, links and backslash escapes.
+pub fn markdown_to_plaintext(markdown: &str) -> String {
+    markdown_lines(markdown)
+        .into_iter()
+        .map(|line| line.text)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn markdown_lines(markdown: &str) -> Vec<PlainLine> {
+    let mut lines = Vec::new();
+    let mut in_code_block = false;
+    for line in markdown.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            lines.push(PlainLine {
+                text: line.to_string(),
+                code: true,
+            });
+            continue;
+        }
+        let trimmed = line.trim_start();
+        let line = if trimmed.starts_with('#') {
+            trimmed.trim_start_matches('#').trim_start()
+        } else if matches!(trimmed, "---" | "***" | "___") {
+            ""
+        } else {
+            line
+        };
+        lines.push(PlainLine {
+            text: strip_inline_markup(line),
+            code: false,
+        });
+    }
+
+    // Collapse the blank lines left behind by removed fences and rules.
+    let mut collapsed: Vec<PlainLine> = Vec::new();
+    let mut blank = true;
+    for mut line in lines {
+        let is_blank = line.text.trim().is_empty();
+        if is_blank && blank {
+            continue;
+        }
+        blank = is_blank;
+        line.text.truncate(line.text.trim_end().len());
+        collapsed.push(line);
+    }
+    while collapsed.last().is_some_and(|line| line.text.is_empty()) {
+        collapsed.pop();
+    }
+    collapsed
+}
+
+fn strip_inline_markup(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() && chars[i + 1].is_ascii_punctuation() => {
+                out.push(chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            '`' => {
+                // Code spans are kept verbatim, without their backticks.
+                let run = chars[i..].iter().take_while(|c| **c == '`').count();
+                let mut end = i + run;
+                let close = loop {
+                    let Some(offset) = chars[end..].iter().position(|c| *c == '`') else {
+                        break None;
+                    };
+                    let start = end + offset;
+                    let length = chars[start..].iter().take_while(|c| **c == '`').count();
+                    if length == run {
+                        break Some(start);
+                    }
+                    end = start + length;
+                };
+                match close {
+                    Some(close) => {
+                        out.extend(&chars[i + run..close]);
+                        i = close + run;
+                    }
+                    None => i += run,
+                }
+                continue;
+            }
+            // Emphasis markers, unless they are part of a word like `snake_case`.
+            '*' | '_' => {
+                let prev = i.checked_sub(1).map(|p| chars[p]);
+                let next = chars.get(i + 1).copied();
+                let inside_word = prev.is_some_and(char::is_alphanumeric)
+                    && next.is_some_and(char::is_alphanumeric);
+                if inside_word {
+                    out.push(chars[i]);
+                }
+            }
+            '[' => {
+                // `[text](target)` keeps the text.
+                if let Some(close) = chars[i..].iter().position(|c| *c == ']') {
+                    let close = i + close;
+                    if chars.get(close + 1) == Some(&'(') {
+                        if let Some(end) = chars[close..].iter().position(|c| *c == ')') {
+                            out.extend(&chars[i + 1..close]);
+                            i = close + end + 1;
+                            continue;
+                        }
+                    }
+                }
+                out.push('[');
+            }
+            c => out.push(c),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Wraps every line of `text` to at most `width` columns, breaking at
+/// spaces. Wide characters, such as CJK ones, take two columns. Words wider
+/// than `width` are split. Indentation is kept, and fenced code blocks are
+/// left as they are.
+pub fn wrap(text: &str, width: usize) -> String {
+    let width = width.max(1);
+    let mut wrapped = Vec::new();
+    let mut in_code_block = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            wrapped.push(line.to_string());
+            continue;
+        }
+        if in_code_block || line.width() <= width {
+            wrapped.push(line.to_string());
+            continue;
+        }
+        wrap_line(line, width, &mut wrapped);
+    }
+    wrapped.join("\n")
+}
+
+fn wrap_line(line: &str, width: usize, wrapped: &mut Vec<String>) {
+    let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+    let indent = if indent.width() < width {
+        indent
+    } else {
+        String::new()
+    };
+    let mut current = indent.clone();
+    let mut used = indent.width();
+    for mut word in line.split_whitespace() {
+        loop {
+            let fresh = current.len() == indent.len();
+            let needed = word.width() + usize::from(!fresh);
+            if used + needed <= width {
+                if !fresh {
+                    current.push(' ');
+                }
+                current.push_str(word);
+                used += needed;
+                break;
+            }
+            if !fresh {
+                wrapped.push(std::mem::replace(&mut current, indent.clone()));
+                used = indent.width();
+                continue;
+            }
+            // The word doesn't fit on a line of its own. At least one
+            // character goes on each line, however wide.
+            let mut split = 0;
+            for (index, c) in word.char_indices() {
+                let char_width = c.width().unwrap_or(0);
+                if split > 0 && used + char_width > width {
+                    break;
+                }
+                split = index + c.len_utf8();
+                used += char_width;
+            }
+            current.push_str(&word[..split]);
+            word = &word[split..];
+            wrapped.push(std::mem::replace(&mut current, indent.clone()));
+            used = indent.width();
+            if word.is_empty() {
+                break;
+            }
+        }
+    }
+    if current.len() > indent.len() {
+        wrapped.push(current);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_plaintext_of_every_variant() {
+        let markup: Hover = serde_json::from_value(json!({
+            "contents": {
+                "kind": "markdown",
+                "value": "```go\nfunc Println(a ...any) (n int, err error)\n```\n\n---\n\n## Println\n\n**Println** formats using the *default* formats, see [fmt](https://pkg.go.dev/fmt) and `snake_case`\\_names."
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            markup.to_plaintext(),
+            "func Println(a ...any) (n int, err error)\n\nPrintln\n\nPrintln formats using the default formats, see fmt and snake_case_names."
+        );
+
+        let marked: Hover = serde_json::from_value(json!({
+            "contents": [{ "language": "rust", "value": "fn main()" }, "Entry *point*"]
+        }))
+        .unwrap();
+        assert_eq!(marked.to_plaintext(), "fn main()\n\nEntry point");
+
+        let scalar: Hover = serde_json::from_value(json!({ "contents": "plain" })).unwrap();
+        assert_eq!(scalar.to_plaintext(), "plain");
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("the quick brown fox jumps", 10),
+            "the quick\nbrown fox\njumps"
+        );
+        assert_eq!(
+            wrap("  indented words here", 12),
+            "  indented\n  words here"
+        );
+        assert_eq!(wrap("abcdefghij", 4), "abcd\nefgh\nij");
+        assert_eq!(wrap("short\n\nlines", 80), "short\n\nlines");
+    }
+
+    #[test]
+    fn test_code_spans_are_kept_verbatim() {
+        assert_eq!(
+            markdown_to_plaintext("Takes a `*const u8` and calls `__init__`, **not** ``a`b``."),
+            "Takes a *const u8 and calls __init__, not a`b."
+        );
+    }
+
+    #[test]
+    fn test_wrap_counts_display_width() {
+        // Each of these characters takes two columns.
+        assert_eq!(wrap("日本語 テキスト", 8), "日本語\nテキスト");
+        assert_eq!(wrap("日本語テキスト", 4), "日本\n語テ\nキス\nト");
+    }
+
+    #[test]
+    fn test_wrap_keeps_code_blocks() {
+        let markdown = "```go\nfunc   Println(a ...any)   (n int, err error)\n```\nformats using the default formats";
+        assert_eq!(
+            wrap(markdown, 12),
+            "```go\nfunc   Println(a ...any)   (n int, err error)\n```\nformats\nusing the\ndefault\nformats"
+        );
+
+        let hover: Hover = serde_json::from_value(json!({
+            "contents": {
+                "kind": "markdown",
+                "value": "```go\nfunc   Println(a ...any)\n```\nformats using the default formats"
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            hover.to_wrapped_plaintext(12),
+            "func   Println(a ...any)\nformats\nusing the\ndefault\nformats"
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! Launching server processes, with retries for servers that are missing or
+//! crash right after starting, and errors that say why.
+
+use crate::client::LspClient;
+use crate::limits::{self, ProcessTree, ResourceLimits};
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// How much of the end of a server's stderr is kept.
+const STDERR_TAIL_BYTES: usize = 8 * 1024;
+
+/// How to start a server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub current_dir: Option<PathBuf>,
+    pub env: Vec<(String, String)>,
+    pub limits: ResourceLimits,
+}
+
+impl ServerCommand {
+    pub fn new(program: impl Into<String>) -> Self {
+        ServerCommand {
+            program: program.into(),
+            args: Vec::new(),
+            current_dir: None,
+            env: Vec::new(),
+            limits: ResourceLimits::default(),
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I: IntoIterator<Item = S>, S: Into<String>>(mut self, args: I) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Caps the memory of the server. See `ResourceLimits::memory_limit`.
+    pub fn memory_limit(mut self, bytes: u64) -> Self {
+        self.limits.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Sets the niceness of the server. See `ResourceLimits::niceness`.
+    pub fn niceness(mut self, niceness: i32) -> Self {
+        self.limits.niceness = Some(niceness);
+        self
+    }
+
+    /// Kills the processes started by the server along with it.
+    pub fn kill_tree(mut self) -> Self {
+        self.limits.kill_tree = true;
+        self
+    }
+
+    /// The binary `program` refers to: the path itself if it contains a
+    /// separator, otherwise the first match in `PATH`.
+    pub fn resolve(&self) -> Option<PathBuf> {
+        let program = Path::new(&self.program);
+        if program.components().count() > 1 {
+            let program = match &self.current_dir {
+                Some(dir) if program.is_relative() => dir.join(program),
+                _ => program.to_path_buf(),
+            };
+            return program.is_file().then_some(program);
+        }
+        std::env::split_paths(&std::env::var_os("PATH")?)
+            .map(|dir| dir.join(program))
+            .find(|path| path.is_file())
+    }
+}
+
+/// How persistently `launch` tries to start a server.
+#[derive(Debug, Clone)]
+pub struct LaunchPolicy {
+    /// Attempts before giving up, including the first one.
+    pub max_attempts: u32,
+    /// The wait before the second attempt, doubled for each further one.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// A server that exits within this time after spawning counts as a
+    /// failed launch. Zero skips the check.
+    pub startup_window: Duration,
+}
+
+impl Default for LaunchPolicy {
+    fn default() -> Self {
+        LaunchPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            startup_window: Duration::from_millis(300),
+        }
+    }
+}
+
+impl LaunchPolicy {
+    fn backoff_after(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Why the last attempt to launch a server failed.
+#[derive(Debug)]
+pub enum LaunchFailure {
+    /// The binary wasn't found.
+    NotFound,
+    /// The binary was found but couldn't be started.
+    Spawn(std::io::Error),
+    /// The server exited within the startup window.
+    Exited(ExitStatus),
+}
+
+/// A server that couldn't be launched, with what is known about why.
+#[derive(Debug)]
+pub struct LaunchError {
+    pub program: String,
+    /// The binary `program` resolved to, if it was found.
+    pub binary: Option<PathBuf>,
+    pub attempts: u32,
+    pub failure: LaunchFailure,
+    /// The end of what the server wrote to stderr before exiting.
+    pub stderr_tail: String,
+}
+
+impl fmt::Display for LaunchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to launch {}", self.program)?;
+        if let Some(binary) = &self.binary {
+            write!(f, " ({})", binary.display())?;
+        }
+        write!(f, " after {} attempt(s): ", self.attempts)?;
+        match &self.failure {
+            LaunchFailure::NotFound => write!(f, "not found in PATH")?,
+            LaunchFailure::Spawn(e) => write!(f, "{}", e)?,
+            LaunchFailure::Exited(status) => write!(f, "exited right away with {}", status)?,
+        }
+        if !self.stderr_tail.is_empty() {
+            write!(f, "\nstderr:\n{}", self.stderr_tail.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LaunchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.failure {
+            LaunchFailure::Spawn(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The last bytes a server wrote to stderr. Stderr is drained continuously,
+/// so a chatty server never blocks on a full pipe.
+#[derive(Debug, Clone)]
+pub struct StderrTail {
+    bytes: Arc<Mutex<VecDeque<u8>>>,
+    /// Set once the pipe closed.
+    closed: Arc<watch::Sender<bool>>,
+}
+
+impl Default for StderrTail {
+    fn default() -> Self {
+        StderrTail {
+            bytes: Arc::default(),
+            closed: Arc::new(watch::Sender::new(false)),
+        }
+    }
+}
+
+impl StderrTail {
+    /// Drains `stderr` in a background task until it closes.
+    pub(crate) fn capture<R: AsyncRead + Unpin + Send + 'static>(
+        mut stderr: R,
+    ) -> (Self, JoinHandle<()>) {
+        let tail = StderrTail::default();
+        let bytes = tail.bytes.clone();
+        let closed = tail.closed.clone();
+        let task = tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            while let Ok(n @ 1..) = stderr.read(&mut buf).await {
+                let mut bytes = bytes.lock().unwrap_or_else(PoisonError::into_inner);
+                bytes.extend(&buf[..n]);
+                let excess = bytes.len().saturating_sub(STDERR_TAIL_BYTES);
+                bytes.drain(..excess);
+            }
+            closed.send_replace(true);
+        });
+        (tail, task)
+    }
+
+    /// Waits until the server closed its stderr, usually by exiting. Never
+    /// returns for a tail that isn't capturing a pipe.
+    pub(crate) async fn closed(&self) {
+        let _ = self.closed.subscribe().wait_for(|closed| *closed).await;
+    }
+
+    pub fn contents(&self) -> String {
+        let bytes = self.bytes.lock().unwrap_or_else(PoisonError::into_inner);
+        String::from_utf8_lossy(&bytes.iter().copied().collect::<Vec<_>>()).into_owned()
+    }
+}
+
+/// A server that was launched successfully.
+#[derive(Clone)]
+pub struct Launched {
+    pub client: LspClient,
+    pub binary: PathBuf,
+    pub stderr: StderrTail,
+}
+
+/// Starts the server described by `command`, retrying with exponential
+/// backoff while the binary is missing or the server exits right away.
+/// Must be called from within a tokio runtime.
+pub async fn launch(
+    command: &ServerCommand,
+    policy: &LaunchPolicy,
+) -> Result<Launched, LaunchError> {
+    let mut attempt = 1;
+    loop {
+        match try_launch(command, policy).await {
+            Ok(launched) => return Ok(launched),
+            Err(mut error) => {
+                error.attempts = attempt;
+                if attempt >= policy.max_attempts {
+                    return Err(error);
+                }
+            }
+        }
+        tokio::time::sleep(policy.backoff_after(attempt)).await;
+        attempt += 1;
+    }
+}
+
+async fn try_launch(
+    command: &ServerCommand,
+    policy: &LaunchPolicy,
+) -> Result<Launched, LaunchError> {
+    let error = |binary: Option<PathBuf>, failure, stderr_tail| LaunchError {
+        program: command.program.clone(),
+        binary,
+        attempts: 1,
+        failure,
+        stderr_tail,
+    };
+    let Some(binary) = command.resolve() else {
+        return Err(error(None, LaunchFailure::NotFound, String::new()));
+    };
+
+    let mut process = Command::new(&binary);
+    process
+        .args(&command.args)
+        .envs(command.env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(dir) = &command.current_dir {
+        process.current_dir(dir);
+    }
+    limits::apply(&mut process, &command.limits);
+    let mut child = match process.spawn() {
+        Ok(child) => child,
+        Err(e) => return Err(error(Some(binary), LaunchFailure::Spawn(e), String::new())),
+    };
+    let tree = match ProcessTree::attach(&child, &command.limits) {
+        Ok(tree) => tree,
+        Err(e) => return Err(error(Some(binary), LaunchFailure::Spawn(e), String::new())),
+    };
+    let (stderr, drain) = match child.stderr.take() {
+        Some(pipe) => StderrTail::capture(pipe),
+        None => (StderrTail::default(), tokio::spawn(async {})),
+    };
+
+    if !policy.startup_window.is_zero() {
+        // `wait` closes stdin, which would make servers waiting for EOF exit.
+        let stdin = child.stdin.take();
+        let exited = tokio::time::timeout(policy.startup_window, child.wait()).await;
+        child.stdin = stdin;
+        if let Ok(status) = exited {
+            let failure = match status {
+                Ok(status) => LaunchFailure::Exited(status),
+                Err(e) => LaunchFailure::Spawn(e),
+            };
+            // Let the drain pick up what was written just before the exit.
+            let _ = tokio::time::timeout(Duration::from_millis(100), drain).await;
+            return Err(error(Some(binary), failure, stderr.contents()));
+        }
+    }
+
+    let client = LspClient::from_child(child).map_err(|e| {
+        error(
+            Some(binary.clone()),
+            LaunchFailure::Spawn(std::io::Error::other(e)),
+            String::new(),
+        )
+    })?;
+    if let Some(tree) = tree {
+        client.adopt_process_tree(tree);
+    }
+    client.set_stderr(stderr.clone());
+    Ok(Launched {
+        client,
+        binary,
+        stderr,
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn policy(max_attempts: u32) -> LaunchPolicy {
+        LaunchPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+            startup_window: Duration::from_millis(200),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_launch_failures_are_explained() {
+        let missing = ServerCommand::new("lsp-client-rs-no-such-server");
+        let error = launch(&missing, &policy(2)).await.err().unwrap();
+        assert_eq!(error.attempts, 2);
+        assert!(error.binary.is_none());
+        assert!(matches!(error.failure, LaunchFailure::NotFound));
+
+        let crashing = ServerCommand::new("sh")
+            .arg("-c")
+            .arg("echo 'config file is broken' >&2; exit 3");
+        let error = launch(&crashing, &policy(1)).await.err().unwrap();
+        assert!(error.binary.unwrap().ends_with("sh"));
+        assert!(matches!(error.failure, LaunchFailure::Exited(status) if status.code() == Some(3)));
+        assert_eq!(error.stderr_tail, "config file is broken\n");
+    }
+
+    #[tokio::test]
+    async fn test_launch_running_server() {
+        let launched = launch(&ServerCommand::new("cat"), &policy(1))
+            .await
+            .unwrap();
+        assert!(launched.binary.ends_with("cat"));
+        launched
+            .client
+            .set_shutdown_grace_period(Duration::from_millis(50));
+        launched.client.close().await.unwrap();
+    }
+}
@@ -1,2 +1,64 @@
+pub mod activity;
+pub mod batch;
+pub mod broker;
+#[cfg(feature = "bsp")]
+pub mod bsp;
+pub mod cache;
+pub mod capabilities;
 pub mod client;
+pub mod code_actions;
+pub mod colors;
+pub mod columns;
+pub mod commands;
+pub mod completion;
+#[cfg(feature = "dap")]
+pub mod dap;
+pub mod diagnostics;
+pub mod documents;
+pub mod edits;
+pub mod event_bus;
+pub mod folding;
+#[cfg(feature = "fuzzy")]
+pub mod fuzzy;
+pub mod highlights;
+pub mod hover;
+pub mod launch;
+pub mod limits;
+#[cfg(feature = "lsp-3-16")]
+pub mod linked_editing;
+pub mod logging;
+pub mod lsif;
+pub mod methods;
+pub mod notifications;
+pub mod pool;
+pub mod position_encoding;
+#[cfg(feature = "presets")]
+pub mod presets;
 pub mod protocol;
+pub mod quickfix;
+pub mod quirks;
+pub mod rate_limit;
+pub mod registry;
+pub mod rename;
+pub mod render;
+pub mod replay;
+pub mod requests;
+pub mod retry;
+#[cfg(feature = "schema-validation")]
+pub mod schema;
+pub mod selector;
+pub mod session_log;
+pub mod settings;
+pub mod signature_help;
+pub mod snapshot;
+pub mod ssh;
+pub mod streaming;
+pub mod supervisor;
+pub mod symbols;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod tracking;
+pub mod transport;
+pub mod uri_map;
+pub mod variables;
+pub mod workspace;
@@ -0,0 +1,283 @@
+//! OS-level limits for spawned servers, so a runaway server can't take down
+//! the editor hosting it. On Unix they are applied with `setrlimit`,
+//! `setpriority` and a process group; on Windows with a job object.
+
+use std::io;
+use tokio::process::{Child, Command};
+
+/// Limits for a server process and the processes it starts. The default
+/// imposes none.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// The most memory the server may use, in bytes. On Unix this caps the
+    /// address space, which is larger than what the server actually uses.
+    pub memory_limit: Option<u64>,
+    /// The niceness of the server, from -20 (greediest) to 19 (most
+    /// yielding). Windows maps it to the closest priority class.
+    pub niceness: Option<i32>,
+    /// Kill the processes started by the server along with it. On Unix the
+    /// server gets its own process group, which is killed when the client
+    /// closes or is dropped.
+    pub kill_tree: bool,
+}
+
+impl ResourceLimits {
+    fn is_empty(&self) -> bool {
+        *self == ResourceLimits::default()
+    }
+}
+
+/// Configures `command` to apply the limits that must be set before the
+/// server starts.
+pub(crate) fn apply(command: &mut Command, limits: &ResourceLimits) {
+    #[cfg(unix)]
+    unix::apply(command, limits);
+    #[cfg(not(unix))]
+    let _ = (command, limits);
+}
+
+/// The processes of a spawned server, killed together when this is dropped.
+pub(crate) struct ProcessTree {
+    #[cfg(unix)]
+    group: Option<libc::pid_t>,
+    #[cfg(windows)]
+    job: windows::Job,
+}
+
+impl ProcessTree {
+    /// Applies the limits that can only be set once the server is running.
+    /// `None` if `limits` need nothing to be kept around.
+    pub(crate) fn attach(child: &Child, limits: &ResourceLimits) -> io::Result<Option<Self>> {
+        if limits.is_empty() {
+            return Ok(None);
+        }
+        #[cfg(unix)]
+        {
+            // `apply` made the server the leader of its own group.
+            let group = match child.id() {
+                Some(pid) if limits.kill_tree => Some(pid as libc::pid_t),
+                _ => None,
+            };
+            Ok(Some(ProcessTree { group }))
+        }
+        #[cfg(windows)]
+        {
+            let job = windows::Job::attach(child, limits)?;
+            Ok(Some(ProcessTree { job }))
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = child;
+            Ok(None)
+        }
+    }
+
+    /// Kills every process of the tree that is still running.
+    pub(crate) fn kill(&self) {
+        #[cfg(unix)]
+        if let Some(group) = self.group {
+            // SAFETY: `kill` has no memory safety preconditions.
+            unsafe {
+                libc::kill(-group, libc::SIGKILL);
+            }
+        }
+        #[cfg(windows)]
+        self.job.kill();
+    }
+}
+
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::ResourceLimits;
+    use std::io;
+    use tokio::process::Command;
+
+    pub(super) fn apply(command: &mut Command, limits: &ResourceLimits) {
+        let ResourceLimits {
+            memory_limit,
+            niceness,
+            kill_tree,
+        } = limits.clone();
+        // SAFETY: the closure only makes async-signal-safe system calls.
+        unsafe {
+            command.pre_exec(move || {
+                if kill_tree && libc::setpgid(0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if let Some(bytes) = memory_limit {
+                    let limit = libc::rlimit {
+                        rlim_cur: bytes as libc::rlim_t,
+                        rlim_max: bytes as libc::rlim_t,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                if let Some(niceness) = niceness {
+                    if libc::setpriority(libc::PRIO_PROCESS as _, 0, niceness) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::ResourceLimits;
+    use std::io;
+    use tokio::process::Child;
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE, JOB_OBJECT_LIMIT_PRIORITY_CLASS,
+        JOB_OBJECT_LIMIT_PROCESS_MEMORY,
+    };
+    use windows_sys::Win32::System::Threading::{
+        ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS,
+        IDLE_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS,
+    };
+
+    pub(super) struct Job {
+        handle: HANDLE,
+        kill_tree: bool,
+    }
+
+    // SAFETY: job handles may be used from any thread.
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        pub(super) fn attach(child: &Child, limits: &ResourceLimits) -> io::Result<Self> {
+            let process = child
+                .raw_handle()
+                .ok_or_else(|| io::Error::other("The server already exited"))?;
+            // SAFETY: null attributes and name create an anonymous job.
+            let job = Job {
+                handle: unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) },
+                kill_tree: limits.kill_tree,
+            };
+            if job.handle == 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            // SAFETY: the struct is plain data, for which zero means "unset".
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+            let basic = &mut info.BasicLimitInformation;
+            if limits.kill_tree {
+                basic.LimitFlags |= JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            }
+            if let Some(bytes) = limits.memory_limit {
+                basic.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+                info.ProcessMemoryLimit = usize::try_from(bytes).unwrap_or(usize::MAX);
+            }
+            if let Some(niceness) = limits.niceness {
+                basic.LimitFlags |= JOB_OBJECT_LIMIT_PRIORITY_CLASS;
+                basic.PriorityClass = match niceness {
+                    ..=-15 => HIGH_PRIORITY_CLASS,
+                    -14..=-1 => ABOVE_NORMAL_PRIORITY_CLASS,
+                    0 => NORMAL_PRIORITY_CLASS,
+                    1..=14 => BELOW_NORMAL_PRIORITY_CLASS,
+                    15.. => IDLE_PRIORITY_CLASS,
+                };
+            }
+            // SAFETY: `info` outlives the call and the size matches its type.
+            let set = unsafe {
+                SetInformationJobObject(
+                    job.handle,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const std::ffi::c_void,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                )
+            };
+            // SAFETY: both handles are valid for the duration of the call.
+            if set == 0 || unsafe { AssignProcessToJobObject(job.handle, process as HANDLE) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(job)
+        }
+
+        pub(super) fn kill(&self) {
+            if !self.kill_tree {
+                return;
+            }
+            // SAFETY: the handle is valid until `drop`.
+            unsafe {
+                TerminateJobObject(self.handle, 1);
+            }
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the handle was created by `attach` and is closed once.
+            unsafe {
+                CloseHandle(self.handle);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::launch::{launch, LaunchPolicy, ServerCommand};
+    use std::time::Duration;
+
+    /// Whether `pid` is still running, as opposed to gone or a zombie.
+    fn is_running(pid: &str) -> bool {
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            Ok(stat) => !stat.contains(") Z "),
+            Err(_) => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_limits_are_applied() {
+        // Reports its limits and the pid of a child, then behaves like a server.
+        let command = ServerCommand::new("sh")
+            .arg("-c")
+            .arg("ulimit -v >&2; nice >&2; sleep 30 & echo $! >&2; cat")
+            .memory_limit(1 << 30)
+            .niceness(5)
+            .kill_tree();
+        let policy = LaunchPolicy {
+            startup_window: Duration::ZERO,
+            ..LaunchPolicy::default()
+        };
+        let launched = launch(&command, &policy).await.unwrap();
+        let mut stderr = String::new();
+        for _ in 0..100 {
+            stderr = launched.stderr.contents();
+            if stderr.lines().count() == 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let lines: Vec<&str> = stderr.lines().collect();
+        assert_eq!(lines[..2], ["1048576", "5"]);
+        let sleep_pid = lines[2];
+        assert!(is_running(sleep_pid));
+
+        launched
+            .client
+            .set_shutdown_grace_period(Duration::from_millis(10));
+        launched.client.close().await.unwrap();
+        // Killing is asynchronous, so the process may take a moment to exit.
+        for _ in 0..100 {
+            if !is_running(sleep_pid) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!is_running(sleep_pid));
+    }
+}
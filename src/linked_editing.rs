@@ -0,0 +1,136 @@
+//! Linked editing: ranges that hold the same text and change together, such
+//! as the names in an HTML tag's opening and closing parts.
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::edits::TextEdit;
+//! use lsp_client_rs::protocol::{Position, Range};
+//! let uri = "file:///index.html".to_string();
+//! if let Some(linked) = client.linked_editing_range(uri, Position::new(0, 2)).await? {
+//!     // The user typed "v" after "di" in `<di>`.
+//!     let typed = TextEdit {
+//!         range: Range::new(Position::new(0, 3), Position::new(0, 3)),
+//!         new_text: "v".to_string(),
+//!         annotation_id: None,
+//!     };
+//!     let mirrored = linked.mirror_edits(&typed, "di");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::columns::utf16_to_byte;
+use crate::edits::TextEdit;
+use crate::protocol::{Position, Range};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Result of a `textDocument/linkedEditingRange` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkedEditingRanges {
+    /// Ranges of the same length and content.
+    pub ranges: Vec<Range>,
+    /// The pattern the content of the ranges must match, if the server
+    /// sent one. It is written for JavaScript's `RegExp`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub word_pattern: Option<String>,
+}
+
+impl LinkedEditingRanges {
+    /// The edits that make the other ranges follow `edit`, an edit inside one
+    /// of them. `text` is the content of that range before the edit.
+    ///
+    /// `None` if the edit isn't inside a single-line range, or if the new
+    /// content no longer matches `word_pattern`; the client should then stop
+    /// editing the ranges together. A pattern the `regex` crate can't
+    /// compile is ignored.
+    pub fn mirror_edits(&self, edit: &TextEdit, text: &str) -> Option<Vec<TextEdit>> {
+        let single_line = |range: &Range| range.start.line == range.end.line;
+        let edited = self.ranges.iter().find(|range| {
+            single_line(range) && range.contains(edit.range.start) && range.contains(edit.range.end)
+        })?;
+        let start = edit.range.start.character - edited.start.character;
+        let end = edit.range.end.character - edited.start.character;
+
+        let new_text = format!(
+            "{}{}{}",
+            &text[..utf16_to_byte(text, start)],
+            edit.new_text,
+            &text[utf16_to_byte(text, end)..]
+        );
+        if !new_text.is_empty() && !self.matches_word_pattern(&new_text) {
+            return None;
+        }
+
+        let mirrored = self
+            .ranges
+            .iter()
+            .filter(|range| *range != edited)
+            .filter(|range| single_line(range))
+            .map(|range| {
+                let at =
+                    |character| Position::new(range.start.line, range.start.character + character);
+                TextEdit {
+                    range: Range::new(at(start), at(end)),
+                    new_text: edit.new_text.clone(),
+                    annotation_id: edit.annotation_id.clone(),
+                }
+            })
+            .collect();
+        Some(mirrored)
+    }
+
+    fn matches_word_pattern(&self, word: &str) -> bool {
+        let Some(pattern) = &self.word_pattern else {
+            return true;
+        };
+        match Regex::new(&format!("^(?:{})$", pattern)) {
+            Ok(pattern) => pattern.is_match(word),
+            Err(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn edit(line: u32, start: u32, end: u32, new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range::new(Position::new(line, start), Position::new(line, end)),
+            new_text: new_text.to_string(),
+            annotation_id: None,
+        }
+    }
+
+    #[test]
+    fn test_mirror_edits() {
+        // <div>
+        // </div>
+        let linked: LinkedEditingRanges = serde_json::from_value(json!({
+            "ranges": [
+                { "start": { "line": 0, "character": 1 }, "end": { "line": 0, "character": 4 } },
+                { "start": { "line": 1, "character": 2 }, "end": { "line": 1, "character": 5 } }
+            ],
+            "wordPattern": "[a-z]+"
+        }))
+        .unwrap();
+
+        // "div" becomes "dav" in the opening tag.
+        assert_eq!(
+            linked.mirror_edits(&edit(0, 2, 3, "a"), "div"),
+            Some(vec![edit(1, 3, 4, "a")])
+        );
+        // Typing a space ends the tag name.
+        assert_eq!(linked.mirror_edits(&edit(0, 4, 4, " "), "div"), None);
+        // Clearing the name is mirrored too.
+        assert_eq!(
+            linked.mirror_edits(&edit(1, 2, 5, ""), "div"),
+            Some(vec![edit(0, 1, 4, "")])
+        );
+        // Outside of the linked ranges.
+        assert_eq!(linked.mirror_edits(&edit(0, 5, 5, "x"), "div"), None);
+    }
+}
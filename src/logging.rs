@@ -0,0 +1,104 @@
+//! Where the client reports its own diagnostics, such as framing errors,
+//! messages it had to drop, or servers that had to be killed. Hosts plug in
+//! the logging ecosystem they use with `LspClient::set_log_sink`.
+
+use std::fmt;
+use std::sync::{Arc, PoisonError, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Receives the diagnostics of a client.
+pub trait LogSink: Send + Sync {
+    fn log(&self, level: Level, message: fmt::Arguments<'_>);
+}
+
+/// Discards everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopSink;
+
+impl LogSink for NoopSink {
+    fn log(&self, _level: Level, _message: fmt::Arguments<'_>) {}
+}
+
+/// Forwards to the `log` crate, under the `lsp_client_rs` target.
+/// Enabled by the `log` feature.
+#[cfg(feature = "log")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogCrateSink;
+
+#[cfg(feature = "log")]
+impl LogSink for LogCrateSink {
+    fn log(&self, level: Level, message: fmt::Arguments<'_>) {
+        let level = match level {
+            Level::Error => log::Level::Error,
+            Level::Warn => log::Level::Warn,
+            Level::Info => log::Level::Info,
+            Level::Debug => log::Level::Debug,
+            Level::Trace => log::Level::Trace,
+        };
+        log::log!(target: "lsp_client_rs", level, "{}", message);
+    }
+}
+
+/// Forwards to `tracing` as events. Enabled by the `tracing` feature.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingSink;
+
+#[cfg(feature = "tracing")]
+impl LogSink for TracingSink {
+    fn log(&self, level: Level, message: fmt::Arguments<'_>) {
+        match level {
+            Level::Error => tracing::error!("{}", message),
+            Level::Warn => tracing::warn!("{}", message),
+            Level::Info => tracing::info!("{}", message),
+            Level::Debug => tracing::debug!("{}", message),
+            Level::Trace => tracing::trace!("{}", message),
+        }
+    }
+}
+
+/// The sink clients start with: `tracing` if that feature is enabled, else
+/// `log` if that one is, else nothing.
+pub fn default_sink() -> Arc<dyn LogSink> {
+    #[cfg(feature = "tracing")]
+    let sink = Arc::new(TracingSink);
+    #[cfg(all(feature = "log", not(feature = "tracing")))]
+    let sink = Arc::new(LogCrateSink);
+    #[cfg(not(any(feature = "log", feature = "tracing")))]
+    let sink = Arc::new(NoopSink);
+    sink
+}
+
+/// The sink of a client, replaceable while the client runs.
+pub(crate) struct Logger {
+    sink: RwLock<Arc<dyn LogSink>>,
+}
+
+impl Logger {
+    pub(crate) fn new() -> Self {
+        Logger {
+            sink: RwLock::new(default_sink()),
+        }
+    }
+
+    pub(crate) fn set_sink(&self, sink: Arc<dyn LogSink>) {
+        *self.sink.write().unwrap_or_else(PoisonError::into_inner) = sink;
+    }
+
+    pub(crate) fn log(&self, level: Level, message: fmt::Arguments<'_>) {
+        let sink = self
+            .sink
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        sink.log(level, message);
+    }
+}
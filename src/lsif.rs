@@ -0,0 +1,304 @@
+//! Offline answers from an LSIF dump: definitions, references and hovers
+//! looked up in an index produced ahead of time, for CI tooling and code
+//! browsers that don't want to run a server.
+//!
+//! Dumps are JSON lines of vertices (documents, ranges, results) and edges
+//! linking them. See the
+//! [LSIF specification](https://microsoft.github.io/language-server-protocol/specifications/lsif/0.6.0/specification/).
+
+use crate::hover::Hover;
+use crate::protocol::{Location, Position, Range};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+/// Vertex and edge ids, which dumps may write as numbers or strings.
+type Id = String;
+
+fn id(value: &serde_json::Value) -> Id {
+    match value {
+        serde_json::Value::String(id) => id.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Element {
+    id: serde_json::Value,
+    #[serde(rename = "type")]
+    kind: String,
+    label: String,
+    uri: Option<String>,
+    start: Option<Position>,
+    end: Option<Position>,
+    result: Option<serde_json::Value>,
+    out_v: Option<serde_json::Value>,
+    in_v: Option<serde_json::Value>,
+    #[serde(default)]
+    in_vs: Vec<serde_json::Value>,
+    property: Option<String>,
+}
+
+/// An LSIF dump, indexed for lookups by document and position.
+#[derive(Debug, Default)]
+pub struct LsifIndex {
+    /// Document URIs by vertex id.
+    documents: HashMap<Id, String>,
+    ranges: HashMap<Id, Range>,
+    /// The document each range belongs to.
+    range_documents: HashMap<Id, Id>,
+    /// The ranges of each document, by URI.
+    document_ranges: HashMap<String, Vec<Id>>,
+    /// `next` edges from ranges and result sets to result sets.
+    next: HashMap<Id, Id>,
+    /// `textDocument/*` edges, by label and out vertex.
+    results: HashMap<(String, Id), Id>,
+    hovers: HashMap<Id, serde_json::Value>,
+    /// `item` edges of results: the ranges or nested results, and their
+    /// `property`, e.g. `definitions` or `references`.
+    items: HashMap<Id, Vec<(Id, Option<String>)>>,
+}
+
+impl LsifIndex {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        Self::parse(std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to read {}", path.display()))
+    }
+
+    /// Reads a dump in the JSON lines format.
+    pub fn parse<R: BufRead>(reader: R) -> Result<Self> {
+        let mut index = LsifIndex::default();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let element: Element = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid LSIF element on line {}", number + 1))?;
+            index.add(element);
+        }
+        index.document_ranges = index
+            .range_documents
+            .iter()
+            .filter_map(|(range, document)| Some((index.documents.get(document)?, range)))
+            .fold(HashMap::new(), |mut ranges, (uri, range)| {
+                ranges
+                    .entry(uri.clone())
+                    .or_insert_with(Vec::new)
+                    .push(range.clone());
+                ranges
+            });
+        Ok(index)
+    }
+
+    fn add(&mut self, element: Element) {
+        let vertex = id(&element.id);
+        match (element.kind.as_str(), element.label.as_str()) {
+            ("vertex", "document") => {
+                if let Some(uri) = element.uri {
+                    self.documents.insert(vertex, uri);
+                }
+            }
+            ("vertex", "range") => {
+                if let (Some(start), Some(end)) = (element.start, element.end) {
+                    self.ranges.insert(vertex, Range::new(start, end));
+                }
+            }
+            ("vertex", "hoverResult") => {
+                if let Some(result) = element.result {
+                    self.hovers.insert(vertex, result);
+                }
+            }
+            ("edge", label) => {
+                let Some(out_v) = element.out_v.as_ref().map(id) else {
+                    return;
+                };
+                let in_vs = element
+                    .in_v
+                    .iter()
+                    .chain(&element.in_vs)
+                    .map(id)
+                    .collect::<Vec<_>>();
+                match label {
+                    "contains" => {
+                        for range in in_vs {
+                            self.range_documents.insert(range, out_v.clone());
+                        }
+                    }
+                    "next" => {
+                        if let Some(in_v) = in_vs.into_iter().next() {
+                            self.next.insert(out_v, in_v);
+                        }
+                    }
+                    "item" => {
+                        let items = self.items.entry(out_v).or_default();
+                        items.extend(
+                            in_vs
+                                .into_iter()
+                                .map(|in_v| (in_v, element.property.clone())),
+                        );
+                    }
+                    label if label.starts_with("textDocument/") => {
+                        if let Some(in_v) = in_vs.into_iter().next() {
+                            self.results.insert((label.to_string(), out_v), in_v);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Where the symbol at `position` in `uri` is defined.
+    pub fn definition(&self, uri: &str, position: Position) -> Vec<Location> {
+        let Some(result) = self.result_at("textDocument/definition", uri, position) else {
+            return Vec::new();
+        };
+        self.item_locations(result, |_| true)
+    }
+
+    /// Where the symbol at `position` in `uri` is used, including its
+    /// declaration if `include_declaration` is set.
+    pub fn references(
+        &self,
+        uri: &str,
+        position: Position,
+        include_declaration: bool,
+    ) -> Vec<Location> {
+        let Some(result) = self.result_at("textDocument/references", uri, position) else {
+            return Vec::new();
+        };
+        self.item_locations(result, |property| {
+            include_declaration || property != Some("definitions")
+        })
+    }
+
+    /// The hover of the symbol at `position` in `uri`.
+    pub fn hover(&self, uri: &str, position: Position) -> Result<Option<Hover>> {
+        let Some(result) = self.result_at("textDocument/hover", uri, position) else {
+            return Ok(None);
+        };
+        let hover = self
+            .hovers
+            .get(result)
+            .ok_or_else(|| anyhow!("Hover result {} has no contents", result))?;
+        Ok(Some(serde_json::from_value(hover.clone())?))
+    }
+
+    /// The result a `label` edge links to from the innermost range at
+    /// `position`, following `next` edges through result sets.
+    fn result_at(&self, label: &str, uri: &str, position: Position) -> Option<&Id> {
+        // Projects contain documents too, so not every contained vertex is a range.
+        let (mut vertex, _) = self
+            .document_ranges
+            .get(uri)?
+            .iter()
+            .filter_map(|id| Some((id, self.ranges.get(id)?)))
+            .filter(|(_, range)| range.contains(position))
+            .min_by_key(|(_, range)| (std::cmp::Reverse(range.start()), range.end()))?;
+        loop {
+            if let Some(result) = self.results.get(&(label.to_string(), vertex.clone())) {
+                return Some(result);
+            }
+            vertex = self.next.get(vertex)?;
+        }
+    }
+
+    /// The locations of the ranges a result lists, with items whose property
+    /// `keep` rejects left out. Nested results, as in reference results of
+    /// other projects, are followed.
+    fn item_locations(&self, result: &Id, keep: impl Fn(Option<&str>) -> bool) -> Vec<Location> {
+        let mut locations = Vec::new();
+        let mut results = vec![result];
+        while let Some(result) = results.pop() {
+            for (item, property) in self.items.get(result).into_iter().flatten() {
+                if !keep(property.as_deref()) {
+                    continue;
+                }
+                match (self.ranges.get(item), self.range_documents.get(item)) {
+                    (Some(range), Some(document)) => {
+                        if let Some(uri) = self.documents.get(document) {
+                            locations.push(Location::new(uri.clone(), *range));
+                        }
+                    }
+                    _ if self.items.contains_key(item) => results.push(item),
+                    _ => {}
+                }
+            }
+        }
+        locations.sort_by(|a, b| (a.uri(), a.range().start()).cmp(&(b.uri(), b.range().start())));
+        locations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `fn add() {}` in lib.rs, called from main.rs.
+    const DUMP: &str = r#"
+{"id":1,"type":"vertex","label":"metaData","version":"0.6.0","positionEncoding":"utf-16"}
+{"id":2,"type":"vertex","label":"document","uri":"file:///app/src/lib.rs","languageId":"rust"}
+{"id":3,"type":"vertex","label":"document","uri":"file:///app/src/main.rs","languageId":"rust"}
+{"id":4,"type":"vertex","label":"range","start":{"line":0,"character":3},"end":{"line":0,"character":6}}
+{"id":5,"type":"vertex","label":"range","start":{"line":1,"character":4},"end":{"line":1,"character":7}}
+{"id":6,"type":"edge","label":"contains","outV":2,"inVs":[4]}
+{"id":7,"type":"edge","label":"contains","outV":3,"inVs":[5]}
+{"id":8,"type":"vertex","label":"resultSet"}
+{"id":9,"type":"edge","label":"next","outV":4,"inV":8}
+{"id":10,"type":"edge","label":"next","outV":5,"inV":8}
+{"id":11,"type":"vertex","label":"definitionResult"}
+{"id":12,"type":"edge","label":"textDocument/definition","outV":8,"inV":11}
+{"id":13,"type":"edge","label":"item","outV":11,"inVs":[4],"document":2}
+{"id":14,"type":"vertex","label":"hoverResult","result":{"contents":{"kind":"markdown","value":"fn add()"}}}
+{"id":15,"type":"edge","label":"textDocument/hover","outV":8,"inV":14}
+{"id":16,"type":"vertex","label":"referenceResult"}
+{"id":17,"type":"edge","label":"textDocument/references","outV":8,"inV":16}
+{"id":18,"type":"edge","label":"item","outV":16,"inVs":[4],"document":2,"property":"definitions"}
+{"id":19,"type":"edge","label":"item","outV":16,"inVs":[5],"document":3,"property":"references"}
+"#;
+
+    #[test]
+    fn test_queries() {
+        let index = LsifIndex::parse(DUMP.as_bytes()).unwrap();
+        let definition = Location::new(
+            "file:///app/src/lib.rs",
+            Range::new(Position::new(0, 3), Position::new(0, 6)),
+        );
+        let call = Location::new(
+            "file:///app/src/main.rs",
+            Range::new(Position::new(1, 4), Position::new(1, 7)),
+        );
+
+        let at_call = Position::new(1, 5);
+        assert_eq!(
+            index.definition("file:///app/src/main.rs", at_call),
+            vec![definition.clone()]
+        );
+        assert_eq!(
+            index.references("file:///app/src/main.rs", at_call, true),
+            vec![definition, call.clone()]
+        );
+        assert_eq!(
+            index.references("file:///app/src/main.rs", at_call, false),
+            vec![call]
+        );
+        let hover = index
+            .hover("file:///app/src/main.rs", at_call)
+            .unwrap()
+            .unwrap();
+        assert_eq!(hover.to_plaintext(), "fn add()");
+
+        assert!(index
+            .definition("file:///app/src/main.rs", Position::new(5, 0))
+            .is_empty());
+        assert!(LsifIndex::parse("{\"id\":1}".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! Names of the LSP methods this crate knows about.
+//!
+//! Use the constants instead of string literals when building or routing
+//! messages, or `Method` to match on a method name received from the server.
+//! Methods introduced by newer protocol versions only exist with the matching
+//! `lsp-3-16` / `lsp-3-17` feature enabled.
+
+use anyhow::{anyhow, Error};
+use std::fmt;
+use std::str::FromStr;
+
+macro_rules! methods {
+    ($($(#[$attr:meta])* $constant:ident, $variant:ident => $name:literal;)*) => {
+        $($(#[$attr])* pub const $constant: &str = $name;)*
+
+        /// A known LSP method. Parse one with `str::parse` and get the wire name
+        /// back with `as_str`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Method {
+            $($(#[$attr])* $variant,)*
+        }
+
+        impl Method {
+            /// Every known method, in declaration order.
+            pub const ALL: &'static [Method] = &[$($(#[$attr])* Method::$variant,)*];
+
+            pub fn as_str(&self) -> &'static str {
+                match *self {
+                    $($(#[$attr])* Method::$variant => $constant,)*
+                }
+            }
+        }
+
+        impl FromStr for Method {
+            type Err = Error;
+
+            fn from_str(method: &str) -> Result<Self, Self::Err> {
+                match method {
+                    $($(#[$attr])* $name => Ok(Method::$variant),)*
+                    _ => Err(anyhow!("Unknown method: {}", method)),
+                }
+            }
+        }
+    };
+}
+
+methods! {
+    // Lifecycle
+    INITIALIZE, Initialize => "initialize";
+    INITIALIZED, Initialized => "initialized";
+    SHUTDOWN, Shutdown => "shutdown";
+    EXIT, Exit => "exit";
+
+    // Document synchronization
+    TEXT_DOCUMENT_DID_OPEN, TextDocumentDidOpen => "textDocument/didOpen";
+    TEXT_DOCUMENT_DID_CHANGE, TextDocumentDidChange => "textDocument/didChange";
+    TEXT_DOCUMENT_DID_SAVE, TextDocumentDidSave => "textDocument/didSave";
+    TEXT_DOCUMENT_DID_CLOSE, TextDocumentDidClose => "textDocument/didClose";
+
+    // Language features
+    TEXT_DOCUMENT_DEFINITION, TextDocumentDefinition => "textDocument/definition";
+    TEXT_DOCUMENT_DECLARATION, TextDocumentDeclaration => "textDocument/declaration";
+    TEXT_DOCUMENT_TYPE_DEFINITION, TextDocumentTypeDefinition => "textDocument/typeDefinition";
+    TEXT_DOCUMENT_IMPLEMENTATION, TextDocumentImplementation => "textDocument/implementation";
+    TEXT_DOCUMENT_REFERENCES, TextDocumentReferences => "textDocument/references";
+    TEXT_DOCUMENT_HOVER, TextDocumentHover => "textDocument/hover";
+    TEXT_DOCUMENT_COMPLETION, TextDocumentCompletion => "textDocument/completion";
+    #[cfg(feature = "proposed")]
+    TEXT_DOCUMENT_INLINE_COMPLETION, TextDocumentInlineCompletion => "textDocument/inlineCompletion";
+    TEXT_DOCUMENT_SIGNATURE_HELP, TextDocumentSignatureHelp => "textDocument/signatureHelp";
+    TEXT_DOCUMENT_DOCUMENT_SYMBOL, TextDocumentDocumentSymbol => "textDocument/documentSymbol";
+    TEXT_DOCUMENT_DOCUMENT_HIGHLIGHT, TextDocumentDocumentHighlight => "textDocument/documentHighlight";
+    TEXT_DOCUMENT_CODE_ACTION, TextDocumentCodeAction => "textDocument/codeAction";
+    #[cfg(feature = "lsp-3-16")]
+    CODE_ACTION_RESOLVE, CodeActionResolve => "codeAction/resolve";
+    TEXT_DOCUMENT_FORMATTING, TextDocumentFormatting => "textDocument/formatting";
+    TEXT_DOCUMENT_FOLDING_RANGE, TextDocumentFoldingRange => "textDocument/foldingRange";
+    TEXT_DOCUMENT_DOCUMENT_COLOR, TextDocumentDocumentColor => "textDocument/documentColor";
+    TEXT_DOCUMENT_COLOR_PRESENTATION, TextDocumentColorPresentation => "textDocument/colorPresentation";
+    TEXT_DOCUMENT_RENAME, TextDocumentRename => "textDocument/rename";
+    #[cfg(feature = "lsp-3-16")]
+    TEXT_DOCUMENT_LINKED_EDITING_RANGE, TextDocumentLinkedEditingRange => "textDocument/linkedEditingRange";
+    #[cfg(feature = "lsp-3-16")]
+    TEXT_DOCUMENT_SEMANTIC_TOKENS_FULL, TextDocumentSemanticTokensFull => "textDocument/semanticTokens/full";
+    #[cfg(feature = "lsp-3-17")]
+    TEXT_DOCUMENT_DIAGNOSTIC, TextDocumentDiagnostic => "textDocument/diagnostic";
+    TEXT_DOCUMENT_PUBLISH_DIAGNOSTICS, TextDocumentPublishDiagnostics => "textDocument/publishDiagnostics";
+
+    // Workspace
+    WORKSPACE_SYMBOL, WorkspaceSymbol => "workspace/symbol";
+    #[cfg(feature = "lsp-3-17")]
+    WORKSPACE_SYMBOL_RESOLVE, WorkspaceSymbolResolve => "workspaceSymbol/resolve";
+    WORKSPACE_EXECUTE_COMMAND, WorkspaceExecuteCommand => "workspace/executeCommand";
+    WORKSPACE_APPLY_EDIT, WorkspaceApplyEdit => "workspace/applyEdit";
+    WORKSPACE_CONFIGURATION, WorkspaceConfiguration => "workspace/configuration";
+    WORKSPACE_DID_CHANGE_CONFIGURATION, WorkspaceDidChangeConfiguration => "workspace/didChangeConfiguration";
+    WORKSPACE_WORKSPACE_FOLDERS, WorkspaceWorkspaceFolders => "workspace/workspaceFolders";
+    WORKSPACE_DID_CHANGE_WORKSPACE_FOLDERS, WorkspaceDidChangeWorkspaceFolders => "workspace/didChangeWorkspaceFolders";
+    #[cfg(feature = "lsp-3-16")]
+    WORKSPACE_SEMANTIC_TOKENS_REFRESH, WorkspaceSemanticTokensRefresh => "workspace/semanticTokens/refresh";
+    #[cfg(feature = "lsp-3-17")]
+    WORKSPACE_INLAY_HINT_REFRESH, WorkspaceInlayHintRefresh => "workspace/inlayHint/refresh";
+    #[cfg(feature = "lsp-3-16")]
+    WORKSPACE_CODE_LENS_REFRESH, WorkspaceCodeLensRefresh => "workspace/codeLens/refresh";
+    #[cfg(feature = "lsp-3-17")]
+    WORKSPACE_DIAGNOSTIC_REFRESH, WorkspaceDiagnosticRefresh => "workspace/diagnostic/refresh";
+    #[cfg(feature = "lsp-3-17")]
+    WORKSPACE_INLINE_VALUE_REFRESH, WorkspaceInlineValueRefresh => "workspace/inlineValue/refresh";
+
+    // Window
+    WINDOW_LOG_MESSAGE, WindowLogMessage => "window/logMessage";
+    WINDOW_SHOW_MESSAGE, WindowShowMessage => "window/showMessage";
+    WINDOW_WORK_DONE_PROGRESS_CREATE, WindowWorkDoneProgressCreate => "window/workDoneProgress/create";
+    WINDOW_WORK_DONE_PROGRESS_CANCEL, WindowWorkDoneProgressCancel => "window/workDoneProgress/cancel";
+
+    // Client
+    CLIENT_REGISTER_CAPABILITY, ClientRegisterCapability => "client/registerCapability";
+    CLIENT_UNREGISTER_CAPABILITY, ClientUnregisterCapability => "client/unregisterCapability";
+
+    // General
+    CANCEL_REQUEST, CancelRequest => "$/cancelRequest";
+    PROGRESS, Progress => "$/progress";
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl AsRef<str> for Method {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_method_round_trip() {
+        for method in Method::ALL {
+            assert_eq!(method.as_str().parse::<Method>().unwrap(), *method);
+        }
+        assert_eq!(
+            TEXT_DOCUMENT_DEFINITION.parse::<Method>().unwrap(),
+            Method::TextDocumentDefinition
+        );
+        assert!("textDocument/defintion".parse::<Method>().is_err());
+    }
+}
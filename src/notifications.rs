@@ -0,0 +1,259 @@
+//! Typed streams of the notifications the server sends: each notification as
+//! a type implementing `Notification`, and `LspClient::subscribe` to receive
+//! the params of one of them.
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::notifications::PublishDiagnostics;
+//! let mut diagnostics = client.subscribe::<PublishDiagnostics>();
+//! while let Ok(params) = diagnostics.recv().await {
+//!     println!("{}: {} diagnostics", params.uri, params.diagnostics.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Notifications a server adds to the protocol are declared with
+//! `lsp_notification!`, which makes their params type a `Notification`:
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct IndexingStateParams {
+//!     done: bool,
+//! }
+//! lsp_client_rs::lsp_notification!("myServer/indexingState", IndexingStateParams);
+//!
+//! let mut indexing = client.subscribe::<IndexingStateParams>();
+//! while !indexing.recv().await?.done {}
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::LspClient;
+use crate::event_bus::IncomingMessage;
+use crate::methods;
+use crate::protocol::{
+    parse_value, BaseMessage, NotificationMessage, ProgressToken, PublishDiagnosticsParams,
+};
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// A notification and the type of its params.
+pub trait Notification {
+    const METHOD: &'static str;
+    type Params: DeserializeOwned;
+}
+
+/// Implements `Notification` for a params type of its own, so that it can be
+/// subscribed to with `LspClient::subscribe` and sent with
+/// `NotificationMessage::new_typed`. Meant for the notifications a server
+/// adds to the protocol, e.g.
+/// `lsp_notification!("myServer/indexingState", IndexingStateParams)`.
+#[macro_export]
+macro_rules! lsp_notification {
+    ($method:expr, $params:ty $(,)?) => {
+        impl $crate::notifications::Notification for $params {
+            const METHOD: &'static str = $method;
+            type Params = $params;
+        }
+    };
+}
+
+macro_rules! notifications {
+    ($($(#[$attr:meta])* $notification:ident => $constant:path, $params:ty;)*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Debug, Clone, Copy)]
+            pub struct $notification;
+
+            impl Notification for $notification {
+                const METHOD: &'static str = $constant;
+                type Params = $params;
+            }
+        )*
+    };
+}
+
+notifications! {
+    /// `textDocument/publishDiagnostics`.
+    PublishDiagnostics => methods::TEXT_DOCUMENT_PUBLISH_DIAGNOSTICS, PublishDiagnosticsParams;
+    /// `window/logMessage`: a message the client may log.
+    LogMessage => methods::WINDOW_LOG_MESSAGE, MessageParams;
+    /// `window/showMessage`: a message to show to the user.
+    ShowMessage => methods::WINDOW_SHOW_MESSAGE, MessageParams;
+    /// `$/progress`: a report about work done for a progress token.
+    Progress => methods::PROGRESS, ProgressParams;
+}
+
+/// Params of `window/logMessage` and `window/showMessage`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MessageParams {
+    #[serde(rename = "type")]
+    pub kind: MessageType,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum MessageType {
+    Error = 1,
+    Warning = 2,
+    Info = 3,
+    Log = 4,
+    /// Added in LSP 3.18.
+    Debug = 5,
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            1 => Ok(MessageType::Error),
+            2 => Ok(MessageType::Warning),
+            3 => Ok(MessageType::Info),
+            4 => Ok(MessageType::Log),
+            5 => Ok(MessageType::Debug),
+            _ => Err(format!("Invalid message type: {}", value)),
+        }
+    }
+}
+
+impl From<MessageType> for u8 {
+    fn from(kind: MessageType) -> Self {
+        kind as u8
+    }
+}
+
+/// Params of `$/progress`. The shape of `value` depends on the token, e.g. a
+/// work done progress begin, report or end.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProgressParams {
+    pub token: ProgressToken,
+    pub value: serde_json::Value,
+}
+
+/// The params of every `N` notification received since the stream was
+/// created. Each stream receives every notification, however many there are.
+pub struct NotificationStream<N> {
+    receiver: broadcast::Receiver<Arc<IncomingMessage>>,
+    _notification: PhantomData<fn() -> N>,
+}
+
+impl<N: Notification> NotificationStream<N> {
+    /// Waits for the next notification. Fails once the connection closed, or
+    /// if the params don't parse. A stream that falls more than the channel's
+    /// capacity behind skips the notifications it missed.
+    pub async fn recv(&mut self) -> Result<N::Params> {
+        loop {
+            let message = match self.receiver.recv().await {
+                Ok(message) => message,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => bail!("Connection to the server was closed"),
+            };
+            if !message.is_notification() || message.method() != Some(N::METHOD) {
+                continue;
+            }
+            return parse_value(message.params(), &format!("{} params", N::METHOD));
+        }
+    }
+}
+
+impl NotificationMessage {
+    /// An `N` notification with `params`.
+    pub fn new_typed<N: Notification>(params: &N::Params) -> Result<Self>
+    where
+        N::Params: Serialize,
+    {
+        Ok(NotificationMessage {
+            base_message: BaseMessage::new(),
+            method: Cow::Borrowed(N::METHOD),
+            params: serde_json::to_value(params)?,
+        })
+    }
+}
+
+impl LspClient {
+    /// Receives the params of every `N` notification from now on.
+    pub fn subscribe<N: Notification>(&self) -> NotificationStream<N> {
+        NotificationStream {
+            receiver: self.incoming_receiver(),
+            _notification: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let frame = |payload: &str| format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+        let (client_side, mut server_side) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_side);
+        let mut diagnostics = client.subscribe::<PublishDiagnostics>();
+        let mut also_diagnostics = client.subscribe::<PublishDiagnostics>();
+        let mut logs = client.subscribe::<LogMessage>();
+
+        for message in [
+            r#"{"jsonrpc":"2.0","method":"window/logMessage","params":{"type":3,"message":"indexing"}}"#,
+            r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///main.go","diagnostics":[]}}"#,
+        ] {
+            server_side
+                .write_all(frame(message).as_bytes())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(diagnostics.recv().await.unwrap().uri, "file:///main.go");
+        assert_eq!(
+            also_diagnostics.recv().await.unwrap().uri,
+            "file:///main.go"
+        );
+        assert_eq!(
+            logs.recv().await.unwrap(),
+            MessageParams {
+                kind: MessageType::Info,
+                message: "indexing".to_string()
+            }
+        );
+
+        drop(server_side);
+        assert!(logs.recv().await.is_err());
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct IndexingStateParams {
+        done: bool,
+    }
+    crate::lsp_notification!("myServer/indexingState", IndexingStateParams);
+
+    #[tokio::test]
+    async fn test_custom_notification() {
+        let notification =
+            NotificationMessage::new_typed::<IndexingStateParams>(&IndexingStateParams {
+                done: true,
+            })
+            .unwrap();
+        assert_eq!(notification.method, "myServer/indexingState");
+        let payload = serde_json::to_string(&notification).unwrap();
+
+        let (client_side, mut server_side) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_side);
+        let mut indexing = client.subscribe::<IndexingStateParams>();
+        let frame = format!("Content-Length: {}\r\n\r\n{}", payload.len(), payload);
+        server_side.write_all(frame.as_bytes()).await.unwrap();
+        assert_eq!(
+            indexing.recv().await.unwrap(),
+            IndexingStateParams { done: true }
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! A set of servers started on first use, e.g. one per language, which shuts
+//! down servers nobody uses to save memory and restarts them when needed.
+
+use crate::registry::ServerRegistry;
+use crate::selector::DocumentSelector;
+use crate::supervisor::{Supervisor, SupervisorBuilder};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// When the pool shuts down servers nobody uses.
+#[derive(Debug, Clone)]
+pub struct IdlePolicy {
+    /// How long a server without open documents may go without being fetched
+    /// from the pool before it is shut down.
+    pub idle_timeout: Duration,
+}
+
+struct Entry {
+    builder: SupervisorBuilder,
+    running: Option<Running>,
+}
+
+struct Running {
+    supervisor: Arc<Supervisor>,
+    last_used: Instant,
+}
+
+/// Servers by name, started on the first `get`.
+#[derive(Default)]
+pub struct ServerPool {
+    entries: tokio::sync::Mutex<HashMap<String, Entry>>,
+    idle_policy: Mutex<Option<IdlePolicy>>,
+    /// Which server handles which documents, tried in order.
+    routes: Mutex<Vec<(String, DocumentSelector)>>,
+}
+
+impl ServerPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers how to start the server `name`. Replaces an earlier
+    /// registration, but not a server that is already running.
+    pub async fn register(&self, name: impl Into<String>, builder: SupervisorBuilder) {
+        let mut entries = self.entries.lock().await;
+        let name = name.into();
+        let running = entries.remove(&name).and_then(|entry| entry.running);
+        entries.insert(name, Entry { builder, running });
+    }
+
+    /// Registers the servers of `registry` under their `languageId`, set up
+    /// for a workspace rooted at `root_uri`.
+    pub async fn register_all(&self, registry: &ServerRegistry, root_uri: &str) {
+        for (language_id, config) in registry.iter() {
+            self.register(language_id, config.supervisor(root_uri))
+                .await;
+            self.route(language_id, DocumentSelector::language(language_id));
+        }
+    }
+
+    /// Routes the documents `selector` matches to the server `name`, unless
+    /// a route added earlier matches them too.
+    pub fn route(&self, name: impl Into<String>, selector: DocumentSelector) {
+        self.routes().push((name.into(), selector));
+    }
+
+    /// The name of the server handling the document `uri` of language
+    /// `language_id`, if a route matches it.
+    pub fn server_for(&self, uri: &str, language_id: &str) -> Option<String> {
+        self.routes()
+            .iter()
+            .find(|(_, selector)| selector.matches(uri, language_id))
+            .map(|(name, _)| name.clone())
+    }
+
+    fn routes(&self) -> std::sync::MutexGuard<'_, Vec<(String, DocumentSelector)>> {
+        self.routes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The server `name`, started if it isn't running, e.g. because it was
+    /// shut down for being idle.
+    pub async fn get(&self, name: &str) -> Result<Arc<Supervisor>> {
+        let mut entries = self.entries.lock().await;
+        let entry = entries
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("No server registered as {}", name))?;
+        let supervisor = match &entry.running {
+            Some(running) => running.supervisor.clone(),
+            None => entry.builder.clone().start().await?,
+        };
+        entry.running = Some(Running {
+            supervisor: supervisor.clone(),
+            last_used: Instant::now(),
+        });
+        Ok(supervisor)
+    }
+
+    /// Whether the server `name` is running.
+    pub async fn is_running(&self, name: &str) -> bool {
+        let entries = self.entries.lock().await;
+        entries
+            .get(name)
+            .is_some_and(|entry| entry.running.is_some())
+    }
+
+    /// Enables shutting down idle servers in `shutdown_idle`. `None`, the
+    /// default, keeps servers running until the pool is dropped.
+    pub fn set_idle_policy(&self, policy: Option<IdlePolicy>) {
+        *self
+            .idle_policy
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = policy;
+    }
+
+    /// Shuts down the servers that have no open documents and weren't fetched
+    /// within the idle timeout. Returns their names.
+    pub async fn shutdown_idle(&self) -> Vec<String> {
+        let policy = self
+            .idle_policy
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let Some(policy) = policy else {
+            return Vec::new();
+        };
+
+        let mut idle = Vec::new();
+        {
+            let mut entries = self.entries.lock().await;
+            for (name, entry) in entries.iter_mut() {
+                let is_idle = entry.running.as_ref().is_some_and(|running| {
+                    running.last_used.elapsed() >= policy.idle_timeout
+                        && running.supervisor.client().documents().is_empty()
+                });
+                if is_idle {
+                    let running = entry.running.take().unwrap();
+                    idle.push((name.clone(), running.supervisor));
+                }
+            }
+        }
+        // Shut down outside the lock so `get` for other servers isn't blocked.
+        let mut names = Vec::with_capacity(idle.len());
+        for (name, supervisor) in idle {
+            let _ = supervisor.shutdown().await;
+            names.push(name);
+        }
+        names
+    }
+
+    /// Calls `shutdown_idle` every `interval` until the pool is dropped.
+    pub fn spawn_idle_reaper(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let pool: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+                pool.shutdown_idle().await;
+            }
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::launch::{LaunchPolicy, ServerCommand};
+    use crate::supervisor::RestartPolicy;
+
+    #[tokio::test]
+    async fn test_idle_servers_are_shut_down_and_respawned() {
+        let pool = ServerPool::new();
+        let builder = Supervisor::builder(ServerCommand::new("cat"))
+            .launch_policy(LaunchPolicy {
+                startup_window: Duration::ZERO,
+                ..LaunchPolicy::default()
+            })
+            .restart_policy(RestartPolicy {
+                grace_period: Duration::from_millis(10),
+                ..RestartPolicy::default()
+            });
+        pool.register("cat", builder).await;
+        assert!(pool.get("dog").await.is_err());
+
+        let first = pool.get("cat").await.unwrap();
+        assert!(pool.shutdown_idle().await.is_empty());
+
+        pool.set_idle_policy(Some(IdlePolicy {
+            idle_timeout: Duration::ZERO,
+        }));
+        first.client().documents().open(
+            "file:///main.go".to_string(),
+            "go".to_string(),
+            String::new(),
+        );
+        assert!(pool.shutdown_idle().await.is_empty());
+
+        first.client().documents().close("file:///main.go");
+        assert_eq!(pool.shutdown_idle().await, vec!["cat".to_string()]);
+        assert!(!pool.is_running("cat").await);
+        assert!(first
+            .client()
+            .send_request(serde_json::json!({}))
+            .await
+            .is_err());
+
+        let second = pool.get("cat").await.unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert!(pool.is_running("cat").await);
+    }
+
+    #[test]
+    fn test_documents_are_routed_by_selector() {
+        let pool = ServerPool::new();
+        let tests: DocumentSelector = serde_json::from_value(serde_json::json!([
+            { "language": "typescript", "pattern": "**/*.test.ts" }
+        ]))
+        .unwrap();
+        pool.route("test-runner", tests);
+        pool.route("tsserver", DocumentSelector::language("typescript"));
+
+        assert_eq!(
+            pool.server_for("file:///web/app.test.ts", "typescript"),
+            Some("test-runner".to_string())
+        );
+        assert_eq!(
+            pool.server_for("file:///web/app.ts", "typescript"),
+            Some("tsserver".to_string())
+        );
+        assert_eq!(pool.server_for("file:///main.go", "go"), None);
+    }
+}
@@ -0,0 +1,330 @@
+//! Converts positions between the host application's encoding and the one the
+//! server picked, so that a host working in bytes or `char`s never has to
+//! think about the `positionEncoding` it got:
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::columns::PositionEncoding;
+//! use lsp_client_rs::protocol::Position;
+//! client.set_native_position_encoding(Some(PositionEncoding::Utf8));
+//! // Byte 12 of line 3, whatever the server counts.
+//! let hover = client
+//!     .hover("file:///src/main.rs".to_string(), Position::new(3, 12))
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Positions are converted with the text of the open documents, in the
+//! params of requests, the results of their responses, the params of the
+//! messages received from the server and the changes sent with `did_change`
+//! and `apply_workspace_edit`. Positions in documents that aren't open are
+//! left as they are.
+
+use crate::capabilities::ServerCapabilities;
+use crate::client::LspClient;
+use crate::columns::{convert_column, PositionEncoding};
+use crate::documents::{apply_change, line_bounds, DocumentStore};
+use crate::protocol::{Position, Range, TextDocumentContentChangeEvent};
+use crate::streaming::RawResponse;
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+impl LspClient {
+    /// The encoding the server picked, UTF-16 until it answered `initialize`
+    /// or if it named an encoding this crate doesn't know.
+    pub fn position_encoding(&self) -> PositionEncoding {
+        negotiated_encoding(self.server_capabilities().as_ref())
+    }
+}
+
+/// The encoding picked by a server with `capabilities`.
+pub(crate) fn negotiated_encoding(capabilities: Option<&ServerCapabilities>) -> PositionEncoding {
+    capabilities
+        .and_then(|capabilities| capabilities.position_encoding.as_deref())
+        .and_then(PositionEncoding::from_name)
+        .unwrap_or_default()
+}
+
+/// A conversion between the host's encoding and the server's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Conversion {
+    native: PositionEncoding,
+    server: PositionEncoding,
+}
+
+impl Conversion {
+    /// `None` if there is nothing to convert.
+    pub(crate) fn between(native: PositionEncoding, server: PositionEncoding) -> Option<Self> {
+        (native != server).then_some(Conversion { native, server })
+    }
+
+    /// Converts the positions in the params of a message to the server.
+    pub(crate) fn convert_outgoing(&self, params: &mut Value, documents: &DocumentStore) {
+        convert_positions(params, None, documents, self.native, self.server);
+    }
+
+    /// Converts the positions in the params of a message from the server.
+    pub(crate) fn convert_incoming(&self, params: &mut Value, documents: &DocumentStore) {
+        convert_positions(params, None, documents, self.server, self.native);
+    }
+
+    /// Converts the positions in the result of the response to a request
+    /// about `uri`, which is the document of positions without a URI of
+    /// their own, such as the range of a hover.
+    pub(crate) fn convert_response(
+        &self,
+        response: RawResponse,
+        uri: Option<&str>,
+        documents: &DocumentStore,
+    ) -> Result<RawResponse> {
+        if response.error.is_some() {
+            return Ok(response);
+        }
+        let mut body: Value = serde_json::from_slice(response.body())?;
+        if let Some(result) = body.get_mut("result") {
+            convert_positions(result, uri, documents, self.server, self.native);
+        }
+        Ok(RawResponse::new(
+            response.id,
+            response.error,
+            serde_json::to_vec(&body)?,
+        ))
+    }
+
+    /// Converts the ranges of `changes`, made in order to `text`. Returns
+    /// them in UTF-16, as the document store counts, and in the server's
+    /// encoding.
+    pub(crate) fn changes(
+        &self,
+        text: &str,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> (
+        Vec<TextDocumentContentChangeEvent>,
+        Vec<TextDocumentContentChangeEvent>,
+    ) {
+        let mut text = text.to_string();
+        let mut stored = Vec::with_capacity(changes.len());
+        let mut sent = Vec::with_capacity(changes.len());
+        for change in changes {
+            let convert = |to| {
+                let mut change = change.clone();
+                change.range = change
+                    .range
+                    .map(|range| convert_range(&text, range, self.native, to));
+                change
+            };
+            let (store_change, sent_change) =
+                (convert(PositionEncoding::Utf16), convert(self.server));
+            apply_change(&mut text, &store_change);
+            stored.push(store_change);
+            sent.push(sent_change);
+        }
+        (stored, sent)
+    }
+}
+
+/// The document a request is about, from its `textDocument` or `uri` param.
+pub(crate) fn params_uri(params: &Value) -> Option<&str> {
+    params
+        .get("textDocument")
+        .and_then(|document| document.get("uri"))
+        .or_else(|| params.get("uri"))
+        .and_then(Value::as_str)
+}
+
+pub(crate) fn convert_range(
+    text: &str,
+    range: Range,
+    from: PositionEncoding,
+    to: PositionEncoding,
+) -> Range {
+    Range::new(
+        convert_position(text, range.start, from, to),
+        convert_position(text, range.end, from, to),
+    )
+}
+
+fn convert_position(
+    text: &str,
+    position: Position,
+    from: PositionEncoding,
+    to: PositionEncoding,
+) -> Position {
+    let Some(line) = line_bounds(text, position.line) else {
+        return position;
+    };
+    let character = convert_column(&text[line], position.character, from, to);
+    Position::new(position.line, character)
+}
+
+/// Converts every position in `value`. Positions belong to the document of
+/// the closest `uri` or `textDocument.uri` around them, the `targetUri` of
+/// location links, or the document keys of a workspace edit's `changes`,
+/// and to `uri` if there is none of these.
+fn convert_positions(
+    value: &mut Value,
+    uri: Option<&str>,
+    documents: &DocumentStore,
+    from: PositionEncoding,
+    to: PositionEncoding,
+) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                convert_positions(item, uri, documents, from, to);
+            }
+        }
+        Value::Object(object) => {
+            if let Some(position) = as_position(object) {
+                let converted = uri
+                    .and_then(|uri| documents.get(uri))
+                    .map_or(position, |document| {
+                        convert_position(&document.text, position, from, to)
+                    });
+                object.insert("character".to_string(), converted.character.into());
+                return;
+            }
+            let own_uri = object
+                .get("textDocument")
+                .and_then(|document| document.get("uri"))
+                .or_else(|| object.get("uri"))
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            let uri = own_uri.as_deref().or(uri);
+            let target_uri = object
+                .get("targetUri")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            for (key, child) in object.iter_mut() {
+                match (key.as_str(), child) {
+                    ("targetRange" | "targetSelectionRange", child) => {
+                        convert_positions(child, target_uri.as_deref().or(uri), documents, from, to)
+                    }
+                    ("changes", Value::Object(changes)) => {
+                        for (uri, edits) in changes.iter_mut() {
+                            convert_positions(edits, Some(uri), documents, from, to);
+                        }
+                    }
+                    (_, child) => convert_positions(child, uri, documents, from, to),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `object` as a position, if it is one.
+fn as_position(object: &Map<String, Value>) -> Option<Position> {
+    if object.len() != 2 {
+        return None;
+    }
+    let line = object.get("line")?.as_u64()?;
+    let character = object.get("character")?.as_u64()?;
+    Some(Position::new(line as u32, character as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{FrameReader, FrameWriter};
+    use serde_json::json;
+
+    #[test]
+    fn test_convert_positions() {
+        let mut documents = DocumentStore::new();
+        documents.open(
+            "file:///a.rs".to_string(),
+            "rust".to_string(),
+            "let é = 1;\nlet 👍 = é;".to_string(),
+        );
+        let conversion =
+            Conversion::between(PositionEncoding::Utf8, PositionEncoding::Utf16).unwrap();
+
+        // Byte 11 of line 1 is after the thumbs up and " = ".
+        let mut params = json!({
+            "textDocument": { "uri": "file:///a.rs" },
+            "position": { "line": 1, "character": 11 }
+        });
+        conversion.convert_outgoing(&mut params, &documents);
+        assert_eq!(params["position"], json!({ "line": 1, "character": 9 }));
+
+        let mut result = json!({
+            "changes": {
+                "file:///a.rs": [{
+                    "range": {
+                        "start": { "line": 0, "character": 4 },
+                        "end": { "line": 0, "character": 5 }
+                    },
+                    "newText": "e"
+                }],
+                // Not open, so left alone.
+                "file:///b.rs": [{
+                    "range": {
+                        "start": { "line": 0, "character": 4 },
+                        "end": { "line": 0, "character": 5 }
+                    },
+                    "newText": "e"
+                }]
+            }
+        });
+        conversion.convert_incoming(&mut result, &documents);
+        let range = &result["changes"]["file:///a.rs"][0]["range"];
+        assert_eq!(range["end"]["character"], 6);
+        let range = &result["changes"]["file:///b.rs"][0]["range"];
+        assert_eq!(range["end"]["character"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_client_converts_positions() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        client.set_native_position_encoding(Some(PositionEncoding::Utf32));
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut reader = FrameReader::new(read_half);
+        let mut writer = FrameWriter::new(write_half);
+        let uri = "file:///a.rs".to_string();
+        client
+            .did_open(uri.clone(), "rust".to_string(), "é = 👍;".to_string())
+            .await
+            .unwrap();
+        reader.read_frame().await.unwrap();
+
+        // The `;` is char 5, but UTF-16 unit 6.
+        let (hover, _) = tokio::join!(client.hover(uri.clone(), Position::new(0, 5)), async {
+            let request: Value =
+                serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+            assert_eq!(request["params"]["position"]["character"], 6);
+            let response = json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": {
+                    "contents": "thumbs up",
+                    "range": {
+                        "start": { "line": 0, "character": 4 },
+                        "end": { "line": 0, "character": 6 }
+                    }
+                }
+            });
+            writer
+                .write_frame(response.to_string().as_bytes())
+                .await
+                .unwrap();
+        });
+        let range = hover.unwrap().unwrap().range.unwrap();
+        assert_eq!(range, Range::new(Position::new(0, 4), Position::new(0, 5)));
+
+        let change = TextDocumentContentChangeEvent {
+            range: Some(range),
+            text: "x".to_string(),
+        };
+        client.did_change(&uri, vec![change]).await.unwrap();
+        let notification: Value =
+            serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+        assert_eq!(
+            notification["params"]["contentChanges"][0]["range"]["end"]["character"],
+            6
+        );
+        assert_eq!(client.documents().get(&uri).unwrap().text, "é = x;");
+    }
+}
@@ -0,0 +1,142 @@
+//! Configurations for popular language servers, tuned so that a session
+//! works out of the box:
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use lsp_client_rs::presets;
+//! let supervisor = presets::rust_analyzer()
+//!     .supervisor("file:///src/app")
+//!     .start()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each preset is a plain `ServerConfig`, so its fields can be adjusted
+//! before starting the server.
+
+use crate::registry::{ServerConfig, ServerRegistry};
+use serde_json::json;
+
+/// rust-analyzer, with build scripts and proc macros enabled and its
+/// `experimental/serverStatus` notifications turned on.
+pub fn rust_analyzer() -> ServerConfig {
+    let mut config = ServerConfig::new("rust-analyzer");
+    config.capabilities = Some(json!({
+        "experimental": { "serverStatusNotification": true }
+    }));
+    config.initialization_options = Some(json!({
+        "cargo": { "buildScripts": { "enable": true } },
+        "procMacro": { "enable": true }
+    }));
+    config
+}
+
+/// gopls, completing packages that aren't imported yet.
+pub fn gopls() -> ServerConfig {
+    let mut config = ServerConfig::new("gopls");
+    config.initialization_options = Some(json!({
+        "completeUnimported": true,
+        "usePlaceholders": true
+    }));
+    config
+}
+
+/// pyright, analyzing open files only. It pulls its settings with
+/// `workspace/configuration`, so they are pushed rather than sent with
+/// `initialize`.
+pub fn pyright() -> ServerConfig {
+    let mut config = ServerConfig::new("pyright-langserver");
+    config.args = vec!["--stdio".to_string()];
+    config.settings = Some(json!({
+        "python": {
+            "analysis": {
+                "autoSearchPaths": true,
+                "useLibraryCodeForTypes": true,
+                "diagnosticMode": "openFilesOnly"
+            }
+        }
+    }));
+    config
+}
+
+/// clangd, with background indexing and UTF-16 offsets, which clangd
+/// otherwise negotiates through its own `offsetEncoding` capability.
+pub fn clangd() -> ServerConfig {
+    let mut config = ServerConfig::new("clangd");
+    config.args = vec!["--background-index".to_string()];
+    config.capabilities = Some(json!({ "offsetEncoding": ["utf-16"] }));
+    config.initialization_options = Some(json!({ "clangdFileStatus": true }));
+    config
+}
+
+/// typescript-language-server, which drives tsserver for TypeScript and
+/// JavaScript.
+pub fn typescript_language_server() -> ServerConfig {
+    let mut config = ServerConfig::new("typescript-language-server");
+    config.args = vec!["--stdio".to_string()];
+    config.initialization_options = Some(json!({
+        "hostInfo": env!("CARGO_PKG_NAME"),
+        "preferences": { "includeCompletionsForModuleExports": true }
+    }));
+    config
+}
+
+/// The preset for a `languageId`, if there is one.
+pub fn for_language(language_id: &str) -> Option<ServerConfig> {
+    match language_id {
+        "rust" => Some(rust_analyzer()),
+        "go" => Some(gopls()),
+        "python" => Some(pyright()),
+        "c" | "cpp" | "objective-c" | "objective-cpp" => Some(clangd()),
+        "typescript" | "typescriptreact" | "javascript" | "javascriptreact" => {
+            Some(typescript_language_server())
+        }
+        _ => None,
+    }
+}
+
+/// A registry of every preset, by `languageId`.
+pub fn registry() -> ServerRegistry {
+    let mut registry = ServerRegistry::new();
+    for language_id in [
+        "rust",
+        "go",
+        "python",
+        "c",
+        "cpp",
+        "objective-c",
+        "objective-cpp",
+        "typescript",
+        "typescriptreact",
+        "javascript",
+        "javascriptreact",
+    ] {
+        if let Some(config) = for_language(language_id) {
+            registry.insert(language_id, config);
+        }
+    }
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_shape_the_initialize_request() {
+        let request = clangd().initialize_request("file:///src/app");
+        let capabilities = &request.params["capabilities"];
+        assert_eq!(capabilities["offsetEncoding"], json!(["utf-16"]));
+        // Merged into the default capabilities rather than replacing them.
+        assert_eq!(capabilities["workspace"]["configuration"], true);
+        assert_eq!(
+            request.params["initializationOptions"],
+            json!({ "clangdFileStatus": true })
+        );
+
+        assert_eq!(registry().get("cpp"), Some(&clangd()));
+        assert_eq!(for_language("python"), Some(pyright()));
+        assert_eq!(for_language("cobol"), None);
+    }
+}
@@ -1,18 +1,74 @@
+use crate::capabilities::ServerCapabilities;
+use crate::completion::{CompletionContext, CompletionList};
+use crate::methods::{self, Method};
+use crate::settings::merge;
+use crate::signature_help::{SignatureHelp, SignatureHelpContext};
 use anyhow::{bail, Result};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BaseMessage {
-    pub jsonrpc: String,
+    pub jsonrpc: Cow<'static, str>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl BaseMessage {
+    /// A JSON-RPC 2.0 header, without allocating.
+    pub fn new() -> Self {
+        BaseMessage {
+            jsonrpc: Cow::Borrowed("2.0"),
+        }
+    }
+}
+
+impl Default for BaseMessage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the static copy of a known method name, or an owned copy otherwise.
+/// Only the methods in `methods` are known: incoming messages using any
+/// other, such as a server's own notifications, still allocate a `String`.
+pub fn intern_method(method: &str) -> Cow<'static, str> {
+    match method.parse::<Method>() {
+        Ok(known) => Cow::Borrowed(known.as_str()),
+        Err(_) => Cow::Owned(method.to_string()),
+    }
+}
+
+pub(crate) fn deserialize_method<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Cow<'static, str>, D::Error> {
+    struct MethodVisitor;
+
+    impl serde::de::Visitor<'_> for MethodVisitor {
+        type Value = Cow<'static, str>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a method name")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, method: &str) -> Result<Self::Value, E> {
+            Ok(intern_method(method))
+        }
+    }
+
+    deserializer.deserialize_str(MethodVisitor)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RequestMessage {
     #[serde(flatten)]
     pub base_message: BaseMessage,
     pub id: serde_json::Value,
     pub notification: u8,
-    pub method: String,
+    #[serde(deserialize_with = "deserialize_method")]
+    pub method: Cow<'static, str>,
+    /// `Null` for methods without params, in which case the field is omitted.
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
     pub params: serde_json::Value,
 }
 
@@ -21,15 +77,38 @@ pub struct ResponseMessage {
     #[serde(flatten)]
     pub base_message: BaseMessage,
     pub id: Option<serde_json::Value>,
+    /// Omitted when `None`, since JSON-RPC forbids a result next to an error.
+    /// A `null` result parses as `None` too.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<serde_json::Value>,
 }
 
+/// JSON-RPC error code for requests that aren't valid, e.g. because they
+/// arrived after `shutdown`.
+pub const INVALID_REQUEST: i64 = -32600;
+/// JSON-RPC error code for requests whose method the receiver doesn't implement.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for requests whose params are invalid.
+pub const INVALID_PARAMS: i64 = -32602;
+/// LSP error code for requests the client cancelled.
+pub const REQUEST_CANCELLED: i64 = -32800;
+/// LSP error code for requests whose result was invalidated by a change to
+/// the document before the server could answer.
+pub const CONTENT_MODIFIED: i64 = -32801;
+/// LSP error code for requests the server cancelled itself, e.g. because it
+/// was busy. Only sent if the client announces `retryOnServerCancelled`.
+pub const SERVER_CANCELLED: i64 = -32802;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NotificationMessage {
     #[serde(flatten)]
     pub base_message: BaseMessage,
-    pub method: String,
+    #[serde(deserialize_with = "deserialize_method")]
+    pub method: Cow<'static, str>,
+    /// `Null` for methods without params, in which case the field is omitted.
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
     pub params: serde_json::Value,
 }
 
@@ -52,7 +131,43 @@ pub struct ClientInfo {
     pub version: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The result of `initialize`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InitializeResult {
+    #[serde(default)]
+    pub capabilities: ServerCapabilities,
+    #[serde(rename = "serverInfo", skip_serializing_if = "Option::is_none")]
+    pub server_info: Option<ServerInfo>,
+}
+
+/// The name and version the server reports in `initialize`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// A capability the server registered dynamically with
+/// `client/registerCapability`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Registration {
+    pub id: String,
+    pub method: String,
+    #[serde(rename = "registerOptions", skip_serializing_if = "Option::is_none")]
+    pub register_options: Option<serde_json::Value>,
+}
+
+impl Registration {
+    /// The documents the registration applies to. `None` if the options have
+    /// no selector, in which case it applies to every document.
+    pub fn document_selector(&self) -> Option<crate::selector::DocumentSelector> {
+        let selector = self.register_options.as_ref()?.get("documentSelector")?;
+        crate::selector::DocumentSelector::deserialize(selector).ok()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct WorkspaceFolder {
     pub uri: String,
     pub name: String,
@@ -63,6 +178,16 @@ pub struct ClientCapabilities {
     pub workspace: Option<CapabilitiesWorkspace>, // Changed from HashMap to direct struct
     #[serde(rename = "textDocument")]
     pub text_document: Option<CapabilitiesTextDocument>, // Changed from HashMap to direct struct
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<CapabilitiesWindow>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CapabilitiesWindow {
+    /// Whether the server may create progress tokens with
+    /// `window/workDoneProgress/create`.
+    #[serde(rename = "workDoneProgress")]
+    pub work_done_progress: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -74,6 +199,20 @@ pub struct CapabilitiesWorkspace {
     #[serde(rename = "workspaceEdit")]
     pub workspace_edit: WorkspaceEdit,
     pub configuration: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<WorkspaceSymbolCapabilities>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkspaceSymbolCapabilities {
+    /// The properties the client can resolve lazily with `workspaceSymbol/resolve`.
+    #[serde(rename = "resolveSupport", skip_serializing_if = "Option::is_none")]
+    pub resolve_support: Option<ResolveSupport>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResolveSupport {
+    pub properties: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -86,6 +225,18 @@ pub struct DidChangeConfiguration {
 pub struct WorkspaceEdit {
     #[serde(rename = "documentChanges")]
     pub document_changes: bool,
+    #[serde(
+        rename = "changeAnnotationSupport",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub change_annotation_support: Option<ChangeAnnotationSupport>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChangeAnnotationSupport {
+    /// Whether the client groups edits with equal labels into tree nodes.
+    #[serde(rename = "groupsOnLabel")]
+    pub groups_on_label: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -94,6 +245,44 @@ pub struct CapabilitiesTextDocument {
     pub completion: Completion,
     #[serde(rename = "codeAction")]
     pub code_action: CodeAction,
+    #[cfg(feature = "proposed")]
+    #[serde(rename = "inlineCompletion", skip_serializing_if = "Option::is_none")]
+    pub inline_completion: Option<InlineCompletionCapabilities>,
+    #[serde(rename = "signatureHelp", skip_serializing_if = "Option::is_none")]
+    pub signature_help: Option<SignatureHelpCapabilities>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignatureHelpCapabilities {
+    #[serde(rename = "signatureInformation")]
+    pub signature_information: SignatureInformationCapabilities,
+    /// Whether requests are sent with a `SignatureHelpContext`.
+    #[serde(rename = "contextSupport")]
+    pub context_support: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignatureInformationCapabilities {
+    #[serde(rename = "documentationFormat")]
+    pub documentation_format: Vec<String>,
+    #[serde(rename = "parameterInformation")]
+    pub parameter_information: ParameterInformationCapabilities,
+    #[serde(rename = "activeParameterSupport")]
+    pub active_parameter_support: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ParameterInformationCapabilities {
+    /// Whether parameter labels may be offsets into the signature's label.
+    #[serde(rename = "labelOffsetSupport")]
+    pub label_offset_support: bool,
+}
+
+#[cfg(feature = "proposed")]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InlineCompletionCapabilities {
+    #[serde(rename = "dynamicRegistration")]
+    pub dynamic_registration: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -106,6 +295,9 @@ pub struct Hover {
 pub struct Completion {
     #[serde(rename = "completionItem")]
     pub completion_item: CompletionItem,
+    /// Whether requests are sent with a `CompletionContext`.
+    #[serde(rename = "contextSupport")]
+    pub context_support: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -132,189 +324,1890 @@ pub struct CodeActionKind {
     pub value_set: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A range inside a document, e.g. the target of a `textDocument/definition` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Location {
-    uri: String,
-    range: Range,
+    pub(crate) uri: String,
+    pub(crate) range: Range,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Location {
+    pub fn new(uri: impl Into<String>, range: Range) -> Self {
+        Location {
+            uri: uri.into(),
+            range,
+        }
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn range(&self) -> Range {
+        self.range
+    }
+}
+
+/// A link to the target of a `textDocument/definition` request, which
+/// servers may send instead of a `Location`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationLink {
+    /// The range of the origin the link was computed for, if the server sent one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin_selection_range: Option<Range>,
+    pub target_uri: String,
+    /// The full range of the target, e.g. the whole function.
+    pub target_range: Range,
+    /// The range to select at the target, e.g. the function's name.
+    pub target_selection_range: Range,
+}
+
+impl From<LocationLink> for Location {
+    fn from(link: LocationLink) -> Self {
+        Location::new(link.target_uri, link.target_selection_range)
+    }
+}
+
+/// A range in a text document, with an exclusive `end`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Range {
-    start: Position,
-    end: Position,
+    pub(crate) start: Position,
+    pub(crate) end: Position,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Range {
+    pub fn new(start: Position, end: Position) -> Self {
+        Range { start, end }
+    }
+
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    pub fn end(&self) -> Position {
+        self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The range with `start` and `end` swapped if `end` comes first, as
+    /// in ranges from servers that send them the wrong way round.
+    pub fn normalized(self) -> Self {
+        if self.end < self.start {
+            Range::new(self.end, self.start)
+        } else {
+            self
+        }
+    }
+
+    /// Whether `position` lies in the range. The end is inclusive here, so a
+    /// cursor placed right after the last character is still inside.
+    pub fn contains(&self, position: Position) -> bool {
+        self.start <= position && position <= self.end
+    }
+
+    /// Whether the ranges share at least one character. Ranges that only touch don't overlap.
+    pub fn overlaps(&self, other: &Range) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    /// The smallest range covering both ranges.
+    pub fn union(&self, other: &Range) -> Range {
+        Range {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// The range covered by both ranges, if any. Touching ranges intersect in an empty range.
+    pub fn intersection(&self, other: &Range) -> Option<Range> {
+        let start = self.start.max(other.start);
+        let end = self.end.min(other.end);
+        (start <= end).then_some(Range { start, end })
+    }
+}
+
+/// A zero-based line and character offset. `character` counts UTF-16 code units.
+/// Positions order by line, then by character.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Position {
-    line: u32,
-    character: u32,
+    pub(crate) line: u32,
+    pub(crate) character: u32,
 }
 
 impl Position {
     pub fn new(line: u32, character: u32) -> Self {
         Position { line, character }
     }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn character(&self) -> u32 {
+        self.character
+    }
 }
 
-impl RequestMessage {
-    /// Helper function to create a new `initialize` request message.
-    /// id - The ID of the request message.
-    /// process_id - The process ID of the client. (usually `std::process::id()`)
-    /// root_uri - The root URI of the workspace. (e.g. `file://path/to/code`)
-    /// client_name - The name of the client. (e.g. `vim-go`)
-    /// workspace_folders - List of folders that the lsp needs context for.
-    /// TODO: This function is currently a bit opinionated towards textdefintion.
-    /// To have a custom initialize message, the workaround for now is to directly
-    /// create a `RequestMessage` with desired capabilities.
-    pub fn new_initialize(
-        id: u32,
-        process_id: u32,
-        root_uri: String,
-        client_name: String,
-        client_version: String,
-        workspace_folders: Vec<WorkspaceFolder>,
-    ) -> Self {
-        let client_info = ClientInfo {
-            name: client_name,
-            version: client_version,
-        };
+/// Names a text document by its URI.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TextDocumentIdentifier {
+    pub uri: String,
+}
 
-        let capabilities = ClientCapabilities {
-            workspace: Some(CapabilitiesWorkspace {
-                workspace_folders: true,
-                did_change_configuration: DidChangeConfiguration {
-                    dynamic_registration: true,
-                },
-                workspace_edit: WorkspaceEdit {
-                    document_changes: true,
-                },
-                configuration: true,
-            }),
-            text_document: Some(CapabilitiesTextDocument {
-                hover: Hover {
-                    content_format: vec!["plaintext".to_string()],
-                },
-                completion: Completion {
-                    completion_item: CompletionItem {
-                        snippet_support: true,
-                    },
-                },
-                code_action: CodeAction {
-                    code_action_literal_support: CodeActionLiteralSupport {
-                        code_action_kind: CodeActionKind {
-                            value_set: vec![
-                                "source.organizeImports".to_string(),
-                                "refactor.rewrite".to_string(),
-                                "refactor.extract".to_string(),
-                            ],
-                        },
-                    },
-                },
-            }),
-        };
+impl TextDocumentIdentifier {
+    pub fn new(uri: impl Into<String>) -> Self {
+        TextDocumentIdentifier { uri: uri.into() }
+    }
+}
 
-        RequestMessage {
-            base_message: BaseMessage {
-                jsonrpc: "2.0".to_string(),
-            },
-            id: serde_json::Value::from(id),
-            method: "initialize".to_string(),
-            notification: 0,
-            params: serde_json::to_value(InitializeParams {
-                process_id,
-                root_uri,
-                client_info,
-                capabilities,
-                workspace_folders: Some(workspace_folders),
-            })
-            .unwrap(),
+/// A text document at a given version, as in `textDocument/didChange`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VersionedTextDocumentIdentifier {
+    pub uri: String,
+    pub version: i32,
+}
+
+impl VersionedTextDocumentIdentifier {
+    pub fn new(uri: impl Into<String>, version: i32) -> Self {
+        VersionedTextDocumentIdentifier {
+            uri: uri.into(),
+            version,
         }
     }
+}
 
-    /// Helper function to create a new `textDocument/definition` request message.
-    /// id - The ID of the request message.
-    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
-    /// line - The line number of the cursor position.
-    /// character - The the cursor position of the character we want to get the definition of.
-    pub fn new_get_definition(id: u32, uri: String, position: Position) -> Self {
-        RequestMessage {
-            base_message: BaseMessage {
-                jsonrpc: "2.0".to_string(),
-            },
-            id: serde_json::Value::from(id),
-            method: "textDocument/definition".to_string(),
-            notification: 0,
-            params: serde_json::json!({
-                "textDocument": {
-                    "uri": uri
-                },
-                "position": {
-                    "line": position.line,
-                    "character": position.character,
-                }
-            }),
+/// A position in a text document, the params shared by the requests about
+/// the code under the cursor such as `textDocument/hover`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentPositionParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+}
+
+impl TextDocumentPositionParams {
+    pub fn new(uri: impl Into<String>, position: Position) -> Self {
+        TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier::new(uri),
+            position,
         }
     }
 }
 
-impl NotificationMessage {
-    /// Helper function to create a new `initialized` notification message.
-    /// This message is sent by the client to the server once it has finished initializing
-    /// and signals that the client is ready to receive requests.
-    pub fn new_initialized() -> Self {
-        NotificationMessage {
-            base_message: BaseMessage {
-                jsonrpc: "2.0".to_string(),
-            },
-            method: "initialized".to_string(),
-            params: serde_json::Value::Object(serde_json::Map::new()),
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum DiagnosticSeverity {
+    Error = 1,
+    Warning = 2,
+    Information = 3,
+    Hint = 4,
+}
+
+impl TryFrom<u8> for DiagnosticSeverity {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            1 => Ok(DiagnosticSeverity::Error),
+            2 => Ok(DiagnosticSeverity::Warning),
+            3 => Ok(DiagnosticSeverity::Information),
+            4 => Ok(DiagnosticSeverity::Hint),
+            _ => Err(format!("Invalid diagnostic severity: {}", value)),
         }
     }
 }
 
-impl ResponseMessage {
-    pub fn handle_initialize(&self) -> Result<()> {
-        if self.error.is_some() {
-            bail!("Error from LSP server: {:?}", self.error);
-        };
+impl From<DiagnosticSeverity> for u8 {
+    fn from(severity: DiagnosticSeverity) -> Self {
+        severity as u8
+    }
+}
 
-        Ok(())
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum DiagnosticTag {
+    Unnecessary = 1,
+    Deprecated = 2,
+}
+
+impl TryFrom<u8> for DiagnosticTag {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            1 => Ok(DiagnosticTag::Unnecessary),
+            2 => Ok(DiagnosticTag::Deprecated),
+            _ => Err(format!("Invalid diagnostic tag: {}", value)),
+        }
     }
+}
 
-    pub fn handle_definition(&self) -> Result<Vec<Location>> {
-        if self.error.is_some() {
-            bail!("Error from LSP server: {:?}", self.error);
-        };
+impl From<DiagnosticTag> for u8 {
+    fn from(tag: DiagnosticTag) -> Self {
+        tag as u8
+    }
+}
 
-        if let Some(res) = &self.result {
-            if res.is_null() {
-                bail!("No definition found.");
-            }
-            let location: Result<Location, _> = serde_json::from_value(res.clone());
-            let locations: Result<Vec<Location>, _> = serde_json::from_value(res.clone());
-
-            match location {
-                Ok(loc) => Ok(vec![loc]),
-                Err(_) => match locations {
-                    Ok(locs) => Ok(locs),
-                    Err(_) => {
-                        anyhow::bail!("Failed to parse definition location(s) from response.")
-                    }
-                },
-            }
-        } else {
-            bail!("No definition found.");
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub range: Range,
+    /// `None` if the server sent no severity or one this crate doesn't know.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_severity",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub severity: Option<DiagnosticSeverity>,
+    /// The diagnostic's code, which the server may send as a number or a string.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<serde_json::Value>,
+    #[serde(rename = "codeDescription", skip_serializing_if = "Option::is_none")]
+    pub code_description: Option<CodeDescription>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub message: String,
+    /// Tags this crate doesn't know are left out.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_tags",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub tags: Option<Vec<DiagnosticTag>>,
+    #[serde(rename = "relatedInformation", skip_serializing_if = "Option::is_none")]
+    pub related_information: Option<Vec<DiagnosticRelatedInformation>>,
+    /// Opaque data the server attached to the diagnostic. It must be sent back
+    /// untouched in the `codeAction` request's context.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// Servers may send severities from a later protocol version, which shouldn't
+/// fail the whole message.
+fn deserialize_severity<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DiagnosticSeverity>, D::Error> {
+    let severity = Option::<u64>::deserialize(deserializer)?;
+    Ok(severity
+        .and_then(|severity| u8::try_from(severity).ok())
+        .and_then(|severity| DiagnosticSeverity::try_from(severity).ok()))
+}
+
+fn deserialize_tags<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<DiagnosticTag>>, D::Error> {
+    let tags = Option::<Vec<u64>>::deserialize(deserializer)?;
+    Ok(tags.map(|tags| {
+        tags.into_iter()
+            .filter_map(|tag| u8::try_from(tag).ok())
+            .filter_map(|tag| DiagnosticTag::try_from(tag).ok())
+            .collect()
+    }))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CodeDescription {
+    /// A URI pointing to documentation about the diagnostic's code.
+    pub href: String,
+}
+
+/// A location related to a diagnostic, e.g. the other symbol in a
+/// "duplicate declaration" error.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DiagnosticRelatedInformation {
+    pub location: Location,
+    pub message: String,
+}
+
+/// Params of the `textDocument/publishDiagnostics` notification.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PublishDiagnosticsParams {
+    pub uri: String,
+    /// The version of the document the diagnostics were computed for, if the server sent one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum SymbolKind {
+    File = 1,
+    Module = 2,
+    Namespace = 3,
+    Package = 4,
+    Class = 5,
+    Method = 6,
+    Property = 7,
+    Field = 8,
+    Constructor = 9,
+    Enum = 10,
+    Interface = 11,
+    Function = 12,
+    Variable = 13,
+    Constant = 14,
+    String = 15,
+    Number = 16,
+    Boolean = 17,
+    Array = 18,
+    Object = 19,
+    Key = 20,
+    Null = 21,
+    EnumMember = 22,
+    Struct = 23,
+    Event = 24,
+    Operator = 25,
+    TypeParameter = 26,
+}
+
+impl TryFrom<u8> for SymbolKind {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            1 => Ok(SymbolKind::File),
+            2 => Ok(SymbolKind::Module),
+            3 => Ok(SymbolKind::Namespace),
+            4 => Ok(SymbolKind::Package),
+            5 => Ok(SymbolKind::Class),
+            6 => Ok(SymbolKind::Method),
+            7 => Ok(SymbolKind::Property),
+            8 => Ok(SymbolKind::Field),
+            9 => Ok(SymbolKind::Constructor),
+            10 => Ok(SymbolKind::Enum),
+            11 => Ok(SymbolKind::Interface),
+            12 => Ok(SymbolKind::Function),
+            13 => Ok(SymbolKind::Variable),
+            14 => Ok(SymbolKind::Constant),
+            15 => Ok(SymbolKind::String),
+            16 => Ok(SymbolKind::Number),
+            17 => Ok(SymbolKind::Boolean),
+            18 => Ok(SymbolKind::Array),
+            19 => Ok(SymbolKind::Object),
+            20 => Ok(SymbolKind::Key),
+            21 => Ok(SymbolKind::Null),
+            22 => Ok(SymbolKind::EnumMember),
+            23 => Ok(SymbolKind::Struct),
+            24 => Ok(SymbolKind::Event),
+            25 => Ok(SymbolKind::Operator),
+            26 => Ok(SymbolKind::TypeParameter),
+            _ => Err(format!("Invalid symbol kind: {}", value)),
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::json;
+impl From<SymbolKind> for u8 {
+    fn from(kind: SymbolKind) -> Self {
+        kind as u8
+    }
+}
 
-    #[test]
-    fn test_initialize_message() {
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum SymbolTag {
+    Deprecated = 1,
+}
+
+impl TryFrom<u8> for SymbolTag {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            1 => Ok(SymbolTag::Deprecated),
+            _ => Err(format!("Invalid symbol tag: {}", value)),
+        }
+    }
+}
+
+impl From<SymbolTag> for u8 {
+    fn from(tag: SymbolTag) -> Self {
+        tag as u8
+    }
+}
+
+/// A symbol of a document outline. `range` spans the whole symbol (e.g. a
+/// function including its body), `selection_range` just its name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DocumentSymbol {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub kind: SymbolKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<SymbolTag>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<bool>,
+    pub range: Range,
+    #[serde(rename = "selectionRange")]
+    pub selection_range: Range,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<DocumentSymbol>,
+}
+
+/// A symbol without hierarchy, as returned by older servers for
+/// `textDocument/documentSymbol` and by `workspace/symbol`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SymbolInformation {
+    pub name: String,
+    pub kind: SymbolKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<SymbolTag>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<bool>,
+    pub location: Location,
+    #[serde(rename = "containerName", skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+}
+
+/// The token of a work done or partial result progress: a number or a
+/// string, serialized as is.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum ProgressToken {
+    Number(i32),
+    String(String),
+}
+
+impl ProgressToken {
+    /// A token for progress the client starts, e.g. as the `workDoneToken`
+    /// of a request. Each call returns a new one, and they are strings with
+    /// the crate's name, so they can't collide with numeric tokens created
+    /// by the server either.
+    pub fn generate() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        let next = NEXT.fetch_add(1, Ordering::Relaxed);
+        ProgressToken::String(format!("{}-{}", env!("CARGO_PKG_NAME"), next))
+    }
+}
+
+impl From<i32> for ProgressToken {
+    fn from(token: i32) -> Self {
+        ProgressToken::Number(token)
+    }
+}
+
+impl From<String> for ProgressToken {
+    fn from(token: String) -> Self {
+        ProgressToken::String(token)
+    }
+}
+
+impl From<&str> for ProgressToken {
+    fn from(token: &str) -> Self {
+        ProgressToken::String(token.to_string())
+    }
+}
+
+impl From<ProgressToken> for serde_json::Value {
+    fn from(token: ProgressToken) -> Self {
+        match token {
+            ProgressToken::Number(token) => token.into(),
+            ProgressToken::String(token) => token.into(),
+        }
+    }
+}
+
+impl fmt::Display for ProgressToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProgressToken::Number(token) => write!(f, "{}", token),
+            ProgressToken::String(token) => f.write_str(token),
+        }
+    }
+}
+
+/// `boolean | T`, the shape of most server capabilities: either a flag, or
+/// options that imply support.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum BoolOr<T> {
+    Bool(bool),
+    Options(T),
+}
+
+impl<T> BoolOr<T> {
+    /// Whether the capability is supported: `true`, or any options.
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            BoolOr::Bool(enabled) => *enabled,
+            BoolOr::Options(_) => true,
+        }
+    }
+
+    /// The options, if they were sent rather than a flag.
+    pub fn options(&self) -> Option<&T> {
+        match self {
+            BoolOr::Bool(_) => None,
+            BoolOr::Options(options) => Some(options),
+        }
+    }
+}
+
+/// `A | B`, for fields the protocol allows in two shapes. `A` is tried first.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum OneOf<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A, B> OneOf<A, B> {
+    pub fn left(&self) -> Option<&A> {
+        match self {
+            OneOf::Left(left) => Some(left),
+            OneOf::Right(_) => None,
+        }
+    }
+
+    pub fn right(&self) -> Option<&B> {
+        match self {
+            OneOf::Left(_) => None,
+            OneOf::Right(right) => Some(right),
+        }
+    }
+}
+
+/// Result of a `textDocument/documentSymbol` request. Servers send a tree if
+/// the client announced `hierarchicalDocumentSymbolSupport`, a flat list otherwise.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum DocumentSymbolResponse {
+    Nested(Vec<DocumentSymbol>),
+    Flat(Vec<SymbolInformation>),
+}
+
+/// A symbol returned by `workspace/symbol`. Since LSP 3.17 the server may omit
+/// the range of the location and leave it to `workspaceSymbol/resolve`.
+#[cfg(feature = "lsp-3-17")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<SymbolTag>>,
+    #[serde(rename = "containerName", skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+    pub location: WorkspaceSymbolLocation,
+    /// Opaque data the server attached to the symbol, sent back on resolve.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+#[cfg(feature = "lsp-3-17")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum WorkspaceSymbolLocation {
+    Full(Location),
+    Uri { uri: String },
+}
+
+#[cfg(feature = "lsp-3-17")]
+impl WorkspaceSymbol {
+    pub fn uri(&self) -> &str {
+        match &self.location {
+            WorkspaceSymbolLocation::Full(location) => location.uri(),
+            WorkspaceSymbolLocation::Uri { uri } => uri,
+        }
+    }
+
+    /// The full location of the symbol, if the server already sent its range.
+    pub fn location(&self) -> Option<&Location> {
+        match &self.location {
+            WorkspaceSymbolLocation::Full(location) => Some(location),
+            WorkspaceSymbolLocation::Uri { .. } => None,
+        }
+    }
+
+    /// Whether the range has to be fetched with `workspaceSymbol/resolve`.
+    pub fn needs_resolve(&self) -> bool {
+        self.location().is_none()
+    }
+}
+
+/// Result of a `workspace/symbol` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum WorkspaceSymbolResponse {
+    Flat(Vec<SymbolInformation>),
+    #[cfg(feature = "lsp-3-17")]
+    Workspace(Vec<WorkspaceSymbol>),
+}
+
+/// Result of a `textDocument/semanticTokens/full` request. See
+/// `SemanticTokensLegend` for decoding `data`.
+#[cfg(feature = "lsp-3-16")]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokens {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_id: Option<String>,
+    /// Five integers per token, relative to the previous token.
+    pub data: Vec<u32>,
+}
+
+/// How an inline completion request was triggered.
+#[cfg(feature = "proposed")]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum InlineCompletionTriggerKind {
+    /// Explicitly requested by the user.
+    Invoked = 1,
+    /// Requested automatically while the user types.
+    Automatic = 2,
+}
+
+#[cfg(feature = "proposed")]
+impl TryFrom<u8> for InlineCompletionTriggerKind {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            1 => Ok(InlineCompletionTriggerKind::Invoked),
+            2 => Ok(InlineCompletionTriggerKind::Automatic),
+            _ => Err(format!("Invalid inline completion trigger kind: {}", value)),
+        }
+    }
+}
+
+#[cfg(feature = "proposed")]
+impl From<InlineCompletionTriggerKind> for u8 {
+    fn from(kind: InlineCompletionTriggerKind) -> Self {
+        kind as u8
+    }
+}
+
+/// The item selected in the completion widget while inline completions are
+/// requested. Inline completions should then extend the selected item.
+#[cfg(feature = "proposed")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SelectedCompletionInfo {
+    pub range: Range,
+    pub text: String,
+}
+
+#[cfg(feature = "proposed")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InlineCompletionContext {
+    #[serde(rename = "triggerKind")]
+    pub trigger_kind: InlineCompletionTriggerKind,
+    #[serde(
+        rename = "selectedCompletionInfo",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub selected_completion_info: Option<SelectedCompletionInfo>,
+}
+
+/// The text of an inline completion, either plain or a snippet.
+#[cfg(feature = "proposed")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum InlineCompletionText {
+    Plain(String),
+    /// A `StringValue`, whose `kind` is always `snippet`.
+    Snippet {
+        kind: String,
+        value: String,
+    },
+}
+
+/// A ghost-text suggestion returned by `textDocument/inlineCompletion`.
+#[cfg(feature = "proposed")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InlineCompletionItem {
+    #[serde(rename = "insertText")]
+    pub insert_text: InlineCompletionText,
+    #[serde(rename = "filterText", skip_serializing_if = "Option::is_none")]
+    pub filter_text: Option<String>,
+    /// The range to replace. Defaults to an empty range at the requested position.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+    /// A command executed after the completion was accepted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<serde_json::Value>,
+}
+
+#[cfg(feature = "proposed")]
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum InlineCompletionResponse {
+    List { items: Vec<InlineCompletionItem> },
+    Items(Vec<InlineCompletionItem>),
+}
+
+/// Result of a `textDocument/diagnostic` (pull diagnostics) request.
+/// An `Unchanged` report means the diagnostics of the previous report with
+/// the same `result_id` are still valid.
+#[cfg(feature = "lsp-3-17")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum DocumentDiagnosticReport {
+    Full {
+        #[serde(rename = "resultId", skip_serializing_if = "Option::is_none")]
+        result_id: Option<String>,
+        items: Vec<Diagnostic>,
+    },
+    Unchanged {
+        #[serde(rename = "resultId")]
+        result_id: String,
+    },
+}
+
+/// How much of a value that failed to parse `parse_value` quotes.
+#[cfg(feature = "error-snippets")]
+const ERROR_SNIPPET_CHARS: usize = 200;
+
+/// Deserializes `value`, failing with what couldn't be parsed and the JSON
+/// path of the offending field, such as `[0].range.start.line`.
+///
+/// With the `error-snippets` feature the error also quotes the start of
+/// `value`. It is off by default: results can hold the user's source code,
+/// and errors tend to end up in logs.
+pub(crate) fn parse_value<'a, T: Deserialize<'a>>(
+    value: &'a serde_json::Value,
+    what: &str,
+) -> Result<T> {
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        let mut message = format!("Failed to parse {}", what);
+        if e.path().iter().next().is_some() {
+            message.push_str(&format!(" at `{}`", e.path()));
+        }
+        message.push_str(&format!(": {}", e.inner()));
+        #[cfg(feature = "error-snippets")]
+        {
+            let raw = value.to_string();
+            let snippet: String = raw.chars().take(ERROR_SNIPPET_CHARS).collect();
+            let ellipsis = if snippet.len() < raw.len() { "..." } else { "" };
+            message.push_str(&format!(" in {}{}", snippet, ellipsis));
+        }
+        anyhow::anyhow!(message)
+    })
+}
+
+/// Builds a `RequestMessage` for any method. See `RequestMessage::builder`.
+#[derive(Debug, Default)]
+pub struct RequestMessageBuilder {
+    id: Option<serde_json::Value>,
+    method: Option<Cow<'static, str>>,
+    params: Option<serde_json::Result<serde_json::Value>>,
+    extra_params: Option<serde_json::Value>,
+}
+
+impl RequestMessageBuilder {
+    /// The ID of the request message, a number or a string.
+    pub fn id(mut self, id: impl Into<serde_json::Value>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn method(mut self, method: impl Into<Cow<'static, str>>) -> Self {
+        self.method = Some(method.into());
+        self
+    }
+
+    /// The params of the request. Anything serializable to a JSON object or
+    /// array is accepted; leave them out for methods without params.
+    pub fn params<P: Serialize>(mut self, params: P) -> Self {
+        self.params = Some(serde_json::to_value(params));
+        self
+    }
+
+    /// Fields merged into the params, such as server specific extensions.
+    /// See `RequestMessage::extend_params`.
+    pub fn extend_params(mut self, extra: serde_json::Value) -> Self {
+        merge(
+            self.extra_params.get_or_insert(serde_json::Value::Null),
+            &extra,
+        );
+        self
+    }
+
+    pub fn build(self) -> Result<RequestMessage> {
+        let id = match self.id {
+            Some(id) if id.is_number() || id.is_string() => id,
+            Some(id) => bail!("Request id must be a number or a string, got {}", id),
+            None => bail!("Request id is missing."),
+        };
+        let method = match self.method {
+            Some(method) if !method.trim().is_empty() => method,
+            _ => bail!("Request method must not be empty."),
+        };
+        let params = match self.params {
+            Some(Ok(params)) if params.is_object() || params.is_array() || params.is_null() => {
+                params
+            }
+            Some(Ok(params)) => bail!(
+                "Request params must be an object or an array, got {}",
+                params
+            ),
+            Some(Err(e)) => bail!("Failed to serialize request params: {}", e),
+            None => serde_json::Value::Null,
+        };
+        let mut params = params;
+        if let Some(extra) = self.extra_params {
+            extend_params(&mut params, extra)?;
+        }
+
+        Ok(RequestMessage {
+            base_message: BaseMessage::new(),
+            id,
+            notification: 0,
+            method,
+            params,
+        })
+    }
+}
+
+/// Merges the fields of `extra` into `params`. Nested objects are merged,
+/// anything else in `extra` replaces what is in `params`. Fails if `params`
+/// is an array or `extra` isn't an object, leaving `params` untouched.
+fn extend_params(params: &mut serde_json::Value, extra: serde_json::Value) -> Result<()> {
+    if params.is_array() {
+        bail!(
+            "Request params are an array, they can't be extended with {}",
+            extra
+        );
+    }
+    if !extra.is_object() {
+        bail!("Extra request params must be an object, got {}", extra);
+    }
+    if params.is_null() {
+        *params = serde_json::Value::Object(Default::default());
+    }
+    merge(params, &extra);
+    Ok(())
+}
+
+/// The `code` of a JSON-RPC error object.
+pub(crate) fn error_code(error: Option<&serde_json::Value>) -> Option<i64> {
+    error?.get("code")?.as_i64()
+}
+
+/// A change to a text document. Without a `range` the `text` replaces the
+/// whole document.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextDocumentContentChangeEvent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+    pub text: String,
+}
+
+/// The `workspace/*/refresh` requests a server can send to ask the client to
+/// re-query some kind of data for all open documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RefreshKind {
+    #[cfg(feature = "lsp-3-16")]
+    SemanticTokens,
+    #[cfg(feature = "lsp-3-17")]
+    InlayHint,
+    #[cfg(feature = "lsp-3-16")]
+    CodeLens,
+    #[cfg(feature = "lsp-3-17")]
+    Diagnostic,
+    #[cfg(feature = "lsp-3-17")]
+    InlineValue,
+}
+
+impl RefreshKind {
+    pub fn from_method(method: &str) -> Option<Self> {
+        match method {
+            #[cfg(feature = "lsp-3-16")]
+            methods::WORKSPACE_SEMANTIC_TOKENS_REFRESH => Some(RefreshKind::SemanticTokens),
+            #[cfg(feature = "lsp-3-17")]
+            methods::WORKSPACE_INLAY_HINT_REFRESH => Some(RefreshKind::InlayHint),
+            #[cfg(feature = "lsp-3-16")]
+            methods::WORKSPACE_CODE_LENS_REFRESH => Some(RefreshKind::CodeLens),
+            #[cfg(feature = "lsp-3-17")]
+            methods::WORKSPACE_DIAGNOSTIC_REFRESH => Some(RefreshKind::Diagnostic),
+            #[cfg(feature = "lsp-3-17")]
+            methods::WORKSPACE_INLINE_VALUE_REFRESH => Some(RefreshKind::InlineValue),
+            _ => None,
+        }
+    }
+
+    pub fn method(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "lsp-3-16")]
+            RefreshKind::SemanticTokens => methods::WORKSPACE_SEMANTIC_TOKENS_REFRESH,
+            #[cfg(feature = "lsp-3-17")]
+            RefreshKind::InlayHint => methods::WORKSPACE_INLAY_HINT_REFRESH,
+            #[cfg(feature = "lsp-3-16")]
+            RefreshKind::CodeLens => methods::WORKSPACE_CODE_LENS_REFRESH,
+            #[cfg(feature = "lsp-3-17")]
+            RefreshKind::Diagnostic => methods::WORKSPACE_DIAGNOSTIC_REFRESH,
+            #[cfg(feature = "lsp-3-17")]
+            RefreshKind::InlineValue => methods::WORKSPACE_INLINE_VALUE_REFRESH,
+        }
+    }
+}
+
+impl RequestMessage {
+    /// Returns a builder for requests without a dedicated helper function.
+    ///
+    /// ```
+    /// # use lsp_client_rs::protocol::RequestMessage;
+    /// let request = RequestMessage::builder()
+    ///     .id(3)
+    ///     .method("textDocument/hover")
+    ///     .params(serde_json::json!({
+    ///         "textDocument": { "uri": "file:///main.go" },
+    ///         "position": { "line": 1, "character": 2 }
+    ///     }))
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(request.method, "textDocument/hover");
+    /// ```
+    pub fn builder() -> RequestMessageBuilder {
+        RequestMessageBuilder::default()
+    }
+
+    /// Merges server specific fields into the params of a request built by
+    /// one of the helpers, keeping everything else they set. Nested objects
+    /// are merged; other values in `extra` replace those in the params.
+    /// Fails, like `RequestMessageBuilder::build`, if the params are an array
+    /// or `extra` isn't an object.
+    ///
+    /// ```
+    /// # use lsp_client_rs::protocol::{Position, RequestMessage};
+    /// let request = RequestMessage::new_hover(
+    ///     2,
+    ///     "file:///src/main.rs".to_string(),
+    ///     Position::new(4, 8),
+    /// )
+    /// .extend_params(serde_json::json!({ "workDoneToken": "hover-2" }))
+    /// .unwrap();
+    /// assert_eq!(request.params["textDocument"]["uri"], "file:///src/main.rs");
+    /// assert_eq!(request.params["workDoneToken"], "hover-2");
+    /// ```
+    pub fn extend_params(mut self, extra: serde_json::Value) -> Result<Self> {
+        extend_params(&mut self.params, extra)?;
+        Ok(self)
+    }
+
+    /// Helper function to create a new `initialize` request message.
+    /// id - The ID of the request message.
+    /// process_id - The process ID of the client. (usually `std::process::id()`)
+    /// root_uri - The root URI of the workspace. (e.g. `file://path/to/code`)
+    /// client_name - The name of the client. (e.g. `vim-go`)
+    /// workspace_folders - List of folders that the lsp needs context for.
+    /// TODO: This function is currently a bit opinionated towards textdefintion.
+    /// To have a custom initialize message, build it with `RequestMessage::builder()`
+    /// and the desired `InitializeParams`.
+    pub fn new_initialize(
+        id: u32,
+        process_id: u32,
+        root_uri: String,
+        client_name: String,
+        client_version: String,
+        workspace_folders: Vec<WorkspaceFolder>,
+    ) -> Self {
+        let client_info = ClientInfo {
+            name: client_name,
+            version: client_version,
+        };
+
+        let capabilities = ClientCapabilities {
+            workspace: Some(CapabilitiesWorkspace {
+                workspace_folders: true,
+                did_change_configuration: DidChangeConfiguration {
+                    dynamic_registration: true,
+                },
+                workspace_edit: WorkspaceEdit {
+                    document_changes: true,
+                    #[cfg(feature = "lsp-3-16")]
+                    change_annotation_support: Some(ChangeAnnotationSupport {
+                        groups_on_label: false,
+                    }),
+                    #[cfg(not(feature = "lsp-3-16"))]
+                    change_annotation_support: None,
+                },
+                configuration: true,
+                #[cfg(feature = "lsp-3-17")]
+                symbol: Some(WorkspaceSymbolCapabilities {
+                    resolve_support: Some(ResolveSupport {
+                        properties: vec!["location.range".to_string()],
+                    }),
+                }),
+                #[cfg(not(feature = "lsp-3-17"))]
+                symbol: None,
+            }),
+            text_document: Some(CapabilitiesTextDocument {
+                hover: Hover {
+                    content_format: vec!["plaintext".to_string()],
+                },
+                completion: Completion {
+                    completion_item: CompletionItem {
+                        snippet_support: true,
+                    },
+                    context_support: true,
+                },
+                code_action: CodeAction {
+                    code_action_literal_support: CodeActionLiteralSupport {
+                        code_action_kind: CodeActionKind {
+                            value_set: vec![
+                                "source.organizeImports".to_string(),
+                                "refactor.rewrite".to_string(),
+                                "refactor.extract".to_string(),
+                            ],
+                        },
+                    },
+                },
+                #[cfg(feature = "proposed")]
+                inline_completion: Some(InlineCompletionCapabilities {
+                    dynamic_registration: false,
+                }),
+                signature_help: Some(SignatureHelpCapabilities {
+                    signature_information: SignatureInformationCapabilities {
+                        documentation_format: vec!["plaintext".to_string()],
+                        parameter_information: ParameterInformationCapabilities {
+                            label_offset_support: true,
+                        },
+                        active_parameter_support: true,
+                    },
+                    context_support: true,
+                }),
+            }),
+            window: Some(CapabilitiesWindow {
+                work_done_progress: true,
+            }),
+        };
+
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::INITIALIZE),
+            notification: 0,
+            params: serde_json::to_value(InitializeParams {
+                process_id,
+                root_uri,
+                client_info,
+                capabilities,
+                workspace_folders: Some(workspace_folders),
+            })
+            .unwrap(),
+        }
+    }
+
+    /// Helper function to create a new `shutdown` request message.
+    /// After the server answers it, the client must only send the `exit` notification.
+    pub fn new_shutdown(id: u32) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::SHUTDOWN),
+            notification: 0,
+            params: serde_json::Value::Null,
+        }
+    }
+
+    /// Helper function to create a new `textDocument/definition` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// line - The line number of the cursor position.
+    /// character - The the cursor position of the character we want to get the definition of.
+    pub fn new_get_definition(id: u32, uri: String, position: Position) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_DEFINITION),
+            notification: 0,
+            params: serde_json::json!(TextDocumentPositionParams::new(uri, position)),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/semanticTokens/full` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    #[cfg(feature = "lsp-3-16")]
+    pub fn new_semantic_tokens_full(id: u32, uri: String) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_SEMANTIC_TOKENS_FULL),
+            notification: 0,
+            params: serde_json::json!({ "textDocument": TextDocumentIdentifier::new(uri) }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/documentSymbol` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    pub fn new_document_symbol(id: u32, uri: String) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_DOCUMENT_SYMBOL),
+            notification: 0,
+            params: serde_json::json!({ "textDocument": TextDocumentIdentifier::new(uri) }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/inlineCompletion` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position of the cursor.
+    /// context - How the request was triggered.
+    #[cfg(feature = "proposed")]
+    pub fn new_inline_completion(
+        id: u32,
+        uri: String,
+        position: Position,
+        context: InlineCompletionContext,
+    ) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_INLINE_COMPLETION),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": TextDocumentIdentifier::new(uri),
+                "position": position,
+                "context": context,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/hover` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position to get hover information for.
+    pub fn new_hover(id: u32, uri: String, position: Position) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_HOVER),
+            notification: 0,
+            params: serde_json::json!(TextDocumentPositionParams::new(uri, position)),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/signatureHelp` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position of the cursor.
+    /// context - How the request was triggered, left out if `None`.
+    pub fn new_signature_help(
+        id: u32,
+        uri: String,
+        position: Position,
+        context: Option<SignatureHelpContext>,
+    ) -> Self {
+        let mut params = serde_json::json!(TextDocumentPositionParams::new(uri, position));
+        if let Some(context) = context {
+            params["context"] = serde_json::json!(context);
+        }
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_SIGNATURE_HELP),
+            notification: 0,
+            params,
+        }
+    }
+
+    /// Helper function to create a new `textDocument/completion` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position of the cursor.
+    /// context - How the request was triggered, left out if `None`.
+    pub fn new_completion(
+        id: u32,
+        uri: String,
+        position: Position,
+        context: Option<CompletionContext>,
+    ) -> Self {
+        let mut params = serde_json::json!(TextDocumentPositionParams::new(uri, position));
+        if let Some(context) = context {
+            params["context"] = serde_json::json!(context);
+        }
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_COMPLETION),
+            notification: 0,
+            params,
+        }
+    }
+
+    /// Helper function to create a new `textDocument/linkedEditingRange` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/index.html`)
+    /// position - The position of the cursor.
+    #[cfg(feature = "lsp-3-16")]
+    pub fn new_linked_editing_range(id: u32, uri: String, position: Position) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_LINKED_EDITING_RANGE),
+            notification: 0,
+            params: serde_json::json!(TextDocumentPositionParams::new(uri, position)),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/documentHighlight` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position of the cursor.
+    pub fn new_document_highlight(id: u32, uri: String, position: Position) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_DOCUMENT_HIGHLIGHT),
+            notification: 0,
+            params: serde_json::json!(TextDocumentPositionParams::new(uri, position)),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/foldingRange` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    pub fn new_folding_range(id: u32, uri: String) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_FOLDING_RANGE),
+            notification: 0,
+            params: serde_json::json!({ "textDocument": TextDocumentIdentifier::new(uri) }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/documentColor` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/style.css`)
+    pub fn new_document_color(id: u32, uri: String) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_DOCUMENT_COLOR),
+            notification: 0,
+            params: serde_json::json!({ "textDocument": TextDocumentIdentifier::new(uri) }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/colorPresentation` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document.
+    /// color - The color to present, usually one picked by the user.
+    /// range - Where the color would be inserted.
+    pub fn new_color_presentation(
+        id: u32,
+        uri: String,
+        color: crate::colors::Color,
+        range: Range,
+    ) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_COLOR_PRESENTATION),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": TextDocumentIdentifier::new(uri),
+                "color": color,
+                "range": range,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/codeAction` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// range - The range to get code actions for, usually the selection.
+    /// diagnostics - The diagnostics overlapping `range`, for quick fixes.
+    pub fn new_code_action(
+        id: u32,
+        uri: String,
+        range: Range,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_CODE_ACTION),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": TextDocumentIdentifier::new(uri),
+                "range": range,
+                "context": { "diagnostics": diagnostics },
+            }),
+        }
+    }
+
+    /// Helper function to create a new `codeAction/resolve` request message.
+    /// id - The ID of the request message.
+    /// action - The code action to resolve, as received from `textDocument/codeAction`.
+    #[cfg(feature = "lsp-3-16")]
+    pub fn new_code_action_resolve(
+        id: u32,
+        action: &crate::code_actions::CodeAction,
+    ) -> Result<Self> {
+        Ok(RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::CODE_ACTION_RESOLVE),
+            notification: 0,
+            params: serde_json::to_value(action)?,
+        })
+    }
+
+    /// Helper function to create a new `workspace/executeCommand` request message.
+    /// id - The ID of the request message.
+    /// command - The command to execute, e.g. the one of a code action.
+    pub fn new_execute_command(id: u32, command: &crate::code_actions::Command) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::WORKSPACE_EXECUTE_COMMAND),
+            notification: 0,
+            params: serde_json::json!({
+                "command": command.command,
+                "arguments": command.arguments,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/rename` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position of the symbol to rename.
+    /// new_name - The new name of the symbol.
+    pub fn new_rename(id: u32, uri: String, position: Position, new_name: String) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_RENAME),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": TextDocumentIdentifier::new(uri),
+                "position": position,
+                "newName": new_name,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `workspace/symbol` request message.
+    /// id - The ID of the request message.
+    /// query - The text to match symbol names against. An empty query asks for all symbols.
+    pub fn new_workspace_symbol(id: u32, query: String) -> Self {
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::WORKSPACE_SYMBOL),
+            notification: 0,
+            params: serde_json::json!({ "query": query }),
+        }
+    }
+
+    /// Helper function to create a new `workspaceSymbol/resolve` request message.
+    /// id - The ID of the request message.
+    /// symbol - The symbol to resolve, as received from `workspace/symbol`.
+    #[cfg(feature = "lsp-3-17")]
+    pub fn new_workspace_symbol_resolve(id: u32, symbol: &WorkspaceSymbol) -> Result<Self> {
+        Ok(RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::WORKSPACE_SYMBOL_RESOLVE),
+            notification: 0,
+            params: serde_json::to_value(symbol)?,
+        })
+    }
+
+    /// Helper function to create a new `textDocument/diagnostic` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// previous_result_id - The `resultId` of the last report received for this document, if any.
+    #[cfg(feature = "lsp-3-17")]
+    pub fn new_document_diagnostic(
+        id: u32,
+        uri: String,
+        previous_result_id: Option<String>,
+    ) -> Self {
+        let mut params = serde_json::json!({ "textDocument": TextDocumentIdentifier::new(uri) });
+        if let Some(previous_result_id) = previous_result_id {
+            params["previousResultId"] = serde_json::Value::from(previous_result_id);
+        }
+
+        RequestMessage {
+            base_message: BaseMessage::new(),
+            id: serde_json::Value::from(id),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_DIAGNOSTIC),
+            notification: 0,
+            params,
+        }
+    }
+}
+
+impl NotificationMessage {
+    /// Merges server specific fields into the params. See
+    /// `RequestMessage::extend_params`.
+    pub fn extend_params(mut self, extra: serde_json::Value) -> Result<Self> {
+        extend_params(&mut self.params, extra)?;
+        Ok(self)
+    }
+
+    /// Helper function to create a new `exit` notification message, which asks
+    /// the server process to exit.
+    pub fn new_exit() -> Self {
+        NotificationMessage {
+            base_message: BaseMessage::new(),
+            method: Cow::Borrowed(methods::EXIT),
+            params: serde_json::Value::Null,
+        }
+    }
+
+    /// Helper function to create a new `initialized` notification message.
+    /// This message is sent by the client to the server once it has finished initializing
+    /// and signals that the client is ready to receive requests.
+    pub fn new_initialized() -> Self {
+        NotificationMessage {
+            base_message: BaseMessage::new(),
+            method: Cow::Borrowed(methods::INITIALIZED),
+            params: serde_json::Value::Object(serde_json::Map::new()),
+        }
+    }
+
+    /// Helper function to create a new `workspace/didChangeConfiguration` notification message.
+    /// settings - The client's settings for the server. (e.g. `{ "gopls": { ... } }`)
+    pub fn new_did_change_configuration(settings: serde_json::Value) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage::new(),
+            method: Cow::Borrowed(methods::WORKSPACE_DID_CHANGE_CONFIGURATION),
+            params: serde_json::json!({ "settings": settings }),
+        }
+    }
+
+    /// A `$/cancelRequest` notification for the request with `id`, a number
+    /// or a string. `LspClient` sends these itself when a caller stops
+    /// waiting for a response.
+    pub fn new_cancel_request(id: impl Into<serde_json::Value>) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage::new(),
+            method: Cow::Borrowed(methods::CANCEL_REQUEST),
+            params: serde_json::json!({ "id": id.into() }),
+        }
+    }
+
+    /// A `window/workDoneProgress/cancel` notification, asking the server to
+    /// cancel the progress reported under `token`.
+    pub fn new_work_done_progress_cancel(token: impl Into<ProgressToken>) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage::new(),
+            method: Cow::Borrowed(methods::WINDOW_WORK_DONE_PROGRESS_CANCEL),
+            params: serde_json::json!({ "token": token.into() }),
+        }
+    }
+
+    /// A `workspace/didChangeWorkspaceFolders` notification.
+    pub fn new_did_change_workspace_folders(
+        added: Vec<WorkspaceFolder>,
+        removed: Vec<WorkspaceFolder>,
+    ) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage::new(),
+            method: Cow::Borrowed(methods::WORKSPACE_DID_CHANGE_WORKSPACE_FOLDERS),
+            params: serde_json::json!({ "event": { "added": added, "removed": removed } }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/didOpen` notification message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// language_id - The language of the document. (e.g. `go`)
+    /// version - The initial version of the document.
+    /// text - The full content of the document.
+    pub fn new_did_open(uri: String, language_id: String, version: i32, text: String) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage::new(),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_DID_OPEN),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": language_id,
+                    "version": version,
+                    "text": text,
+                }
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/didChange` notification message.
+    /// uri - The URI of the text document.
+    /// version - The version of the document after the changes have been applied.
+    /// changes - The changes, in the order they were applied.
+    pub fn new_did_change(
+        uri: String,
+        version: i32,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage::new(),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_DID_CHANGE),
+            params: serde_json::json!({
+                "textDocument": VersionedTextDocumentIdentifier::new(uri, version),
+                "contentChanges": changes,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/didClose` notification message.
+    /// uri - The URI of the text document.
+    pub fn new_did_close(uri: String) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage::new(),
+            method: Cow::Borrowed(methods::TEXT_DOCUMENT_DID_CLOSE),
+            params: serde_json::json!({ "textDocument": TextDocumentIdentifier::new(uri) }),
+        }
+    }
+}
+
+impl ResponseMessage {
+    /// The code of the error, if the request failed.
+    pub fn error_code(&self) -> Option<i64> {
+        error_code(self.error.as_ref())
+    }
+
+    /// Whether the server gave up on the request because the document
+    /// changed while it was working on it. See `RetryPolicy` to resend such
+    /// requests automatically.
+    pub fn is_content_modified(&self) -> bool {
+        self.error_code() == Some(CONTENT_MODIFIED)
+    }
+
+    /// Helper function to create a successful response to a request sent by the server.
+    pub fn new_result(id: serde_json::Value, result: serde_json::Value) -> Self {
+        ResponseMessage {
+            base_message: BaseMessage::new(),
+            id: Some(id),
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    /// Helper function to create an error response to a request sent by the server.
+    pub fn new_error(id: serde_json::Value, code: i64, message: String) -> Self {
+        ResponseMessage {
+            base_message: BaseMessage::new(),
+            id: Some(id),
+            result: None,
+            error: Some(serde_json::json!({
+                "code": code,
+                "message": message,
+            })),
+        }
+    }
+
+    pub fn handle_initialize(&self) -> Result<InitializeResult> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => parse_value(res, "initialize result"),
+            None => bail!("No initialize result."),
+        }
+    }
+
+    /// Parses a `textDocument/definition` result: a location, an array of
+    /// them, or an array of `LocationLink`s, which become the locations of
+    /// their targets.
+    pub fn handle_definition(&self) -> Result<Vec<Location>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        if let Some(res) = &self.result {
+            if res.is_null() {
+                bail!("No definition found.");
+            }
+            // Deserialize straight from the borrowed value: the result is
+            // walked once and never cloned, whichever shape the server chose.
+            // Picking the shape up front, rather than trying both, keeps the
+            // path of a bad field in the error.
+            let links = res
+                .get(0)
+                .is_some_and(|first| first.get("targetUri").is_some());
+            if links {
+                let links: Vec<LocationLink> = parse_value(res, "definition links")?;
+                Ok(links.into_iter().map(Location::from).collect())
+            } else if res.is_array() {
+                parse_value(res, "definition locations")
+            } else {
+                parse_value(res, "definition location").map(|loc| vec![loc])
+            }
+        } else {
+            bail!("No definition found.");
+        }
+    }
+
+    /// Parses a `textDocument/documentSymbol` result. A `null` result is an empty outline.
+    pub fn handle_document_symbol(&self) -> Result<DocumentSymbolResponse> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        // Like `handle_definition`, the shape is picked from the first item,
+        // so the untagged enum doesn't buffer the result to try each one.
+        match &self.result {
+            Some(res)
+                if res
+                    .get(0)
+                    .is_some_and(|first| first.get("location").is_some()) =>
+            {
+                parse_value(res, "document symbols").map(DocumentSymbolResponse::Flat)
+            }
+            Some(res) if !res.is_null() => {
+                parse_value(res, "document symbols").map(DocumentSymbolResponse::Nested)
+            }
+            _ => Ok(DocumentSymbolResponse::Nested(Vec::new())),
+        }
+    }
+
+    /// Parses a `textDocument/inlineCompletion` result, which the server may
+    /// send as a list, an array of items or `null`.
+    #[cfg(feature = "proposed")]
+    pub fn handle_inline_completion(&self) -> Result<Vec<InlineCompletionItem>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => match parse_value(res, "inline completions")? {
+                InlineCompletionResponse::List { items } => Ok(items),
+                InlineCompletionResponse::Items(items) => Ok(items),
+            },
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Parses a `textDocument/hover` result. `None` means there is nothing to show.
+    pub fn handle_hover(&self) -> Result<Option<crate::hover::Hover>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => parse_value(res, "hover").map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Parses a `textDocument/signatureHelp` result. `None` means there is
+    /// no signature to show at the position.
+    pub fn handle_signature_help(&self) -> Result<Option<SignatureHelp>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => parse_value(res, "signature help").map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Parses a `textDocument/linkedEditingRange` result. `None` means the
+    /// position isn't in ranges that are edited together.
+    #[cfg(feature = "lsp-3-16")]
+    pub fn handle_linked_editing_range(
+        &self,
+    ) -> Result<Option<crate::linked_editing::LinkedEditingRanges>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => parse_value(res, "linked editing ranges").map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Parses a `textDocument/documentHighlight` result.
+    pub fn handle_document_highlight(&self) -> Result<Vec<crate::highlights::DocumentHighlight>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => parse_value(res, "document highlights"),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Parses a `textDocument/foldingRange` result.
+    pub fn handle_folding_range(&self) -> Result<Vec<crate::folding::FoldingRange>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => parse_value(res, "folding ranges"),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Parses a `textDocument/documentColor` result.
+    pub fn handle_document_color(&self) -> Result<Vec<crate::colors::ColorInformation>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => parse_value(res, "document colors"),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Parses a `textDocument/colorPresentation` result.
+    pub fn handle_color_presentation(&self) -> Result<Vec<crate::colors::ColorPresentation>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => parse_value(res, "color presentations"),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Parses a `textDocument/codeAction` result.
+    pub fn handle_code_action(
+        &self,
+    ) -> Result<Vec<OneOf<crate::code_actions::Command, crate::code_actions::CodeAction>>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => parse_value(res, "code actions"),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Parses a `codeAction/resolve` result.
+    #[cfg(feature = "lsp-3-16")]
+    pub fn handle_code_action_resolve(&self) -> Result<crate::code_actions::CodeAction> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => parse_value(res, "resolved code action"),
+            None => bail!("No code action found."),
+        }
+    }
+
+    /// Parses a `workspace/executeCommand` result, which is up to the command.
+    pub fn handle_execute_command(&self) -> Result<Option<serde_json::Value>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        Ok(self.result.clone().filter(|res| !res.is_null()))
+    }
+
+    /// Parses a `textDocument/rename` result. `None` means the symbol can't
+    /// be renamed.
+    pub fn handle_rename(&self) -> Result<Option<crate::edits::WorkspaceEdit>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => parse_value(res, "workspace edit").map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Parses a `textDocument/completion` result. A bare array of items is a
+    /// complete list and `null` an empty one.
+    pub fn handle_completion(&self) -> Result<CompletionList> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res @ serde_json::Value::Array(_)) => {
+                let items = parse_value(res, "completion items")?;
+                Ok(CompletionList {
+                    is_incomplete: false,
+                    items,
+                })
+            }
+            Some(res) if !res.is_null() => parse_value(res, "completion list"),
+            _ => Ok(CompletionList::default()),
+        }
+    }
+
+    /// Parses a `workspace/symbol` result. A `null` result means no symbols.
+    pub fn handle_workspace_symbol(&self) -> Result<WorkspaceSymbolResponse> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => {
+                // Symbols all sent with a range are `SymbolInformation`, as
+                // the untagged enum would pick, without buffering the result.
+                #[cfg(feature = "lsp-3-17")]
+                if !res.as_array().is_some_and(|symbols| {
+                    symbols
+                        .iter()
+                        .all(|symbol| symbol["location"].get("range").is_some())
+                }) {
+                    return parse_value(res, "workspace symbols")
+                        .map(WorkspaceSymbolResponse::Workspace);
+                }
+                parse_value(res, "workspace symbols").map(WorkspaceSymbolResponse::Flat)
+            }
+            _ => Ok(WorkspaceSymbolResponse::Flat(Vec::new())),
+        }
+    }
+
+    /// Parses a `textDocument/semanticTokens/full` result. `None` if the
+    /// server has no tokens for the document.
+    #[cfg(feature = "lsp-3-16")]
+    pub fn handle_semantic_tokens(&self) -> Result<Option<SemanticTokens>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) if !res.is_null() => parse_value(res, "semantic tokens").map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    #[cfg(feature = "lsp-3-17")]
+    pub fn handle_workspace_symbol_resolve(&self) -> Result<WorkspaceSymbol> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => parse_value(res, "resolved workspace symbol"),
+            None => bail!("No workspace symbol found."),
+        }
+    }
+
+    #[cfg(feature = "lsp-3-17")]
+    pub fn handle_document_diagnostic(&self) -> Result<DocumentDiagnosticReport> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => parse_value(res, "diagnostic report"),
+            None => bail!("No diagnostic report found."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_initialize_message() {
         let process_id = std::process::id();
         let expected_init_json = json!({
             "jsonrpc": "2.0",
@@ -335,9 +2228,13 @@ mod tests {
                             "dynamicRegistration": true
                         },
                         "workspaceEdit": {
-                            "documentChanges": true
+                            "documentChanges": true,
+                            "changeAnnotationSupport": { "groupsOnLabel": false }
                         },
-                        "configuration": true
+                        "configuration": true,
+                        "symbol": {
+                            "resolveSupport": { "properties": ["location.range"] }
+                        }
                     },
                     "textDocument": {
                         "hover": {
@@ -346,7 +2243,8 @@ mod tests {
                         "completion": {
                             "completionItem": {
                                 "snippetSupport": true // Set to false if your client does not support snippets
-                            }
+                            },
+                            "contextSupport": true
                         },
                         "codeAction": {
                             "codeActionLiteralSupport": {
@@ -354,7 +2252,18 @@ mod tests {
                                     "valueSet": ["source.organizeImports", "refactor.rewrite", "refactor.extract"]
                                 }
                             }
+                        },
+                        "signatureHelp": {
+                            "signatureInformation": {
+                                "documentationFormat": ["plaintext"],
+                                "parameterInformation": { "labelOffsetSupport": true },
+                                "activeParameterSupport": true
+                            },
+                            "contextSupport": true
                         }
+                    },
+                    "window": {
+                        "workDoneProgress": true
                     }
                 },
                 "workspaceFolders": [{
@@ -376,6 +2285,32 @@ mod tests {
             }],
         );
 
+        #[cfg(not(feature = "lsp-3-17"))]
+        let expected_init_json = {
+            let mut expected = expected_init_json;
+            expected["params"]["capabilities"]["workspace"]
+                .as_object_mut()
+                .unwrap()
+                .remove("symbol");
+            expected
+        };
+        #[cfg(feature = "proposed")]
+        let expected_init_json = {
+            let mut expected = expected_init_json;
+            expected["params"]["capabilities"]["textDocument"]["inlineCompletion"] =
+                json!({ "dynamicRegistration": false });
+            expected
+        };
+        #[cfg(not(feature = "lsp-3-16"))]
+        let expected_init_json = {
+            let mut expected = expected_init_json;
+            expected["params"]["capabilities"]["workspace"]["workspaceEdit"]
+                .as_object_mut()
+                .unwrap()
+                .remove("changeAnnotationSupport");
+            expected
+        };
+
         // Check that the JSON serialization is correct
         let init_params_json = serde_json::to_value(init_params).unwrap();
         assert_eq!(expected_init_json, init_params_json);
@@ -394,6 +2329,446 @@ mod tests {
         assert_eq!(expected_initialized_json, initialized_notification_json);
     }
 
+    #[test]
+    fn test_diagnostic_round_trip() {
+        let diagnostic_json = json!({
+            "range": {
+                "start": { "line": 3, "character": 1 },
+                "end": { "line": 3, "character": 5 }
+            },
+            "severity": 1,
+            "code": "E0425",
+            "codeDescription": {
+                "href": "https://doc.rust-lang.org/error_codes/E0425.html"
+            },
+            "source": "rustc",
+            "message": "cannot find value `x` in this scope",
+            "relatedInformation": [{
+                "location": {
+                    "uri": "file://path/to/code/main.rs",
+                    "range": {
+                        "start": { "line": 1, "character": 4 },
+                        "end": { "line": 1, "character": 5 }
+                    }
+                },
+                "message": "a local variable with a similar name exists"
+            }],
+            "data": { "rendered": "error[E0425]", "fixes": [1, 2] }
+        });
+
+        let diagnostic: Diagnostic = serde_json::from_value(diagnostic_json.clone()).unwrap();
+        assert_eq!(
+            diagnostic.code_description.as_ref().unwrap().href,
+            "https://doc.rust-lang.org/error_codes/E0425.html"
+        );
+        assert_eq!(diagnostic.related_information.as_ref().unwrap().len(), 1);
+        assert_eq!(serde_json::to_value(diagnostic).unwrap(), diagnostic_json);
+    }
+
+    #[test]
+    fn test_handle_definition_shapes() {
+        let location = json!({
+            "uri": "file://path/to/code/main.go",
+            "range": {
+                "start": { "line": 1, "character": 2 },
+                "end": { "line": 1, "character": 6 }
+            }
+        });
+        let response = |result: serde_json::Value| ResponseMessage {
+            base_message: BaseMessage::new(),
+            id: Some(json!(1)),
+            result: Some(result),
+            error: None,
+        };
+
+        let single = response(location.clone()).handle_definition().unwrap();
+        let many = response(json!([location.clone(), location]))
+            .handle_definition()
+            .unwrap();
+        assert_eq!(single.len(), 1);
+        assert_eq!(many.len(), 2);
+        assert_eq!(single[0], many[1]);
+        assert!(response(json!({ "uri": 1 })).handle_definition().is_err());
+
+        let link = json!({
+            "originSelectionRange": {
+                "start": { "line": 9, "character": 0 },
+                "end": { "line": 9, "character": 4 }
+            },
+            "targetUri": "file://path/to/code/main.go",
+            "targetRange": {
+                "start": { "line": 0, "character": 0 },
+                "end": { "line": 3, "character": 1 }
+            },
+            "targetSelectionRange": {
+                "start": { "line": 1, "character": 2 },
+                "end": { "line": 1, "character": 6 }
+            }
+        });
+        let links = response(json!([link])).handle_definition().unwrap();
+        assert_eq!(links, single);
+
+        let expected = Location::new(
+            "file://path/to/code/main.go",
+            Range::new(Position::new(1, 2), Position::new(1, 6)),
+        );
+        assert_eq!(single[0], expected);
+        assert_eq!(single[0].uri(), "file://path/to/code/main.go");
+        assert_eq!(single[0].range().start().character(), 2);
+        assert_eq!(single[0].range().end(), Position::new(1, 6));
+    }
+
+    #[test]
+    fn test_parse_error_names_the_path() {
+        let result = json!([{
+            "uri": "file:///main.go",
+            "range": {
+                "start": { "line": "one", "character": 2 },
+                "end": { "line": 1, "character": 6 }
+            }
+        }]);
+        let response = ResponseMessage {
+            base_message: BaseMessage::new(),
+            id: Some(json!(1)),
+            result: Some(result),
+            error: None,
+        };
+
+        let error = response.handle_definition().unwrap_err().to_string();
+        assert!(error.starts_with(
+            "Failed to parse definition locations at `[0].range.start.line`: invalid type"
+        ));
+        assert_eq!(
+            error.contains("file:///main.go"),
+            cfg!(feature = "error-snippets")
+        );
+    }
+
+    #[test]
+    fn test_range_geometry() {
+        let range = |start: (u32, u32), end: (u32, u32)| {
+            Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+        };
+        let outer = range((1, 0), (3, 4));
+        let inner = range((2, 2), (2, 8));
+        let after = range((3, 4), (5, 0));
+
+        assert!(Position::new(1, 9) < Position::new(2, 0));
+        assert!(outer.contains(Position::new(3, 4)));
+        assert!(!outer.contains(Position::new(0, 9)));
+        assert!(outer.overlaps(&inner));
+        assert!(!outer.overlaps(&after));
+        assert_eq!(outer.intersection(&inner), Some(inner));
+        assert!(outer.intersection(&after).unwrap().is_empty());
+        assert_eq!(inner.intersection(&after), None);
+        assert_eq!(inner.union(&after), range((2, 2), (5, 0)));
+    }
+
+    #[test]
+    #[cfg(feature = "lsp-3-17")]
+    fn test_workspace_symbol_resolve() {
+        let response = |result: serde_json::Value| ResponseMessage {
+            base_message: BaseMessage::new(),
+            id: Some(json!(1)),
+            result: Some(result),
+            error: None,
+        };
+        let symbols = response(json!([{
+            "name": "main",
+            "kind": 12,
+            "location": { "uri": "file:///main.go" },
+            "data": { "id": 42 }
+        }]))
+        .handle_workspace_symbol()
+        .unwrap();
+        let WorkspaceSymbolResponse::Workspace(symbols) = symbols else {
+            panic!("expected workspace symbols, got {:?}", symbols);
+        };
+        assert!(symbols[0].needs_resolve());
+        assert_eq!(symbols[0].uri(), "file:///main.go");
+
+        let request = RequestMessage::new_workspace_symbol_resolve(2, &symbols[0]).unwrap();
+        assert_eq!(request.method, "workspaceSymbol/resolve");
+        assert_eq!(request.params["data"], json!({ "id": 42 }));
+
+        let resolved = response(json!({
+            "name": "main",
+            "kind": 12,
+            "location": {
+                "uri": "file:///main.go",
+                "range": {
+                    "start": { "line": 2, "character": 5 },
+                    "end": { "line": 2, "character": 9 }
+                }
+            }
+        }))
+        .handle_workspace_symbol_resolve()
+        .unwrap();
+        assert!(!resolved.needs_resolve());
+        assert_eq!(
+            resolved.location().unwrap().range().start(),
+            Position::new(2, 5)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "lsp-3-17")]
+    fn test_symbol_and_token_shapes() {
+        let response = |result: serde_json::Value| ResponseMessage {
+            base_message: BaseMessage::new(),
+            id: Some(json!(1)),
+            result: Some(result),
+            error: None,
+        };
+        let range = json!({
+            "start": { "line": 2, "character": 5 },
+            "end": { "line": 2, "character": 9 }
+        });
+        let information = json!({
+            "name": "main",
+            "kind": 12,
+            "location": { "uri": "file:///main.go", "range": range }
+        });
+
+        let symbols = response(json!([information]))
+            .handle_workspace_symbol()
+            .unwrap();
+        assert!(matches!(symbols, WorkspaceSymbolResponse::Flat(symbols) if symbols.len() == 1));
+
+        let outline = response(json!([information]))
+            .handle_document_symbol()
+            .unwrap();
+        assert!(matches!(outline, DocumentSymbolResponse::Flat(symbols) if symbols.len() == 1));
+        let outline = response(json!([{
+            "name": "main",
+            "kind": 12,
+            "range": range,
+            "selectionRange": range
+        }]))
+        .handle_document_symbol()
+        .unwrap();
+        assert!(matches!(outline, DocumentSymbolResponse::Nested(symbols) if symbols.len() == 1));
+
+        let tokens = response(json!({ "resultId": "1", "data": [2, 5, 4, 0, 0] }))
+            .handle_semantic_tokens()
+            .unwrap()
+            .unwrap();
+        assert_eq!(tokens.result_id.as_deref(), Some("1"));
+        assert_eq!(tokens.data, [2, 5, 4, 0, 0]);
+        assert_eq!(
+            response(json!(null)).handle_semantic_tokens().unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "proposed")]
+    fn test_inline_completion() {
+        let request = RequestMessage::new_inline_completion(
+            4,
+            "file:///main.go".to_string(),
+            Position::new(3, 8),
+            InlineCompletionContext {
+                trigger_kind: InlineCompletionTriggerKind::Automatic,
+                selected_completion_info: None,
+            },
+        );
+        assert_eq!(
+            request.params,
+            json!({
+                "textDocument": { "uri": "file:///main.go" },
+                "position": { "line": 3, "character": 8 },
+                "context": { "triggerKind": 2 }
+            })
+        );
+
+        let response = |result: serde_json::Value| ResponseMessage {
+            base_message: BaseMessage::new(),
+            id: Some(json!(4)),
+            result: Some(result),
+            error: None,
+        };
+        let list = response(json!({ "items": [{ "insertText": "fmt.Println()" }] }))
+            .handle_inline_completion()
+            .unwrap();
+        assert_eq!(
+            list[0].insert_text,
+            InlineCompletionText::Plain("fmt.Println()".to_string())
+        );
+        let items = response(json!([{
+            "insertText": { "kind": "snippet", "value": "fmt.Println($0)" },
+            "range": {
+                "start": { "line": 3, "character": 4 },
+                "end": { "line": 3, "character": 8 }
+            }
+        }]))
+        .handle_inline_completion()
+        .unwrap();
+        assert!(matches!(
+            items[0].insert_text,
+            InlineCompletionText::Snippet { .. }
+        ));
+        assert!(response(serde_json::Value::Null)
+            .handle_inline_completion()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_handle_completion_shapes() {
+        let response = |result: serde_json::Value| ResponseMessage {
+            base_message: BaseMessage::new(),
+            id: Some(json!(1)),
+            result: Some(result),
+            error: None,
+        };
+
+        let items = response(json!([{ "label": "Println" }]))
+            .handle_completion()
+            .unwrap();
+        assert!(!items.is_incomplete);
+        assert_eq!(items.items[0].label, "Println");
+
+        let list = response(json!({ "isIncomplete": true, "items": [] }))
+            .handle_completion()
+            .unwrap();
+        assert!(list.is_incomplete);
+        assert_eq!(
+            response(serde_json::Value::Null)
+                .handle_completion()
+                .unwrap(),
+            CompletionList::default()
+        );
+    }
+
+    #[test]
+    fn test_known_methods_are_interned() {
+        let known: NotificationMessage = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{}}"#,
+        )
+        .unwrap();
+        let custom: NotificationMessage =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","method":"gopls/custom","params":{}}"#)
+                .unwrap();
+
+        assert!(matches!(known.method, Cow::Borrowed(_)));
+        assert!(matches!(custom.method, Cow::Owned(_)));
+        assert_eq!(custom.method, "gopls/custom");
+    }
+
+    #[test]
+    fn test_cancel_notifications() {
+        let cancel = NotificationMessage::new_cancel_request("req-7");
+        assert_eq!(cancel.method, "$/cancelRequest");
+        assert_eq!(cancel.params, json!({ "id": "req-7" }));
+
+        let cancel = NotificationMessage::new_work_done_progress_cancel(3);
+        assert_eq!(cancel.method, "window/workDoneProgress/cancel");
+        assert_eq!(cancel.params, json!({ "token": 3 }));
+    }
+
+    #[test]
+    fn test_progress_token() {
+        let tokens: Vec<ProgressToken> = serde_json::from_value(json!([7, "index"])).unwrap();
+        assert_eq!(
+            tokens,
+            [ProgressToken::from(7), ProgressToken::from("index")]
+        );
+        assert_eq!(serde_json::to_value(&tokens).unwrap(), json!([7, "index"]));
+
+        let generated: std::collections::HashSet<_> =
+            (0..3).map(|_| ProgressToken::generate()).collect();
+        assert_eq!(generated.len(), 3);
+        assert!(generated.iter().all(|token| !tokens.contains(token)));
+    }
+
+    #[test]
+    fn test_request_builder() {
+        let request = RequestMessage::builder()
+            .id(1)
+            .method("textDocument/hover")
+            .params(TextDocumentContentChangeEvent {
+                range: None,
+                text: "hover".to_string(),
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(request).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "notification": 0,
+                "method": "textDocument/hover",
+                "params": { "text": "hover" }
+            })
+        );
+
+        let extended = RequestMessage::builder()
+            .id(2)
+            .method("textDocument/hover")
+            .params(json!({ "textDocument": { "uri": "file:///main.c" } }))
+            .extend_params(json!({ "textDocument": { "version": 4 } }))
+            .extend_params(json!({ "workDoneToken": "t" }))
+            .build()
+            .unwrap();
+        assert_eq!(
+            extended.params,
+            json!({
+                "textDocument": { "uri": "file:///main.c", "version": 4 },
+                "workDoneToken": "t"
+            })
+        );
+        assert!(RequestMessage::builder()
+            .id(2)
+            .method("custom")
+            .params([1])
+            .extend_params(json!({ "extra": true }))
+            .build()
+            .is_err());
+
+        let shutdown = RequestMessage::builder().id("s").method("shutdown").build();
+        assert!(shutdown.unwrap().params.is_null());
+
+        assert!(RequestMessage::builder().id(1).method(" ").build().is_err());
+        assert!(RequestMessage::builder()
+            .method("shutdown")
+            .build()
+            .is_err());
+        assert!(RequestMessage::builder()
+            .id(1)
+            .method("textDocument/hover")
+            .params(42)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_extend_params_is_validated() {
+        let positional = RequestMessage::builder()
+            .id(3)
+            .method("custom")
+            .params([1])
+            .build()
+            .unwrap();
+        let error = positional
+            .extend_params(json!({ "extra": true }))
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Request params are an array"));
+
+        let hover = RequestMessage::new_hover(4, "file:///main.c".to_string(), Position::new(0, 0));
+        assert!(hover.clone().extend_params(json!([1])).is_err());
+        let hover = hover
+            .extend_params(json!({ "workDoneToken": "t" }))
+            .unwrap();
+        assert_eq!(hover.params["workDoneToken"], "t");
+        assert_eq!(hover.params["textDocument"]["uri"], "file:///main.c");
+
+        assert!(NotificationMessage::new_exit()
+            .extend_params(json!("extra"))
+            .is_err());
+    }
+
     #[test]
     fn test_get_definition() {
         let expected_get_definition_json = json!({
@@ -423,5 +2798,12 @@ mod tests {
 
         let get_definition_json = serde_json::to_value(get_definition).unwrap();
         assert_eq!(expected_get_definition_json, get_definition_json);
+
+        let params: TextDocumentPositionParams =
+            serde_json::from_value(get_definition_json["params"].clone()).unwrap();
+        assert_eq!(
+            params,
+            TextDocumentPositionParams::new("file://path/to/code/main.go", Position::new(1, 2))
+        );
     }
 }
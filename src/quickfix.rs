@@ -0,0 +1,163 @@
+//! Locations and diagnostics as Vim quickfix lists: the dictionaries
+//! `setqflist()` takes, serialized as JSON, or classic lines matching the
+//! default `errorformat`, `%f:%l:%c: %t%*[^:]: %m`.
+//!
+//! Columns are the LSP character offsets plus one. Vim counts bytes, so they
+//! only line up on ASCII lines.
+
+use crate::protocol::{Diagnostic, DiagnosticSeverity, Location, Range};
+use crate::workspace::uri_to_path;
+use serde::{Deserialize, Serialize};
+
+/// An entry of a quickfix list, as taken by `setqflist()`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct QuickfixItem {
+    pub filename: String,
+    /// 1-based.
+    pub lnum: u32,
+    /// 1-based.
+    pub col: u32,
+    pub end_lnum: u32,
+    pub end_col: u32,
+    pub text: String,
+    /// `E`, `W`, `I` or `N`, or empty for plain locations.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The diagnostic's code, if it is a number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nr: Option<i64>,
+}
+
+impl QuickfixItem {
+    fn new(uri: &str, range: Range, text: String) -> Self {
+        let filename = match uri_to_path(uri) {
+            Some(path) => path.display().to_string(),
+            None => uri.to_string(),
+        };
+        QuickfixItem {
+            filename,
+            lnum: range.start().line() + 1,
+            col: range.start().character() + 1,
+            end_lnum: range.end().line() + 1,
+            end_col: range.end().character() + 1,
+            text,
+            kind: String::new(),
+            nr: None,
+        }
+    }
+
+    /// The item for `location`, e.g. a reference. Without the source at hand
+    /// the text is left empty.
+    pub fn from_location(location: &Location) -> Self {
+        Self::new(location.uri(), location.range(), String::new())
+    }
+
+    /// The item for a diagnostic published for `uri`.
+    pub fn from_diagnostic(uri: &str, diagnostic: &Diagnostic) -> Self {
+        let mut text = diagnostic.message.replace('\n', " ");
+        if let Some(source) = &diagnostic.source {
+            text = format!("{}: {}", source, text);
+        }
+        let mut item = Self::new(uri, diagnostic.range, text);
+        item.kind = match diagnostic.severity {
+            Some(DiagnosticSeverity::Error) | None => "E",
+            Some(DiagnosticSeverity::Warning) => "W",
+            Some(DiagnosticSeverity::Information) => "I",
+            Some(DiagnosticSeverity::Hint) => "N",
+        }
+        .to_string();
+        item.nr = diagnostic.code.as_ref().and_then(|code| code.as_i64());
+        item
+    }
+
+    /// The item as a line of the default `errorformat`, e.g.
+    /// `src/main.rs:2:18: error: mismatched types`.
+    pub fn to_errorformat(&self) -> String {
+        let kind = match self.kind.as_str() {
+            "E" => "error: ",
+            "W" => "warning: ",
+            "I" => "info: ",
+            "N" => "note: ",
+            _ => "",
+        };
+        format!(
+            "{}:{}:{}: {}{}",
+            self.filename, self.lnum, self.col, kind, self.text
+        )
+    }
+}
+
+/// The quickfix list for `locations`, e.g. the result of a references request.
+pub fn locations_to_quickfix(locations: &[Location]) -> Vec<QuickfixItem> {
+    locations.iter().map(QuickfixItem::from_location).collect()
+}
+
+/// The quickfix list for the diagnostics of each document, sorted by file and
+/// position.
+pub fn diagnostics_to_quickfix<'a>(
+    diagnostics: impl IntoIterator<Item = (&'a str, &'a [Diagnostic])>,
+) -> Vec<QuickfixItem> {
+    let mut items: Vec<QuickfixItem> = diagnostics
+        .into_iter()
+        .flat_map(|(uri, diagnostics)| {
+            diagnostics
+                .iter()
+                .map(move |diagnostic| QuickfixItem::from_diagnostic(uri, diagnostic))
+        })
+        .collect();
+    items.sort_by(|a, b| (&a.filename, a.lnum, a.col).cmp(&(&b.filename, b.lnum, b.col)));
+    items
+}
+
+/// The list as JSON for `setqflist()`, e.g. `call setqflist(json_decode(...))`.
+pub fn to_quickfix_json(items: &[QuickfixItem]) -> serde_json::Result<String> {
+    serde_json::to_string(items)
+}
+
+/// The list as `errorformat` lines, one per item, for `:cfile` or `:cexpr`.
+pub fn to_errorformat(items: &[QuickfixItem]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_errorformat() + "\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Position;
+    use serde_json::json;
+
+    #[test]
+    fn test_quickfix_export() {
+        let diagnostic: Diagnostic = serde_json::from_value(json!({
+            "range": { "start": { "line": 1, "character": 17 }, "end": { "line": 1, "character": 22 } },
+            "severity": 2,
+            "code": 308,
+            "source": "rustc",
+            "message": "unused variable\nhelp: prefix it with an underscore",
+        }))
+        .unwrap();
+        let diagnostics = [diagnostic];
+        let items = diagnostics_to_quickfix([("file:///app/src/main.rs", &diagnostics[..])]);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&to_quickfix_json(&items).unwrap()).unwrap(),
+            json!([{
+                "filename": "/app/src/main.rs",
+                "lnum": 2, "col": 18, "end_lnum": 2, "end_col": 23,
+                "text": "rustc: unused variable help: prefix it with an underscore",
+                "type": "W", "nr": 308
+            }])
+        );
+        assert_eq!(
+            to_errorformat(&items),
+            "/app/src/main.rs:2:18: warning: rustc: unused variable help: prefix it with an underscore\n"
+        );
+
+        let references = locations_to_quickfix(&[Location::new(
+            "file:///app/src/lib.rs",
+            Range::new(Position::new(0, 3), Position::new(0, 6)),
+        )]);
+        assert_eq!(to_errorformat(&references), "/app/src/lib.rs:1:4: \n");
+    }
+}
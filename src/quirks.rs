@@ -0,0 +1,188 @@
+//! Per-server minimum versions and quirk flags, keyed by the name the server
+//! reports in `serverInfo`. The client checks the minimum version when the
+//! server answers `initialize`, and callers ask it for quirks to pick the
+//! shape of their requests.
+//!
+//! ```
+//! use lsp_client_rs::quirks::ServerQuirks;
+//! let quirks = ServerQuirks::new()
+//!     .minimum_version("gopls", "0.11.0")
+//!     .quirk_below("gopls", "0.14.0", "definition-returns-location");
+//! ```
+
+use crate::protocol::ServerInfo;
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+struct QuirkRule {
+    server: String,
+    /// The rule only applies to versions older than this one.
+    below: Option<String>,
+    quirk: String,
+}
+
+/// Minimum versions and quirks of the servers the client may talk to.
+///
+/// Servers that don't report a version, or report one that isn't made of
+/// numbers, such as `(devel)`, pass every minimum version and only get the
+/// quirks registered for all their versions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServerQuirks {
+    minimum_versions: HashMap<String, String>,
+    rules: Vec<QuirkRule>,
+}
+
+impl ServerQuirks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuses `server` older than `version`.
+    pub fn minimum_version(
+        mut self,
+        server: impl Into<String>,
+        version: impl Into<String>,
+    ) -> Self {
+        self.minimum_versions.insert(server.into(), version.into());
+        self
+    }
+
+    /// Flags every version of `server` with `quirk`.
+    pub fn quirk(mut self, server: impl Into<String>, quirk: impl Into<String>) -> Self {
+        self.rules.push(QuirkRule {
+            server: server.into(),
+            below: None,
+            quirk: quirk.into(),
+        });
+        self
+    }
+
+    /// Flags the versions of `server` older than `version` with `quirk`.
+    pub fn quirk_below(
+        mut self,
+        server: impl Into<String>,
+        version: impl Into<String>,
+        quirk: impl Into<String>,
+    ) -> Self {
+        self.rules.push(QuirkRule {
+            server: server.into(),
+            below: Some(version.into()),
+            quirk: quirk.into(),
+        });
+        self
+    }
+
+    /// Fails if `server` is older than its minimum version.
+    pub fn check(&self, server: &ServerInfo) -> Result<()> {
+        let (Some(minimum), Some(version)) =
+            (self.minimum_versions.get(&server.name), &server.version)
+        else {
+            return Ok(());
+        };
+        if compare_versions(version, minimum) == Some(Ordering::Less) {
+            bail!(
+                "{} {} is older than the minimum supported version {}",
+                server.name,
+                version,
+                minimum
+            );
+        }
+        Ok(())
+    }
+
+    /// The quirks of `server`, in the order they were registered.
+    pub fn quirks_of<'a>(&'a self, server: &'a ServerInfo) -> impl Iterator<Item = &'a str> {
+        self.rules
+            .iter()
+            .filter(move |rule| rule.server == server.name)
+            .filter(move |rule| match (&rule.below, &server.version) {
+                (None, _) => true,
+                (Some(below), Some(version)) => {
+                    compare_versions(version, below) == Some(Ordering::Less)
+                }
+                (Some(_), None) => false,
+            })
+            .map(|rule| rule.quirk.as_str())
+    }
+
+    pub fn has_quirk(&self, server: &ServerInfo, quirk: &str) -> bool {
+        self.quirks_of(server).any(|flag| flag == quirk)
+    }
+}
+
+/// Compares dotted version numbers, such as `v0.14.2-pre.1` and `0.14`.
+/// A leading `v` and everything from the first character that isn't a digit
+/// or a dot are ignored, and missing components count as 0. `None` if either
+/// version has no number at all.
+pub fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    let (a, b) = (version_numbers(a)?, version_numbers(b)?);
+    let len = a.len().max(b.len());
+    let component = |numbers: &[u64], i: usize| numbers.get(i).copied().unwrap_or(0);
+    Some(
+        (0..len)
+            .map(|i| component(&a, i).cmp(&component(&b, i)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal),
+    )
+}
+
+fn version_numbers(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim();
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let end = version
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(version.len());
+    let numbers: Vec<u64> = version[..end]
+        .split('.')
+        .map_while(|number| number.parse().ok())
+        .collect();
+    (!numbers.is_empty()).then_some(numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(name: &str, version: Option<&str>) -> ServerInfo {
+        ServerInfo {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_versions_and_quirks() {
+        assert_eq!(
+            compare_versions("v0.14.2-pre", "0.14"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare_versions("1.2", "1.2.0"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("0.9.1", "0.10"), Some(Ordering::Less));
+        assert_eq!(compare_versions("(devel)", "0.10"), None);
+
+        let quirks = ServerQuirks::new()
+            .minimum_version("gopls", "0.11.0")
+            .quirk_below("gopls", "0.14.0", "definition-returns-location")
+            .quirk("pyright", "no-snippets");
+
+        assert!(quirks.check(&server("gopls", Some("v0.10.1"))).is_err());
+        assert!(quirks.check(&server("gopls", Some("v0.11.0"))).is_ok());
+        assert!(quirks.check(&server("gopls", None)).is_ok());
+        assert!(quirks.check(&server("clangd", Some("1.0"))).is_ok());
+
+        let old = server("gopls", Some("v0.13.2"));
+        assert!(quirks.has_quirk(&old, "definition-returns-location"));
+        assert!(!quirks.has_quirk(
+            &server("gopls", Some("v0.14.0")),
+            "definition-returns-location"
+        ));
+        assert_eq!(
+            quirks
+                .quirks_of(&server("pyright", None))
+                .collect::<Vec<_>>(),
+            ["no-snippets"]
+        );
+    }
+}
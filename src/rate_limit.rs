@@ -0,0 +1,215 @@
+//! Limits on how often requests of a method are sent, to protect slow
+//! servers from editors that ask again on every keystroke or scroll. Off by
+//! default; see `LspClient::set_rate_limits`.
+//!
+//! ```no_run
+//! # fn example(client: lsp_client_rs::client::LspClient) {
+//! use lsp_client_rs::rate_limit::{Overflow, RateLimit, RateLimits};
+//! use std::time::Duration;
+//! // At most 2 semantic token requests a second for each document, the
+//! // others failing with `RateLimited`.
+//! let limit = RateLimit::new(2, Duration::from_secs(1))
+//!     .per_document()
+//!     .overflow(Overflow::Drop);
+//! client.set_rate_limits(Some(
+//!     RateLimits::new().limit("textDocument/semanticTokens/full", limit),
+//! ));
+//! # }
+//! ```
+
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// What happens to requests over the limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Hold them back until they are within the limit, in the order they
+    /// were made.
+    #[default]
+    Queue,
+    /// Fail them with `RateLimited`.
+    Drop,
+}
+
+/// At most `max` requests every `period`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    max: usize,
+    period: Duration,
+    per_document: bool,
+    overflow: Overflow,
+}
+
+impl RateLimit {
+    /// `max` requests every `period` in total, queueing the others. A `max`
+    /// of 0 is taken as 1.
+    pub fn new(max: usize, period: Duration) -> Self {
+        RateLimit {
+            max: max.max(1),
+            period,
+            per_document: false,
+            overflow: Overflow::Queue,
+        }
+    }
+
+    /// Applies the limit to each document separately.
+    pub fn per_document(mut self) -> Self {
+        self.per_document = true;
+        self
+    }
+
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+}
+
+/// The error of requests dropped for being over their rate limit.
+/// Returned inside `anyhow::Error`; use `downcast_ref` to tell it apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    pub method: String,
+    /// The document, if the limit applies to each document.
+    pub uri: Option<String>,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rate limit of {} exceeded", self.method)?;
+        match &self.uri {
+            Some(uri) => write!(f, " for {}", uri),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// The rate limits by method, and when the limited requests were sent.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    limits: HashMap<String, RateLimit>,
+    /// The last send times of each method, or method and document, up to
+    /// its `max`. Times in the future are reserved by queued requests.
+    sent: HashMap<(String, Option<String>), (Duration, VecDeque<Instant>)>,
+}
+
+impl RateLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the requests of `method`, replacing its limit if it had one.
+    pub fn limit(mut self, method: impl Into<String>, limit: RateLimit) -> Self {
+        self.limits.insert(method.into(), limit);
+        self
+    }
+
+    /// Reserves a time to send a `method` request for `uri` at, `now` or
+    /// later. Fails with `RateLimited` if it would be later and the limit
+    /// drops the requests over it.
+    pub(crate) fn reserve(
+        &mut self,
+        method: &str,
+        uri: Option<&str>,
+        now: Instant,
+    ) -> Result<Instant> {
+        let Some(limit) = self.limits.get(method) else {
+            return Ok(now);
+        };
+        let uri = uri.filter(|_| limit.per_document).map(str::to_string);
+        // Forget the windows that are over, so that one per document
+        // doesn't pile up.
+        self.sent
+            .retain(|_, (period, times)| times.back().is_some_and(|last| *last + *period > now));
+        let (_, times) = self
+            .sent
+            .entry((method.to_string(), uri.clone()))
+            .or_insert_with(|| (limit.period, VecDeque::new()));
+
+        let at = match times.front() {
+            Some(first) if times.len() >= limit.max => now.max(*first + limit.period),
+            _ => now,
+        };
+        if at > now && limit.overflow == Overflow::Drop {
+            return Err(RateLimited {
+                method: method.to_string(),
+                uri,
+            }
+            .into());
+        }
+        times.push_back(at);
+        if times.len() > limit.max {
+            times.pop_front();
+        }
+        Ok(at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limits() {
+        let second = Duration::from_secs(1);
+        let mut limits = RateLimits::new()
+            .limit("textDocument/hover", RateLimit::new(2, second))
+            .limit(
+                "textDocument/semanticTokens/full",
+                RateLimit::new(1, second)
+                    .per_document()
+                    .overflow(Overflow::Drop),
+            );
+        let now = Instant::now();
+
+        // Queued: the third waits for the first to leave the window, the
+        // fourth for the second.
+        let hover = |limits: &mut RateLimits| {
+            limits
+                .reserve("textDocument/hover", Some("file:///a.go"), now)
+                .unwrap()
+        };
+        assert_eq!(hover(&mut limits), now);
+        assert_eq!(hover(&mut limits), now);
+        assert_eq!(hover(&mut limits), now + second);
+        assert_eq!(hover(&mut limits), now + second);
+        assert_eq!(hover(&mut limits), now + 2 * second);
+
+        // Dropped, but only for the same document.
+        let tokens = "textDocument/semanticTokens/full";
+        assert_eq!(
+            limits.reserve(tokens, Some("file:///a.go"), now).unwrap(),
+            now
+        );
+        assert_eq!(
+            limits.reserve(tokens, Some("file:///b.go"), now).unwrap(),
+            now
+        );
+        let error = limits
+            .reserve(tokens, Some("file:///a.go"), now)
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<RateLimited>(),
+            Some(&RateLimited {
+                method: tokens.to_string(),
+                uri: Some("file:///a.go".to_string()),
+            })
+        );
+        let later = now + second;
+        assert_eq!(
+            limits.reserve(tokens, Some("file:///a.go"), later).unwrap(),
+            later
+        );
+
+        assert_eq!(
+            limits
+                .reserve("textDocument/definition", None, now)
+                .unwrap(),
+            now
+        );
+    }
+}
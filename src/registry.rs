@@ -0,0 +1,233 @@
+//! Which server to run for which language, as data: a registry loaded from a
+//! JSON or TOML file lets users pick their servers without code changes.
+//!
+//! ```toml
+//! [rust]
+//! command = "rust-analyzer"
+//! settings = { rust-analyzer = { checkOnSave = false } }
+//!
+//! [go]
+//! command = "gopls"
+//! args = ["-remote=auto"]
+//! env = { GOFLAGS = "-mod=mod" }
+//! initializationOptions = { usePlaceholders = true }
+//! ```
+
+use crate::launch::ServerCommand;
+use crate::protocol::{RequestMessage, WorkspaceFolder};
+use crate::settings::merge;
+use crate::supervisor::{Supervisor, SupervisorBuilder};
+use crate::variables::Variables;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// How to start and set up the server of one language.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerConfig {
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Sent as `initializationOptions` of the `initialize` request, with
+    /// variables such as `${workspaceFolder}` resolved. See `variables`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initialization_options: Option<serde_json::Value>,
+    /// Merged into the client capabilities of the `initialize` request, for
+    /// server specific extensions such as clangd's `offsetEncoding`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<serde_json::Value>,
+    /// Sent with `workspace/didChangeConfiguration` once the server is
+    /// initialized, with variables resolved like `initialization_options`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings: Option<serde_json::Value>,
+}
+
+impl ServerConfig {
+    pub fn new(command: impl Into<String>) -> Self {
+        ServerConfig {
+            command: command.into(),
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            initialization_options: None,
+            capabilities: None,
+            settings: None,
+        }
+    }
+
+    /// The command that starts the server.
+    pub fn server_command(&self) -> ServerCommand {
+        self.env.iter().fold(
+            ServerCommand::new(&self.command).args(&self.args),
+            |command, (key, value)| command.env(key, value),
+        )
+    }
+
+    /// The `initialize` request for a workspace rooted at `root_uri`,
+    /// carrying the configured `initializationOptions` and capabilities.
+    pub fn initialize_request(&self, root_uri: &str) -> RequestMessage {
+        let root_uri = root_uri.trim_end_matches('/');
+        let name = root_uri.rsplit('/').next().unwrap_or_default().to_string();
+        let mut request = RequestMessage::new_initialize(
+            1,
+            std::process::id(),
+            root_uri.to_string(),
+            env!("CARGO_PKG_NAME").to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+            vec![WorkspaceFolder {
+                uri: root_uri.to_string(),
+                name,
+            }],
+        );
+        if let (Some(options), Some(params)) =
+            (&self.initialization_options, request.params.as_object_mut())
+        {
+            let options = Variables::for_workspace(root_uri).substitute(options);
+            params.insert("initializationOptions".to_string(), options);
+        }
+        if let Some(capabilities) = &self.capabilities {
+            merge(&mut request.params["capabilities"], capabilities);
+        }
+        request
+    }
+
+    /// A supervisor builder that starts and initializes the server for a
+    /// workspace rooted at `root_uri`.
+    pub fn supervisor(&self, root_uri: &str) -> SupervisorBuilder {
+        let builder = Supervisor::builder(self.server_command())
+            .initialize(self.initialize_request(root_uri));
+        match &self.settings {
+            Some(settings) => {
+                builder.settings(Variables::for_workspace(root_uri).substitute(settings))
+            }
+            None => builder,
+        }
+    }
+}
+
+/// Server configurations by `languageId`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ServerRegistry {
+    servers: BTreeMap<String, ServerConfig>,
+}
+
+impl ServerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Invalid server registry")
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        toml::from_str(toml).context("Invalid server registry")
+    }
+
+    /// Reads a registry from a `.json` file, or a `.toml` file with the
+    /// `toml` feature.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let registry = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&contents),
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml(&contents),
+            _ => bail!("Unsupported server registry format: {}", path.display()),
+        };
+        registry.with_context(|| format!("Failed to load {}", path.display()))
+    }
+
+    /// Adds or replaces the server of `language_id`.
+    pub fn insert(&mut self, language_id: impl Into<String>, config: ServerConfig) {
+        self.servers.insert(language_id.into(), config);
+    }
+
+    pub fn get(&self, language_id: &str) -> Option<&ServerConfig> {
+        self.servers.get(language_id)
+    }
+
+    /// The configured languages and their servers, by `languageId`.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ServerConfig)> {
+        self.servers
+            .iter()
+            .map(|(language_id, config)| (language_id.as_str(), config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_registry_from_json() {
+        let registry = ServerRegistry::from_json(
+            r#"{
+                "rust": { "command": "rust-analyzer" },
+                "go": {
+                    "command": "gopls",
+                    "args": ["-remote=auto"],
+                    "env": { "GOFLAGS": "-mod=mod" },
+                    "initializationOptions": {
+                        "usePlaceholders": true,
+                        "cacheDir": "${workspaceFolder}/.cache"
+                    },
+                    "settings": { "gopls": { "staticcheck": true } }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            registry.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            vec!["go", "rust"]
+        );
+        assert_eq!(
+            registry.get("rust"),
+            Some(&ServerConfig::new("rust-analyzer"))
+        );
+
+        let gopls = registry.get("go").unwrap();
+        let command = gopls.server_command();
+        assert_eq!(command.program, "gopls");
+        assert_eq!(command.args, vec!["-remote=auto"]);
+        assert_eq!(
+            command.env,
+            vec![("GOFLAGS".to_string(), "-mod=mod".to_string())]
+        );
+        let initialize = gopls.initialize_request("file:///code/app/");
+        assert_eq!(initialize.params["rootUri"], "file:///code/app");
+        assert_eq!(
+            initialize.params["workspaceFolders"],
+            json!([{ "uri": "file:///code/app", "name": "app" }])
+        );
+        assert_eq!(
+            initialize.params["initializationOptions"],
+            json!({ "usePlaceholders": true, "cacheDir": "/code/app/.cache" })
+        );
+
+        assert!(ServerRegistry::from_json(r#"{ "go": { "args": [] } }"#).is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_registry_from_toml() {
+        let registry = ServerRegistry::from_toml(
+            r#"
+            [go]
+            command = "gopls"
+            initializationOptions = { usePlaceholders = true }
+            "#,
+        )
+        .unwrap();
+        let mut expected = ServerConfig::new("gopls");
+        expected.initialization_options = Some(json!({ "usePlaceholders": true }));
+        assert_eq!(registry.get("go"), Some(&expected));
+    }
+}
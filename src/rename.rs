@@ -0,0 +1,285 @@
+//! A preview of the workspace edit of a rename, for clients that let the user
+//! review the changes before applying them:
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::protocol::Position;
+//! let uri = "file:///src/main.rs".to_string();
+//! if let Some(edit) = client.rename(uri, Position::new(3, 7), "total".to_string()).await? {
+//!     for file in client.preview_workspace_edit(&edit).await?.files {
+//!         for line in &file.lines {
+//!             println!("{}:{}\n- {}\n+ {}", file.uri, line.line + 1, line.before, line.after);
+//!         }
+//!     }
+//!     client.apply_workspace_edit(&edit, |_, _| true).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::LspClient;
+use crate::documents::{apply_change, line_bounds};
+use crate::edits::{
+    to_content_changes, DocumentChange, ResourceOperation, TextEdit, WorkspaceEdit,
+};
+use crate::protocol::{Position, Range};
+use crate::workspace::uri_to_path;
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+
+/// The changes of a workspace edit, by file.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RenamePreview {
+    /// The files with text edits, in the order the server sent them.
+    pub files: Vec<FilePreview>,
+    /// The files the edit creates, renames or deletes.
+    pub operations: Vec<ResourceOperation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePreview {
+    pub uri: String,
+    /// The version the edits were computed for, if the server sent one.
+    pub version: Option<i32>,
+    /// The changed lines, in document order.
+    pub lines: Vec<LineChange>,
+}
+
+/// Lines before and after an edit. Edits on the same lines are shown together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineChange {
+    /// The first line of `before`, zero-based.
+    pub line: u32,
+    pub before: String,
+    pub after: String,
+}
+
+impl RenamePreview {
+    /// Previews `edit` against the current text of its files, which `texts`
+    /// holds by URI. Files missing from `texts` are previewed as empty.
+    pub fn new(edit: &WorkspaceEdit, texts: &HashMap<String, String>) -> Self {
+        let mut preview = RenamePreview::default();
+        for (uri, version, edits) in file_edits(edit, &mut preview.operations) {
+            let text = texts.get(uri).map_or("", String::as_str);
+            preview.files.push(FilePreview {
+                uri: uri.to_string(),
+                version,
+                lines: line_changes(text, edits),
+            });
+        }
+        preview
+    }
+}
+
+impl LspClient {
+    /// Previews `edit`, e.g. the result of `rename`, with the text of the open
+    /// documents, or of the files on disk for documents that aren't open.
+    pub async fn preview_workspace_edit(&self, edit: &WorkspaceEdit) -> Result<RenamePreview> {
+        let mut texts = HashMap::new();
+        let mut unopened = Vec::new();
+        {
+            let documents = self.documents();
+            for (uri, _, _) in file_edits(edit, &mut Vec::new()) {
+                match documents.get(uri) {
+                    Some(document) => {
+                        texts.insert(uri.to_string(), document.text.clone());
+                    }
+                    None => unopened.push(uri.to_string()),
+                }
+            }
+        }
+        for uri in unopened {
+            let path =
+                uri_to_path(&uri).ok_or_else(|| anyhow!("Can't read {}: not a file URI", uri))?;
+            match tokio::fs::read_to_string(&path).await {
+                Ok(text) => {
+                    texts.insert(uri, text);
+                }
+                // Created by the edit itself.
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(|| format!("Can't read {}", path.display())),
+            }
+        }
+        Ok(RenamePreview::new(edit, &texts))
+    }
+}
+
+/// The text edits of each file, preferring `documentChanges` as
+/// `WorkspaceEdit::content_changes` does. Resource operations are collected
+/// into `operations`.
+fn file_edits<'a>(
+    edit: &'a WorkspaceEdit,
+    operations: &mut Vec<ResourceOperation>,
+) -> Vec<(&'a str, Option<i32>, Vec<&'a TextEdit>)> {
+    let mut files = Vec::new();
+    if let Some(changes) = &edit.document_changes {
+        for change in changes {
+            match change {
+                DocumentChange::Edit(edit) => {
+                    let document = &edit.text_document;
+                    files.push((
+                        document.uri.as_str(),
+                        document.version,
+                        edit.edits.iter().collect(),
+                    ));
+                }
+                DocumentChange::Operation(operation) => operations.push(operation.clone()),
+            }
+        }
+    } else if let Some(changes) = &edit.changes {
+        for (uri, edits) in changes {
+            files.push((uri.as_str(), None, edits.iter().collect()));
+        }
+    }
+    files
+}
+
+/// Groups `edits` by the lines they touch and applies each group to its lines.
+fn line_changes(text: &str, mut edits: Vec<&TextEdit>) -> Vec<LineChange> {
+    edits.sort_by_key(|edit| edit.range.normalized().start);
+    let mut groups: Vec<(u32, u32, Vec<&TextEdit>)> = Vec::new();
+    for edit in edits {
+        let range = edit.range.normalized();
+        let (start, end) = (range.start.line, range.end.line);
+        match groups.last_mut() {
+            Some((_, last, group)) if start <= *last => {
+                *last = (*last).max(end);
+                group.push(edit);
+            }
+            _ => groups.push((start, end, vec![edit])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(first, last, edits)| {
+            let lines = match (line_bounds(text, first), line_bounds(text, last)) {
+                (Some(first), Some(last)) => first.start..last.end,
+                _ => text.len()..text.len(),
+            };
+            let before = text[lines].to_string();
+            let mut after = before.clone();
+            let rebase = |position: Position| {
+                Position::new(position.line.saturating_sub(first), position.character)
+            };
+            for mut change in to_content_changes(edits) {
+                change.range = change
+                    .range
+                    .map(|range| Range::new(rebase(range.start), rebase(range.end)));
+                apply_change(&mut after, &change);
+            }
+            LineChange {
+                line: first,
+                before,
+                after,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_rename_preview() {
+        let edit: WorkspaceEdit = serde_json::from_value(json!({
+            "documentChanges": [
+                {
+                    "textDocument": { "uri": "file:///main.go", "version": 2 },
+                    "edits": [
+                        {
+                            "range": {
+                                "start": { "line": 2, "character": 12 },
+                                "end": { "line": 2, "character": 15 }
+                            },
+                            "newText": "total"
+                        },
+                        {
+                            "range": {
+                                "start": { "line": 2, "character": 2 },
+                                "end": { "line": 2, "character": 5 }
+                            },
+                            "newText": "total"
+                        },
+                        {
+                            "range": {
+                                "start": { "line": 0, "character": 4 },
+                                "end": { "line": 0, "character": 7 }
+                            },
+                            "newText": "total"
+                        }
+                    ]
+                },
+                { "kind": "rename", "oldUri": "file:///sum.go", "newUri": "file:///total.go" }
+            ]
+        }))
+        .unwrap();
+        let texts = HashMap::from([(
+            "file:///main.go".to_string(),
+            "var sum int\nfunc add(n int) {\n  sum = n + sum\n}\n".to_string(),
+        )]);
+
+        let preview = RenamePreview::new(&edit, &texts);
+        assert_eq!(preview.operations.len(), 1);
+        assert_eq!(preview.files.len(), 1);
+        let file = &preview.files[0];
+        assert_eq!(file.version, Some(2));
+        assert_eq!(
+            file.lines,
+            [
+                LineChange {
+                    line: 0,
+                    before: "var sum int".to_string(),
+                    after: "var total int".to_string(),
+                },
+                LineChange {
+                    line: 2,
+                    before: "  sum = n + sum".to_string(),
+                    after: "  total = n + total".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inverted_range() {
+        // A server sending the end of a range before its start.
+        let edit: WorkspaceEdit = serde_json::from_value(json!({
+            "changes": {
+                "file:///main.go": [{
+                    "range": {
+                        "start": { "line": 1, "character": 2 },
+                        "end": { "line": 0, "character": 4 }
+                    },
+                    "newText": "x"
+                }]
+            }
+        }))
+        .unwrap();
+        let texts = HashMap::from([(
+            "file:///main.go".to_string(),
+            "var sum
+  int
+"
+            .to_string(),
+        )]);
+
+        let preview = RenamePreview::new(&edit, &texts);
+        assert_eq!(
+            preview.files[0].lines,
+            [LineChange {
+                line: 0,
+                before: "var sum\n  int".to_string(),
+                after: "var xint".to_string(),
+            }]
+        );
+        let changes = edit.content_changes(|_, _| true).unwrap();
+        assert_eq!(
+            changes[0].changes[0].range,
+            Some(Range::new(Position::new(0, 4), Position::new(1, 2)))
+        );
+    }
+}
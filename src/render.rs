@@ -0,0 +1,202 @@
+//! Diagnostics formatted for terminals, as annotated source snippets in the
+//! style of rustc, so command line tools can print them without another
+//! formatting dependency.
+//!
+//! ```text
+//! error[E0308]: mismatched types
+//!  --> src/main.rs:2:18
+//!   |
+//! 2 |     let x: u32 = "one";
+//!   |                  ^^^^^
+//!   = note: expected due to this: src/main.rs:2:12
+//! ```
+
+use crate::documents::{line_bounds, offset_at};
+use crate::protocol::{Diagnostic, DiagnosticSeverity, Position};
+use crate::workspace::uri_to_path;
+use std::fmt::Write;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const GUTTER: &str = "\x1b[1;34m";
+
+/// Formats diagnostics as annotated snippets of the document they belong to.
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticRenderer {
+    color: bool,
+}
+
+impl DiagnosticRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Colors the output with ANSI escape codes. Off by default.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Renders the diagnostics of the document at `path` with the contents
+    /// `text`, separated by blank lines.
+    pub fn render_all(&self, path: &str, text: &str, diagnostics: &[Diagnostic]) -> String {
+        diagnostics
+            .iter()
+            .map(|diagnostic| self.render(path, text, diagnostic))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Renders one diagnostic of the document at `path` with the contents
+    /// `text`. Only the first line of a multi-line range is shown.
+    pub fn render(&self, path: &str, text: &str, diagnostic: &Diagnostic) -> String {
+        let start = diagnostic.range.start();
+        let end = diagnostic.range.end();
+        let line_number = (start.line() + 1).to_string();
+        let pad = " ".repeat(line_number.len());
+        let (severity, color) = severity_style(diagnostic.severity);
+        let (line, start_column, end_column) = snippet(text, start, end);
+
+        let mut out = String::new();
+        let code = match &diagnostic.code {
+            Some(serde_json::Value::String(code)) => format!("[{}]", code),
+            Some(code) => format!("[{}]", code),
+            None => String::new(),
+        };
+        let _ = writeln!(
+            out,
+            "{}: {}",
+            self.paint(&format!("{}{}", severity, code), color),
+            self.paint(&diagnostic.message, BOLD),
+        );
+        let _ = writeln!(
+            out,
+            "{}{} {}:{}:{}",
+            pad,
+            self.paint("-->", GUTTER),
+            path,
+            start.line() + 1,
+            start_column + 1
+        );
+        let _ = writeln!(out, "{} {}", pad, self.paint("|", GUTTER));
+        let _ = writeln!(
+            out,
+            "{} {} {}",
+            self.paint(&line_number, GUTTER),
+            self.paint("|", GUTTER),
+            line
+        );
+        // Tabs are kept so the markers line up with the source above.
+        let indent: String = line
+            .chars()
+            .take(start_column)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let markers = "^".repeat(end_column.saturating_sub(start_column).max(1));
+        let _ = writeln!(
+            out,
+            "{} {} {}{}",
+            pad,
+            self.paint("|", GUTTER),
+            indent,
+            self.paint(&markers, color)
+        );
+        for related in diagnostic.related_information.iter().flatten() {
+            let location = &related.location;
+            let related_path = match uri_to_path(location.uri()) {
+                Some(path) => path.display().to_string(),
+                None => location.uri().to_string(),
+            };
+            let position = location.range().start();
+            let _ = writeln!(
+                out,
+                "{} {} {}: {}: {}:{}:{}",
+                pad,
+                self.paint("=", GUTTER),
+                self.paint("note", BOLD),
+                related.message,
+                related_path,
+                position.line() + 1,
+                position.character() + 1
+            );
+        }
+        out
+    }
+
+    fn paint(&self, text: &str, style: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+fn severity_style(severity: Option<DiagnosticSeverity>) -> (&'static str, &'static str) {
+    match severity {
+        Some(DiagnosticSeverity::Error) | None => ("error", "\x1b[1;31m"),
+        Some(DiagnosticSeverity::Warning) => ("warning", "\x1b[1;33m"),
+        Some(DiagnosticSeverity::Information) => ("info", "\x1b[1;36m"),
+        Some(DiagnosticSeverity::Hint) => ("hint", "\x1b[1;32m"),
+    }
+}
+
+/// The line `start` is on, and the columns in characters the range covers on
+/// it. A range ending on a later line covers the rest of the line.
+fn snippet(text: &str, start: Position, end: Position) -> (&str, usize, usize) {
+    let start_offset = offset_at(text, &start);
+    let bounds = line_bounds(text, start.line).unwrap_or(text.len()..text.len());
+    let line = &text[bounds.clone()];
+    let end_offset = offset_at(text, &end).clamp(start_offset, bounds.end);
+    let start_column = text[bounds.start..start_offset].chars().count();
+    let end_column = start_column + text[start_offset..end_offset].chars().count();
+    (line, start_column, end_column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{DiagnosticRelatedInformation, Location, Range};
+    use serde_json::json;
+
+    #[test]
+    fn test_render_diagnostic() {
+        let text = "fn main() {\n    let x: u32 = \"one\";\n}\n";
+        let range = |start: (u32, u32), end: (u32, u32)| {
+            Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+        };
+        let mut diagnostic: Diagnostic = serde_json::from_value(json!({
+            "range": { "start": { "line": 1, "character": 17 }, "end": { "line": 1, "character": 22 } },
+            "severity": 1,
+            "code": "E0308",
+            "message": "mismatched types",
+        }))
+        .unwrap();
+        diagnostic.related_information = Some(vec![DiagnosticRelatedInformation {
+            location: Location::new("file:///app/src/main.rs", range((1, 11), (1, 14))),
+            message: "expected due to this".to_string(),
+        }]);
+
+        let renderer = DiagnosticRenderer::new();
+        assert_eq!(
+            renderer.render("src/main.rs", text, &diagnostic),
+            "error[E0308]: mismatched types\n \
+             --> src/main.rs:2:18\n  \
+             |\n\
+             2 |     let x: u32 = \"one\";\n  \
+             |                  ^^^^^\n  \
+             = note: expected due to this: /app/src/main.rs:2:12\n"
+        );
+
+        diagnostic.severity = Some(DiagnosticSeverity::Warning);
+        diagnostic.code = None;
+        diagnostic.related_information = None;
+        diagnostic.range = range((0, 3), (2, 0));
+        let colored =
+            DiagnosticRenderer::new()
+                .color(true)
+                .render("src/main.rs", text, &diagnostic);
+        assert!(colored.starts_with("\x1b[1;33mwarning\x1b[0m: \x1b[1mmismatched types\x1b[0m\n"));
+        assert!(colored.contains("|\x1b[0m    \x1b[1;33m^^^^^^^^\x1b[0m\n"));
+    }
+}
@@ -0,0 +1,186 @@
+//! Replays a recorded session as a mock server: the messages the server sent
+//! are sent again, each once the client sent what preceded it in the
+//! recording. Sessions are recorded with `LspClient::set_session_log`.
+//!
+//! With `Timing::Original` or `Timing::Scaled` the recorded delays between
+//! messages are reproduced, so logic such as debouncing or cancellation can be
+//! tested against realistic traces. The delays are tokio sleeps, so tests that
+//! pause the clock with `tokio::time::pause` run them instantly and
+//! deterministically.
+
+use crate::client::LspClient;
+use crate::session_log::{read_session, Direction, SessionEntry};
+use crate::transport::{FrameReader, FrameWriter};
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// How the delays between recorded messages are reproduced.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Timing {
+    /// Messages are sent as soon as they are due.
+    #[default]
+    Immediate,
+    /// Each message is sent as long after the previous one as it was recorded.
+    Original,
+    /// Like `Original`, with delays multiplied by the factor, e.g. `0.5` to
+    /// replay twice as fast.
+    Scaled(f64),
+}
+
+impl Timing {
+    fn delay(self, recorded: Duration) -> Duration {
+        match self {
+            Timing::Immediate => Duration::ZERO,
+            Timing::Original => recorded,
+            Timing::Scaled(factor) => recorded.mul_f64(factor.max(0.0)),
+        }
+    }
+}
+
+/// A recorded session, replayed from the server's side.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    entries: Vec<SessionEntry>,
+    timing: Timing,
+}
+
+impl Replay {
+    pub fn new(entries: Vec<SessionEntry>) -> Self {
+        Replay {
+            entries,
+            timing: Timing::default(),
+        }
+    }
+
+    /// Loads a session log written by `SessionLog`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let entries = read_session(std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Self::new(entries))
+    }
+
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Plays the server's side of the session over `stream`. Every message
+    /// the client sent in the recording is waited for, and fails the replay
+    /// if its method differs.
+    pub async fn serve<S: AsyncRead + AsyncWrite + Unpin>(&self, stream: S) -> Result<()> {
+        let (read_half, write_half) = tokio::io::split(stream);
+        let mut reader = FrameReader::new(read_half);
+        let mut writer = FrameWriter::new(write_half);
+        let mut previous: Option<u64> = None;
+        for entry in &self.entries {
+            match entry.direction {
+                Direction::Outgoing => {
+                    let body = reader.read_frame().await.with_context(|| {
+                        format!("Client stopped before sending {}", entry.message)
+                    })?;
+                    let message: serde_json::Value = serde_json::from_slice(&body)?;
+                    if message.get("method") != entry.message.get("method") {
+                        bail!(
+                            "Expected {}, but the client sent {}",
+                            entry.message,
+                            message
+                        );
+                    }
+                }
+                Direction::Incoming => {
+                    let recorded =
+                        previous.map_or(0, |previous| entry.timestamp_ms.saturating_sub(previous));
+                    let delay = self.timing.delay(Duration::from_millis(recorded));
+                    if !delay.is_zero() {
+                        tokio::time::sleep(delay).await;
+                    }
+                    writer
+                        .write_frame(&serde_json::to_vec(&entry.message)?)
+                        .await?;
+                }
+            }
+            previous = Some(entry.timestamp_ms);
+        }
+        Ok(())
+    }
+
+    /// Starts replaying in the background and returns a client connected to
+    /// the replay. Must be called from within a tokio runtime.
+    pub fn connect(self) -> LspClient {
+        let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move { self.serve(server_end).await });
+        LspClient::from_stream(client_end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::RequestMessage;
+    use serde_json::json;
+
+    fn entry(direction: Direction, timestamp_ms: u64, message: serde_json::Value) -> SessionEntry {
+        SessionEntry {
+            direction,
+            timestamp_ms,
+            latency_ms: None,
+            message,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replay_timing() {
+        let entries = vec![
+            entry(
+                Direction::Outgoing,
+                1_000,
+                json!({ "jsonrpc": "2.0", "id": 1, "method": "textDocument/hover" }),
+            ),
+            entry(
+                Direction::Incoming,
+                1_250,
+                json!({ "jsonrpc": "2.0", "id": 1, "result": null }),
+            ),
+        ];
+        let request = || {
+            RequestMessage::builder()
+                .id(1)
+                .method("textDocument/hover")
+                .build()
+                .unwrap()
+        };
+
+        for (timing, expected) in [
+            (Timing::Immediate, Duration::ZERO),
+            (Timing::Original, Duration::from_millis(250)),
+            (Timing::Scaled(2.0), Duration::from_millis(500)),
+        ] {
+            let client = Replay::new(entries.clone()).timing(timing).connect();
+            let started = tokio::time::Instant::now();
+            let response = client.request(request()).await.unwrap();
+            assert_eq!(response.id, Some(json!(1)));
+            assert_eq!(started.elapsed(), expected);
+        }
+
+        let replay = Replay::new(entries);
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let serve = tokio::spawn(async move { replay.serve(server_end).await });
+        client
+            .send_request(
+                RequestMessage::builder()
+                    .id(1)
+                    .method("shutdown")
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(serve.await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! Typed requests: each request the crate supports as a type implementing
+//! `Request`, and a method on `LspClient` that sends it and parses the result.
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::protocol::Position;
+//! let hover = client
+//!     .hover("file:///app/main.go".to_string(), Position::new(3, 7))
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::LspClient;
+use crate::code_actions::{CodeAction, Command};
+use crate::colors::{Color, ColorInformation, ColorPresentation};
+use crate::completion::{CompletionContext, CompletionList};
+use crate::edits::WorkspaceEdit;
+use crate::folding::FoldingRange;
+use crate::highlights::DocumentHighlight;
+use crate::methods;
+#[cfg(feature = "lsp-3-16")]
+use crate::protocol::SemanticTokens;
+use crate::protocol::{
+    Diagnostic, DocumentSymbolResponse, Location, OneOf, Position, Range, RequestMessage,
+    ResponseMessage, WorkspaceSymbolResponse,
+};
+#[cfg(feature = "lsp-3-17")]
+use crate::protocol::{DocumentDiagnosticReport, WorkspaceSymbol};
+#[cfg(feature = "proposed")]
+use crate::protocol::{InlineCompletionContext, InlineCompletionItem};
+use crate::signature_help::{SignatureHelp, SignatureHelpContext};
+use anyhow::{ensure, Result};
+
+/// A request and the type of its result.
+pub trait Request {
+    const METHOD: &'static str;
+    type Result;
+
+    /// Parses the result out of the response to the request.
+    fn parse(response: &ResponseMessage) -> Result<Self::Result>;
+}
+
+impl LspClient {
+    /// Sends `request`, which must be an `R` request, and parses its result.
+    pub async fn request_typed<R: Request>(&self, request: RequestMessage) -> Result<R::Result> {
+        ensure!(
+            request.method == R::METHOD,
+            "Expected a {} request, got {}",
+            R::METHOD,
+            request.method
+        );
+        R::parse(&self.request(request).await?)
+    }
+}
+
+/// Defines a `Request` type per entry, and the `LspClient` method sending it.
+/// The method builds its request with `$build`, given a fresh id as `$id`.
+/// The attributes, usually the doc comment, apply to both.
+macro_rules! requests {
+    ($(
+        $(#[$attr:meta])*
+        $request:ident => $constant:path, $parse:ident;
+        fn $name:ident($($arg:ident: $ty:ty),*) -> $result:ty = |$id:ident| $build:expr;
+    )*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Debug, Clone, Copy)]
+            pub struct $request;
+
+            $(#[$attr])*
+            impl Request for $request {
+                const METHOD: &'static str = $constant;
+                type Result = $result;
+
+                fn parse(response: &ResponseMessage) -> Result<$result> {
+                    response.$parse()
+                }
+            }
+
+            impl LspClient {
+                $(#[$attr])*
+                pub async fn $name(&self, $($arg: $ty),*) -> Result<$result> {
+                    let $id = self.next_request_id();
+                    let response = self.request_coalesced($build).await?;
+                    <$request as Request>::parse(&response.to_response()?)
+                }
+            }
+        )*
+    };
+}
+
+requests! {
+    /// `textDocument/definition`: where the symbol at `position` is defined.
+    DefinitionRequest => methods::TEXT_DOCUMENT_DEFINITION, handle_definition;
+    fn definition(uri: String, position: Position) -> Vec<Location> =
+        |id| RequestMessage::new_get_definition(id, uri, position);
+
+    /// `textDocument/hover`: the hover at `position`, if there is one.
+    HoverRequest => methods::TEXT_DOCUMENT_HOVER, handle_hover;
+    fn hover(uri: String, position: Position) -> Option<crate::hover::Hover> =
+        |id| RequestMessage::new_hover(id, uri, position);
+
+    /// `textDocument/signatureHelp`: the signatures of the call at
+    /// `position`, if it is in one.
+    SignatureHelpRequest => methods::TEXT_DOCUMENT_SIGNATURE_HELP, handle_signature_help;
+    fn signature_help(
+        uri: String,
+        position: Position,
+        context: Option<SignatureHelpContext>
+    ) -> Option<SignatureHelp> =
+        |id| RequestMessage::new_signature_help(id, uri, position, context);
+
+    /// `textDocument/completion`: the completions at `position`.
+    CompletionRequest => methods::TEXT_DOCUMENT_COMPLETION, handle_completion;
+    fn completion(
+        uri: String,
+        position: Position,
+        context: Option<CompletionContext>
+    ) -> CompletionList =
+        |id| RequestMessage::new_completion(id, uri, position, context);
+
+    /// `textDocument/inlineCompletion`: ghost-text suggestions at `position`.
+    #[cfg(feature = "proposed")]
+    InlineCompletionRequest => methods::TEXT_DOCUMENT_INLINE_COMPLETION, handle_inline_completion;
+    fn inline_completion(
+        uri: String,
+        position: Position,
+        context: InlineCompletionContext
+    ) -> Vec<InlineCompletionItem> =
+        |id| RequestMessage::new_inline_completion(id, uri, position, context);
+
+    /// `textDocument/linkedEditingRange`: the ranges edited together with
+    /// the one at `position`. See `LinkedEditingRanges::mirror_edits`.
+    #[cfg(feature = "lsp-3-16")]
+    LinkedEditingRangeRequest => methods::TEXT_DOCUMENT_LINKED_EDITING_RANGE, handle_linked_editing_range;
+    fn linked_editing_range(
+        uri: String,
+        position: Position
+    ) -> Option<crate::linked_editing::LinkedEditingRanges> =
+        |id| RequestMessage::new_linked_editing_range(id, uri, position);
+
+    /// `textDocument/documentHighlight`: the occurrences of the symbol at
+    /// `position`. See `highlights::merge_highlights`.
+    DocumentHighlightRequest => methods::TEXT_DOCUMENT_DOCUMENT_HIGHLIGHT, handle_document_highlight;
+    fn document_highlight(uri: String, position: Position) -> Vec<DocumentHighlight> =
+        |id| RequestMessage::new_document_highlight(id, uri, position);
+
+    /// `textDocument/foldingRange`: the ranges that can be folded, in no
+    /// particular order. See `folding::fold_tree`.
+    FoldingRangeRequest => methods::TEXT_DOCUMENT_FOLDING_RANGE, handle_folding_range;
+    fn folding_range(uri: String) -> Vec<FoldingRange> =
+        |id| RequestMessage::new_folding_range(id, uri);
+
+    /// `textDocument/documentColor`: the colors in the document.
+    DocumentColorRequest => methods::TEXT_DOCUMENT_DOCUMENT_COLOR, handle_document_color;
+    fn document_color(uri: String) -> Vec<ColorInformation> =
+        |id| RequestMessage::new_document_color(id, uri);
+
+    /// `textDocument/colorPresentation`: the ways of writing `color` at `range`.
+    ColorPresentationRequest => methods::TEXT_DOCUMENT_COLOR_PRESENTATION, handle_color_presentation;
+    fn color_presentation(uri: String, color: Color, range: Range) -> Vec<ColorPresentation> =
+        |id| RequestMessage::new_color_presentation(id, uri, color, range);
+
+    /// `textDocument/codeAction`: the commands and code actions available at
+    /// `range`. See `LspClient::apply_code_action`.
+    CodeActionRequest => methods::TEXT_DOCUMENT_CODE_ACTION, handle_code_action;
+    fn code_action(
+        uri: String,
+        range: Range,
+        diagnostics: Vec<Diagnostic>
+    ) -> Vec<OneOf<Command, CodeAction>> =
+        |id| RequestMessage::new_code_action(id, uri, range, diagnostics);
+
+    /// `codeAction/resolve`: `action` with its edit filled in.
+    #[cfg(feature = "lsp-3-16")]
+    CodeActionResolveRequest => methods::CODE_ACTION_RESOLVE, handle_code_action_resolve;
+    fn code_action_resolve(action: &CodeAction) -> CodeAction =
+        |id| RequestMessage::new_code_action_resolve(id, action)?;
+
+    /// `workspace/executeCommand`: runs `command` on the server. The server
+    /// usually makes its changes with `workspace/applyEdit` before answering.
+    ExecuteCommandRequest => methods::WORKSPACE_EXECUTE_COMMAND, handle_execute_command;
+    fn execute_command(command: &Command) -> Option<serde_json::Value> =
+        |id| RequestMessage::new_execute_command(id, command);
+
+    /// `textDocument/rename`: the edit renaming the symbol at `position` to
+    /// `new_name`. See `rename::RenamePreview`.
+    RenameRequest => methods::TEXT_DOCUMENT_RENAME, handle_rename;
+    fn rename(uri: String, position: Position, new_name: String) -> Option<WorkspaceEdit> =
+        |id| RequestMessage::new_rename(id, uri, position, new_name);
+
+    /// `textDocument/documentSymbol`: the outline of the document.
+    DocumentSymbolRequest => methods::TEXT_DOCUMENT_DOCUMENT_SYMBOL, handle_document_symbol;
+    fn document_symbol(uri: String) -> DocumentSymbolResponse =
+        |id| RequestMessage::new_document_symbol(id, uri);
+
+    /// `textDocument/diagnostic`: the diagnostics of the document, pulled.
+    #[cfg(feature = "lsp-3-17")]
+    DocumentDiagnosticRequest => methods::TEXT_DOCUMENT_DIAGNOSTIC, handle_document_diagnostic;
+    fn document_diagnostic(
+        uri: String,
+        previous_result_id: Option<String>
+    ) -> DocumentDiagnosticReport =
+        |id| RequestMessage::new_document_diagnostic(id, uri, previous_result_id);
+
+    /// `textDocument/semanticTokens/full`: the semantic tokens of the
+    /// document, if the server has any.
+    #[cfg(feature = "lsp-3-16")]
+    SemanticTokensFullRequest => methods::TEXT_DOCUMENT_SEMANTIC_TOKENS_FULL, handle_semantic_tokens;
+    fn semantic_tokens_full(uri: String) -> Option<SemanticTokens> =
+        |id| RequestMessage::new_semantic_tokens_full(id, uri);
+
+    /// `workspace/symbol`: the symbols matching `query`.
+    WorkspaceSymbolRequest => methods::WORKSPACE_SYMBOL, handle_workspace_symbol;
+    fn workspace_symbol(query: String) -> WorkspaceSymbolResponse =
+        |id| RequestMessage::new_workspace_symbol(id, query);
+
+    /// `workspaceSymbol/resolve`: `symbol` with its full location.
+    #[cfg(feature = "lsp-3-17")]
+    WorkspaceSymbolResolveRequest => methods::WORKSPACE_SYMBOL_RESOLVE, handle_workspace_symbol_resolve;
+    fn workspace_symbol_resolve(symbol: &WorkspaceSymbol) -> WorkspaceSymbol =
+        |id| RequestMessage::new_workspace_symbol_resolve(id, symbol)?;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{FrameReader, FrameWriter};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_typed_requests() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        let server = tokio::spawn(async move {
+            let (read_half, write_half) = tokio::io::split(server_end);
+            let mut reader = FrameReader::new(read_half);
+            let mut writer = FrameWriter::new(write_half);
+            let mut ids = Vec::new();
+            for result in [
+                json!({ "contents": { "kind": "plaintext", "value": "func main()" } }),
+                json!([{ "label": "main" }]),
+            ] {
+                let request: serde_json::Value =
+                    serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+                let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result });
+                writer
+                    .write_frame(response.to_string().as_bytes())
+                    .await
+                    .unwrap();
+                ids.push(request["id"].clone());
+            }
+            ids
+        });
+
+        let uri = "file:///app/main.go".to_string();
+        let hover = client
+            .hover(uri.clone(), Position::new(2, 5))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(hover.to_plaintext(), "func main()");
+        let completions = client
+            .completion(uri, Position::new(2, 5), None)
+            .await
+            .unwrap();
+        assert_eq!(completions.items[0].label, "main");
+
+        let ids = server.await.unwrap();
+        assert_ne!(ids[0], ids[1]);
+
+        let mismatched = RequestMessage::new_document_symbol(1, "file:///app/main.go".to_string());
+        assert!(client
+            .request_typed::<HoverRequest>(mismatched)
+            .await
+            .is_err());
+    }
+}
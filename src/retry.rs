@@ -0,0 +1,193 @@
+//! Retrying of idempotent requests that failed for reasons likely to go away
+//! on their own: transport timeouts, or the server answering `ContentModified`
+//! or `ServerCancelled`. Off by default; see `LspClient::set_retry_policy`.
+
+use crate::methods;
+use crate::protocol::{CONTENT_MODIFIED, SERVER_CANCELLED};
+use crate::streaming::RawResponse;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Requests that only query the server, so sending them twice is harmless.
+const IDEMPOTENT_METHODS: &[&str] = &[
+    methods::TEXT_DOCUMENT_DEFINITION,
+    methods::TEXT_DOCUMENT_DECLARATION,
+    methods::TEXT_DOCUMENT_TYPE_DEFINITION,
+    methods::TEXT_DOCUMENT_IMPLEMENTATION,
+    methods::TEXT_DOCUMENT_REFERENCES,
+    methods::TEXT_DOCUMENT_HOVER,
+    methods::TEXT_DOCUMENT_COMPLETION,
+    methods::TEXT_DOCUMENT_SIGNATURE_HELP,
+    methods::TEXT_DOCUMENT_DOCUMENT_SYMBOL,
+    methods::TEXT_DOCUMENT_DOCUMENT_HIGHLIGHT,
+    methods::TEXT_DOCUMENT_CODE_ACTION,
+    methods::TEXT_DOCUMENT_FORMATTING,
+    methods::TEXT_DOCUMENT_RENAME,
+    #[cfg(feature = "lsp-3-17")]
+    methods::TEXT_DOCUMENT_DIAGNOSTIC,
+    methods::WORKSPACE_SYMBOL,
+    #[cfg(feature = "lsp-3-17")]
+    methods::WORKSPACE_SYMBOL_RESOLVE,
+];
+
+/// Whether `method` only queries the server, so that sending it twice is
+/// harmless.
+pub(crate) fn is_idempotent(method: &str) -> bool {
+    IDEMPOTENT_METHODS.contains(&method)
+}
+
+/// How often and how patiently failed requests are retried.
+///
+/// Only idempotent query methods are retried by default. Use `method_attempts`
+/// to retry other methods, or to change the attempts of a single method.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    method_attempts: HashMap<String, u32>,
+    resync_on_content_modified: bool,
+    attempt_timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            method_attempts: HashMap::new(),
+            resync_on_content_modified: false,
+            attempt_timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many times idempotent requests are sent at most, including the
+    /// first attempt.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// The wait before the first retry, doubled for each further retry up to
+    /// `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Overrides the attempts for `method`. `1` disables retries for it.
+    pub fn method_attempts(mut self, method: impl Into<String>, attempts: u32) -> Self {
+        self.method_attempts.insert(method.into(), attempts);
+        self
+    }
+
+    /// Resends a request that failed with `ContentModified` as soon as the
+    /// next `didChange` went out, rather than after the backoff, which is
+    /// then only the longest wait. Document changes held back by the
+    /// debounce are sent right away.
+    pub fn resync_on_content_modified(mut self, resync: bool) -> Self {
+        self.resync_on_content_modified = resync;
+        self
+    }
+
+    pub fn resyncs_on_content_modified(&self) -> bool {
+        self.resync_on_content_modified
+    }
+
+    /// Gives up on an attempt the server didn't answer within `timeout`,
+    /// cancelling it, and retries it like other transient failures. By
+    /// default attempts wait as long as it takes.
+    pub fn attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.attempt_timeout = Some(timeout);
+        self
+    }
+
+    /// How long an attempt may wait for its response, if it is limited.
+    pub fn timeout_per_attempt(&self) -> Option<Duration> {
+        self.attempt_timeout
+    }
+
+    /// How many times a `method` request is sent at most.
+    pub fn attempts_for(&self, method: &str) -> u32 {
+        match self.method_attempts.get(method) {
+            Some(attempts) => *attempts,
+            None if IDEMPOTENT_METHODS.contains(&method) => self.max_attempts,
+            None => 1,
+        }
+    }
+
+    /// How long to wait after the `attempt`th attempt failed, counting from 1.
+    pub fn backoff_after(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Whether the outcome of a request is a transient failure. Of the
+    /// errors raised by the client itself only timeouts are: a closed
+    /// connection or a message that can't be serialized fails again.
+    pub fn is_transient(response: &Result<RawResponse>) -> bool {
+        match response {
+            Ok(response) => matches!(
+                response.error_code(),
+                Some(CONTENT_MODIFIED | SERVER_CANCELLED)
+            ),
+            Err(error) => error.chain().any(|cause| {
+                cause.is::<tokio::time::error::Elapsed>()
+                    || cause
+                        .downcast_ref::<std::io::Error>()
+                        .is_some_and(|error| error.kind() == std::io::ErrorKind::TimedOut)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_attempts_and_backoff() {
+        let policy = RetryPolicy::new()
+            .max_attempts(4)
+            .backoff(Duration::from_millis(100), Duration::from_millis(350))
+            .method_attempts(methods::TEXT_DOCUMENT_HOVER, 1)
+            .method_attempts("rust-analyzer/expandMacro", 2);
+
+        assert_eq!(policy.attempts_for(methods::TEXT_DOCUMENT_DEFINITION), 4);
+        assert_eq!(policy.attempts_for(methods::TEXT_DOCUMENT_HOVER), 1);
+        assert_eq!(policy.attempts_for("rust-analyzer/expandMacro"), 2);
+        assert_eq!(policy.attempts_for(methods::WORKSPACE_EXECUTE_COMMAND), 1);
+
+        let backoffs: Vec<u128> = (1..=4)
+            .map(|attempt| policy.backoff_after(attempt).as_millis())
+            .collect();
+        assert_eq!(backoffs, vec![100, 200, 350, 350]);
+
+        let response = |error| Ok(RawResponse::new(Some(json!(1)), error, Vec::new()));
+        assert!(RetryPolicy::is_transient(&response(Some(
+            json!({ "code": CONTENT_MODIFIED, "message": "modified" })
+        ))));
+        assert!(!RetryPolicy::is_transient(&response(Some(
+            json!({ "code": -32603, "message": "internal" })
+        ))));
+        assert!(!RetryPolicy::is_transient(&response(None)));
+        assert!(!RetryPolicy::is_transient(&Err(anyhow::anyhow!("closed"))));
+        let timed_out = std::io::Error::from(std::io::ErrorKind::TimedOut);
+        assert!(RetryPolicy::is_transient(&Err(anyhow::Error::from(
+            timed_out
+        )
+        .context("Failed to write"))));
+    }
+}
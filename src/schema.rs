@@ -0,0 +1,648 @@
+//! Validation of messages against JSON Schemas generated from the LSP
+//! metaModel, for debugging new method support or misbehaving servers.
+//! Enabled by the `schema-validation` feature.
+//!
+//! The metaModel is the machine readable form of the specification, published
+//! as `protocol/metaModel.json` in the vscode-languageserver-node repository.
+//! Load it with `Schemas::load` and pass the result to
+//! `LspClient::enable_schema_validation`.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A place where a message doesn't match its schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Where in the message, e.g. `params.textDocument.uri` or `result[2].range`.
+    pub path: String,
+    pub expected: String,
+    pub found: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, found {}",
+            self.path, self.expected, self.found
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct MethodSchemas {
+    params: Option<Value>,
+    result: Option<Value>,
+}
+
+/// The JSON Schemas of the params and results of every method in a metaModel.
+#[derive(Debug, Clone)]
+pub struct Schemas {
+    version: String,
+    /// A JSON Schema document whose `$defs` hold every structure,
+    /// enumeration and type alias of the metaModel.
+    document: Value,
+    methods: HashMap<String, MethodSchemas>,
+}
+
+impl Schemas {
+    /// Reads a `metaModel.json` file and generates the schemas from it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_meta_model(&serde_json::from_str(&text)?)
+    }
+
+    pub fn from_meta_model(meta_model: &Value) -> Result<Self> {
+        let list = |key: &str| -> &[Value] {
+            meta_model
+                .get(key)
+                .and_then(Value::as_array)
+                .map_or(&[], Vec::as_slice)
+        };
+
+        let mut defs = Map::new();
+        for structure in list("structures") {
+            let mut parts = Vec::new();
+            for key in ["extends", "mixins"] {
+                for parent in structure.get(key).and_then(Value::as_array).into_iter() {
+                    for parent in parent {
+                        parts.push(type_schema(parent)?);
+                    }
+                }
+            }
+            parts.push(object_schema(
+                structure.get("properties").unwrap_or(&Value::Null),
+            )?);
+            let schema = if parts.len() == 1 {
+                parts.pop().unwrap()
+            } else {
+                json!({ "allOf": parts })
+            };
+            defs.insert(name_of(structure)?.to_string(), schema);
+        }
+        for enumeration in list("enumerations") {
+            let base = type_schema(&enumeration["type"])?;
+            let schema = if enumeration["supportsCustomValues"] == true {
+                base
+            } else {
+                let values: Vec<Value> = enumeration["values"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|value| value["value"].clone())
+                    .collect();
+                json!({ "enum": values })
+            };
+            defs.insert(name_of(enumeration)?.to_string(), schema);
+        }
+        for alias in list("typeAliases") {
+            defs.insert(name_of(alias)?.to_string(), type_schema(&alias["type"])?);
+        }
+
+        let mut methods = HashMap::new();
+        for (key, has_result) in [("requests", true), ("notifications", false)] {
+            for message in list(key) {
+                let method = message["method"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Message without a method in the metaModel"))?;
+                let params = match message.get("params") {
+                    // Params given as a list of types are positional.
+                    Some(Value::Array(items)) => Some(json!({
+                        "type": "array",
+                        "prefixItems": items.iter().map(type_schema).collect::<Result<Vec<_>>>()?,
+                    })),
+                    Some(params) => Some(type_schema(params)?),
+                    None => None,
+                };
+                let result = match message.get("result") {
+                    Some(result) if has_result => Some(type_schema(result)?),
+                    _ => None,
+                };
+                methods.insert(method.to_string(), MethodSchemas { params, result });
+            }
+        }
+
+        let version = meta_model["metaData"]["version"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        Ok(Schemas {
+            version,
+            document: json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "$defs": defs,
+            }),
+            methods,
+        })
+    }
+
+    /// The protocol version of the metaModel, e.g. `3.17.0`.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// The generated JSON Schema document, with one `$defs` entry per type,
+    /// for use with other tools.
+    pub fn document(&self) -> &Value {
+        &self.document
+    }
+
+    /// Whether the metaModel describes `method`.
+    pub fn knows(&self, method: &str) -> bool {
+        self.methods.contains_key(method)
+    }
+
+    /// Checks the params of a `method` request or notification. Methods the
+    /// metaModel doesn't know, such as server specific extensions, always pass.
+    pub fn validate_params(&self, method: &str, params: &Value) -> Vec<Mismatch> {
+        match self.methods.get(method).and_then(|m| m.params.as_ref()) {
+            Some(schema) => self.validate(schema, params, "params"),
+            None => Vec::new(),
+        }
+    }
+
+    /// Checks the result of a `method` request.
+    pub fn validate_result(&self, method: &str, result: &Value) -> Vec<Mismatch> {
+        match self.methods.get(method).and_then(|m| m.result.as_ref()) {
+            Some(schema) => self.validate(schema, result, "result"),
+            None => Vec::new(),
+        }
+    }
+
+    fn validate(&self, schema: &Value, value: &Value, path: &str) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        self.check(schema, value, path, &mut mismatches);
+        mismatches
+    }
+
+    fn check(&self, schema: &Value, value: &Value, path: &str, out: &mut Vec<Mismatch>) {
+        let mismatch = |expected: String| Mismatch {
+            path: path.to_string(),
+            expected,
+            found: describe(value),
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference.trim_start_matches("#/$defs/");
+            if let Some(target) = self.document["$defs"].get(name) {
+                self.check(target, value, path, out);
+            }
+            return;
+        }
+        if let Some(parts) = schema.get("allOf").and_then(Value::as_array) {
+            for part in parts {
+                self.check(part, value, path, out);
+            }
+        }
+        if let Some(alternatives) = schema.get("anyOf").and_then(Value::as_array) {
+            self.check_alternatives(alternatives, value, path, out);
+        }
+        if let Some(constant) = schema.get("const") {
+            if value != constant {
+                out.push(mismatch(constant.to_string()));
+            }
+            return;
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            if !values.contains(value) {
+                let values: Vec<String> = values.iter().map(Value::to_string).collect();
+                out.push(mismatch(format!("one of {}", values.join(", "))));
+            }
+            return;
+        }
+        let Some(expected) = schema.get("type").and_then(Value::as_str) else {
+            return;
+        };
+        if !has_type(value, expected) {
+            out.push(mismatch(expected.to_string()));
+            return;
+        }
+
+        match value {
+            Value::Number(number) => {
+                let in_range = |bound: &str, ok: fn(f64, f64) -> bool| {
+                    schema
+                        .get(bound)
+                        .and_then(Value::as_f64)
+                        .is_none_or(|bound| ok(number.as_f64().unwrap_or_default(), bound))
+                };
+                if !in_range("minimum", |n, min| n >= min)
+                    || !in_range("maximum", |n, max| n <= max)
+                {
+                    let range = format!(
+                        "{} in [{}, {}]",
+                        expected, schema["minimum"], schema["maximum"]
+                    );
+                    out.push(mismatch(range));
+                }
+            }
+            Value::Object(object) => {
+                let properties = schema.get("properties").and_then(Value::as_object);
+                for required in schema
+                    .get("required")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                {
+                    if !object.contains_key(required) {
+                        out.push(Mismatch {
+                            path: format!("{}.{}", path, required),
+                            expected: "required property".to_string(),
+                            found: "nothing".to_string(),
+                        });
+                    }
+                }
+                for (key, item) in object {
+                    let item_path = format!("{}.{}", path, key);
+                    match properties.and_then(|properties| properties.get(key)) {
+                        Some(property) => self.check(property, item, &item_path, out),
+                        None => {
+                            if let Some(additional) = schema.get("additionalProperties") {
+                                self.check(additional, item, &item_path, out);
+                            }
+                        }
+                    }
+                }
+            }
+            Value::Array(items) => {
+                let prefix = schema.get("prefixItems").and_then(Value::as_array);
+                if let Some(prefix) = prefix {
+                    if items.len() != prefix.len() {
+                        out.push(mismatch(format!("{} items", prefix.len())));
+                    }
+                }
+                for (i, item) in items.iter().enumerate() {
+                    let item_schema = match prefix {
+                        Some(prefix) => prefix.get(i),
+                        None => schema.get("items"),
+                    };
+                    if let Some(item_schema) = item_schema {
+                        self.check(item_schema, item, &format!("{}[{}]", path, i), out);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Passes if any alternative matches. Otherwise reports the mismatches of
+    /// the alternative that got furthest into the value, which is usually the
+    /// one that was meant, or a single mismatch if none got past `path`.
+    fn check_alternatives(
+        &self,
+        alternatives: &[Value],
+        value: &Value,
+        path: &str,
+        out: &mut Vec<Mismatch>,
+    ) {
+        let mut failures = Vec::with_capacity(alternatives.len());
+        for alternative in alternatives {
+            let mismatches = self.validate(alternative, value, path);
+            if mismatches.is_empty() {
+                return;
+            }
+            failures.push(mismatches);
+        }
+        let depth = |mismatches: &Vec<Mismatch>| {
+            mismatches
+                .iter()
+                .map(|mismatch| mismatch.path.len())
+                .max()
+                .unwrap_or_default()
+        };
+        let deepest = failures.iter().map(depth).max().unwrap_or_default();
+        if deepest > path.len() {
+            let best = failures.into_iter().find(|m| depth(m) == deepest).unwrap();
+            out.extend(best);
+            return;
+        }
+        let mut expected: Vec<String> = Vec::new();
+        for mismatch in failures.into_iter().flatten() {
+            if !expected.contains(&mismatch.expected) {
+                expected.push(mismatch.expected);
+            }
+        }
+        out.push(Mismatch {
+            path: path.to_string(),
+            expected: expected.join(" or "),
+            found: describe(value),
+        });
+    }
+}
+
+fn name_of(definition: &Value) -> Result<&str> {
+    definition["name"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Definition without a name in the metaModel: {}", definition))
+}
+
+/// The schema of an object with the given metaModel properties.
+fn object_schema(properties: &Value) -> Result<Value> {
+    let mut schemas = Map::new();
+    let mut required = Vec::new();
+    for property in properties.as_array().into_iter().flatten() {
+        let name = name_of(property)?;
+        schemas.insert(name.to_string(), type_schema(&property["type"])?);
+        if property["optional"] != true {
+            required.push(name);
+        }
+    }
+    Ok(json!({ "type": "object", "properties": schemas, "required": required }))
+}
+
+/// Translates a metaModel type to JSON Schema.
+fn type_schema(ty: &Value) -> Result<Value> {
+    let items = || -> Result<Vec<Value>> {
+        ty["items"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(type_schema)
+            .collect()
+    };
+    Ok(match ty["kind"].as_str().unwrap_or_default() {
+        "base" => match ty["name"].as_str().unwrap_or_default() {
+            "string" => json!({ "type": "string" }),
+            "URI" | "DocumentUri" => json!({ "type": "string", "format": "uri" }),
+            "RegExp" => json!({ "type": "string", "format": "regex" }),
+            "integer" => json!({ "type": "integer", "minimum": i32::MIN, "maximum": i32::MAX }),
+            "uinteger" => json!({ "type": "integer", "minimum": 0, "maximum": i32::MAX }),
+            "decimal" => json!({ "type": "number" }),
+            "boolean" => json!({ "type": "boolean" }),
+            "null" => json!({ "type": "null" }),
+            name => bail!("Unknown base type {} in the metaModel", name),
+        },
+        "reference" => json!({ "$ref": format!("#/$defs/{}", name_of(ty)?) }),
+        "array" => json!({ "type": "array", "items": type_schema(&ty["element"])? }),
+        "map" => json!({ "type": "object", "additionalProperties": type_schema(&ty["value"])? }),
+        "and" => json!({ "allOf": items()? }),
+        "or" => json!({ "anyOf": items()? }),
+        "tuple" => json!({ "type": "array", "prefixItems": items()? }),
+        "literal" => object_schema(&ty["value"]["properties"])?,
+        "stringLiteral" | "integerLiteral" | "booleanLiteral" => json!({ "const": ty["value"] }),
+        kind => bail!("Unknown type kind {:?} in the metaModel", kind),
+    })
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        _ => true,
+    }
+}
+
+/// A short description of `value` for mismatch reports.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Object(_) => "object".to_string(),
+        Value::Array(_) => "array".to_string(),
+        Value::String(s) if s.chars().count() > 40 => {
+            format!("string {:?}...", s.chars().take(40).collect::<String>())
+        }
+        scalar => scalar.to_string(),
+    }
+}
+
+/// Which side sent a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the client to the server.
+    Outgoing,
+    /// From the server to the client.
+    Incoming,
+}
+
+/// Validates the messages of one connection. Responses are checked against
+/// the result schema of the request they answer, so requests are remembered
+/// until their response passes by.
+#[derive(Debug)]
+pub struct MessageValidator {
+    schemas: Arc<Schemas>,
+    outgoing_requests: HashMap<String, String>,
+    incoming_requests: HashMap<String, String>,
+}
+
+impl MessageValidator {
+    pub fn new(schemas: Arc<Schemas>) -> Self {
+        MessageValidator {
+            schemas,
+            outgoing_requests: HashMap::new(),
+            incoming_requests: HashMap::new(),
+        }
+    }
+
+    /// Checks a message sent in `direction`. Returns the method the message
+    /// belongs to, if known, and its mismatches.
+    pub fn check(
+        &mut self,
+        direction: Direction,
+        message: &Value,
+    ) -> (Option<String>, Vec<Mismatch>) {
+        let (requests, answered) = match direction {
+            Direction::Outgoing => (&mut self.outgoing_requests, &mut self.incoming_requests),
+            Direction::Incoming => (&mut self.incoming_requests, &mut self.outgoing_requests),
+        };
+        let id = message.get("id").map(Value::to_string);
+
+        if let Some(method) = message.get("method").and_then(Value::as_str) {
+            if let Some(id) = id {
+                requests.insert(id, method.to_string());
+            }
+            let params = message.get("params").unwrap_or(&Value::Null);
+            let mismatches = self.schemas.validate_params(method, params);
+            return (Some(method.to_string()), mismatches);
+        }
+
+        let Some(method) = id.and_then(|id| answered.remove(&id)) else {
+            return (None, Vec::new());
+        };
+        let mismatches = match message.get("result") {
+            Some(result) if message.get("error").is_none() => {
+                self.schemas.validate_result(&method, result)
+            }
+            _ => Vec::new(),
+        };
+        (Some(method), mismatches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A slice of the 3.17 metaModel, enough for `textDocument/hover`.
+    fn meta_model() -> Value {
+        let base = |name: &str| json!({ "kind": "base", "name": name });
+        let reference = |name: &str| json!({ "kind": "reference", "name": name });
+        let property = |name: &str, ty: Value| json!({ "name": name, "type": ty });
+        json!({
+            "metaData": { "version": "3.17.0" },
+            "requests": [{
+                "method": "textDocument/hover",
+                "messageDirection": "clientToServer",
+                "params": reference("HoverParams"),
+                "result": { "kind": "or", "items": [reference("Hover"), base("null")] },
+            }],
+            "notifications": [{
+                "method": "exit",
+                "messageDirection": "clientToServer",
+            }],
+            "structures": [
+                {
+                    "name": "HoverParams",
+                    "properties": [],
+                    "mixins": [reference("TextDocumentPositionParams")],
+                },
+                {
+                    "name": "TextDocumentPositionParams",
+                    "properties": [
+                        property("textDocument", reference("TextDocumentIdentifier")),
+                        property("position", reference("Position")),
+                    ],
+                },
+                {
+                    "name": "TextDocumentIdentifier",
+                    "properties": [property("uri", base("DocumentUri"))],
+                },
+                {
+                    "name": "Position",
+                    "properties": [
+                        property("line", base("uinteger")),
+                        property("character", base("uinteger")),
+                    ],
+                },
+                {
+                    "name": "Range",
+                    "properties": [
+                        property("start", reference("Position")),
+                        property("end", reference("Position")),
+                    ],
+                },
+                {
+                    "name": "Hover",
+                    "properties": [
+                        property("contents", reference("MarkupContent")),
+                        { "name": "range", "type": reference("Range"), "optional": true },
+                    ],
+                },
+                {
+                    "name": "MarkupContent",
+                    "properties": [
+                        property("kind", reference("MarkupKind")),
+                        property("value", base("string")),
+                    ],
+                },
+            ],
+            "enumerations": [{
+                "name": "MarkupKind",
+                "type": base("string"),
+                "values": [
+                    { "name": "PlainText", "value": "plaintext" },
+                    { "name": "Markdown", "value": "markdown" },
+                ],
+            }],
+            "typeAliases": [],
+        })
+    }
+
+    #[test]
+    fn test_params_mismatches_have_paths() {
+        let schemas = Schemas::from_meta_model(&meta_model()).unwrap();
+        assert_eq!(schemas.version(), "3.17.0");
+        assert!(schemas.knows("textDocument/hover"));
+        assert!(schemas.document()["$defs"]["Position"].is_object());
+
+        let valid = json!({
+            "textDocument": { "uri": "file:///main.rs" },
+            "position": { "line": 1, "character": 2 },
+        });
+        assert!(schemas
+            .validate_params("textDocument/hover", &valid)
+            .is_empty());
+
+        let invalid = json!({
+            "textDocument": {},
+            "position": { "line": -1, "character": "2" },
+        });
+        let mismatches: Vec<String> = schemas
+            .validate_params("textDocument/hover", &invalid)
+            .iter()
+            .map(Mismatch::to_string)
+            .collect();
+        assert_eq!(
+            mismatches,
+            vec![
+                "params.position.character: expected integer, found \"2\"",
+                "params.position.line: expected integer in [0, 2147483647], found -1",
+                "params.textDocument.uri: expected required property, found nothing",
+            ]
+        );
+
+        // Unknown methods aren't checked.
+        assert!(schemas
+            .validate_params("rust-analyzer/ssr", &json!(1))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_responses_are_checked_against_their_request() {
+        let schemas = Arc::new(Schemas::from_meta_model(&meta_model()).unwrap());
+        let mut validator = MessageValidator::new(schemas);
+
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "textDocument/hover",
+            "params": {
+                "textDocument": { "uri": "file:///main.rs" },
+                "position": { "line": 1, "character": 2 },
+            },
+        });
+        let (method, mismatches) = validator.check(Direction::Outgoing, &request);
+        assert_eq!(method.as_deref(), Some("textDocument/hover"));
+        assert!(mismatches.is_empty());
+
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": { "contents": { "kind": "html", "value": "" } },
+        });
+        let (method, mismatches) = validator.check(Direction::Incoming, &response);
+        assert_eq!(method.as_deref(), Some("textDocument/hover"));
+        assert_eq!(
+            mismatches,
+            vec![Mismatch {
+                path: "result.contents.kind".to_string(),
+                expected: "one of \"plaintext\", \"markdown\"".to_string(),
+                found: "\"html\"".to_string(),
+            }]
+        );
+
+        // A result that is neither a hover nor null.
+        validator.check(Direction::Outgoing, &request);
+        let (_, mismatches) =
+            validator.check(Direction::Incoming, &json!({ "id": 1, "result": [] }));
+        assert_eq!(
+            mismatches[0].to_string(),
+            "result: expected object or null, found array"
+        );
+
+        // Responses to requests that weren't seen pass.
+        let (method, mismatches) =
+            validator.check(Direction::Incoming, &json!({ "id": 2, "result": 1 }));
+        assert_eq!((method, mismatches), (None, Vec::new()));
+    }
+}
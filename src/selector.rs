@@ -0,0 +1,297 @@
+//! `DocumentSelector` matching: which documents a dynamic registration
+//! applies to, and which server of a `ServerPool` handles a document.
+//!
+//! Glob patterns follow the LSP specification:
+//! - `*` matches zero or more characters in a path segment
+//! - `?` matches one character in a path segment
+//! - `**` matches any number of path segments, including none
+//! - `{a,b}` matches either alternative, e.g. `**/*.{ts,js}`
+//! - `[a-z]` matches one character of a range, `[!0-9]` one outside it
+
+use crate::workspace::uri_to_path;
+use serde::{Deserialize, Serialize};
+
+/// Documents matching any of the filters.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct DocumentSelector(pub Vec<DocumentFilter>);
+
+impl DocumentSelector {
+    /// The documents of one language.
+    pub fn language(language_id: impl Into<String>) -> Self {
+        DocumentSelector(vec![DocumentFilter {
+            language: Some(language_id.into()),
+            ..DocumentFilter::default()
+        }])
+    }
+
+    pub fn matches(&self, uri: &str, language_id: &str) -> bool {
+        self.0.iter().any(|filter| filter.matches(uri, language_id))
+    }
+}
+
+/// Documents matching every field that is set.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DocumentFilter {
+    /// A language id, such as `typescript`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// A URI scheme, such as `file` or `untitled`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    /// Matched against the path of the document.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<GlobPattern>,
+}
+
+impl DocumentFilter {
+    pub fn matches(&self, uri: &str, language_id: &str) -> bool {
+        if self.language.as_deref().is_some_and(|l| l != language_id) {
+            return false;
+        }
+        let scheme = uri.split_once(':').map_or("", |(scheme, _)| scheme);
+        if self.scheme.as_deref().is_some_and(|s| s != scheme) {
+            return false;
+        }
+        match &self.pattern {
+            Some(pattern) => pattern.matches(&document_path(uri)),
+            None => true,
+        }
+    }
+}
+
+/// A glob, either on its own or relative to a base folder.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum GlobPattern {
+    Pattern(String),
+    Relative(RelativePattern),
+}
+
+impl GlobPattern {
+    /// Whether `path`, with `/` or `\` separators, matches.
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            GlobPattern::Pattern(pattern) => glob_matches(pattern, path),
+            GlobPattern::Relative(relative) => {
+                let base = document_path(relative.base_uri.uri());
+                let base = base.trim_end_matches(['/', '\\']);
+                path.strip_prefix(base)
+                    .and_then(|rest| rest.strip_prefix(['/', '\\']))
+                    .is_some_and(|rest| glob_matches(&relative.pattern, rest))
+            }
+        }
+    }
+}
+
+/// A glob matched against paths relative to `base_uri`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RelativePattern {
+    #[serde(rename = "baseUri")]
+    pub base_uri: BaseUri,
+    pub pattern: String,
+}
+
+/// A folder URI, or a workspace folder.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum BaseUri {
+    Uri(String),
+    WorkspaceFolder { uri: String, name: String },
+}
+
+impl BaseUri {
+    pub fn uri(&self) -> &str {
+        match self {
+            BaseUri::Uri(uri) | BaseUri::WorkspaceFolder { uri, .. } => uri,
+        }
+    }
+}
+
+/// The path of a `file:` URI, or what follows the scheme and authority of
+/// other URIs.
+fn document_path(uri: &str) -> String {
+    if let Some(path) = uri_to_path(uri) {
+        return path.to_string_lossy().into_owned();
+    }
+    let rest = uri.split_once(':').map_or(uri, |(_, rest)| rest);
+    match rest.strip_prefix("//") {
+        Some(authority_and_path) => authority_and_path
+            .find('/')
+            .map_or("", |slash| &authority_and_path[slash..])
+            .to_string(),
+        None => rest.to_string(),
+    }
+}
+
+/// Whether `path` matches the glob `pattern`. See the module documentation
+/// for the syntax.
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    let path = path.replace('\\', "/");
+    let path: Vec<Vec<char>> = path.split('/').map(|s| s.chars().collect()).collect();
+    expand_braces(pattern).iter().any(|pattern| {
+        let segments: Vec<Vec<char>> = pattern.split('/').map(|s| s.chars().collect()).collect();
+        match_segments(&segments, &path)
+    })
+}
+
+fn match_segments(pattern: &[Vec<char>], path: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((segment, rest)) if segment[..] == ['*', '*'] => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path)) => match_segment(segment, name) && match_segments(rest, path),
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| match_segment(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && match_segment(rest, &name[1..]),
+        Some(('[', rest)) => match parse_class(rest) {
+            Some((class, len)) => name
+                .split_first()
+                .is_some_and(|(c, name)| class.matches(*c) && match_segment(&rest[len..], name)),
+            // An unclosed bracket is a literal character.
+            None => name.first() == Some(&'[') && match_segment(rest, &name[1..]),
+        },
+        Some((c, rest)) => name.first() == Some(c) && match_segment(rest, &name[1..]),
+    }
+}
+
+struct CharClass {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        let within = self.ranges.iter().any(|&(low, high)| low <= c && c <= high);
+        within != self.negated
+    }
+}
+
+/// Parses the class following a `[`, returning it and the number of
+/// characters it spans, including the closing `]`.
+fn parse_class(pattern: &[char]) -> Option<(CharClass, usize)> {
+    let mut i = 0;
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let start = i;
+    loop {
+        let c = *pattern.get(i)?;
+        // A `]` first in the class is a literal one.
+        if c == ']' && i > start {
+            return Some((CharClass { negated, ranges }, i + 1));
+        }
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some('-'), Some(&high)) if high != ']' => {
+                ranges.push((c, high));
+                i += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Expands every `{a,b}` group into one pattern per alternative.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+    let mut depth = 0;
+    let mut alternatives = Vec::new();
+    let mut start = open + 1;
+    for (i, c) in pattern.char_indices().skip_while(|&(i, _)| i < open) {
+        match c {
+            '{' => depth += 1,
+            ',' if depth == 1 => {
+                alternatives.push(&pattern[start..i]);
+                start = i + 1;
+            }
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    alternatives.push(&pattern[start..i]);
+                    let (prefix, suffix) = (&pattern[..open], &pattern[i + 1..]);
+                    return alternatives
+                        .into_iter()
+                        .flat_map(|alternative| {
+                            expand_braces(&format!("{}{}{}", prefix, alternative, suffix))
+                        })
+                        .collect();
+                }
+            }
+            _ => {}
+        }
+    }
+    // An unclosed brace is a literal character.
+    vec![pattern.to_string()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_glob_matches() {
+        for (pattern, path, expected) in [
+            ("**/*.rs", "/app/src/main.rs", true),
+            ("**/*.rs", "main.rs", true),
+            ("*.rs", "/app/main.rs", false),
+            ("src/*.rs", "src/main.rs", true),
+            ("src/*.rs", "src/bin/main.rs", false),
+            ("src/**/*.rs", "src/main.rs", true),
+            ("src/**/*.rs", "src/bin/tool/main.rs", true),
+            ("**/*.{ts,js}", "/web/app.js", true),
+            ("**/*.{ts,js}", "/web/app.css", false),
+            ("**/{src,test}/**/*.{ts,tsx}", "/web/test/ui/app.tsx", true),
+            ("file?.txt", "file1.txt", true),
+            ("file?.txt", "file10.txt", false),
+            ("log[0-9].txt", "log7.txt", true),
+            ("log[!0-9].txt", "log7.txt", false),
+            ("log[!0-9].txt", "logx.txt", true),
+            ("[]].txt", "].txt", true),
+            ("a[b.txt", "a[b.txt", true),
+            ("**", "/any/thing", true),
+            ("**/*.rs", "C:\\app\\main.rs", true),
+        ] {
+            assert_eq!(
+                glob_matches(pattern, path),
+                expected,
+                "{} against {}",
+                pattern,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_document_selector() {
+        let selector: DocumentSelector = serde_json::from_value(json!([
+            { "language": "typescript", "scheme": "file" },
+            { "pattern": "**/package.json" },
+            { "pattern": { "baseUri": "file:///web", "pattern": "src/**/*.css" } }
+        ]))
+        .unwrap();
+
+        assert!(selector.matches("file:///web/index.ts", "typescript"));
+        assert!(!selector.matches("untitled:Untitled-1", "typescript"));
+        assert!(selector.matches("file:///web/package.json", "json"));
+        assert!(selector.matches("file:///web/src/ui/app.css", "css"));
+        assert!(!selector.matches("file:///other/src/app.css", "css"));
+        assert!(DocumentSelector::language("go").matches("file:///main.go", "go"));
+    }
+}
@@ -0,0 +1,193 @@
+//! A record of every message of a session, one JSON object per line, for
+//! tools that analyse sessions after the fact. Attach one with
+//! `LspClient::set_session_log`.
+//!
+//! Each line is a `SessionEntry`, and the fields are stable:
+//!
+//! ```json
+//! {"direction":"outgoing","timestamp_ms":1700000000123,"message":{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}}
+//! {"direction":"incoming","timestamp_ms":1700000000170,"latency_ms":47.2,"message":{"jsonrpc":"2.0","id":1,"result":{}}}
+//! ```
+//!
+//! - `direction`: `outgoing` for messages the client sent, `incoming` for
+//!   messages it received.
+//! - `timestamp_ms`: when the message was sent or received, in milliseconds
+//!   since the Unix epoch.
+//! - `latency_ms`: on responses only, the time since the request they answer,
+//!   in either direction, was logged. Absent if that request wasn't.
+//! - `message`: the JSON-RPC message as written on the wire.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Outgoing,
+    Incoming,
+}
+
+/// One line of a session log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionEntry {
+    pub direction: Direction,
+    pub timestamp_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    pub message: serde_json::Value,
+}
+
+/// Writes the messages of a session as JSON lines.
+pub struct SessionLog {
+    out: Box<dyn Write + Send>,
+    /// When the requests not answered yet were logged, by direction and the
+    /// JSON form of their id.
+    requests: HashMap<(Direction, String), Instant>,
+}
+
+impl SessionLog {
+    /// Writes to `out`. Each line is flushed as it is written, so wrap files
+    /// in a `BufWriter` only if losing the tail of a crashed session is fine.
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        SessionLog {
+            out: Box::new(out),
+            requests: HashMap::new(),
+        }
+    }
+
+    /// Writes to the file at `path`, replacing it.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self::new(file))
+    }
+
+    /// Logs the message `body`, sent or received as given by `direction`.
+    /// Bodies that aren't JSON are logged as strings.
+    pub fn record(&mut self, direction: Direction, body: &[u8]) -> Result<()> {
+        let now = Instant::now();
+        let message = serde_json::from_slice(body).unwrap_or_else(|_| {
+            serde_json::Value::String(String::from_utf8_lossy(body).into_owned())
+        });
+        let mut latency_ms = None;
+        if let Some(id) = message.get("id").filter(|id| !id.is_null()) {
+            let key = id.to_string();
+            if message.get("method").is_some() {
+                self.requests.insert((direction, key), now);
+            } else {
+                let request_direction = match direction {
+                    Direction::Outgoing => Direction::Incoming,
+                    Direction::Incoming => Direction::Outgoing,
+                };
+                latency_ms = self
+                    .requests
+                    .remove(&(request_direction, key))
+                    .map(|sent| now.duration_since(sent).as_secs_f64() * 1000.0);
+            }
+        }
+        let entry = SessionEntry {
+            direction,
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+            latency_ms,
+            message,
+        };
+        serde_json::to_writer(&mut self.out, &entry)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads the entries of a session log.
+pub fn read_session<R: BufRead>(reader: R) -> Result<Vec<SessionEntry>> {
+    let mut entries = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid session log entry on line {}", number + 1))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    /// A `Write` whose contents the test can read after handing it away.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_session_log() {
+        let buffer = SharedBuffer::default();
+        let mut log = SessionLog::new(buffer.clone());
+        let messages = [
+            (
+                Direction::Outgoing,
+                json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" }),
+            ),
+            (
+                Direction::Incoming,
+                json!({ "jsonrpc": "2.0", "id": "a", "method": "workspace/configuration" }),
+            ),
+            (
+                Direction::Incoming,
+                json!({ "jsonrpc": "2.0", "id": 1, "result": {} }),
+            ),
+            (
+                Direction::Outgoing,
+                json!({ "jsonrpc": "2.0", "id": "a", "result": [] }),
+            ),
+            (
+                Direction::Outgoing,
+                json!({ "jsonrpc": "2.0", "id": 2, "result": null }),
+            ),
+        ];
+        for (direction, message) in &messages {
+            log.record(*direction, message.to_string().as_bytes())
+                .unwrap();
+        }
+
+        let contents = buffer.0.lock().unwrap().clone();
+        let entries = read_session(contents.as_slice()).unwrap();
+        assert_eq!(entries.len(), messages.len());
+        for (entry, (direction, message)) in entries.iter().zip(&messages) {
+            assert_eq!(entry.direction, *direction);
+            assert_eq!(&entry.message, message);
+            assert!(entry.timestamp_ms > 0);
+        }
+        let latencies: Vec<bool> = entries
+            .iter()
+            .map(|entry| entry.latency_ms.is_some())
+            .collect();
+        assert_eq!(latencies, [false, false, true, true, false]);
+
+        let line: serde_json::Value =
+            serde_json::from_slice(contents.split(|&b| b == b'\n').next().unwrap()).unwrap();
+        assert_eq!(line["direction"], "outgoing");
+        assert!(line.get("latency_ms").is_none());
+    }
+}
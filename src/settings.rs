@@ -0,0 +1,218 @@
+//! One source of truth for the settings servers read: `SettingsStore` answers
+//! the `workspace/configuration` requests servers pull settings with, and
+//! pushes `workspace/didChangeConfiguration` when the application changes them.
+//! Attach a store to a client with `LspClient::set_settings_store`.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::sync::watch;
+
+/// Settings by dotted section, e.g. `rust-analyzer.checkOnSave`, with
+/// overrides for scope URIs such as a workspace folder.
+///
+/// The store is a cheap handle: clones share the same settings.
+#[derive(Clone)]
+pub struct SettingsStore {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    settings: Mutex<Settings>,
+    /// Bumped on every change, so attached clients can push the settings.
+    changes: watch::Sender<u64>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Settings {
+    global: Map<String, Value>,
+    /// Overrides by scope URI.
+    scoped: BTreeMap<String, Map<String, Value>>,
+}
+
+impl Default for SettingsStore {
+    fn default() -> Self {
+        SettingsStore {
+            inner: Arc::new(Inner {
+                settings: Mutex::new(Settings::default()),
+                changes: watch::channel(0).0,
+            }),
+        }
+    }
+}
+
+impl SettingsStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `section` for every scope, e.g. `set("gopls.staticcheck", true)`.
+    pub fn set(&self, section: &str, value: impl Into<Value>) {
+        insert(&mut self.settings().global, section, value.into());
+        self.changed();
+    }
+
+    /// Sets `section` for `scope_uri` and the documents below it, overriding
+    /// the value set for every scope.
+    pub fn set_scoped(&self, scope_uri: &str, section: &str, value: impl Into<Value>) {
+        let scope = scope_uri.trim_end_matches('/').to_string();
+        let mut settings = self.settings();
+        insert(
+            settings.scoped.entry(scope).or_default(),
+            section,
+            value.into(),
+        );
+        drop(settings);
+        self.changed();
+    }
+
+    /// The value of `section`, or of all settings if `None`, as seen from
+    /// `scope_uri`. `Null` if it isn't set.
+    pub fn get(&self, section: Option<&str>, scope_uri: Option<&str>) -> Value {
+        let settings = self.settings();
+        let mut merged = Value::Object(settings.global.clone());
+        if let Some(scope_uri) = scope_uri {
+            // Shorter scopes first, so the closest one wins.
+            for (scope, overrides) in &settings.scoped {
+                if is_within(scope_uri, scope) {
+                    merge(&mut merged, &Value::Object(overrides.clone()));
+                }
+            }
+        }
+        match section {
+            Some(section) => lookup(&merged, section).cloned().unwrap_or(Value::Null),
+            None => merged,
+        }
+    }
+
+    /// The result of a `workspace/configuration` request with `params`: one
+    /// value per requested item.
+    pub fn answer(&self, params: &Value) -> Value {
+        let items = params["items"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        items
+            .iter()
+            .map(|item| self.get(item["section"].as_str(), item["scopeUri"].as_str()))
+            .collect()
+    }
+
+    /// All settings, including the scoped ones, as read by `from_value`.
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(&*self.settings()).unwrap_or_default()
+    }
+
+    /// A store with the settings of `to_value`.
+    pub fn from_value(value: Value) -> Result<Self> {
+        let store = SettingsStore::new();
+        *store.settings() = serde_json::from_value(value)?;
+        Ok(store)
+    }
+
+    /// Receives a new value on every change.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<u64> {
+        self.inner.changes.subscribe()
+    }
+
+    fn changed(&self) {
+        self.inner.changes.send_modify(|version| *version += 1);
+    }
+
+    fn settings(&self) -> MutexGuard<'_, Settings> {
+        self.inner
+            .settings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Whether `uri` is `scope` or below it.
+fn is_within(uri: &str, scope: &str) -> bool {
+    match uri.strip_prefix(scope) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+fn insert(settings: &mut Map<String, Value>, section: &str, value: Value) {
+    let (parents, key) = match section.rsplit_once('.') {
+        Some((parents, key)) => (Some(parents), key),
+        None => (None, section),
+    };
+    let mut object = settings;
+    for parent in parents.into_iter().flat_map(|parents| parents.split('.')) {
+        let child = object
+            .entry(parent)
+            .or_insert_with(|| Value::Object(Map::new()));
+        if !child.is_object() {
+            *child = Value::Object(Map::new());
+        }
+        object = child.as_object_mut().unwrap();
+    }
+    object.insert(key.to_string(), value);
+}
+
+fn lookup<'a>(settings: &'a Value, section: &str) -> Option<&'a Value> {
+    section
+        .split('.')
+        .try_fold(settings, |value, key| value.get(key))
+}
+
+/// Merges `overrides` into `base`, replacing everything but objects.
+pub(crate) fn merge(base: &mut Value, overrides: &Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(base.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_settings_are_scoped() {
+        let store = SettingsStore::new();
+        store.set("gopls.staticcheck", true);
+        store.set("gopls.buildFlags", json!(["-tags=integration"]));
+        store.set_scoped("file:///code/legacy/", "gopls.staticcheck", false);
+
+        assert_eq!(store.get(Some("gopls.staticcheck"), None), json!(true));
+        assert_eq!(
+            store.get(
+                Some("gopls.staticcheck"),
+                Some("file:///code/legacy/main.go")
+            ),
+            json!(false)
+        );
+        assert_eq!(
+            store.get(
+                Some("gopls.staticcheck"),
+                Some("file:///code/legacy2/main.go")
+            ),
+            json!(true)
+        );
+        assert_eq!(store.get(Some("gopls.missing"), None), Value::Null);
+        assert_eq!(
+            store.get(Some("gopls"), Some("file:///code/legacy")),
+            json!({ "staticcheck": false, "buildFlags": ["-tags=integration"] })
+        );
+
+        let answer = store.answer(&json!({
+            "items": [
+                { "section": "gopls.buildFlags" },
+                { "scopeUri": "file:///code/legacy/main.go", "section": "gopls.staticcheck" },
+                { "section": "rust-analyzer" },
+            ]
+        }));
+        assert_eq!(answer, json!([["-tags=integration"], false, null]));
+    }
+}
@@ -0,0 +1,208 @@
+//! Signature help, and the context it is requested with. Editors ask for it
+//! again while it is showing, e.g. after each `,`, passing the help shown
+//! so the server can keep the signature the user picked:
+//!
+//! ```no_run
+//! # async fn example(client: lsp_client_rs::client::LspClient) -> anyhow::Result<()> {
+//! use lsp_client_rs::protocol::Position;
+//! use lsp_client_rs::signature_help::SignatureHelpContext;
+//! let uri = "file:///app/main.go".to_string();
+//! let shown = client
+//!     .signature_help(uri.clone(), Position::new(3, 12), Some(SignatureHelpContext::trigger_character("(")))
+//!     .await?;
+//! let context = SignatureHelpContext::trigger_character(",").retrigger(shown);
+//! client.signature_help(uri, Position::new(3, 15), Some(context)).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::hover::MarkupContent;
+use crate::protocol::OneOf;
+use serde::{Deserialize, Serialize};
+
+/// Result of a `textDocument/signatureHelp` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureHelp {
+    pub signatures: Vec<SignatureInformation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_signature: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_parameter: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureInformation {
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<OneOf<String, MarkupContent>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Vec<ParameterInformation>>,
+    /// Overrides `SignatureHelp::active_parameter` for this signature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_parameter: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParameterInformation {
+    /// A substring of the signature's label, or its start and end offsets
+    /// in UTF-16 code units.
+    pub label: OneOf<String, [u32; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<OneOf<String, MarkupContent>>,
+}
+
+impl SignatureHelp {
+    /// The signature to show: the active one, or the first.
+    pub fn active(&self) -> Option<&SignatureInformation> {
+        let index = self.active_signature.unwrap_or(0) as usize;
+        self.signatures.get(index).or(self.signatures.first())
+    }
+}
+
+impl SignatureInformation {
+    /// The part of the label naming parameter `index`, to highlight it.
+    pub fn parameter_label(&self, index: usize) -> Option<&str> {
+        match &self.parameters.as_ref()?.get(index)?.label {
+            OneOf::Left(label) => Some(label),
+            OneOf::Right([start, end]) => {
+                let offset = |utf16: u32| {
+                    let mut units = 0;
+                    self.label
+                        .char_indices()
+                        .find(|(_, c)| {
+                            let found = units >= utf16 as usize;
+                            units += c.len_utf16();
+                            found
+                        })
+                        .map_or(self.label.len(), |(i, _)| i)
+                };
+                self.label.get(offset(*start)..offset(*end))
+            }
+        }
+    }
+}
+
+/// How a signature help request was triggered.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum SignatureHelpTriggerKind {
+    /// Explicitly requested by the user.
+    Invoked = 1,
+    /// Requested on typing one of the server's trigger characters.
+    TriggerCharacter = 2,
+    /// Requested because the cursor moved or the document changed.
+    ContentChange = 3,
+}
+
+impl TryFrom<u8> for SignatureHelpTriggerKind {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            1 => Ok(SignatureHelpTriggerKind::Invoked),
+            2 => Ok(SignatureHelpTriggerKind::TriggerCharacter),
+            3 => Ok(SignatureHelpTriggerKind::ContentChange),
+            _ => Err(format!("Invalid signature help trigger kind: {}", value)),
+        }
+    }
+}
+
+impl From<SignatureHelpTriggerKind> for u8 {
+    fn from(kind: SignatureHelpTriggerKind) -> Self {
+        kind as u8
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureHelpContext {
+    pub trigger_kind: SignatureHelpTriggerKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_character: Option<String>,
+    /// Whether signature help was showing when this request was made.
+    pub is_retrigger: bool,
+    /// The help showing, with `active_signature` updated to the one the
+    /// user picked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_signature_help: Option<SignatureHelp>,
+}
+
+impl SignatureHelpContext {
+    pub fn invoked() -> Self {
+        Self::new(SignatureHelpTriggerKind::Invoked, None)
+    }
+
+    pub fn trigger_character(character: impl Into<String>) -> Self {
+        Self::new(
+            SignatureHelpTriggerKind::TriggerCharacter,
+            Some(character.into()),
+        )
+    }
+
+    pub fn content_change() -> Self {
+        Self::new(SignatureHelpTriggerKind::ContentChange, None)
+    }
+
+    /// Marks the request as made while `showing` is shown, if anything is.
+    pub fn retrigger(mut self, showing: Option<SignatureHelp>) -> Self {
+        self.is_retrigger = showing.is_some();
+        self.active_signature_help = showing;
+        self
+    }
+
+    fn new(trigger_kind: SignatureHelpTriggerKind, trigger_character: Option<String>) -> Self {
+        SignatureHelpContext {
+            trigger_kind,
+            trigger_character,
+            is_retrigger: false,
+            active_signature_help: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_signature_help() {
+        let help: SignatureHelp = serde_json::from_value(json!({
+            "signatures": [{
+                "label": "func Join(elems []string, sep string) string",
+                "parameters": [{ "label": [10, 24] }, { "label": "sep string" }]
+            }],
+            "activeSignature": 0,
+            "activeParameter": 1
+        }))
+        .unwrap();
+        let signature = help.active().unwrap();
+        assert_eq!(signature.parameter_label(0), Some("elems []string"));
+        assert_eq!(signature.parameter_label(1), Some("sep string"));
+        assert_eq!(signature.parameter_label(2), None);
+
+        let context = SignatureHelpContext::trigger_character(",").retrigger(Some(help));
+        assert_eq!(
+            serde_json::to_value(&context).unwrap(),
+            json!({
+                "triggerKind": 2,
+                "triggerCharacter": ",",
+                "isRetrigger": true,
+                "activeSignatureHelp": {
+                    "signatures": [{
+                        "label": "func Join(elems []string, sep string) string",
+                        "parameters": [{ "label": [10, 24] }, { "label": "sep string" }]
+                    }],
+                    "activeSignature": 0,
+                    "activeParameter": 1
+                }
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(SignatureHelpContext::invoked().retrigger(None)).unwrap(),
+            json!({ "triggerKind": 1, "isRetrigger": false })
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Saving the state of a session to disk and bringing it back after a
+//! restart: the `initialize` params, the workspace folders, the open
+//! documents and their versions, the registered capabilities and the
+//! settings. Restoring initializes the new server the same way and opens
+//! the documents again:
+//!
+//! ```no_run
+//! # async fn example(
+//! #     old: lsp_client_rs::client::LspClient,
+//! #     new: lsp_client_rs::client::LspClient,
+//! # ) -> anyhow::Result<()> {
+//! old.snapshot().save("/home/me/.cache/my-editor/session.json")?;
+//! // ... after the editor restarted:
+//! use lsp_client_rs::snapshot::Snapshot;
+//! new.restore(&Snapshot::load("/home/me/.cache/my-editor/session.json")?)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::LspClient;
+use crate::documents::TextDocument;
+use crate::methods;
+use crate::protocol::{
+    intern_method, BaseMessage, NotificationMessage, Registration, RequestMessage, WorkspaceFolder,
+};
+use crate::settings::SettingsStore;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    /// `None` if the session wasn't initialized.
+    pub initialize_params: Option<Value>,
+    pub workspace_folders: Vec<WorkspaceFolder>,
+    pub documents: Vec<TextDocument>,
+    pub registrations: Vec<Registration>,
+    /// The settings of the attached `SettingsStore`, if there is one.
+    pub settings: Option<Value>,
+}
+
+impl Snapshot {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_slice(&bytes)
+            .with_context(|| format!("Failed to parse snapshot {}", path.display()))
+    }
+
+    /// Writes the snapshot to `path`, replacing the file at once so that an
+    /// interrupted save leaves the previous snapshot.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&temporary, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write {}", temporary.display()))?;
+        std::fs::rename(&temporary, path)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+impl LspClient {
+    /// The current state of the session.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut documents: Vec<TextDocument> = self.documents().iter().cloned().collect();
+        documents.sort_by(|a, b| a.uri.cmp(&b.uri));
+        Snapshot {
+            initialize_params: self.initialize_params(),
+            workspace_folders: self.workspace_folders(),
+            documents,
+            registrations: self.registrations(),
+            settings: self.settings_store().map(|store| store.to_value()),
+        }
+    }
+
+    /// Brings a fresh client to the state of `snapshot`: attaches its
+    /// settings, initializes the server with the same params and the
+    /// current workspace folders, and opens its documents at their
+    /// versions. Documents open already are left as they are.
+    pub async fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        if let Some(settings) = &snapshot.settings {
+            self.set_settings_store(SettingsStore::from_value(settings.clone())?);
+        }
+        self.set_registrations(snapshot.registrations.clone());
+
+        if let Some(params) = &snapshot.initialize_params {
+            let mut params = params.clone();
+            if let Value::Object(object) = &mut params {
+                object.insert("processId".to_string(), std::process::id().into());
+                object.insert(
+                    "workspaceFolders".to_string(),
+                    serde_json::to_value(&snapshot.workspace_folders)?,
+                );
+            }
+            let initialize = RequestMessage {
+                base_message: BaseMessage::new(),
+                id: self.next_request_id().into(),
+                method: intern_method(methods::INITIALIZE),
+                notification: 0,
+                params,
+            };
+            let response = self.request(initialize).await?;
+            if response.error.is_some() {
+                bail!("Server failed to initialize: {:?}", response.error);
+            }
+            self.send_request(NotificationMessage::new_initialized())
+                .await?;
+        }
+
+        for document in &snapshot.documents {
+            self.reopen(document.clone()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::TextDocumentContentChangeEvent;
+    use crate::transport::{FrameReader, FrameWriter};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_snapshot_and_restore() {
+        let (client_end, _server_end) = tokio::io::duplex(1 << 16);
+        let old = LspClient::from_stream(client_end);
+        let uri = "file:///app/main.go".to_string();
+        old.did_open(uri.clone(), "go".to_string(), "package main".to_string())
+            .await
+            .unwrap();
+        old.did_change(
+            &uri,
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                text: "package app".to_string(),
+            }],
+        )
+        .await
+        .unwrap();
+        let settings = SettingsStore::new();
+        settings.set("gopls.staticcheck", true);
+        settings.set_scoped("file:///app", "gopls.staticcheck", false);
+        old.set_settings_store(settings);
+
+        let mut snapshot = old.snapshot();
+        snapshot.initialize_params = Some(json!({ "processId": 1, "capabilities": {} }));
+        snapshot.workspace_folders = vec![WorkspaceFolder {
+            uri: "file:///app".to_string(),
+            name: "app".to_string(),
+        }];
+        let path = std::env::temp_dir().join(format!(
+            "lsp-client-rs-snapshot-{}.json",
+            std::process::id()
+        ));
+        snapshot.save(&path).unwrap();
+        let snapshot = Snapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (client_end, server_end) = tokio::io::duplex(1 << 16);
+        let new = LspClient::from_stream(client_end);
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut reader = FrameReader::new(read_half);
+        let mut writer = FrameWriter::new(write_half);
+        let (restored, _) = tokio::join!(new.restore(&snapshot), async {
+            let initialize: Value =
+                serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+            assert_eq!(initialize["method"], "initialize");
+            assert_eq!(initialize["params"]["processId"], std::process::id());
+            assert_eq!(
+                initialize["params"]["workspaceFolders"][0]["uri"],
+                "file:///app"
+            );
+            let response = json!({ "jsonrpc": "2.0", "id": initialize["id"], "result": { "capabilities": {} } });
+            writer
+                .write_frame(response.to_string().as_bytes())
+                .await
+                .unwrap();
+        });
+        restored.unwrap();
+
+        let initialized: Value =
+            serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+        assert_eq!(initialized["method"], "initialized");
+        let open: Value = serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+        assert_eq!(open["method"], "textDocument/didOpen");
+        assert_eq!(open["params"]["textDocument"]["version"], 1);
+        assert_eq!(open["params"]["textDocument"]["text"], "package app");
+        assert_eq!(new.documents().version(&uri), Some(1));
+        assert_eq!(new.workspace_folders().len(), 1);
+        assert_eq!(
+            new.settings_store()
+                .unwrap()
+                .get(Some("gopls.staticcheck"), Some("file:///app/main.go")),
+            json!(false)
+        );
+    }
+}
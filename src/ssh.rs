@@ -0,0 +1,196 @@
+//! Running a server on another machine over SSH, talking to it through the
+//! stdio of the `ssh` process, with the paths of the remote checkout mapped
+//! to the local one:
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use lsp_client_rs::launch::{LaunchPolicy, ServerCommand};
+//! use lsp_client_rs::ssh::SshLauncher;
+//! let gopls = ServerCommand::new("gopls").current_dir("/srv/app");
+//! let launched = SshLauncher::new("me@devbox", gopls)
+//!     .map_paths("/home/me/app", "/srv/app")
+//!     .launch(&LaunchPolicy::default())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `ssh` must be able to log in without prompting, e.g. with an agent or a
+//! key, since its stdin carries the protocol.
+
+use crate::launch::{launch, LaunchError, LaunchPolicy, Launched, ServerCommand};
+use crate::uri_map::UriMap;
+use std::path::{Path, PathBuf};
+
+/// Starts a server on a remote host with `ssh`.
+#[derive(Debug, Clone)]
+pub struct SshLauncher {
+    destination: String,
+    ssh_program: String,
+    port: Option<u16>,
+    identity_file: Option<PathBuf>,
+    options: Vec<(String, String)>,
+    server: ServerCommand,
+    uri_map: UriMap,
+}
+
+impl SshLauncher {
+    /// Runs `server` on `destination`, a host or `user@host`. The program,
+    /// arguments, environment and directory of `server` are the remote ones;
+    /// its resource limits apply to the local `ssh` process.
+    pub fn new(destination: impl Into<String>, server: ServerCommand) -> Self {
+        SshLauncher {
+            destination: destination.into(),
+            ssh_program: "ssh".to_string(),
+            port: None,
+            identity_file: None,
+            options: Vec::new(),
+            server,
+            uri_map: UriMap::new(),
+        }
+    }
+
+    /// The SSH client to run, `ssh` by default.
+    pub fn ssh_program(mut self, program: impl Into<String>) -> Self {
+        self.ssh_program = program.into();
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn identity_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.identity_file = Some(path.into());
+        self
+    }
+
+    /// Passes `-o key=value`, e.g. `ConnectTimeout=5`.
+    pub fn option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.push((key.into(), value.into()));
+        self
+    }
+
+    /// Maps the local files under `local` to the remote ones under
+    /// `remote`. See `UriMap::map`.
+    pub fn map_paths(mut self, local: impl AsRef<Path>, remote: impl AsRef<Path>) -> Self {
+        self.uri_map = self.uri_map.map(local, remote);
+        self
+    }
+
+    /// The local command that runs the server remotely.
+    pub fn command(&self) -> ServerCommand {
+        let mut command = ServerCommand::new(&self.ssh_program)
+            // No terminal, so the stream stays 8-bit clean, and no prompts,
+            // which would read from the protocol.
+            .args(["-T", "-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            command = command.arg("-p").arg(port.to_string());
+        }
+        if let Some(identity_file) = &self.identity_file {
+            command = command.arg("-i").arg(identity_file.to_string_lossy());
+        }
+        for (key, value) in &self.options {
+            command = command.arg("-o").arg(format!("{}={}", key, value));
+        }
+        command.limits = self.server.limits.clone();
+        command
+            .arg(&self.destination)
+            .arg("--")
+            .arg(self.remote_command())
+    }
+
+    /// Starts the server and sets up the path mapping on its client.
+    pub async fn launch(&self, policy: &LaunchPolicy) -> Result<Launched, LaunchError> {
+        let launched = launch(&self.command(), policy).await?;
+        launched.client.set_uri_map(Some(self.uri_map.clone()));
+        Ok(launched)
+    }
+
+    /// The shell command run on the remote host.
+    fn remote_command(&self) -> String {
+        let server = &self.server;
+        let mut words = Vec::new();
+        if let Some(dir) = &server.current_dir {
+            words.extend([
+                "cd".to_string(),
+                quote(&dir.to_string_lossy()),
+                "&&".to_string(),
+            ]);
+        }
+        // `exec` is a shell builtin, so it has to come before `env`.
+        words.push("exec".to_string());
+        if !server.env.is_empty() {
+            words.push("env".to_string());
+            words.extend(
+                server
+                    .env
+                    .iter()
+                    .map(|(key, value)| quote(&format!("{}={}", key, value))),
+            );
+        }
+        words.push(quote(&server.program));
+        words.extend(server.args.iter().map(|arg| quote(arg)));
+        words.join(" ")
+    }
+}
+
+/// `word` quoted for a POSIX shell.
+fn quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ssh_command() {
+        let server = ServerCommand::new("gopls")
+            .args(["-remote=auto", "it's"])
+            .env("GOFLAGS", "-mod=mod")
+            .current_dir("/srv/my app");
+        let command = SshLauncher::new("me@devbox", server)
+            .port(2222)
+            .option("ConnectTimeout", "5")
+            .command();
+        assert_eq!(command.program, "ssh");
+        assert_eq!(
+            command.args,
+            [
+                "-T",
+                "-o",
+                "BatchMode=yes",
+                "-p",
+                "2222",
+                "-o",
+                "ConnectTimeout=5",
+                "me@devbox",
+                "--",
+                r"cd '/srv/my app' && exec env 'GOFLAGS=-mod=mod' 'gopls' '-remote=auto' 'it'\''s'",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remote_command_runs_in_sh() {
+        let dir = std::env::temp_dir();
+        let server = ServerCommand::new("sh")
+            .args(["-c", r#"printf '%s %s' "$GOFLAGS" "$(pwd -P)""#])
+            .env("GOFLAGS", "-mod=mod")
+            .current_dir(&dir);
+        let remote = SshLauncher::new("me@devbox", server).remote_command();
+        let output = std::process::Command::new("sh")
+            .args(["-c", &remote])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let dir = dir.canonicalize().unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!("-mod=mod {}", dir.display())
+        );
+    }
+}
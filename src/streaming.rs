@@ -0,0 +1,296 @@
+use crate::protocol::{error_code, BaseMessage, ResponseMessage, CONTENT_MODIFIED};
+use anyhow::{anyhow, bail, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::marker::PhantomData;
+
+/// A response whose body was kept as raw bytes, so that large array results
+/// (e.g. `workspace/symbol`, `textDocument/references`) can be consumed item
+/// by item instead of being materialized at once.
+#[derive(Debug, Clone)]
+pub struct RawResponse {
+    pub id: Option<serde_json::Value>,
+    pub error: Option<serde_json::Value>,
+    body: Vec<u8>,
+}
+
+impl RawResponse {
+    pub(crate) fn new(
+        id: Option<serde_json::Value>,
+        error: Option<serde_json::Value>,
+        body: Vec<u8>,
+    ) -> Self {
+        Self { id, error, body }
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The code of the error, if the request failed.
+    pub fn error_code(&self) -> Option<i64> {
+        error_code(self.error.as_ref())
+    }
+
+    /// Whether the server gave up on the request because the document
+    /// changed while it was working on it. Asking again usually succeeds.
+    pub fn is_content_modified(&self) -> bool {
+        self.error_code() == Some(CONTENT_MODIFIED)
+    }
+
+    /// The response with `id`, for a request that was answered with the
+    /// response to another.
+    pub(crate) fn with_id(self, id: serde_json::Value) -> Result<Self> {
+        let mut body: serde_json::Value = serde_json::from_slice(&self.body)?;
+        body["id"] = id.clone();
+        Ok(RawResponse::new(
+            Some(id),
+            self.error,
+            serde_json::to_vec(&body)?,
+        ))
+    }
+
+    /// Iterates over the items of an array result, deserializing one at a time.
+    /// A `null` result yields no items.
+    pub fn items<T: DeserializeOwned>(&self) -> Result<ResultItems<'_, T>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        }
+        ResultItems::new(&self.body)
+    }
+
+    /// Parses the whole body into a `ResponseMessage`.
+    pub fn to_response(&self) -> Result<ResponseMessage> {
+        // Parsed through a flat struct: `ResponseMessage` flattens its base
+        // message, which makes serde buffer the whole body first.
+        #[derive(Deserialize)]
+        struct Body {
+            jsonrpc: Cow<'static, str>,
+            id: Option<serde_json::Value>,
+            result: Option<serde_json::Value>,
+            error: Option<serde_json::Value>,
+        }
+
+        let body: Body = serde_json::from_slice(&self.body)
+            .map_err(|e| anyhow!("Failed to parse response body: {}", e))?;
+        Ok(ResponseMessage {
+            base_message: BaseMessage {
+                jsonrpc: body.jsonrpc,
+            },
+            id: body.id,
+            result: body.result,
+            error: body.error,
+        })
+    }
+}
+
+/// Iterator over the elements of the `result` array of a raw response body.
+/// Only the element being yielded is ever deserialized.
+pub struct ResultItems<'a, T> {
+    buf: &'a [u8],
+    pos: usize,
+    done: bool,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T: DeserializeOwned> ResultItems<'a, T> {
+    /// Positions the iterator on the first element of the `result` array of `body`.
+    pub fn new(body: &'a [u8]) -> Result<Self> {
+        let mut scanner = Scanner { buf: body, pos: 0 };
+        let value_start = scanner.find_top_level_key(b"result")?;
+        let mut items = ResultItems {
+            buf: body,
+            pos: value_start,
+            done: false,
+            _marker: PhantomData,
+        };
+        match body.get(value_start) {
+            None => items.done = true,
+            Some(b'n') if body[value_start..].starts_with(b"null") => items.done = true,
+            Some(b'[') => items.pos += 1,
+            Some(_) => bail!("Result is not an array."),
+        }
+        Ok(items)
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for ResultItems<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut scanner = Scanner {
+            buf: self.buf,
+            pos: self.pos,
+        };
+        scanner.skip_whitespace();
+        match scanner.peek() {
+            Some(b']') | None => {
+                self.done = true;
+                return None;
+            }
+            Some(b',') => {
+                scanner.pos += 1;
+                scanner.skip_whitespace();
+            }
+            Some(_) => {}
+        }
+
+        let start = scanner.pos;
+        let item = scanner.skip_value().and_then(|_| {
+            serde_json::from_slice(&self.buf[start..scanner.pos])
+                .map_err(|e| anyhow!("Failed to parse result item: {}", e))
+        });
+        if item.is_err() {
+            self.done = true;
+        }
+        self.pos = scanner.pos;
+        Some(item)
+    }
+}
+
+/// Just enough of a JSON tokenizer to find value boundaries without building values.
+struct Scanner<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.buf.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            bail!("Expected '{}' at offset {}", byte as char, self.pos);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Returns the offset of the value of `key` in the top-level object.
+    /// If the key is missing the offset is the end of the buffer.
+    fn find_top_level_key(&mut self, key: &[u8]) -> Result<usize> {
+        self.expect(b'{')?;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'}') | None => return Ok(self.buf.len()),
+                Some(b',') => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let key_start = self.pos + 1;
+            self.skip_string()?;
+            let found = &self.buf[key_start..self.pos - 1] == key;
+            self.expect(b':')?;
+            self.skip_whitespace();
+            if found {
+                return Ok(self.pos);
+            }
+            self.skip_value()?;
+        }
+    }
+
+    fn skip_string(&mut self) -> Result<()> {
+        self.skip_whitespace();
+        if self.peek() != Some(b'"') {
+            bail!("Expected a string at offset {}", self.pos);
+        }
+        self.pos += 1;
+        while let Some(byte) = self.peek() {
+            self.pos += 1;
+            match byte {
+                b'\\' => self.pos += 1,
+                b'"' => return Ok(()),
+                _ => {}
+            }
+        }
+        bail!("Unterminated string.")
+    }
+
+    fn skip_value(&mut self) -> Result<()> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'"') => self.skip_string(),
+            Some(b'{' | b'[') => {
+                let mut depth = 0usize;
+                while let Some(byte) = self.peek() {
+                    match byte {
+                        b'"' => {
+                            self.skip_string()?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                self.pos += 1;
+                                return Ok(());
+                            }
+                        }
+                        _ => {}
+                    }
+                    self.pos += 1;
+                }
+                bail!("Unterminated object or array.")
+            }
+            Some(_) => {
+                while !matches!(
+                    self.peek(),
+                    None | Some(b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r')
+                ) {
+                    self.pos += 1;
+                }
+                Ok(())
+            }
+            None => bail!("Unexpected end of input."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Symbol {
+        name: String,
+    }
+
+    #[test]
+    fn test_result_items() {
+        let body = br#"{"jsonrpc":"2.0","id":3,"extra":{"result":[0]},"result": [
+            {"name": "main", "tags": [1, {"nested": "]}"}]},
+            {"name": "say \"hi\""}
+        ]}"#;
+
+        let names: Vec<String> = ResultItems::<Symbol>::new(body)
+            .unwrap()
+            .map(|symbol| symbol.unwrap().name)
+            .collect();
+        assert_eq!(names, vec!["main".to_string(), "say \"hi\"".to_string()]);
+    }
+
+    #[test]
+    fn test_null_and_non_array_results() {
+        let null_body = br#"{"jsonrpc":"2.0","id":3,"result":null}"#;
+        assert_eq!(ResultItems::<Symbol>::new(null_body).unwrap().count(), 0);
+
+        let object_body = br#"{"jsonrpc":"2.0","id":3,"result":{"name":"main"}}"#;
+        assert!(ResultItems::<Symbol>::new(object_body).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Keeping a server usable: periodic health checks, and restarts of servers
+//! that stopped responding even though their process is still alive.
+
+use crate::client::LspClient;
+use crate::launch::{launch, LaunchPolicy, Launched, ServerCommand, StderrTail};
+use crate::protocol::{NotificationMessage, RequestMessage};
+use anyhow::{bail, Result};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Method of the default probe request. No server implements it, and any
+/// answer, including `MethodNotFound`, shows that the server is responsive.
+pub const PING_METHOD: &str = "$/lsp-client-rs/ping";
+
+/// How a supervisor checks that its server still responds.
+#[derive(Debug, Clone)]
+pub struct HealthCheck {
+    pub interval: Duration,
+    /// How long the probe may take before the server counts as unresponsive.
+    pub timeout: Duration,
+    /// The method of the probe request. It is sent without params.
+    pub probe_method: String,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        HealthCheck {
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            probe_method: PING_METHOD.to_string(),
+        }
+    }
+}
+
+/// When a supervisor restarts an unresponsive server.
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    /// Restarts before the supervisor gives up and leaves the server marked
+    /// unhealthy.
+    pub max_restarts: u32,
+    /// How long the old server gets to shut down before it is killed.
+    pub grace_period: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            max_restarts: 3,
+            grace_period: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Builds a `Supervisor`. See `Supervisor::builder`.
+#[derive(Clone)]
+pub struct SupervisorBuilder {
+    command: ServerCommand,
+    launch_policy: LaunchPolicy,
+    restart_policy: RestartPolicy,
+    initialize: Option<RequestMessage>,
+    settings: Option<serde_json::Value>,
+}
+
+impl SupervisorBuilder {
+    pub fn launch_policy(mut self, policy: LaunchPolicy) -> Self {
+        self.launch_policy = policy;
+        self
+    }
+
+    pub fn restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// The `initialize` request sent to every server the supervisor starts,
+    /// followed by `initialized`.
+    pub fn initialize(mut self, request: RequestMessage) -> Self {
+        self.initialize = Some(request);
+        self
+    }
+
+    /// Settings sent with `workspace/didChangeConfiguration` after every
+    /// `initialized`.
+    pub fn settings(mut self, settings: serde_json::Value) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// Launches the server.
+    pub async fn start(self) -> Result<Arc<Supervisor>> {
+        let launched = launch(&self.command, &self.launch_policy).await?;
+        let supervisor = Supervisor {
+            command: self.command,
+            launch_policy: self.launch_policy,
+            restart_policy: self.restart_policy,
+            initialize: self.initialize,
+            settings: self.settings,
+            current: Mutex::new(launched.clone()),
+            restart_lock: tokio::sync::Mutex::new(()),
+            healthy: AtomicBool::new(true),
+            restarts: AtomicU32::new(0),
+        };
+        supervisor.initialize(&launched.client).await?;
+        Ok(Arc::new(supervisor))
+    }
+}
+
+/// Owns a server process and replaces it when it stops responding. Documents
+/// open on the old server are reopened on the new one.
+pub struct Supervisor {
+    command: ServerCommand,
+    launch_policy: LaunchPolicy,
+    restart_policy: RestartPolicy,
+    initialize: Option<RequestMessage>,
+    settings: Option<serde_json::Value>,
+    current: Mutex<Launched>,
+    /// Held while restarting, so concurrent restarts don't race.
+    restart_lock: tokio::sync::Mutex<()>,
+    healthy: AtomicBool,
+    restarts: AtomicU32,
+}
+
+impl Supervisor {
+    pub fn builder(command: ServerCommand) -> SupervisorBuilder {
+        SupervisorBuilder {
+            command,
+            launch_policy: LaunchPolicy::default(),
+            restart_policy: RestartPolicy::default(),
+            initialize: None,
+            settings: None,
+        }
+    }
+
+    /// The client of the current server. Fetch it again after a restart.
+    pub fn client(&self) -> LspClient {
+        self.current().client.clone()
+    }
+
+    /// The end of the current server's stderr.
+    pub fn stderr(&self) -> StderrTail {
+        self.current().stderr.clone()
+    }
+
+    /// False once a health check failed, until a restart succeeded.
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::SeqCst)
+    }
+
+    /// How many times the server was restarted.
+    pub fn restarts(&self) -> u32 {
+        self.restarts.load(Ordering::SeqCst)
+    }
+
+    /// Sends the probe of `check` and records whether the server answered in time.
+    pub async fn probe(&self, check: &HealthCheck) -> bool {
+        static PROBES: AtomicU32 = AtomicU32::new(0);
+        let id = format!(
+            "lsp-client-rs/ping-{}",
+            PROBES.fetch_add(1, Ordering::Relaxed)
+        );
+        let request = RequestMessage::builder()
+            .id(id.as_str())
+            .method(check.probe_method.clone())
+            .build();
+        let alive = match request {
+            Ok(request) => {
+                let client = self.client();
+                let response = tokio::time::timeout(check.timeout, client.request(request)).await;
+                matches!(response, Ok(Ok(_)))
+            }
+            Err(_) => false,
+        };
+        self.healthy.store(alive, Ordering::SeqCst);
+        alive
+    }
+
+    /// Replaces the server with a freshly launched one and reopens the
+    /// documents that were open on the old one.
+    pub async fn restart(&self) -> Result<()> {
+        let _restarting = self.restart_lock.lock().await;
+        let old = self.client();
+        let documents: Vec<_> = old.documents().iter().cloned().collect();
+        old.set_shutdown_grace_period(self.restart_policy.grace_period);
+        // The old server is likely unresponsive; it gets killed if need be.
+        let _ = old.close().await;
+
+        let launched = launch(&self.command, &self.launch_policy).await?;
+        self.initialize(&launched.client).await?;
+        for document in documents {
+            launched
+                .client
+                .did_open(document.uri, document.language_id, document.text)
+                .await?;
+        }
+        *self.current() = launched;
+        self.restarts.fetch_add(1, Ordering::SeqCst);
+        self.healthy.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Shuts the server down gracefully, killing it if it doesn't exit
+    /// within the grace period of the restart policy.
+    pub async fn shutdown(&self) -> Result<()> {
+        let _restarting = self.restart_lock.lock().await;
+        let client = self.client();
+        client.set_shutdown_grace_period(self.restart_policy.grace_period);
+        client.close().await
+    }
+
+    /// Probes the server every `check.interval` and restarts it when it
+    /// doesn't answer, within the limits of the restart policy. The task ends
+    /// when the supervisor is dropped or gives up.
+    pub fn spawn_health_checks(self: &Arc<Self>, check: HealthCheck) -> JoinHandle<()> {
+        let supervisor: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(check.interval).await;
+                let Some(supervisor) = supervisor.upgrade() else {
+                    return;
+                };
+                if supervisor.probe(&check).await {
+                    continue;
+                }
+                if supervisor.restarts() >= supervisor.restart_policy.max_restarts {
+                    return;
+                }
+                // A failed restart leaves the server unhealthy; try again next time.
+                let _ = supervisor.restart().await;
+            }
+        })
+    }
+
+    async fn initialize(&self, client: &LspClient) -> Result<()> {
+        let Some(initialize) = &self.initialize else {
+            return Ok(());
+        };
+        let response = client.request(initialize.clone()).await?;
+        if response.error.is_some() {
+            bail!("Server failed to initialize: {:?}", response.error);
+        }
+        client
+            .send_request(NotificationMessage::new_initialized())
+            .await?;
+        if let Some(settings) = &self.settings {
+            client
+                .send_request(NotificationMessage::new_did_change_configuration(
+                    settings.clone(),
+                ))
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn current(&self) -> MutexGuard<'_, Launched> {
+        self.current.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unresponsive_server_is_restarted() {
+        // `sleep` stays alive but never answers.
+        let supervisor = Supervisor::builder(ServerCommand::new("sleep").arg("30"))
+            .launch_policy(LaunchPolicy {
+                startup_window: Duration::ZERO,
+                ..LaunchPolicy::default()
+            })
+            .restart_policy(RestartPolicy {
+                max_restarts: 1,
+                grace_period: Duration::from_millis(10),
+            })
+            .start()
+            .await
+            .unwrap();
+        let check = HealthCheck {
+            interval: Duration::from_millis(10),
+            timeout: Duration::from_millis(50),
+            ..HealthCheck::default()
+        };
+
+        assert!(!supervisor.probe(&check).await);
+        assert!(!supervisor.is_healthy());
+
+        let checks = supervisor.spawn_health_checks(check);
+        tokio::time::timeout(Duration::from_secs(10), checks)
+            .await
+            .unwrap()
+            .unwrap();
+        // The restarted server is just as unresponsive, so the task gave up.
+        assert_eq!(supervisor.restarts(), 1);
+        assert!(!supervisor.is_healthy());
+    }
+}
@@ -0,0 +1,171 @@
+//! Helpers over `DocumentSymbol` trees, so outline and breadcrumb features
+//! don't need their own traversal code.
+
+use crate::protocol::{
+    DocumentSymbol, DocumentSymbolResponse, Location, Position, SymbolInformation, SymbolKind,
+};
+
+/// Depth-first, pre-order iterator over a symbol tree. Yields each symbol with
+/// its depth, top-level symbols being at depth 0.
+pub struct Walk<'a> {
+    stack: Vec<(usize, &'a DocumentSymbol)>,
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (usize, &'a DocumentSymbol);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (depth, symbol) = self.stack.pop()?;
+        self.stack
+            .extend(symbol.children.iter().rev().map(|child| (depth + 1, child)));
+        Some((depth, symbol))
+    }
+}
+
+/// Iterates over every symbol of the tree, parents before their children.
+pub fn walk(symbols: &[DocumentSymbol]) -> Walk<'_> {
+    Walk {
+        stack: symbols.iter().rev().map(|symbol| (0, symbol)).collect(),
+    }
+}
+
+/// The symbols enclosing `position`, from the outermost to the innermost.
+/// This is what a breadcrumb bar shows.
+pub fn path_at(symbols: &[DocumentSymbol], position: Position) -> Vec<&DocumentSymbol> {
+    let mut path = Vec::new();
+    let mut level = symbols;
+    while let Some(symbol) = level.iter().find(|symbol| symbol.range.contains(position)) {
+        path.push(symbol);
+        level = &symbol.children;
+    }
+    path
+}
+
+/// The innermost symbol enclosing `position`.
+pub fn symbol_at(symbols: &[DocumentSymbol], position: Position) -> Option<&DocumentSymbol> {
+    path_at(symbols, position).pop()
+}
+
+/// The symbols of the given kinds, in `walk` order.
+pub fn filter_kind<'a>(
+    symbols: &'a [DocumentSymbol],
+    kinds: &'a [SymbolKind],
+) -> impl Iterator<Item = &'a DocumentSymbol> + 'a {
+    walk(symbols)
+        .map(|(_, symbol)| symbol)
+        .filter(|symbol| kinds.contains(&symbol.kind))
+}
+
+/// Flattens a symbol tree of the document `uri` into `SymbolInformation`s, in
+/// `walk` order. The container name of a symbol is the name of its parent.
+pub fn flatten(symbols: &[DocumentSymbol], uri: &str) -> Vec<SymbolInformation> {
+    let mut flat = Vec::new();
+    flatten_into(symbols, uri, None, &mut flat);
+    flat
+}
+
+fn flatten_into(
+    symbols: &[DocumentSymbol],
+    uri: &str,
+    container_name: Option<&str>,
+    flat: &mut Vec<SymbolInformation>,
+) {
+    for symbol in symbols {
+        flat.push(SymbolInformation {
+            name: symbol.name.clone(),
+            kind: symbol.kind,
+            tags: symbol.tags.clone(),
+            deprecated: symbol.deprecated,
+            location: Location::new(uri, symbol.range),
+            container_name: container_name.map(str::to_string),
+        });
+        flatten_into(&symbol.children, uri, Some(&symbol.name), flat);
+    }
+}
+
+impl DocumentSymbolResponse {
+    /// Returns the symbols as a flat list, whichever shape the server sent.
+    pub fn into_flat(self, uri: &str) -> Vec<SymbolInformation> {
+        match self {
+            DocumentSymbolResponse::Nested(symbols) => flatten(&symbols, uri),
+            DocumentSymbolResponse::Flat(symbols) => symbols,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn outline() -> Vec<DocumentSymbol> {
+        let symbol = |name: &str, kind: u8, lines: (u32, u32), children| {
+            json!({
+                "name": name,
+                "kind": kind,
+                "range": {
+                    "start": { "line": lines.0, "character": 0 },
+                    "end": { "line": lines.1, "character": 1 }
+                },
+                "selectionRange": {
+                    "start": { "line": lines.0, "character": 5 },
+                    "end": { "line": lines.0, "character": 9 }
+                },
+                "children": children
+            })
+        };
+        serde_json::from_value(json!([
+            symbol(
+                "Server",
+                23,
+                (0, 10),
+                json!([
+                    symbol("start", 6, (2, 5), json!([])),
+                    symbol("stop", 6, (7, 9), json!([]))
+                ])
+            ),
+            symbol("main", 12, (12, 15), json!([])),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_walk_and_lookup() {
+        let symbols = outline();
+
+        let walked: Vec<(usize, &str)> = walk(&symbols)
+            .map(|(depth, symbol)| (depth, symbol.name.as_str()))
+            .collect();
+        assert_eq!(
+            walked,
+            vec![(0, "Server"), (1, "start"), (1, "stop"), (0, "main")]
+        );
+
+        let path: Vec<&str> = path_at(&symbols, Position::new(8, 3))
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+        assert_eq!(path, vec!["Server", "stop"]);
+        assert_eq!(
+            symbol_at(&symbols, Position::new(1, 0)).unwrap().name,
+            "Server"
+        );
+        assert!(symbol_at(&symbols, Position::new(11, 0)).is_none());
+
+        let methods: Vec<&str> = filter_kind(&symbols, &[SymbolKind::Method])
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+        assert_eq!(methods, vec!["start", "stop"]);
+    }
+
+    #[test]
+    fn test_flatten_sets_container_names() {
+        let flat = DocumentSymbolResponse::Nested(outline()).into_flat("file:///main.go");
+
+        assert_eq!(flat.len(), 4);
+        assert_eq!(flat[0].container_name, None);
+        assert_eq!(flat[2].name, "stop");
+        assert_eq!(flat[2].container_name.as_deref(), Some("Server"));
+        assert_eq!(flat[2].location.uri(), "file:///main.go");
+    }
+}
@@ -0,0 +1,126 @@
+//! OpenTelemetry export of request latencies and errors, for monitoring
+//! language servers at scale. Enabled by the `otel` feature.
+//!
+//! Instruments come from the global meter and tracer providers at the time a
+//! client is created, so install the providers before connecting.
+
+use crate::streaming::RawResponse;
+use anyhow::Result;
+use opentelemetry::global::{self, BoxedSpan, BoxedTracer};
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+use opentelemetry::KeyValue;
+use std::time::Instant;
+
+/// Name of the instrumentation scope of the client's meter and tracer.
+pub const SCOPE: &str = "lsp-client-rs";
+/// Histogram of request durations in seconds, by `rpc.method`.
+pub const REQUEST_DURATION: &str = "lsp.client.request.duration";
+/// Counter of failed requests, by `rpc.method` and `error.type`.
+pub const REQUEST_ERRORS: &str = "lsp.client.request.errors";
+
+pub(crate) struct Instruments {
+    tracer: BoxedTracer,
+    duration: Histogram<f64>,
+    errors: Counter<u64>,
+}
+
+impl Instruments {
+    pub(crate) fn new() -> Self {
+        let meter = global::meter(SCOPE);
+        Instruments {
+            tracer: global::tracer(SCOPE),
+            duration: meter
+                .f64_histogram(REQUEST_DURATION)
+                .with_unit("s")
+                .with_description("Duration of requests to the language server")
+                .build(),
+            errors: meter
+                .u64_counter(REQUEST_ERRORS)
+                .with_description("Requests answered with an error or never answered")
+                .build(),
+        }
+    }
+
+    /// Starts measuring a request. Finish it with `RequestTelemetry::finish`.
+    pub(crate) fn start(&self, method: &str, id: &serde_json::Value) -> RequestTelemetry<'_> {
+        let span = self
+            .tracer
+            .span_builder(method.to_string())
+            .with_kind(SpanKind::Client)
+            .with_attributes([
+                KeyValue::new("rpc.system", "jsonrpc"),
+                KeyValue::new("rpc.method", method.to_string()),
+                KeyValue::new("rpc.jsonrpc.request_id", id.to_string()),
+            ])
+            .start(&self.tracer);
+        RequestTelemetry {
+            instruments: self,
+            method: method.to_string(),
+            span,
+            started: Instant::now(),
+        }
+    }
+}
+
+pub(crate) struct RequestTelemetry<'a> {
+    instruments: &'a Instruments,
+    method: String,
+    span: BoxedSpan,
+    started: Instant,
+}
+
+impl RequestTelemetry<'_> {
+    pub(crate) fn finish(mut self, response: &Result<RawResponse>) {
+        let method = KeyValue::new("rpc.method", self.method);
+        self.instruments.duration.record(
+            self.started.elapsed().as_secs_f64(),
+            std::slice::from_ref(&method),
+        );
+        if let Some(error_type) = error_type(response) {
+            self.instruments.errors.add(
+                1,
+                &[method, KeyValue::new("error.type", error_type.clone())],
+            );
+            self.span.set_status(Status::error(error_type));
+        }
+        self.span.end();
+    }
+}
+
+/// The `error.type` of a failed request: the JSON-RPC error code, or
+/// `no_response` if the request failed before an answer arrived.
+fn error_type(response: &Result<RawResponse>) -> Option<String> {
+    match response {
+        Ok(response) => {
+            let error = response.error.as_ref()?;
+            Some(match error.get("code") {
+                Some(code) => code.to_string(),
+                None => "unknown".to_string(),
+            })
+        }
+        Err(_) => Some("no_response".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_error_type() {
+        let response = |error| Ok(RawResponse::new(Some(json!(1)), error, Vec::new()));
+        assert_eq!(error_type(&response(None)), None);
+        assert_eq!(
+            error_type(&response(Some(
+                json!({ "code": -32801, "message": "modified" })
+            ))),
+            Some("-32801".to_string())
+        );
+        assert_eq!(
+            error_type(&Err(anyhow::anyhow!("closed"))),
+            Some("no_response".to_string())
+        );
+    }
+}
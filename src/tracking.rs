@@ -0,0 +1,224 @@
+//! Keeps positions the server computed for an older version of a document,
+//! such as diagnostics or code lens ranges, anchored to the same text while
+//! newer results are on their way.
+//!
+//! ```
+//! use lsp_client_rs::protocol::{Position, Range, TextDocumentContentChangeEvent};
+//! use lsp_client_rs::tracking::EditTracker;
+//!
+//! let mut tracker = EditTracker::new();
+//! // A line is inserted at the top of the document.
+//! tracker.record(&[TextDocumentContentChangeEvent {
+//!     range: Some(Range::new(Position::new(0, 0), Position::new(0, 0))),
+//!     text: "use std::fmt;\n".to_string(),
+//! }]);
+//! assert_eq!(tracker.map_position(Position::new(4, 2)), Some(Position::new(5, 2)));
+//! ```
+
+use crate::protocol::{Position, Range, TextDocumentContentChangeEvent};
+
+/// One recorded change: the range it replaced, and where the text it
+/// inserted ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Edit {
+    replaced: Range,
+    inserted_end: Position,
+}
+
+/// Maps positions forward through the changes recorded since it was created
+/// or last cleared.
+///
+/// A position inside text that was replaced no longer exists and maps to
+/// `None`. Text inserted exactly at a position is inserted after it, and a
+/// change without a range replaces the whole document, after which nothing
+/// maps at all.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditTracker {
+    edits: Vec<Edit>,
+    replaced_document: bool,
+}
+
+impl EditTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the changes of a `didChange` notification, in order.
+    pub fn record(&mut self, changes: &[TextDocumentContentChangeEvent]) {
+        for change in changes {
+            let Some(range) = change.range else {
+                self.edits.clear();
+                self.replaced_document = true;
+                continue;
+            };
+            let (lines, last_line) = measure(&change.text);
+            let inserted_end = if lines == 0 {
+                Position::new(range.start.line, range.start.character + last_line)
+            } else {
+                Position::new(range.start.line + lines, last_line)
+            };
+            self.edits.push(Edit {
+                replaced: Range::new(range.start, range.end.max(range.start)),
+                inserted_end,
+            });
+        }
+    }
+
+    /// Whether no changes were recorded, so positions map to themselves.
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty() && !self.replaced_document
+    }
+
+    /// Forgets the recorded changes, e.g. once fresh results arrived.
+    pub fn clear(&mut self) {
+        self.edits.clear();
+        self.replaced_document = false;
+    }
+
+    /// Where `position` is now, or `None` if its text was replaced.
+    pub fn map_position(&self, position: Position) -> Option<Position> {
+        if self.replaced_document {
+            return None;
+        }
+        self.edits
+            .iter()
+            .try_fold(position, |position, edit| shift(edit, position, false))
+    }
+
+    /// Where `range` is now. Text inserted at either end stays outside of it.
+    /// `None` if all of its text was replaced.
+    pub fn map_range(&self, range: Range) -> Option<Range> {
+        if self.replaced_document {
+            return None;
+        }
+        let mut start = range.start;
+        let mut end = range.end;
+        for edit in &self.edits {
+            // An end inside the replaced text moves back to where it began,
+            // and a start inside it forward to where the new text ends.
+            end = shift(edit, end, false).unwrap_or(edit.replaced.start);
+            start = shift(edit, start, true).unwrap_or(edit.inserted_end);
+            if start > end || (start == end && range.start != range.end) {
+                return None;
+            }
+        }
+        Some(Range::new(start, end))
+    }
+}
+
+/// Where `position` is after `edit`, or `None` if its text was replaced.
+/// `after_insertions` decides on which side of text inserted right at the
+/// position it ends up.
+fn shift(edit: &Edit, position: Position, after_insertions: bool) -> Option<Position> {
+    let Edit {
+        replaced,
+        inserted_end,
+    } = *edit;
+    let before = if after_insertions {
+        position < replaced.start
+    } else {
+        position <= replaced.start
+    };
+    if before {
+        return Some(position);
+    }
+    if position < replaced.end {
+        return None;
+    }
+    if position.line != replaced.end.line {
+        let line = position.line - replaced.end.line + inserted_end.line;
+        return Some(Position::new(line, position.character));
+    }
+    let character = inserted_end.character + (position.character - replaced.end.character);
+    Some(Position::new(inserted_end.line, character))
+}
+
+/// The number of line breaks in `text`, and the length of its last line in
+/// UTF-16 code units.
+fn measure(text: &str) -> (u32, u32) {
+    let mut lines = 0;
+    let mut last_line = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\r' | '\n' => {
+                lines += 1;
+                last_line = 0;
+            }
+            _ => last_line += ch.len_utf16() as u32,
+        }
+    }
+    (lines, last_line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            text: text.to_string(),
+        }
+    }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    #[test]
+    fn test_map_through_edits() {
+        let mut tracker = EditTracker::new();
+        assert!(tracker.is_empty());
+        tracker.record(&[
+            // "fmt.Println" becomes "log.Printf" on line 3.
+            change((3, 1), (3, 12), "log.Printf"),
+            // Two lines inserted in the middle of line 1.
+            change((1, 4), (1, 4), "a\r\nbb\n👍"),
+        ]);
+
+        // Before every edit.
+        assert_eq!(
+            tracker.map_position(Position::new(0, 9)),
+            Some(Position::new(0, 9))
+        );
+        // After the insertion on the same line, and on a later line.
+        assert_eq!(
+            tracker.map_position(Position::new(1, 6)),
+            Some(Position::new(3, 4))
+        );
+        assert_eq!(
+            tracker.map_position(Position::new(3, 20)),
+            Some(Position::new(5, 19))
+        );
+        // Inside the replaced text.
+        assert_eq!(tracker.map_position(Position::new(3, 5)), None);
+
+        // Insertions at either end stay outside of a range.
+        assert_eq!(
+            tracker.map_range(range((1, 4), (1, 8))),
+            Some(range((3, 2), (3, 6)))
+        );
+        // A range overlapping the replaced text shrinks to what is left.
+        assert_eq!(
+            tracker.map_range(range((3, 0), (3, 5))),
+            Some(range((5, 0), (5, 1)))
+        );
+        assert_eq!(tracker.map_range(range((3, 2), (3, 6))), None);
+
+        tracker.record(&[TextDocumentContentChangeEvent {
+            range: None,
+            text: String::new(),
+        }]);
+        assert_eq!(tracker.map_position(Position::new(0, 0)), None);
+        tracker.clear();
+        assert_eq!(
+            tracker.map_position(Position::new(0, 0)),
+            Some(Position::new(0, 0))
+        );
+    }
+}
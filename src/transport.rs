@@ -0,0 +1,664 @@
+//! The layers shared by the JSON-based protocols the crate speaks: the
+//! `Content-Length` framing used by LSP and the Debug Adapter Protocol alike,
+//! and the correlation of responses with the requests waiting for them.
+//!
+//! `StdioTransport` sends and receives whole LSP messages over the stdio of
+//! a server, for callers that correlate them themselves. `launch::launch`
+//! and `LspClient::from_child` go further: the `LspClient` routes responses
+//! back to the requests waiting for them.
+
+use crate::protocol::{
+    intern_method, BaseMessage, NotificationMessage, RequestMessage, ResponseMessage,
+};
+use anyhow::{anyhow, ensure, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{IoSlice, Write};
+use std::process::Stdio;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// The `Content-Type` the LSP specification defines, for hosts that
+/// require the header.
+pub const DEFAULT_CONTENT_TYPE: &str = "application/vscode-jsonrpc; charset=utf-8";
+
+/// How much `FrameReader` reads from the stream at once.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Buffers grown past this by a large message are shrunk back once it has
+/// been consumed, so one huge response doesn't pin its memory for the rest
+/// of the session.
+const MAX_RETAINED_BUFFER: usize = 1024 * 1024;
+
+/// Reads `Content-Length` framed message bodies.
+///
+/// Header parsing is lenient: names are case-insensitive, lines may end with
+/// `\n` instead of `\r\n`, blank lines between messages and lines that aren't
+/// headers are skipped, and headers other than `Content-Length` are kept for
+/// `headers` without being interpreted.
+///
+/// The stream is read in chunks into one buffer that is reused for every
+/// message, and so are the strings of the headers: `next_frame` reads a
+/// message without allocating once the buffer has grown to fit the largest.
+pub struct FrameReader<R> {
+    stream: R,
+    /// Read from the stream but not consumed yet: `buf[start..end]`.
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+    /// The first `header_count` entries are the headers of the last message;
+    /// the rest are kept for their allocations.
+    headers: Vec<(String, String)>,
+    header_count: usize,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(stream: R) -> Self {
+        FrameReader {
+            stream,
+            buf: Vec::new(),
+            start: 0,
+            end: 0,
+            headers: Vec::new(),
+            header_count: 0,
+        }
+    }
+
+    /// Reads the body of the next message.
+    pub async fn read_frame(&mut self) -> Result<Vec<u8>> {
+        Ok(self.next_frame().await?.to_vec())
+    }
+
+    /// Reads the next message and borrows its body from the reader's buffer,
+    /// until the next call.
+    pub async fn next_frame(&mut self) -> Result<&[u8]> {
+        self.header_count = 0;
+        let mut seen_line = false;
+        loop {
+            let line = self.next_line().await?;
+            let Ok(line) = std::str::from_utf8(&self.buf[line]) else {
+                seen_line = true;
+                continue;
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                if seen_line {
+                    break;
+                }
+                continue;
+            }
+            seen_line = true;
+            if let Some((name, value)) = line.split_once(':') {
+                if self.header_count == self.headers.len() {
+                    self.headers.push(Default::default());
+                }
+                let header = &mut self.headers[self.header_count];
+                header.0.clear();
+                header.0.push_str(name.trim());
+                header.1.clear();
+                header.1.push_str(value.trim());
+                self.header_count += 1;
+            }
+        }
+
+        let content_length = self
+            .header("Content-Length")
+            .ok_or_else(|| anyhow!("Failed to find Content-Length header"))?;
+        let content_length: usize = content_length
+            .parse()
+            .map_err(|e| anyhow!("Invalid Content-Length {:?}: {}", content_length, e))?;
+        self.fill(content_length).await?;
+        let body = self.start..self.start + content_length;
+        self.start = body.end;
+        Ok(&self.buf[body])
+    }
+
+    /// The headers of the last message read, in the order received.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers[..self.header_count]
+    }
+
+    /// The value of a header of the last message read, by case-insensitive name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers()
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Consumes the next line and returns where it lies in `buf`, without
+    /// its `\n`.
+    async fn next_line(&mut self) -> Result<std::ops::Range<usize>> {
+        let mut scanned = 0;
+        loop {
+            let unread = &self.buf[self.start + scanned..self.end];
+            if let Some(newline) = unread.iter().position(|&byte| byte == b'\n') {
+                let line = self.start..self.start + scanned + newline;
+                self.start = line.end + 1;
+                return Ok(line);
+            }
+            scanned += unread.len();
+            self.fill(scanned + 1).await?;
+        }
+    }
+
+    /// Reads until at least `len` bytes are unconsumed.
+    async fn fill(&mut self, len: usize) -> Result<()> {
+        if self.end - self.start >= len {
+            return Ok(());
+        }
+        if self.start == self.end {
+            (self.start, self.end) = (0, 0);
+            if self.buf.len() > MAX_RETAINED_BUFFER.max(len) {
+                self.buf = Vec::new();
+            }
+        }
+        // Move what is left to the front, then make room for the rest.
+        self.buf.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+        if self.buf.len() < len.max(READ_CHUNK) {
+            self.buf.resize(len.max(READ_CHUNK), 0);
+        }
+        while self.end < len {
+            let read = self.stream.read(&mut self.buf[self.end..]).await?;
+            if read == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            self.end += read;
+        }
+        Ok(())
+    }
+}
+
+/// The headers written before each body, besides `Content-Length`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameHeaders {
+    /// Omitted when `None`, which servers assume means `DEFAULT_CONTENT_TYPE`.
+    pub content_type: Option<String>,
+    /// Written in order after `Content-Type`, e.g. for proxies that route on
+    /// a header.
+    pub extra: Vec<(String, String)>,
+}
+
+impl FrameHeaders {
+    /// Checks that every header can be written as is: names are tokens
+    /// without `:`, values have no line breaks, and `Content-Length`, which is
+    /// always written, isn't set again.
+    pub fn validate(&self) -> Result<()> {
+        let content_type = self
+            .content_type
+            .as_ref()
+            .map(|value| ("Content-Type", value.as_str()));
+        let extra = self
+            .extra
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()));
+        for (name, value) in content_type.into_iter().chain(extra) {
+            ensure!(
+                !name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic() && b != b':'),
+                "Invalid header name {:?}",
+                name
+            );
+            ensure!(
+                !value.contains(['\r', '\n']),
+                "Invalid value for header {}: {:?}",
+                name,
+                value
+            );
+            ensure!(
+                !name.eq_ignore_ascii_case("Content-Length"),
+                "Content-Length is always written and can't be set"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Writes `Content-Length` framed message bodies.
+///
+/// The headers and the body of a message go out in one vectored write when
+/// the stream supports it, and messages queued with `queue_frame` go out
+/// along with the next one written, so a burst of small notifications costs
+/// one system call instead of one per message.
+pub struct FrameWriter<W> {
+    stream: W,
+    headers: FrameHeaders,
+    // Reused across messages so the hot path doesn't allocate per frame.
+    frame_buf: Vec<u8>,
+    /// Frames queued and not written yet, headers included.
+    queued: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> FrameWriter<W> {
+    pub fn new(stream: W) -> Self {
+        FrameWriter {
+            stream,
+            headers: FrameHeaders::default(),
+            frame_buf: Vec::new(),
+            queued: Vec::new(),
+        }
+    }
+
+    /// Sets the headers written with every following message.
+    pub fn set_headers(&mut self, headers: FrameHeaders) -> Result<()> {
+        headers.validate()?;
+        self.headers = headers;
+        Ok(())
+    }
+
+    /// Writes the queued messages, then `body` as one message, and flushes
+    /// them.
+    pub async fn write_frame(&mut self, body: &[u8]) -> Result<()> {
+        if !self.stream.is_write_vectored() {
+            self.queue_frame(body)?;
+            return self.flush_queued().await;
+        }
+        self.frame_buf.clear();
+        write_headers(&mut self.frame_buf, &self.headers, body.len())?;
+        let mut slices = [
+            IoSlice::new(&self.queued),
+            IoSlice::new(&self.frame_buf),
+            IoSlice::new(body),
+        ];
+        let written = write_all_vectored(&mut self.stream, &mut slices).await;
+        self.queued.clear();
+        written?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Queues `body` as one message, to be written with the next message or
+    /// `flush_queued`.
+    pub fn queue_frame(&mut self, body: &[u8]) -> Result<()> {
+        write_headers(&mut self.queued, &self.headers, body.len())?;
+        self.queued.extend_from_slice(body);
+        Ok(())
+    }
+
+    /// Writes and flushes the queued messages.
+    pub async fn flush_queued(&mut self) -> Result<()> {
+        if self.queued.is_empty() {
+            return Ok(());
+        }
+        let written = self.stream.write_all(&self.queued).await;
+        self.queued.clear();
+        written?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Closes the stream, e.g. to signal EOF to a server reading stdin.
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+fn write_headers(buf: &mut Vec<u8>, headers: &FrameHeaders, content_length: usize) -> Result<()> {
+    write!(buf, "Content-Length: {}\r\n", content_length)?;
+    if let Some(content_type) = &headers.content_type {
+        write!(buf, "Content-Type: {}\r\n", content_type)?;
+    }
+    for (name, value) in &headers.extra {
+        write!(buf, "{}: {}\r\n", name, value)?;
+    }
+    buf.extend_from_slice(b"\r\n");
+    Ok(())
+}
+
+async fn write_all_vectored<W: AsyncWrite + Unpin>(
+    stream: &mut W,
+    mut slices: &mut [IoSlice<'_>],
+) -> Result<()> {
+    IoSlice::advance_slices(&mut slices, 0);
+    while !slices.is_empty() {
+        let written = stream.write_vectored(slices).await?;
+        if written == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+        }
+        IoSlice::advance_slices(&mut slices, written);
+    }
+    Ok(())
+}
+
+/// A message received from a language server.
+#[derive(Debug)]
+pub enum ServerMessage {
+    Response(ResponseMessage),
+    Notification(NotificationMessage),
+    /// A request the server expects the client to answer.
+    Request(RequestMessage),
+}
+
+/// Frames messages to a language server over its stdin and reads the ones
+/// it writes to its stdout.
+pub struct StdioTransport<R = ChildStdout, W = ChildStdin> {
+    reader: FrameReader<R>,
+    writer: FrameWriter<W>,
+}
+
+impl StdioTransport {
+    /// Spawns a server with piped stdin and stdout and talks to it over them.
+    /// The caller owns the returned process, e.g. to wait for it to exit.
+    pub fn spawn(command: &mut Command) -> Result<(Self, Child)> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("The server's stdin must be piped"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("The server's stdout must be piped"))?;
+        Ok((StdioTransport::new(stdout, stdin), child))
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> StdioTransport<R, W> {
+    /// A transport over the stdout and stdin of a server that is already
+    /// running.
+    pub fn new(stdout: R, stdin: W) -> Self {
+        StdioTransport {
+            reader: FrameReader::new(stdout),
+            writer: FrameWriter::new(stdin),
+        }
+    }
+
+    /// Sends a request, notification or response to the server.
+    pub async fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let body = serde_json::to_vec(message)?;
+        self.writer.write_frame(&body).await
+    }
+
+    /// Waits for the next message from the server.
+    pub async fn receive(&mut self) -> Result<ServerMessage> {
+        let mut message: serde_json::Value =
+            serde_json::from_slice(self.reader.next_frame().await?)?;
+        let method = message.get("method").and_then(serde_json::Value::as_str);
+        Ok(match (method, message.get("id")) {
+            (Some(method), Some(id)) => ServerMessage::Request(RequestMessage {
+                base_message: BaseMessage::new(),
+                id: id.clone(),
+                method: intern_method(method),
+                notification: 0,
+                params: message["params"].take(),
+            }),
+            (Some(_), None) => ServerMessage::Notification(serde_json::from_value(message)?),
+            (None, _) => ServerMessage::Response(serde_json::from_value(message)?),
+        })
+    }
+}
+
+/// Requests waiting for their response, by a key derived from the request id.
+pub struct PendingRequests<T> {
+    waiters: Mutex<HashMap<String, oneshot::Sender<T>>>,
+}
+
+impl<T> Default for PendingRequests<T> {
+    fn default() -> Self {
+        PendingRequests {
+            waiters: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T> PendingRequests<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts waiting for the response with `key`.
+    pub fn register(&self, key: String) -> oneshot::Receiver<T> {
+        let (tx, rx) = oneshot::channel();
+        self.waiters().insert(key, tx);
+        rx
+    }
+
+    /// Stops waiting for the response with `key`, e.g. because sending the
+    /// request failed.
+    pub fn forget(&self, key: &str) {
+        self.waiters().remove(key);
+    }
+
+    /// Stops waiting for every response, e.g. because the connection closed.
+    pub fn clear(&self) {
+        self.waiters().clear();
+    }
+
+    /// Hands `response` to whoever waits for `key`. Gives it back if nobody
+    /// does.
+    pub fn complete(&self, key: &str, response: T) -> Option<T> {
+        match self.waiters().remove(key) {
+            Some(waiter) => waiter.send(response).err(),
+            None => Some(response),
+        }
+    }
+
+    fn waiters(&self) -> MutexGuard<'_, HashMap<String, oneshot::Sender<T>>> {
+        self.waiters.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Stops a background task once the last handle is gone.
+pub(crate) struct BackgroundTask(pub(crate) JoinHandle<()>);
+
+impl Drop for BackgroundTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frames_round_trip() {
+        let (a, b) = tokio::io::duplex(64);
+        let mut writer = FrameWriter::new(a);
+        let mut reader = FrameReader::new(b);
+        writer.write_frame(br#"{"seq":1}"#).await.unwrap();
+        writer.write_frame(b"{}").await.unwrap();
+        assert_eq!(reader.read_frame().await.unwrap(), br#"{"seq":1}"#);
+        assert_eq!(reader.read_frame().await.unwrap(), b"{}");
+
+        let pending = PendingRequests::new();
+        let response = pending.register("1".to_string());
+        assert_eq!(pending.complete("2", "other"), Some("other"));
+        assert_eq!(pending.complete("1", "mine"), None);
+        assert_eq!(response.await.unwrap(), "mine");
+    }
+
+    #[tokio::test]
+    async fn test_stdio_transport() {
+        let (client_stdin, server_stdin) = tokio::io::duplex(1024);
+        let (server_stdout, client_stdout) = tokio::io::duplex(1024);
+        let mut transport = StdioTransport::new(client_stdout, client_stdin);
+        let mut server_reader = FrameReader::new(server_stdin);
+        let mut server_writer = FrameWriter::new(server_stdout);
+
+        let hover = RequestMessage::new_hover(
+            1,
+            "file:///main.go".to_string(),
+            crate::protocol::Position::new(0, 0),
+        );
+        transport.send(&hover).await.unwrap();
+        let sent: serde_json::Value =
+            serde_json::from_slice(&server_reader.read_frame().await.unwrap()).unwrap();
+        assert_eq!(sent["method"], "textDocument/hover");
+
+        for message in [
+            r#"{"jsonrpc":"2.0","method":"window/logMessage","params":{"type":3,"message":"hi"}}"#,
+            r#"{"jsonrpc":"2.0","id":"a","method":"workspace/configuration","params":{"items":[]}}"#,
+            r#"{"jsonrpc":"2.0","id":1,"result":null}"#,
+        ] {
+            server_writer.write_frame(message.as_bytes()).await.unwrap();
+        }
+        let ServerMessage::Notification(log) = transport.receive().await.unwrap() else {
+            panic!("expected a notification");
+        };
+        assert_eq!(log.method, "window/logMessage");
+        let ServerMessage::Request(request) = transport.receive().await.unwrap() else {
+            panic!("expected a request");
+        };
+        assert_eq!(request.id, serde_json::json!("a"));
+        assert_eq!(request.params, serde_json::json!({ "items": [] }));
+        let ServerMessage::Response(response) = transport.receive().await.unwrap() else {
+            panic!("expected a response");
+        };
+        assert_eq!(response.id, Some(serde_json::json!(1)));
+    }
+
+    #[tokio::test]
+    async fn test_stdio_transport_spawns_server() {
+        // `cat` sends every message back.
+        let (mut transport, mut child) = StdioTransport::spawn(&mut Command::new("cat")).unwrap();
+        transport
+            .send(&NotificationMessage::new_initialized())
+            .await
+            .unwrap();
+        let ServerMessage::Notification(echoed) = transport.receive().await.unwrap() else {
+            panic!("expected a notification");
+        };
+        assert_eq!(echoed.method, "initialized");
+        drop(transport);
+        assert!(child.wait().await.unwrap().success());
+    }
+
+    /// Records every write, writing all slices of a vectored one at once.
+    #[derive(Default)]
+    struct RecordingStream {
+        writes: usize,
+        data: Vec<u8>,
+    }
+
+    impl AsyncWrite for RecordingStream {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.writes += 1;
+            self.data.extend_from_slice(buf);
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_write_vectored(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.writes += 1;
+            for buf in bufs {
+                self.data.extend_from_slice(buf);
+            }
+            std::task::Poll::Ready(Ok(bufs.iter().map(|buf| buf.len()).sum()))
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            true
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queued_frames_share_one_write() {
+        let mut writer = FrameWriter::new(RecordingStream::default());
+        writer.write_frame(b"[1]").await.unwrap();
+        assert_eq!(writer.stream.writes, 1);
+
+        writer.queue_frame(b"[2]").unwrap();
+        writer.queue_frame(b"[3]").unwrap();
+        assert_eq!(writer.stream.writes, 1);
+        writer.write_frame(b"[4]").await.unwrap();
+        assert_eq!(writer.stream.writes, 2);
+        writer.flush_queued().await.unwrap();
+        assert_eq!(writer.stream.writes, 2);
+
+        let mut reader = FrameReader::new(&writer.stream.data[..]);
+        for body in [b"[1]", b"[2]", b"[3]", b"[4]"] {
+            assert_eq!(reader.next_frame().await.unwrap(), body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reader_reuses_its_buffer() {
+        // A small pipe splits every frame over several reads.
+        let (a, b) = tokio::io::duplex(32);
+        let mut writer = FrameWriter::new(a);
+        let mut reader = FrameReader::new(b);
+        let large = vec![b'x'; 2 * MAX_RETAINED_BUFFER];
+
+        let sent = large.clone();
+        let writing = tokio::spawn(async move {
+            writer.write_frame(b"[1]").await.unwrap();
+            writer.write_frame(&sent).await.unwrap();
+            writer
+        });
+        assert_eq!(reader.next_frame().await.unwrap(), b"[1]");
+        assert_eq!(reader.next_frame().await.unwrap(), &large[..]);
+        let mut writer = writing.await.unwrap();
+
+        writer.write_frame(b"[2]").await.unwrap();
+        assert_eq!(reader.next_frame().await.unwrap(), b"[2]");
+        assert_eq!(reader.header("Content-Length"), Some("3"));
+        assert_eq!(reader.headers().len(), 1);
+        assert!(reader.buf.len() <= READ_CHUNK);
+    }
+
+    #[tokio::test]
+    async fn test_frame_headers() {
+        let (a, b) = tokio::io::duplex(256);
+        let mut writer = FrameWriter::new(a);
+        writer
+            .set_headers(FrameHeaders {
+                content_type: Some(DEFAULT_CONTENT_TYPE.to_string()),
+                extra: vec![("X-Session".to_string(), "42".to_string())],
+            })
+            .unwrap();
+        writer.write_frame(b"{}").await.unwrap();
+        let mut reader = FrameReader::new(b);
+        assert_eq!(reader.read_frame().await.unwrap(), b"{}");
+        assert_eq!(reader.header("content-type"), Some(DEFAULT_CONTENT_TYPE));
+        assert_eq!(reader.header("x-session"), Some("42"));
+
+        for headers in [
+            FrameHeaders {
+                content_type: None,
+                extra: vec![("Content-Length".to_string(), "1".to_string())],
+            },
+            FrameHeaders {
+                content_type: Some("text/plain\r\nX-Injected: 1".to_string()),
+                extra: Vec::new(),
+            },
+        ] {
+            assert!(writer.set_headers(headers).is_err());
+        }
+
+        // Bare line feeds, lowercase names, stray lines and blank lines.
+        let lenient = b"\r\ncontent-length:2\nnot a header\nX-Proxy: a:b\n\n{}";
+        let mut reader = FrameReader::new(&lenient[..]);
+        assert_eq!(reader.read_frame().await.unwrap(), b"{}");
+        assert_eq!(reader.header("X-Proxy"), Some("a:b"));
+    }
+}
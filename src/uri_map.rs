@@ -0,0 +1,256 @@
+//! Rewrites the paths in messages for servers that see the files at other
+//! paths than the client, such as servers running in a container or on
+//! another machine over SSH:
+//!
+//! ```no_run
+//! # fn example(client: lsp_client_rs::client::LspClient) {
+//! use lsp_client_rs::uri_map::UriMap;
+//! // The checkout is mounted at /workspace in the container.
+//! client.set_uri_map(Some(UriMap::new().map("/home/me/app", "/workspace")));
+//! # }
+//! ```
+//!
+//! Every string in a message that is a `file://` URI or an absolute path
+//! under a mapped prefix is rewritten, as are object keys, such as the
+//! document URIs of a workspace edit's `changes`: local paths on the way to
+//! the server and remote ones on the way back. Fields holding document text
+//! or prose, such as `text`, `newText` or the `contents` of a hover, are
+//! left alone, even where they start with a mapped path.
+
+use crate::workspace::path_to_uri;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UriMap {
+    mappings: Vec<Mapping>,
+}
+
+/// A local prefix and its remote counterpart, as paths and as URIs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Mapping {
+    local: [String; 2],
+    remote: [String; 2],
+}
+
+impl UriMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps the files under `local` to the same files under `remote` on the
+    /// server. Where prefixes nest, the longest one wins.
+    pub fn map(mut self, local: impl AsRef<Path>, remote: impl AsRef<Path>) -> Self {
+        let forms = |path: &Path| {
+            let uri = path_to_uri(path);
+            let uri = uri.trim_end_matches('/').to_string();
+            let path = path.to_string_lossy().trim_end_matches('/').to_string();
+            [path, uri]
+        };
+        self.mappings.push(Mapping {
+            local: forms(local.as_ref()),
+            remote: forms(remote.as_ref()),
+        });
+        self.mappings
+            .sort_by_key(|mapping| std::cmp::Reverse(mapping.local[0].len()));
+        self
+    }
+
+    /// `uri` or path as the server sees it, if it is under a mapped prefix.
+    pub fn local_to_remote(&self, uri: &str) -> Option<String> {
+        self.mappings
+            .iter()
+            .find_map(|mapping| replace_prefix(uri, &mapping.local, &mapping.remote))
+    }
+
+    /// `uri` or path as the client sees it, if it is under a mapped prefix.
+    pub fn remote_to_local(&self, uri: &str) -> Option<String> {
+        let mut mappings: Vec<&Mapping> = self.mappings.iter().collect();
+        mappings.sort_by_key(|mapping| std::cmp::Reverse(mapping.remote[0].len()));
+        mappings
+            .into_iter()
+            .find_map(|mapping| replace_prefix(uri, &mapping.remote, &mapping.local))
+    }
+
+    /// Rewrites a message to the server.
+    pub(crate) fn rewrite_outgoing(&self, message: &mut Value) {
+        rewrite(message, &|uri| self.local_to_remote(uri));
+    }
+
+    /// Rewrites a message from the server.
+    pub(crate) fn rewrite_incoming(&self, message: &mut Value) {
+        rewrite(message, &|uri| self.remote_to_local(uri));
+    }
+}
+
+/// `text` with `from` replaced by `to`, if it starts with either form of
+/// `from` followed by the end of the string or a `/`.
+fn replace_prefix(text: &str, from: &[String; 2], to: &[String; 2]) -> Option<String> {
+    from.iter().zip(to).find_map(|(from, to)| {
+        let rest = text
+            .strip_prefix(from.as_str())
+            .filter(|_| !from.is_empty())?;
+        (rest.is_empty() || rest.starts_with('/')).then(|| format!("{}{}", to, rest))
+    })
+}
+
+/// Fields holding source text or prose rather than paths, skipped with
+/// everything in them.
+const TEXT_FIELDS: &[&str] = &[
+    "text",
+    "newText",
+    "insertText",
+    "value",
+    "contents",
+    "documentation",
+    "detail",
+    "message",
+    "label",
+];
+
+fn rewrite(value: &mut Value, replace: &dyn Fn(&str) -> Option<String>) {
+    match value {
+        Value::String(text) => {
+            if let Some(replaced) = replace(text) {
+                *text = replaced;
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                rewrite(item, replace);
+            }
+        }
+        Value::Object(object) => {
+            let mut rewritten = Map::with_capacity(object.len());
+            for (key, mut child) in std::mem::take(object) {
+                if TEXT_FIELDS.contains(&key.as_str()) {
+                    rewritten.insert(key, child);
+                    continue;
+                }
+                rewrite(&mut child, replace);
+                rewritten.insert(replace(&key).unwrap_or(key), child);
+            }
+            *object = rewritten;
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_uri_map() {
+        let map = UriMap::new()
+            .map("/home/me/app", "/workspace")
+            .map("/home/me/app/vendor", "/vendor");
+        assert_eq!(
+            map.local_to_remote("file:///home/me/app/src/main.rs")
+                .as_deref(),
+            Some("file:///workspace/src/main.rs")
+        );
+        assert_eq!(
+            map.local_to_remote("/home/me/app/vendor/lib.rs").as_deref(),
+            Some("/vendor/lib.rs")
+        );
+        assert_eq!(map.local_to_remote("file:///home/me/application"), None);
+
+        let mut message = json!({
+            "rootUri": "file:///home/me/app",
+            "rootPath": "/home/me/app",
+            "changes": { "file:///home/me/app/a.rs": [] },
+            "note": "see file:///home/me/app/a.rs"
+        });
+        map.rewrite_outgoing(&mut message);
+        assert_eq!(
+            message,
+            json!({
+                "rootUri": "file:///workspace",
+                "rootPath": "/workspace",
+                "changes": { "file:///workspace/a.rs": [] },
+                "note": "see file:///home/me/app/a.rs"
+            })
+        );
+        let mut result =
+            json!([{ "uri": "file:///vendor/lib.rs" }, { "uri": "file:///usr/lib.rs" }]);
+        map.rewrite_incoming(&mut result);
+        assert_eq!(
+            result,
+            json!([{ "uri": "file:///home/me/app/vendor/lib.rs" }, { "uri": "file:///usr/lib.rs" }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_rewrites_uris() {
+        use crate::client::LspClient;
+        use crate::event_bus::EventFilter;
+        use crate::transport::{FrameReader, FrameWriter};
+
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let client = LspClient::from_stream(client_end);
+        client.set_uri_map(Some(UriMap::new().map("/home/me/app", "/workspace")));
+        let mut notifications = client.subscribe_incoming(EventFilter::new());
+        let (read_half, write_half) = tokio::io::split(server_end);
+        let mut reader = FrameReader::new(read_half);
+        let mut writer = FrameWriter::new(write_half);
+
+        let uri = "file:///home/me/app/main.go".to_string();
+        client
+            .did_open(uri.clone(), "go".to_string(), "package main".to_string())
+            .await
+            .unwrap();
+        let open: Value = serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+        assert_eq!(
+            open["params"]["textDocument"]["uri"],
+            "file:///workspace/main.go"
+        );
+
+        let diagnostics = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": "file:///workspace/main.go", "diagnostics": [] }
+        });
+        writer
+            .write_frame(diagnostics.to_string().as_bytes())
+            .await
+            .unwrap();
+        let notification = notifications.recv().await.unwrap();
+        assert_eq!(notification.uri(), Some(uri.as_str()));
+    }
+
+    #[test]
+    fn test_document_text_is_not_rewritten() {
+        let map = UriMap::new().map("/home/me/app", "/workspace");
+        // A document whose text starts with the mapped path.
+        let mut open = json!({
+            "textDocument": {
+                "uri": "file:///home/me/app/paths.txt",
+                "text": "/home/me/app/src"
+            }
+        });
+        map.rewrite_outgoing(&mut open);
+        assert_eq!(open["textDocument"]["uri"], "file:///workspace/paths.txt");
+        assert_eq!(open["textDocument"]["text"], "/home/me/app/src");
+
+        let mut change = json!({ "contentChanges": [{ "text": "/home/me/app" }] });
+        map.rewrite_outgoing(&mut change);
+        assert_eq!(change["contentChanges"][0]["text"], "/home/me/app");
+
+        let mut edit = json!({
+            "changes": { "file:///workspace/paths.txt": [{ "newText": "/workspace/lib" }] }
+        });
+        map.rewrite_incoming(&mut edit);
+        assert_eq!(
+            edit["changes"]["file:///home/me/app/paths.txt"][0]["newText"],
+            "/workspace/lib"
+        );
+        let mut completion = json!({ "insertText": "/workspace", "label": "/workspace" });
+        map.rewrite_incoming(&mut completion);
+        assert_eq!(
+            completion,
+            json!({ "insertText": "/workspace", "label": "/workspace" })
+        );
+    }
+}
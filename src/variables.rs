@@ -0,0 +1,140 @@
+//! Variables in configured `initializationOptions` and settings, resolved
+//! when the server is initialized for a workspace, so that one configuration
+//! file works for every checkout:
+//!
+//! ```json
+//! {
+//!     "go": {
+//!         "command": "gopls",
+//!         "settings": { "gopls": { "env": { "GOPATH": "${env:GOPATH}" } } },
+//!         "initializationOptions": { "cacheDir": "${workspaceFolder}/.cache" }
+//!     }
+//! }
+//! ```
+//!
+//! Known variables are `${workspaceFolder}`, `${workspaceFolderBasename}`,
+//! `${userHome}`, `${pathSeparator}` and `${env:NAME}`, plus any set with
+//! `Variables::set`. Unset environment variables resolve to an empty string,
+//! and unknown variables are left as they are.
+
+use crate::workspace::uri_to_path;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::MAIN_SEPARATOR_STR;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Variables {
+    values: BTreeMap<String, String>,
+}
+
+impl Variables {
+    /// The variables that don't depend on a workspace.
+    pub fn new() -> Self {
+        let mut variables = Variables::default();
+        if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+            variables = variables.set("userHome", home.to_string_lossy());
+        }
+        variables.set("pathSeparator", MAIN_SEPARATOR_STR)
+    }
+
+    /// The variables of a workspace rooted at `root_uri`. The folder is the
+    /// root's path, or the URI itself if it isn't a `file://` URI.
+    pub fn for_workspace(root_uri: &str) -> Self {
+        let root_uri = root_uri.trim_end_matches('/');
+        let (folder, basename) = match uri_to_path(root_uri) {
+            Some(path) => (
+                path.display().to_string(),
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            ),
+            None => (
+                root_uri.to_string(),
+                root_uri.rsplit('/').next().unwrap_or_default().to_string(),
+            ),
+        };
+        Variables::new()
+            .set("workspaceFolder", folder)
+            .set("workspaceFolderBasename", basename)
+    }
+
+    /// Adds or replaces the variable `name`, referenced as `${name}`.
+    pub fn set(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.values.insert(name.into(), value.into());
+        self
+    }
+
+    /// The value of `name`, without the `${}` around it.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name.strip_prefix("env:") {
+            Some(variable) => Some(std::env::var(variable).unwrap_or_default()),
+            None => self.values.get(name).cloned(),
+        }
+    }
+
+    /// `text` with its variables replaced.
+    pub fn substitute_str(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            let Some(length) = rest[start..].find('}') else {
+                break;
+            };
+            let reference = &rest[start..start + length + 1];
+            result.push_str(&rest[..start]);
+            match self.get(&reference[2..reference.len() - 1]) {
+                Some(value) => result.push_str(&value),
+                None => result.push_str(reference),
+            }
+            rest = &rest[start + reference.len()..];
+        }
+        result.push_str(rest);
+        result
+    }
+
+    /// `value` with the variables of every string in it replaced. Object
+    /// keys are left alone.
+    pub fn substitute(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.substitute_str(text)),
+            Value::Array(items) => items.iter().map(|item| self.substitute(item)).collect(),
+            Value::Object(object) => object
+                .iter()
+                .map(|(key, value)| (key.clone(), self.substitute(value)))
+                .collect(),
+            _ => value.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_substitute_variables() {
+        let variables =
+            Variables::for_workspace("file:///code/my%20app/").set("userHome", "/home/me");
+        let options = json!({
+            "cacheDir": "${workspaceFolder}/.cache",
+            "name": "${workspaceFolderBasename}",
+            "paths": ["${userHome}/go", "${env:LSP_RS_UNSET_VARIABLE}", 3],
+            "${workspaceFolder}": "${unknown} and ${unterminated"
+        });
+        assert_eq!(
+            variables.substitute(&options),
+            json!({
+                "cacheDir": "/code/my app/.cache",
+                "name": "my app",
+                "paths": ["/home/me/go", "", 3],
+                "${workspaceFolder}": "${unknown} and ${unterminated"
+            })
+        );
+        let path = std::env::var("PATH").unwrap_or_default();
+        assert_eq!(
+            variables.substitute_str("PATH=${env:PATH}"),
+            format!("PATH={}", path)
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Finding the project root of a file. Servers answer relative to the root
+//! they were initialized with, so a wrong root is a common cause of empty
+//! results.
+
+use crate::protocol::WorkspaceFolder;
+use std::path::{Component, Path, PathBuf};
+
+/// Files or directories marking a project root, used by
+/// `RootDetector::default`.
+pub const DEFAULT_ROOT_MARKERS: &[&str] = &["Cargo.toml", "go.mod", "package.json", ".git"];
+
+/// Finds the root of the project a file belongs to: the closest directory
+/// above it that contains one of the markers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootDetector {
+    markers: Vec<String>,
+}
+
+impl Default for RootDetector {
+    fn default() -> Self {
+        RootDetector::new(DEFAULT_ROOT_MARKERS.iter().copied())
+    }
+}
+
+impl RootDetector {
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(markers: I) -> Self {
+        RootDetector {
+            markers: markers.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Adds a marker, e.g. `pyproject.toml`.
+    pub fn marker(mut self, marker: impl Into<String>) -> Self {
+        self.markers.push(marker.into());
+        self
+    }
+
+    /// The closest directory containing `path`, or `path` itself if it is a
+    /// directory, that has one of the markers. `None` if no ancestor has one.
+    pub fn find_root(&self, path: impl AsRef<Path>) -> Option<PathBuf> {
+        let path = std::path::absolute(path.as_ref()).ok()?;
+        let start = if path.is_dir() {
+            path.as_path()
+        } else {
+            path.parent()?
+        };
+        start
+            .ancestors()
+            .find(|dir| self.markers.iter().any(|marker| dir.join(marker).exists()))
+            .map(Path::to_path_buf)
+    }
+
+    /// The workspace to initialize a server with for `path`: its project
+    /// root, or the directory of `path` if it isn't in a project.
+    pub fn detect(&self, path: impl AsRef<Path>) -> Option<WorkspaceRoot> {
+        let path = path.as_ref();
+        let root = match self.find_root(path) {
+            Some(root) => root,
+            None => {
+                let path = std::path::absolute(path).ok()?;
+                if path.is_dir() {
+                    path
+                } else {
+                    path.parent()?.to_path_buf()
+                }
+            }
+        };
+        Some(WorkspaceRoot { path: root })
+    }
+}
+
+/// A project root, as the `rootUri` and `workspaceFolders` of `initialize`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceRoot {
+    pub path: PathBuf,
+}
+
+impl WorkspaceRoot {
+    /// The `rootUri` of the workspace.
+    pub fn uri(&self) -> String {
+        path_to_uri(&self.path)
+    }
+
+    /// The directory name of the root, used as the workspace folder name.
+    pub fn name(&self) -> String {
+        match self.path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => self.path.display().to_string(),
+        }
+    }
+
+    /// The `workspaceFolders` of the workspace: the root alone.
+    pub fn workspace_folders(&self) -> Vec<WorkspaceFolder> {
+        vec![WorkspaceFolder {
+            uri: self.uri(),
+            name: self.name(),
+        }]
+    }
+}
+
+/// The `file://` URI of an absolute path, percent-encoding the characters
+/// that aren't allowed in a URI path.
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for component in path.components() {
+        let segment = match component {
+            Component::Prefix(prefix) => {
+                // `C:` on Windows; URIs of drive paths start with `/C:`.
+                uri.push('/');
+                uri.push_str(&prefix.as_os_str().to_string_lossy());
+                continue;
+            }
+            Component::RootDir => continue,
+            component => component.as_os_str().to_string_lossy(),
+        };
+        uri.push('/');
+        for byte in segment.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    uri.push(byte as char)
+                }
+                _ => uri.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+    }
+    if uri.len() == "file://".len() {
+        uri.push('/');
+    }
+    uri
+}
+
+/// The path of a `file://` URI, decoding percent-encoded characters. `None`
+/// for other schemes.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = match tail {
+            [high, low, ..] if byte == b'%' => std::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // `/C:/Users` on Windows.
+    let path = match path.as_bytes() {
+        [b'/', _, b':', ..] if cfg!(windows) => &path[1..],
+        _ => &path[..],
+    };
+    Some(PathBuf::from(path))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_detection() {
+        let root = std::env::temp_dir().join(format!("lsp-client-rs-roots-{}", std::process::id()));
+        let crate_dir = root.join("my crate");
+        std::fs::create_dir_all(crate_dir.join("src")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(crate_dir.join("Cargo.toml"), "").unwrap();
+        std::fs::write(crate_dir.join("src/main.rs"), "").unwrap();
+        std::fs::write(root.join("notes.txt"), "").unwrap();
+
+        let detector = RootDetector::default();
+        assert_eq!(
+            detector.find_root(crate_dir.join("src/main.rs")),
+            Some(crate_dir.clone())
+        );
+        assert_eq!(
+            detector.find_root(root.join("notes.txt")),
+            Some(root.clone())
+        );
+        assert_eq!(
+            RootDetector::new(["go.mod"]).find_root(crate_dir.join("src/main.rs")),
+            None
+        );
+
+        let workspace = detector.detect(crate_dir.join("src/main.rs")).unwrap();
+        assert_eq!(
+            workspace.uri(),
+            format!("file://{}/my%20crate", root.display())
+        );
+        assert_eq!(workspace.workspace_folders()[0].name, "my crate");
+        let fallback = RootDetector::new(["go.mod"])
+            .detect(crate_dir.join("src/main.rs"))
+            .unwrap();
+        assert_eq!(fallback.path, crate_dir.join("src"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_path_to_uri() {
+        assert_eq!(path_to_uri(Path::new("/")), "file:///");
+        assert_eq!(
+            path_to_uri(Path::new("/home/me/c++/a#b.rs")),
+            "file:///home/me/c%2B%2B/a%23b.rs"
+        );
+        assert_eq!(
+            uri_to_path("file:///home/me/c%2B%2B/a%23b.rs"),
+            Some(PathBuf::from("/home/me/c++/a#b.rs"))
+        );
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    }
+}
@@ -0,0 +1,169 @@
+//! Harness for running the client against real language servers.
+//!
+//! Servers are looked up in `LSP_RS_<NAME>` (e.g. `LSP_RS_GOPLS`) and then in
+//! `PATH`. A server that can't be found or doesn't start is skipped, so the
+//! suite passes on machines that only have some of the servers installed.
+
+use anyhow::{bail, Result};
+use lsp_client_rs::client::LspClient;
+use lsp_client_rs::protocol::{
+    NotificationMessage, RequestMessage, ResponseMessage, WorkspaceFolder,
+};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// How long a single request may take. Servers index the workspace on
+/// startup, so the first requests can be slow.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Returns the path of the server binary `name`, if it is installed and runs.
+pub fn locate(name: &str) -> Option<PathBuf> {
+    let env_var = format!("LSP_RS_{}", name.to_uppercase().replace('-', "_"));
+    let candidate = match std::env::var_os(&env_var) {
+        Some(path) => PathBuf::from(path),
+        None => std::env::split_paths(&std::env::var_os("PATH")?)
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())?,
+    };
+    // rustup installs proxies for components that may not be installed.
+    let runs = std::process::Command::new(&candidate)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    runs.then_some(candidate)
+}
+
+/// A scratch directory holding the files of a test workspace. It is removed on drop.
+pub struct Workspace {
+    root: PathBuf,
+}
+
+impl Workspace {
+    pub fn new(files: &[(&str, &str)]) -> Result<Self> {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "lsp-client-rs-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        for (path, text) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, text)?;
+        }
+        Ok(Workspace { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn uri(&self, path: &str) -> String {
+        format!("file://{}", self.root.join(path).display())
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// A running server, initialized for a workspace.
+pub struct Session {
+    pub client: LspClient,
+    pub workspace: Workspace,
+    next_id: AtomicU32,
+}
+
+impl Session {
+    /// Starts the server `name` on `workspace` and runs the initialize
+    /// handshake. Returns `None` if the server isn't available.
+    pub async fn start(name: &str, args: &[&str], workspace: Workspace) -> Result<Option<Self>> {
+        let Some(binary) = locate(name) else {
+            eprintln!("skipping: {} not found", name);
+            return Ok(None);
+        };
+        let child = tokio::process::Command::new(binary)
+            .args(args)
+            .current_dir(workspace.root())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let session = Session {
+            client: LspClient::from_child(child)?,
+            workspace,
+            next_id: AtomicU32::new(1),
+        };
+        let root_uri = session.workspace.uri("");
+        let root_uri = root_uri.trim_end_matches('/').to_string();
+        let initialize = RequestMessage::new_initialize(
+            session.next_id(),
+            std::process::id(),
+            root_uri.clone(),
+            "lsp-client-rs-integration".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+            vec![WorkspaceFolder {
+                uri: root_uri.clone(),
+                name: root_uri,
+            }],
+        );
+        let response = session.request(initialize).await?;
+        if response.error.is_some() {
+            bail!("initialize failed: {:?}", response.error);
+        }
+        session
+            .client
+            .send_request(NotificationMessage::new_initialized())
+            .await?;
+        Ok(Some(session))
+    }
+
+    pub fn next_id(&self) -> u32 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub async fn request(&self, request: RequestMessage) -> Result<ResponseMessage> {
+        let method = request.method.clone();
+        match tokio::time::timeout(REQUEST_TIMEOUT, self.client.request(request)).await {
+            Ok(response) => response,
+            Err(_) => bail!("{} timed out after {:?}", method, REQUEST_TIMEOUT),
+        }
+    }
+
+    /// Sends the requests built by `build` until `done` accepts a response.
+    /// Servers answer with empty results while they are still loading the
+    /// workspace, so a single request isn't enough.
+    pub async fn request_until<B, D>(&self, mut build: B, mut done: D) -> Result<ResponseMessage>
+    where
+        B: FnMut(u32) -> RequestMessage,
+        D: FnMut(&ResponseMessage) -> bool,
+    {
+        let deadline = tokio::time::Instant::now() + REQUEST_TIMEOUT;
+        loop {
+            let response = self.request(build(self.next_id())).await?;
+            if done(&response) {
+                return Ok(response);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                bail!(
+                    "no usable response before {:?}: {:?}",
+                    REQUEST_TIMEOUT,
+                    response
+                );
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    pub async fn close(self) -> Result<()> {
+        self.client.close().await
+    }
+}
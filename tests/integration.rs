@@ -0,0 +1,120 @@
+//! End-to-end flows against real language servers. Run with
+//! `cargo test --features integration`; servers that aren't installed are skipped.
+
+mod common;
+
+use common::{Session, Workspace};
+use lsp_client_rs::protocol::{Position, RequestMessage};
+
+const GO_MOD: &str = "module example.com/hello\n\ngo 1.21\n";
+const MAIN_GO: &str = r#"package main
+
+import "fmt"
+
+func greet(name string) string {
+	return "Hello, " + name
+}
+
+func main() {
+	fmt.Println(greet("world"))
+}
+"#;
+
+const CARGO_TOML: &str = r#"[package]
+name = "hello"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+"#;
+const MAIN_RS: &str = r#"fn greet(name: &str) -> String {
+    format!("Hello, {}", name)
+}
+
+fn main() {
+    println!("{}", greet("world"));
+}
+"#;
+
+/// Opens `path`, asks for the definition of the symbol at `usage` and checks
+/// that it is at `definition`, then asks for hover information there.
+async fn definition_and_hover(
+    session: &Session,
+    path: &str,
+    language_id: &str,
+    text: &str,
+    usage: Position,
+    definition: Position,
+) {
+    let uri = session.workspace.uri(path);
+    session
+        .client
+        .did_open(uri.clone(), language_id.to_string(), text.to_string())
+        .await
+        .unwrap();
+
+    let response = session
+        .request_until(
+            |id| RequestMessage::new_get_definition(id, uri.clone(), usage),
+            |response| {
+                response
+                    .handle_definition()
+                    .is_ok_and(|locs| !locs.is_empty())
+            },
+        )
+        .await
+        .unwrap();
+    let locations = response.handle_definition().unwrap();
+    assert!(locations[0].uri().ends_with(path));
+    assert_eq!(locations[0].range().start().line(), definition.line());
+
+    let hover = session
+        .request(RequestMessage::new_hover(session.next_id(), uri, usage))
+        .await
+        .unwrap()
+        .handle_hover()
+        .unwrap();
+    assert!(hover.is_some_and(|hover| hover.to_plaintext().contains("greet")));
+}
+
+#[tokio::test]
+async fn test_gopls() {
+    let workspace = Workspace::new(&[("go.mod", GO_MOD), ("main.go", MAIN_GO)]).unwrap();
+    let Some(session) = Session::start("gopls", &[], workspace).await.unwrap() else {
+        return;
+    };
+
+    definition_and_hover(
+        &session,
+        "main.go",
+        "go",
+        MAIN_GO,
+        Position::new(9, 14),
+        Position::new(4, 5),
+    )
+    .await;
+    session.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_rust_analyzer() {
+    let workspace =
+        Workspace::new(&[("Cargo.toml", CARGO_TOML), ("src/main.rs", MAIN_RS)]).unwrap();
+    let Some(session) = Session::start("rust-analyzer", &[], workspace)
+        .await
+        .unwrap()
+    else {
+        return;
+    };
+
+    definition_and_hover(
+        &session,
+        "src/main.rs",
+        "rust",
+        MAIN_RS,
+        Position::new(5, 20),
+        Position::new(0, 3),
+    )
+    .await;
+    session.close().await.unwrap();
+}
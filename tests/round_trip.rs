@@ -0,0 +1,585 @@
+//! Round-trip tests of the protocol types: each sample must deserialize,
+//! serialize back to exactly the same JSON, and do so again from its own
+//! output, and every field name in it must be camelCase. A new field whose
+//! `rename` was forgotten fails here instead of on a real server.
+//!
+//! Every public serializable type of `protocol` and `edits` is listed in
+//! `round_trip_tests!`, which `test_every_type_has_a_sample` checks. Add new
+//! types with a sample that sets every field, so each field name is checked.
+
+use lsp_client_rs::capabilities::ServerCapabilities;
+use lsp_client_rs::completion::{CompletionItem, CompletionList};
+use lsp_client_rs::edits::{
+    ChangeAnnotation, DocumentChange, OptionalVersionedTextDocumentIdentifier, ResourceOperation,
+    TextDocumentEdit, TextEdit, WorkspaceEdit,
+};
+use lsp_client_rs::hover::Hover;
+use lsp_client_rs::protocol::{
+    BaseMessage, BoolOr, CapabilitiesTextDocument, CapabilitiesWindow, CapabilitiesWorkspace,
+    ChangeAnnotationSupport, ClientCapabilities, ClientInfo, CodeAction, CodeActionKind,
+    CodeActionLiteralSupport, CodeDescription, Completion, Diagnostic,
+    DiagnosticRelatedInformation, DiagnosticSeverity, DiagnosticTag, DidChangeConfiguration,
+    DocumentSymbol, DocumentSymbolResponse, InitializeParams, InitializeResult, Location,
+    LocationLink, NotificationMessage, OneOf, ParameterInformationCapabilities, Position,
+    ProgressToken, PublishDiagnosticsParams, Range, Registration, RequestMessage, ResolveSupport,
+    ResponseMessage, ServerInfo, SignatureHelpCapabilities, SignatureInformationCapabilities,
+    SymbolInformation, SymbolKind, SymbolTag, TextDocumentContentChangeEvent,
+    TextDocumentIdentifier, TextDocumentPositionParams, VersionedTextDocumentIdentifier,
+    WorkspaceFolder, WorkspaceSymbolCapabilities, WorkspaceSymbolResponse,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+fn assert_round_trip<T: Serialize + DeserializeOwned>(sample: Value) {
+    let parsed: T = serde_json::from_value(sample.clone())
+        .unwrap_or_else(|e| panic!("Sample doesn't deserialize: {}", e));
+    let serialized = serde_json::to_value(&parsed).unwrap();
+    assert_eq!(serialized, sample, "Serializing changed the sample");
+    let reparsed: T = serde_json::from_value(serialized.clone()).unwrap();
+    assert_eq!(
+        serde_json::to_value(&reparsed).unwrap(),
+        serialized,
+        "Serialization isn't stable"
+    );
+    assert_camel_case(&serialized, "$");
+}
+
+/// Checks the keys of every object in `value`. Keys that aren't identifiers,
+/// such as the URIs keying `WorkspaceEdit::changes`, are map keys rather than
+/// field names and are skipped.
+fn assert_camel_case(value: &Value, path: &str) {
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                let path = format!("{}.{}", path, key);
+                let identifier = key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if identifier {
+                    assert!(
+                        !key.contains('_') && key.starts_with(|c: char| c.is_ascii_lowercase()),
+                        "{} isn't camelCase",
+                        path
+                    );
+                }
+                assert_camel_case(value, &path);
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                assert_camel_case(item, &format!("{}[{}]", path, index));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Generates one test per type, asserting the round trip of its sample.
+macro_rules! round_trip_tests {
+    ($($(#[$attr:meta])* $name:ident: $ty:ty => $sample:expr;)*) => {
+        $(
+            $(#[$attr])*
+            #[test]
+            fn $name() {
+                assert_round_trip::<$ty>($sample);
+            }
+        )*
+    };
+}
+
+fn range() -> Value {
+    json!({ "start": { "line": 1, "character": 4 }, "end": { "line": 1, "character": 9 } })
+}
+
+fn location() -> Value {
+    json!({ "uri": "file:///app/src/main.rs", "range": range() })
+}
+
+fn diagnostic() -> Value {
+    json!({
+        "range": range(),
+        "severity": 1,
+        "code": "E0308",
+        "codeDescription": { "href": "https://doc.rust-lang.org/error_codes/E0308.html" },
+        "source": "rustc",
+        "message": "mismatched types",
+        "tags": [1],
+        "relatedInformation": [{ "location": location(), "message": "expected due to this" }],
+        "data": { "fixId": 3 }
+    })
+}
+
+fn text_document_capabilities() -> Value {
+    json!({
+        "hover": { "contentFormat": ["markdown", "plaintext"] },
+        "completion": {
+            "completionItem": { "snippetSupport": true },
+            "contextSupport": true
+        },
+        "codeAction": {
+            "codeActionLiteralSupport": { "codeActionKind": { "valueSet": ["quickfix"] } }
+        },
+        "signatureHelp": {
+            "signatureInformation": {
+                "documentationFormat": ["markdown"],
+                "parameterInformation": { "labelOffsetSupport": true },
+                "activeParameterSupport": true
+            },
+            "contextSupport": true
+        }
+    })
+}
+
+fn workspace_capabilities() -> Value {
+    json!({
+        "workspaceFolders": true,
+        "didChangeConfiguration": { "dynamicRegistration": false },
+        "workspaceEdit": {
+            "documentChanges": true,
+            "changeAnnotationSupport": { "groupsOnLabel": true }
+        },
+        "configuration": true,
+        "symbol": { "resolveSupport": { "properties": ["location.range"] } }
+    })
+}
+
+fn symbol_information() -> Value {
+    json!({
+        "name": "main",
+        "kind": 12,
+        "tags": [1],
+        "deprecated": false,
+        "location": location(),
+        "containerName": "app"
+    })
+}
+
+round_trip_tests! {
+    test_request_message: RequestMessage => json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "notification": 0,
+        "method": "textDocument/hover",
+        "params": { "textDocument": { "uri": "file:///app/src/main.rs" } }
+    });
+    test_response_message: ResponseMessage => json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "error": { "code": -32801, "message": "content modified" }
+    });
+    test_notification_message: NotificationMessage => json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didClose",
+        "params": { "textDocument": { "uri": "file:///app/src/main.rs" } }
+    });
+    test_initialize_params: InitializeParams => json!({
+        "processId": 42,
+        "rootUri": "file:///app",
+        "clientInfo": { "name": "lsp-client-rs", "version": "0.1.0" },
+        "capabilities": {
+            "workspace": {
+                "workspaceFolders": true,
+                "didChangeConfiguration": { "dynamicRegistration": false },
+                "workspaceEdit": {
+                    "documentChanges": true,
+                    "changeAnnotationSupport": { "groupsOnLabel": true }
+                },
+                "configuration": true,
+                "symbol": { "resolveSupport": { "properties": ["location.range"] } }
+            },
+            "textDocument": {
+                "hover": { "contentFormat": ["markdown", "plaintext"] },
+                "completion": {
+                    "completionItem": { "snippetSupport": true },
+                    "contextSupport": true
+                },
+                "codeAction": {
+                    "codeActionLiteralSupport": { "codeActionKind": { "valueSet": ["quickfix"] } }
+                }
+            }
+        },
+        "workspaceFolders": [{ "uri": "file:///app", "name": "app" }]
+    });
+    test_base_message: BaseMessage => json!({ "jsonrpc": "2.0" });
+    test_client_info: ClientInfo => json!({ "name": "lsp-client-rs", "version": "0.1.0" });
+    test_server_info: ServerInfo => json!({ "name": "gopls", "version": "v0.14.2" });
+    test_registration: Registration => json!({
+        "id": "watch-1",
+        "method": "workspace/didChangeWatchedFiles",
+        "registerOptions": { "watchers": [{ "globPattern": "**/*.go" }] }
+    });
+    test_workspace_folder: WorkspaceFolder => json!({ "uri": "file:///app", "name": "app" });
+    test_client_capabilities: ClientCapabilities => json!({
+        "workspace": workspace_capabilities(),
+        "textDocument": text_document_capabilities(),
+        "window": { "workDoneProgress": true }
+    });
+    test_capabilities_window: CapabilitiesWindow => json!({ "workDoneProgress": true });
+    test_capabilities_workspace: CapabilitiesWorkspace => workspace_capabilities();
+    test_workspace_symbol_capabilities: WorkspaceSymbolCapabilities => json!({
+        "resolveSupport": { "properties": ["location.range"] }
+    });
+    test_resolve_support: ResolveSupport => json!({ "properties": ["location.range"] });
+    test_did_change_configuration: DidChangeConfiguration => json!({ "dynamicRegistration": true });
+    test_workspace_edit_capabilities: lsp_client_rs::protocol::WorkspaceEdit => json!({
+        "documentChanges": true,
+        "changeAnnotationSupport": { "groupsOnLabel": false }
+    });
+    test_change_annotation_support: ChangeAnnotationSupport => json!({ "groupsOnLabel": true });
+    test_capabilities_text_document: CapabilitiesTextDocument => text_document_capabilities();
+    test_signature_help_capabilities: SignatureHelpCapabilities => json!({
+        "signatureInformation": {
+            "documentationFormat": ["plaintext"],
+            "parameterInformation": { "labelOffsetSupport": false },
+            "activeParameterSupport": false
+        },
+        "contextSupport": false
+    });
+    test_signature_information_capabilities: SignatureInformationCapabilities => json!({
+        "documentationFormat": ["markdown", "plaintext"],
+        "parameterInformation": { "labelOffsetSupport": true },
+        "activeParameterSupport": true
+    });
+    test_parameter_information_capabilities: ParameterInformationCapabilities => json!({
+        "labelOffsetSupport": true
+    });
+    #[cfg(feature = "proposed")]
+    test_inline_completion_capabilities: lsp_client_rs::protocol::InlineCompletionCapabilities => json!({
+        "dynamicRegistration": false
+    });
+    test_hover_capabilities: lsp_client_rs::protocol::Hover => json!({
+        "contentFormat": ["markdown"]
+    });
+    test_completion_capabilities: Completion => json!({
+        "completionItem": { "snippetSupport": false },
+        "contextSupport": true
+    });
+    test_completion_item_capabilities: lsp_client_rs::protocol::CompletionItem => json!({
+        "snippetSupport": true
+    });
+    test_code_action_capabilities: CodeAction => json!({
+        "codeActionLiteralSupport": { "codeActionKind": { "valueSet": ["refactor"] } }
+    });
+    test_code_action_literal_support: CodeActionLiteralSupport => json!({
+        "codeActionKind": { "valueSet": ["quickfix", "source"] }
+    });
+    test_code_action_kind: CodeActionKind => json!({ "valueSet": ["quickfix"] });
+    test_initialize_result: InitializeResult => json!({
+        "capabilities": { "hoverProvider": true, "positionEncoding": "utf-16" },
+        "serverInfo": { "name": "gopls", "version": "v0.14.2" }
+    });
+    test_server_capabilities: ServerCapabilities => json!({
+        "positionEncoding": "utf-8",
+        "textDocumentSync": {
+            "openClose": true,
+            "change": 2,
+            "willSave": false,
+            "willSaveWaitUntil": false,
+            "save": { "includeText": true }
+        },
+        "hoverProvider": true,
+        "completionProvider": {
+            "triggerCharacters": ["."],
+            "allCommitCharacters": [";"],
+            "resolveProvider": true,
+            "completionItem": { "labelDetailsSupport": true },
+            "workDoneProgress": false
+        },
+        "signatureHelpProvider": { "triggerCharacters": ["("], "retriggerCharacters": [","] },
+        "codeLensProvider": { "resolveProvider": false },
+        "documentLinkProvider": { "resolveProvider": true },
+        "inlayHintProvider": { "resolveProvider": true },
+        "semanticTokensProvider": {
+            "legend": { "tokenTypes": ["type"], "tokenModifiers": ["readonly"] },
+            "range": {},
+            "full": { "delta": true }
+        },
+        "diagnosticProvider": {
+            "identifier": "rustc",
+            "interFileDependencies": true,
+            "workspaceDiagnostics": false
+        },
+        "executeCommandProvider": { "commands": ["rust-analyzer.runSingle"] },
+        "definitionProvider": { "workDoneProgress": true },
+        "codeActionProvider": { "codeActionKinds": ["quickfix"], "resolveProvider": true },
+        "renameProvider": { "prepareProvider": true },
+        "documentOnTypeFormattingProvider": {
+            "firstTriggerCharacter": "}",
+            "moreTriggerCharacter": [";"]
+        },
+        "experimental": { "serverStatusNotification": true }
+    });
+    test_position: Position => json!({ "line": 3, "character": 7 });
+    test_range: Range => range();
+    test_location: Location => location();
+    test_location_link: LocationLink => json!({
+        "originSelectionRange": range(),
+        "targetUri": "file:///app/src/lib.rs",
+        "targetRange": range(),
+        "targetSelectionRange": range()
+    });
+    test_text_document_identifier: TextDocumentIdentifier => json!({ "uri": "file:///app/src/main.rs" });
+    test_versioned_text_document_identifier: VersionedTextDocumentIdentifier => json!({
+        "uri": "file:///app/src/main.rs",
+        "version": 3
+    });
+    test_text_document_position_params: TextDocumentPositionParams => json!({
+        "textDocument": { "uri": "file:///app/src/main.rs" },
+        "position": { "line": 3, "character": 7 }
+    });
+    test_diagnostic_severity: DiagnosticSeverity => json!(2);
+    test_diagnostic_tag: DiagnosticTag => json!(2);
+    test_code_description: CodeDescription => json!({
+        "href": "https://doc.rust-lang.org/error_codes/E0308.html"
+    });
+    test_diagnostic_related_information: DiagnosticRelatedInformation => json!({
+        "location": location(),
+        "message": "first defined here"
+    });
+    test_diagnostic: Diagnostic => diagnostic();
+    test_publish_diagnostics_params: PublishDiagnosticsParams => json!({
+        "uri": "file:///app/src/main.rs",
+        "version": 4,
+        "diagnostics": [diagnostic()]
+    });
+    test_document_symbol_response: DocumentSymbolResponse => json!([{
+        "name": "App",
+        "detail": "struct App",
+        "kind": 23,
+        "tags": [1],
+        "deprecated": true,
+        "range": range(),
+        "selectionRange": range(),
+        "children": [{ "name": "run", "kind": 6, "range": range(), "selectionRange": range() }]
+    }]);
+    test_flat_document_symbol_response: DocumentSymbolResponse => json!([symbol_information()]);
+    test_document_symbol: DocumentSymbol => json!({
+        "name": "run",
+        "detail": "fn run()",
+        "kind": 6,
+        "tags": [1],
+        "deprecated": false,
+        "range": range(),
+        "selectionRange": range(),
+        "children": [{ "name": "x", "kind": 13, "range": range(), "selectionRange": range() }]
+    });
+    test_symbol_kind: SymbolKind => json!(26);
+    test_symbol_tag: SymbolTag => json!(1);
+    test_symbol_information: SymbolInformation => symbol_information();
+    test_workspace_symbol_response: WorkspaceSymbolResponse => json!([symbol_information()]);
+    #[cfg(feature = "lsp-3-17")]
+    test_unresolved_workspace_symbol_response: WorkspaceSymbolResponse => json!([{
+        "name": "main",
+        "kind": 12,
+        "location": { "uri": "file:///app/src/main.rs" }
+    }]);
+    #[cfg(feature = "lsp-3-17")]
+    test_workspace_symbol_location: lsp_client_rs::protocol::WorkspaceSymbolLocation => location();
+    test_number_progress_token: ProgressToken => json!(7);
+    test_string_progress_token: ProgressToken => json!("indexing");
+    test_bool_or_flag: BoolOr<WorkspaceFolder> => json!(true);
+    test_bool_or_options: BoolOr<WorkspaceFolder> => json!({ "uri": "file:///app", "name": "app" });
+    test_one_of_left: OneOf<Location, LocationLink> => location();
+    test_one_of_right: OneOf<Location, LocationLink> => json!({
+        "targetUri": "file:///app/src/lib.rs",
+        "targetRange": range(),
+        "targetSelectionRange": range()
+    });
+    #[cfg(feature = "lsp-3-16")]
+    test_semantic_tokens: lsp_client_rs::protocol::SemanticTokens => json!({
+        "resultId": "3",
+        "data": [0, 4, 5, 1, 0]
+    });
+    #[cfg(feature = "lsp-3-17")]
+    test_workspace_symbol: lsp_client_rs::protocol::WorkspaceSymbol => json!({
+        "name": "main",
+        "kind": 12,
+        "tags": [1],
+        "containerName": "app",
+        "location": { "uri": "file:///app/src/main.rs" },
+        "data": { "symbolId": 9 }
+    });
+    #[cfg(feature = "lsp-3-17")]
+    test_full_diagnostic_report: lsp_client_rs::protocol::DocumentDiagnosticReport => json!({
+        "kind": "full",
+        "resultId": "7",
+        "items": [diagnostic()]
+    });
+    #[cfg(feature = "lsp-3-17")]
+    test_unchanged_diagnostic_report: lsp_client_rs::protocol::DocumentDiagnosticReport => json!({
+        "kind": "unchanged",
+        "resultId": "7"
+    });
+    #[cfg(feature = "proposed")]
+    test_inline_completion_trigger_kind: lsp_client_rs::protocol::InlineCompletionTriggerKind => json!(1);
+    #[cfg(feature = "proposed")]
+    test_selected_completion_info: lsp_client_rs::protocol::SelectedCompletionInfo => json!({
+        "range": range(),
+        "text": "println"
+    });
+    #[cfg(feature = "proposed")]
+    test_inline_completion_text: lsp_client_rs::protocol::InlineCompletionText => json!("println!()");
+    #[cfg(feature = "proposed")]
+    test_inline_completion_context: lsp_client_rs::protocol::InlineCompletionContext => json!({
+        "triggerKind": 2,
+        "selectedCompletionInfo": { "range": range(), "text": "println" }
+    });
+    #[cfg(feature = "proposed")]
+    test_inline_completion_item: lsp_client_rs::protocol::InlineCompletionItem => json!({
+        "insertText": { "kind": "snippet", "value": "println!(\"$1\")" },
+        "filterText": "println",
+        "range": range(),
+        "command": { "title": "Accept", "command": "app.accept" }
+    });
+    test_text_document_content_change_event: TextDocumentContentChangeEvent => json!({
+        "range": range(),
+        "text": "let"
+    });
+    test_completion_item: CompletionItem => json!({
+        "label": "println!",
+        "kind": 15,
+        "detail": "macro",
+        "documentation": { "kind": "markdown", "value": "Prints to stdout." },
+        "preselect": true,
+        "sortText": "0001",
+        "filterText": "println",
+        "insertText": "println!(\"$1\")",
+        "textEdit": { "range": range(), "newText": "println!" },
+        "additionalTextEdits": [{ "range": range(), "newText": "use std::io;" }],
+        "command": { "title": "Trigger", "command": "editor.action.triggerParameterHints" },
+        "data": { "resolveId": 1 }
+    });
+    test_completion_list: CompletionList => json!({
+        "isIncomplete": true,
+        "items": [{ "label": "println!" }]
+    });
+    test_completion_context: lsp_client_rs::completion::CompletionContext => json!({
+        "triggerKind": 2,
+        "triggerCharacter": "."
+    });
+    test_text_edit: TextEdit => json!({
+        "range": range(),
+        "newText": "x",
+        "annotationId": "rename"
+    });
+    test_change_annotation: ChangeAnnotation => json!({
+        "label": "Rename",
+        "needsConfirmation": true,
+        "description": "Renames x"
+    });
+    test_optional_versioned_text_document_identifier: OptionalVersionedTextDocumentIdentifier => json!({
+        "uri": "file:///app/src/main.rs",
+        "version": 3
+    });
+    test_resource_operation: ResourceOperation => json!({
+        "kind": "create",
+        "uri": "file:///app/src/new.rs",
+        "options": { "ignoreIfExists": true },
+        "annotationId": "create"
+    });
+    test_document_change: DocumentChange => json!({
+        "kind": "delete",
+        "uri": "file:///app/src/gone.rs"
+    });
+    test_text_document_edit: TextDocumentEdit => json!({
+        "textDocument": { "uri": "file:///app/src/main.rs", "version": null },
+        "edits": [{ "range": range(), "newText": "x" }]
+    });
+    test_workspace_edit: WorkspaceEdit => json!({
+        "changes": { "file:///app/src/lib.rs": [{ "range": range(), "newText": "y" }] },
+        "documentChanges": [
+            {
+                "textDocument": { "uri": "file:///app/src/main.rs", "version": 3 },
+                "edits": [{ "range": range(), "newText": "x", "annotationId": "rename" }]
+            },
+            { "kind": "create", "uri": "file:///app/src/new.rs", "options": { "overwrite": false } },
+            {
+                "kind": "rename",
+                "oldUri": "file:///app/src/old.rs",
+                "newUri": "file:///app/src/renamed.rs",
+                "annotationId": "rename"
+            },
+            { "kind": "delete", "uri": "file:///app/src/gone.rs", "options": { "recursive": true } }
+        ],
+        "changeAnnotations": { "rename": { "label": "Rename", "needsConfirmation": false } }
+    });
+    test_markup_hover: Hover => json!({
+        "contents": { "kind": "markdown", "value": "```rust\nfn main()\n```" },
+        "range": range()
+    });
+    test_marked_string_hover: Hover => json!({
+        "contents": ["Entry point", { "language": "rust", "value": "fn main()" }]
+    });
+    #[cfg(feature = "bsp")]
+    test_bsp_initialize_params: lsp_client_rs::bsp::InitializeBuildParams => json!({
+        "displayName": "lsp-client-rs",
+        "version": "0.1.0",
+        "bspVersion": "2.1.0",
+        "rootUri": "file:///app",
+        "capabilities": { "languageIds": ["scala"] },
+        "data": { "clientClasspath": [] }
+    });
+    #[cfg(feature = "bsp")]
+    test_bsp_build_target: lsp_client_rs::bsp::BuildTarget => json!({
+        "id": { "uri": "file:///app/?id=app" },
+        "displayName": "app",
+        "baseDirectory": "file:///app",
+        "tags": ["application"],
+        "languageIds": ["scala"],
+        "dependencies": [{ "uri": "file:///app/?id=core" }],
+        "capabilities": { "canCompile": true, "canTest": false, "canRun": true, "canDebug": false },
+        "dataKind": "scala",
+        "data": { "scalaVersion": "3.3.1" }
+    });
+    #[cfg(feature = "bsp")]
+    test_bsp_compile_result: lsp_client_rs::bsp::CompileResult => json!({
+        "originId": "build-1",
+        "statusCode": 1,
+        "dataKind": "compile-report",
+        "data": { "errors": 0 }
+    });
+}
+
+/// The public types of `src/protocol.rs` and `src/edits.rs` deriving both
+/// `Serialize` and `Deserialize`.
+fn serializable_types(source: &str) -> Vec<&str> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut types = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let Some(name) = line
+            .strip_prefix("pub struct ")
+            .or_else(|| line.strip_prefix("pub enum "))
+        else {
+            continue;
+        };
+        let attributes = lines[..index]
+            .iter()
+            .rev()
+            .take_while(|line| line.starts_with("#[") || line.starts_with("///"));
+        let derives = attributes
+            .filter(|line| line.starts_with("#[derive("))
+            .any(|line| line.contains("Serialize") && line.contains("Deserialize"));
+        if derives {
+            let end = name
+                .find(|c: char| !c.is_alphanumeric())
+                .unwrap_or(name.len());
+            types.push(&name[..end]);
+        }
+    }
+    types
+}
+
+#[test]
+fn test_every_type_has_a_sample() {
+    let tests = include_str!("round_trip.rs");
+    let sources = [
+        include_str!("../src/protocol.rs"),
+        include_str!("../src/edits.rs"),
+    ];
+    for name in sources.into_iter().flat_map(serializable_types) {
+        let listed = [" =>", "<"].iter().any(|after| {
+            tests.contains(&format!(": {}{}", name, after))
+                || tests.contains(&format!("::{}{}", name, after))
+        });
+        assert!(listed, "{} has no round-trip sample", name);
+    }
+}
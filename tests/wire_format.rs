@@ -0,0 +1,231 @@
+//! Snapshot tests of the wire format: the JSON produced by every request and
+//! notification builder, and the parsed form of responses recorded from real
+//! servers. Any serde change that alters either shows up as a snapshot diff.
+//!
+//! Snapshots live in `tests/fixtures/snapshots`. After an intended change,
+//! regenerate them with `UPDATE_SNAPSHOTS=1 cargo test --test wire_format`
+//! and review the diff.
+
+use lsp_client_rs::protocol::{
+    NotificationMessage, Position, PublishDiagnosticsParams, Range, RequestMessage,
+    ResponseMessage, TextDocumentContentChangeEvent,
+};
+use std::path::PathBuf;
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = fixtures().join("snapshots").join(name);
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "Missing snapshot {}; run with UPDATE_SNAPSHOTS=1 to create it",
+            path.display()
+        )
+    });
+    assert_eq!(
+        expected, actual,
+        "Snapshot {} changed; run with UPDATE_SNAPSHOTS=1 to accept the change",
+        name
+    );
+}
+
+fn assert_wire_snapshot<T: serde::Serialize>(name: &str, message: &T) {
+    let json = serde_json::to_string_pretty(message).unwrap();
+    assert_snapshot(&format!("{}.json", name), &(json + "\n"));
+}
+
+fn recorded(server: &str, name: &str) -> String {
+    let path = fixtures()
+        .join("responses")
+        .join(server)
+        .join(format!("{}.json", name));
+    std::fs::read_to_string(path).unwrap()
+}
+
+fn recorded_response(server: &str, name: &str) -> ResponseMessage {
+    serde_json::from_str(&recorded(server, name)).unwrap()
+}
+
+fn assert_parsed_snapshot<T: std::fmt::Debug>(name: &str, parsed: &T) {
+    assert_snapshot(&format!("{}.snap", name), &format!("{:#?}\n", parsed));
+}
+
+const URI: &str = "file:///workspace/src/main.rs";
+
+#[test]
+// The capabilities depend on the enabled protocol features.
+#[cfg(all(feature = "lsp-3-17", not(feature = "proposed")))]
+fn test_initialize_request() {
+    use lsp_client_rs::protocol::WorkspaceFolder;
+
+    let request = RequestMessage::new_initialize(
+        1,
+        4242,
+        "file:///workspace".to_string(),
+        "lsp-client-rs".to_string(),
+        "0.1.0".to_string(),
+        vec![WorkspaceFolder {
+            uri: "file:///workspace".to_string(),
+            name: "workspace".to_string(),
+        }],
+    );
+    assert_wire_snapshot("initialize", &request);
+}
+
+#[test]
+fn test_request_builders() {
+    let position = Position::new(14, 20);
+    assert_wire_snapshot("shutdown", &RequestMessage::new_shutdown(2));
+    assert_wire_snapshot(
+        "definition",
+        &RequestMessage::new_get_definition(3, URI.to_string(), position),
+    );
+    assert_wire_snapshot(
+        "hover",
+        &RequestMessage::new_hover(4, URI.to_string(), position),
+    );
+    assert_wire_snapshot(
+        "completion",
+        &RequestMessage::new_completion(5, URI.to_string(), position, None),
+    );
+    assert_wire_snapshot(
+        "document_symbol",
+        &RequestMessage::new_document_symbol(6, URI.to_string()),
+    );
+    assert_wire_snapshot(
+        "workspace_symbol",
+        &RequestMessage::new_workspace_symbol(7, "greet".to_string()),
+    );
+    assert_wire_snapshot(
+        "signature_help",
+        &RequestMessage::new_signature_help(
+            10,
+            URI.to_string(),
+            position,
+            Some(lsp_client_rs::signature_help::SignatureHelpContext::trigger_character(",")),
+        ),
+    );
+    #[cfg(feature = "lsp-3-17")]
+    assert_wire_snapshot(
+        "document_diagnostic",
+        &RequestMessage::new_document_diagnostic(8, URI.to_string(), Some("r1".to_string())),
+    );
+    #[cfg(feature = "proposed")]
+    assert_wire_snapshot(
+        "inline_completion",
+        &RequestMessage::new_inline_completion(
+            9,
+            URI.to_string(),
+            position,
+            lsp_client_rs::protocol::InlineCompletionContext {
+                trigger_kind: lsp_client_rs::protocol::InlineCompletionTriggerKind::Invoked,
+                selected_completion_info: None,
+            },
+        ),
+    );
+}
+
+#[test]
+fn test_notification_builders() {
+    assert_wire_snapshot("initialized", &NotificationMessage::new_initialized());
+    assert_wire_snapshot("exit", &NotificationMessage::new_exit());
+    assert_wire_snapshot(
+        "did_open",
+        &NotificationMessage::new_did_open(
+            URI.to_string(),
+            "rust".to_string(),
+            0,
+            "fn main() {}\n".to_string(),
+        ),
+    );
+    assert_wire_snapshot(
+        "did_change",
+        &NotificationMessage::new_did_change(
+            URI.to_string(),
+            1,
+            vec![
+                TextDocumentContentChangeEvent {
+                    range: Some(Range::new(Position::new(0, 3), Position::new(0, 7))),
+                    text: "start".to_string(),
+                },
+                TextDocumentContentChangeEvent {
+                    range: None,
+                    text: "fn start() {}\n".to_string(),
+                },
+            ],
+        ),
+    );
+    assert_wire_snapshot(
+        "did_close",
+        &NotificationMessage::new_did_close(URI.to_string()),
+    );
+    assert_wire_snapshot(
+        "did_change_configuration",
+        &NotificationMessage::new_did_change_configuration(serde_json::json!({
+            "rust-analyzer": { "checkOnSave": false }
+        })),
+    );
+}
+
+#[test]
+fn test_response_builders() {
+    let error = ResponseMessage::new_error(
+        serde_json::json!(11),
+        -32601,
+        "Unhandled method workspace/foo".to_string(),
+    );
+    // JSON-RPC forbids a result next to an error.
+    assert!(serde_json::to_value(&error)
+        .unwrap()
+        .get("result")
+        .is_none());
+    assert_wire_snapshot("error_response", &error);
+    assert_wire_snapshot(
+        "null_result_response",
+        &ResponseMessage::new_result(serde_json::json!(12), serde_json::Value::Null),
+    );
+}
+
+#[test]
+fn test_recorded_rust_analyzer_responses() {
+    let server = "rust-analyzer";
+    assert_parsed_snapshot(
+        "rust-analyzer-definition",
+        &recorded_response(server, "definition")
+            .handle_definition()
+            .unwrap(),
+    );
+    assert_parsed_snapshot(
+        "rust-analyzer-hover",
+        &recorded_response(server, "hover").handle_hover().unwrap(),
+    );
+    assert_parsed_snapshot(
+        "rust-analyzer-completion",
+        &recorded_response(server, "completion")
+            .handle_completion()
+            .unwrap(),
+    );
+    assert_parsed_snapshot(
+        "rust-analyzer-document_symbol",
+        &recorded_response(server, "document_symbol")
+            .handle_document_symbol()
+            .unwrap(),
+    );
+    assert_parsed_snapshot(
+        "rust-analyzer-workspace_symbol",
+        &recorded_response(server, "workspace_symbol")
+            .handle_workspace_symbol()
+            .unwrap(),
+    );
+
+    let notification: NotificationMessage =
+        serde_json::from_str(&recorded(server, "publish_diagnostics")).unwrap();
+    let params: PublishDiagnosticsParams = serde_json::from_value(notification.params).unwrap();
+    assert_parsed_snapshot("rust-analyzer-publish_diagnostics", &params);
+}